@@ -0,0 +1,479 @@
+use crate::{
+    crypto::{MasterHeader, Slot, VaultKey},
+    secret::SecretString,
+    types::{InternalError, InternalResult},
+    vaults::Vault,
+};
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use std::{
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+/// Dir of the agent's socket, only accessible by its owner
+const AGENT_DIR: &str = "agent";
+const SOCKET_NAME: &str = "agent.sock";
+/// Line printed by the agent once it accepts connections
+const READY: &str = "ready";
+/// Upper bound for a single request, so a stuck client can't block others
+const IO_TIMEOUT: Duration = Duration::from_secs(5);
+/// Upper bound of how late the agent notices it's idle
+const MAX_POLL: Duration = Duration::from_secs(1);
+/// Prefix of the slugs session keys are kept under, vault slugs are hex
+const SESSION_PREFIX: &str = "session-";
+
+/// One line of JSON sent to the agent, answered w/ one line of
+/// `Option<CachedKey>`, or [`Status`] for `status`
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+enum Request {
+    Add {
+        slug: String,
+        cached: CachedKey,
+    },
+    Get {
+        slug: String,
+    },
+    Forget {
+        slug: String,
+    },
+    /// drop all keys and exit
+    Lock,
+    /// list the keys held, w/o counting as use
+    Status,
+}
+
+/// What a running agent holds, see [`status`]
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub(crate) struct Status {
+    /// slugs of the vaults whose keys are held, w/ the seconds since
+    pub vaults: Vec<(String, u64)>,
+    /// interactive sessions keeping their sealing key in the agent
+    pub sessions: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CachedKey {
+    /// base64, see [`VaultKey::to_base64`]
+    key: SecretString,
+    slot: Slot,
+}
+
+/// Key of `vault` cached by a running agent, `None` if there is none or
+/// the key no longer opens the vault, e.g. after `vuoto passwd`
+pub(crate) fn get<P: AsRef<Path>>(home_dir: &P, vault: &Vault) -> Option<(VaultKey, Slot)> {
+    let request = Request::Get {
+        slug: vault.slug.clone(),
+    };
+
+    let cached = send::<Option<CachedKey>>(home_dir.as_ref(), &request).ok()??;
+    let key = VaultKey::from_base64(cached.key.expose()).ok()?;
+    let header = MasterHeader::load(home_dir, vault).ok()?;
+
+    header
+        .accepts(&key, cached.slot)
+        .then_some((key, cached.slot))
+}
+
+/// Cache `key` of `vault` in the agent, starting one if none is running
+pub(crate) fn add<P: AsRef<Path>>(
+    home_dir: &P,
+    vault: &Vault,
+    key: &VaultKey,
+    slot: Slot,
+) -> InternalResult<()> {
+    put(home_dir.as_ref(), &vault.slug, key, slot)
+}
+
+/// Keep the ephemeral key of interactive session `id` in the agent,
+/// starting one if none is running, see [`crate::session::Session`]
+pub(crate) fn add_session_key<P: AsRef<Path>>(
+    home_dir: &P,
+    id: &str,
+    key: &VaultKey,
+) -> InternalResult<()> {
+    put(
+        home_dir.as_ref(),
+        &format!("{SESSION_PREFIX}{id}"),
+        key,
+        Slot::Master,
+    )
+}
+
+/// Ephemeral key of session `id`, `None` once the agent locked
+pub(crate) fn session_key<P: AsRef<Path>>(home_dir: &P, id: &str) -> Option<VaultKey> {
+    let request = Request::Get {
+        slug: format!("{SESSION_PREFIX}{id}"),
+    };
+
+    let cached = send::<Option<CachedKey>>(home_dir.as_ref(), &request).ok()??;
+
+    VaultKey::from_base64(cached.key.expose()).ok()
+}
+
+/// Make the agent forget the key of session `id`, once it ended
+pub(crate) fn forget_session_key<P: AsRef<Path>>(home_dir: &P, id: &str) -> InternalResult<()> {
+    let request = Request::Forget {
+        slug: format!("{SESSION_PREFIX}{id}"),
+    };
+
+    send::<Option<CachedKey>>(home_dir.as_ref(), &request).map(|_| ())
+}
+
+fn put(home_dir: &Path, slug: &str, key: &VaultKey, slot: Slot) -> InternalResult<()> {
+    let request = Request::Add {
+        slug: slug.to_string(),
+        cached: CachedKey {
+            key: SecretString::from(key.to_base64().as_str()),
+            slot,
+        },
+    };
+
+    if send::<Option<CachedKey>>(home_dir, &request).is_err() {
+        spawn(home_dir)?;
+        send::<Option<CachedKey>>(home_dir, &request)?;
+    }
+
+    Ok(())
+}
+
+/// Make a running agent forget all keys and exit, `false` if none was
+/// running
+pub(crate) fn lock<P: AsRef<Path>>(home_dir: &P) -> InternalResult<bool> {
+    if !socket_path(home_dir.as_ref()).exists() {
+        return Ok(false);
+    }
+
+    match send::<Option<CachedKey>>(home_dir.as_ref(), &Request::Lock) {
+        Ok(_) => Ok(true),
+        Err(_) => {
+            // left behind by an agent which was killed
+            let _ = std::fs::remove_file(socket_path(home_dir.as_ref()));
+
+            Ok(false)
+        }
+    }
+}
+
+/// Keys held by the running agent, `None` if none is running
+pub(crate) fn status<P: AsRef<Path>>(home_dir: &P) -> Option<Status> {
+    send(home_dir.as_ref(), &Request::Status).ok()
+}
+
+fn socket_path(home_dir: &Path) -> PathBuf {
+    home_dir.join(AGENT_DIR).join(SOCKET_NAME)
+}
+
+#[cfg(unix)]
+fn send<T: DeserializeOwned>(home_dir: &Path, request: &Request) -> InternalResult<T> {
+    use std::{
+        io::{BufRead, BufReader, Write},
+        os::unix::net::UnixStream,
+    };
+    use zeroize::Zeroizing;
+
+    let mut stream = UnixStream::connect(socket_path(home_dir))?;
+    stream.set_read_timeout(Some(IO_TIMEOUT))?;
+    stream.set_write_timeout(Some(IO_TIMEOUT))?;
+
+    let mut line = Zeroizing::new(
+        serde_json::to_vec(request)
+            .map_err(|e| InternalError::IO(format!("Invalid agent request: {e}")))?,
+    );
+    line.push(b'\n');
+    stream.write_all(&line)?;
+
+    let mut response = Zeroizing::new(String::new());
+    BufReader::new(stream).read_line(&mut response)?;
+
+    serde_json::from_str(&response)
+        .map_err(|e| InternalError::IO(format!("Invalid agent response: {e}")))
+}
+
+#[cfg(not(unix))]
+fn send<T: DeserializeOwned>(_home_dir: &Path, _request: &Request) -> InternalResult<T> {
+    Err(unsupported())
+}
+
+/// Start the agent in the background, for the data dir `home_dir`
+#[cfg(unix)]
+fn spawn(home_dir: &Path) -> InternalResult<()> {
+    use std::{
+        io::{BufRead, BufReader},
+        os::unix::process::CommandExt,
+        process::{Command, Stdio},
+    };
+
+    let mut child = Command::new(std::env::current_exe()?)
+        .arg("agent")
+        .env(crate::DATA_DIR_ENV, home_dir)
+        .env_remove(crate::PROFILE_ENV)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        // own process group, so Ctrl-C in the terminal doesn't reach it
+        .process_group(0)
+        .spawn()
+        .map_err(|e| InternalError::IO(format!("Failed to start the agent: {e}")))?;
+
+    let mut line = String::new();
+
+    if let Some(stdout) = child.stdout.take() {
+        BufReader::new(stdout).read_line(&mut line)?;
+    }
+
+    if line.trim_end() != READY {
+        let _ = child.kill();
+        let _ = child.wait();
+
+        return Err(InternalError::IO("Failed to start the agent".into()));
+    }
+
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn spawn(_home_dir: &Path) -> InternalResult<()> {
+    Err(unsupported())
+}
+
+#[cfg(not(unix))]
+fn unsupported() -> InternalError {
+    InternalError::IO("The agent requires Unix domain sockets".into())
+}
+
+/// Serve cached keys until locked, until no request came in for
+/// `lock_after` or w/ `lock_on_sleep` until the screen locks or the machine
+/// sleeps, vault keys are dropped `ttl` after they were added
+#[cfg(unix)]
+pub(crate) fn serve<P: AsRef<Path>>(
+    home_dir: &P,
+    lock_after: Option<Duration>,
+    lock_on_sleep: bool,
+    ttl: Option<Duration>,
+) -> InternalResult<()> {
+    use crate::{clipboard, screenlock::Watch};
+    use std::{
+        collections::HashMap,
+        fs::{DirBuilder, Permissions},
+        io::Write,
+        os::unix::{
+            fs::{DirBuilderExt, PermissionsExt},
+            net::UnixListener,
+        },
+        sync::{Arc, Mutex},
+        time::Instant,
+    };
+
+    struct State {
+        keys: HashMap<String, (CachedKey, Instant)>,
+        last_active: Instant,
+    }
+
+    let path = socket_path(home_dir.as_ref());
+    let dir = home_dir.as_ref().join(AGENT_DIR);
+
+    DirBuilder::new().recursive(true).mode(0o700).create(&dir)?;
+    // mode only applies to newly created dirs
+    std::fs::set_permissions(&dir, Permissions::from_mode(0o700))?;
+
+    // left behind by an agent which was killed, a live one got the request
+    let _ = std::fs::remove_file(&path);
+    let listener = UnixListener::bind(&path)?;
+
+    let state = Arc::new(Mutex::new(State {
+        keys: HashMap::new(),
+        last_active: Instant::now(),
+    }));
+
+    if lock_after.is_some() || lock_on_sleep || ttl.is_some() {
+        let state = Arc::clone(&state);
+        let path = path.clone();
+        let mut watch = lock_on_sleep.then(Watch::new);
+        let poll = lock_after.map_or(MAX_POLL, |after| MAX_POLL.min(after));
+
+        std::thread::spawn(move || {
+            loop {
+                std::thread::sleep(poll);
+
+                let slept = watch.as_mut().is_some_and(Watch::triggered);
+                let mut state = state.lock().unwrap_or_else(|e| e.into_inner());
+
+                if let Some(ttl) = ttl {
+                    state.keys.retain(|slug, (_, added)| {
+                        slug.starts_with(SESSION_PREFIX) || added.elapsed() < ttl
+                    });
+                }
+
+                let idle = lock_after.is_some_and(|after| state.last_active.elapsed() >= after);
+
+                // keys expired, nothing left to serve
+                if slept || idle || (ttl.is_some() && state.keys.is_empty()) {
+                    state.keys.clear();
+                    let _ = std::fs::remove_file(&path);
+
+                    if slept {
+                        let _ = clipboard::clear();
+                    }
+
+                    std::process::exit(0);
+                }
+            }
+        });
+    }
+
+    println!("{READY}");
+    std::io::stdout().flush()?;
+
+    for stream in listener.incoming() {
+        let Ok(stream) = stream else {
+            continue;
+        };
+
+        let mut state = state.lock().unwrap_or_else(|e| e.into_inner());
+
+        // a misbehaving client only fails its own request
+        let handled = handle(stream, &mut state.keys);
+
+        if !matches!(handled, Ok(Request::Status)) {
+            state.last_active = Instant::now();
+        }
+
+        match handled {
+            Ok(Request::Lock) => {
+                state.keys.clear();
+                break;
+            }
+            // started for a session which ended, nothing left to serve
+            Ok(Request::Forget { .. }) if state.keys.is_empty() => break,
+            _ => {}
+        }
+    }
+
+    let _ = std::fs::remove_file(&path);
+
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub(crate) fn serve<P: AsRef<Path>>(
+    _home_dir: &P,
+    _lock_after: Option<Duration>,
+    _lock_on_sleep: bool,
+    _ttl: Option<Duration>,
+) -> InternalResult<()> {
+    Err(unsupported())
+}
+
+/// Answer a single request, returning it for the caller to act on
+#[cfg(unix)]
+fn handle(
+    stream: std::os::unix::net::UnixStream,
+    keys: &mut std::collections::HashMap<String, (CachedKey, std::time::Instant)>,
+) -> InternalResult<Request> {
+    use std::{
+        io::{BufRead, BufReader, Write},
+        time::Instant,
+    };
+    use zeroize::Zeroizing;
+
+    stream.set_read_timeout(Some(IO_TIMEOUT))?;
+    stream.set_write_timeout(Some(IO_TIMEOUT))?;
+
+    let mut line = Zeroizing::new(String::new());
+    let mut reader = BufReader::new(&stream);
+    reader.read_line(&mut line)?;
+
+    let request: Request = serde_json::from_str(&line)
+        .map_err(|e| InternalError::IO(format!("Invalid agent request: {e}")))?;
+
+    let response = match &request {
+        Request::Add { slug, cached } => {
+            keys.insert(slug.clone(), (cached.clone(), Instant::now()));
+            None
+        }
+        Request::Get { slug } => keys.get(slug).map(|(cached, _)| cached.clone()),
+        Request::Forget { slug } => {
+            keys.remove(slug);
+            None
+        }
+        Request::Lock | Request::Status => None,
+    };
+
+    let out = match &request {
+        Request::Status => {
+            let mut status = Status::default();
+
+            for (slug, (_, added)) in keys.iter() {
+                match slug.starts_with(SESSION_PREFIX) {
+                    true => status.sessions += 1,
+                    false => status
+                        .vaults
+                        .push((slug.clone(), added.elapsed().as_secs())),
+                }
+            }
+
+            serde_json::to_vec(&status)
+        }
+        _ => serde_json::to_vec(&response),
+    };
+    let mut out =
+        Zeroizing::new(out.map_err(|e| InternalError::IO(format!("Invalid agent response: {e}")))?);
+    out.push(b'\n');
+    (&stream).write_all(&out)?;
+
+    Ok(request)
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::testing::{TEST_KDF, vault};
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_agent_caches_keys_until_locked() {
+        let temp_dir = TempDir::new().unwrap();
+        let home_dir = temp_dir.path().to_path_buf();
+
+        let vault = vault();
+        let key = MasterHeader::create(&home_dir, &vault, "pw", None, TEST_KDF).unwrap();
+
+        let agent = {
+            let home_dir = home_dir.clone();
+            std::thread::spawn(move || serve(&home_dir, None, false, None))
+        };
+
+        while !socket_path(&home_dir).exists() {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+
+        assert!(get(&home_dir, &vault).is_none());
+
+        let request = Request::Add {
+            slug: vault.slug.clone(),
+            cached: CachedKey {
+                key: SecretString::from(key.to_base64().as_str()),
+                slot: Slot::Master,
+            },
+        };
+        send::<Option<CachedKey>>(&home_dir, &request).unwrap();
+        add_session_key(&home_dir, "42", &VaultKey::random().unwrap()).unwrap();
+
+        let held = status(&home_dir).unwrap();
+        assert_eq!(held.vaults.len(), 1);
+        assert_eq!(held.vaults[0].0, vault.slug);
+        assert_eq!(held.sessions, 1);
+
+        let (cached, slot) = get(&home_dir, &vault).unwrap();
+        assert_eq!(slot, Slot::Master);
+        assert_eq!(cached.to_base64(), key.to_base64());
+
+        assert!(lock(&home_dir).unwrap());
+        agent.join().unwrap().unwrap();
+
+        assert!(!socket_path(&home_dir).exists());
+        assert!(!lock(&home_dir).unwrap());
+        assert!(status(&home_dir).is_none());
+    }
+}
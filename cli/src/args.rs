@@ -0,0 +1,1680 @@
+use crate::{
+    browser::Browser,
+    crypto::KdfAlgorithm,
+    types::{InternalError, InternalResult},
+    yubikey,
+};
+
+/// Vault used by commands w/o --vault, before the default one
+pub(crate) const VAULT_ENV: &str = "VUOTO_VAULT";
+
+const USAGE: &str = "\
+Usage: vuoto [OPTIONS]
+       vuoto list --vault <VAULT> [--json [--show-secrets]]
+       vuoto get --vault <VAULT> <NAME> [--field <FIELD>] [--json [--show-secrets]]
+       vuoto add --vault <VAULT> <NAME> [--username <USER>] [--url <URL>]
+                 [--generate | --password-stdin]
+       vuoto rm --vault <VAULT> <NAME> [--yes]
+       vuoto vault list [--json]
+       vuoto vault create <NAME> [--json]
+       vuoto vault rename <NAME> <NEW_NAME> [--json]
+       vuoto vault delete <NAME> [--yes] [--json]
+       vuoto vault default [<NAME> | --clear] [--json]
+       vuoto entry inspect --vault <VAULT> <NAME> [--raw] [--reason <TEXT>]
+       vuoto import --format <FORMAT> --vault <VAULT> <FILE>
+       vuoto import --from-browser chrome|firefox|edge --vault <VAULT>
+       vuoto import --from-keychain --vault <VAULT>
+       vuoto import --from-windows-credentials --vault <VAULT>
+       vuoto import --from-secret-service|--from-kwallet --vault <VAULT>
+       vuoto export --format <FORMAT> --vault <VAULT> [--passphrase-protect] [FILE]
+                    [--reason <TEXT>]
+       vuoto import|export --list-formats
+       vuoto lookup [--format ansible|json] <VAULT>/<ENTRY>[#FIELD]
+       vuoto history verify|migrate --vault <VAULT>
+       vuoto verify --vault <VAULT> [--reset]
+       vuoto audit --vault <VAULT> [--score] [--reuse-graph] [--json]
+       vuoto passwd --vault <VAULT> [--decoy]
+       vuoto unlock --vault <VAULT>
+       vuoto lock [--all]
+       vuoto status
+       vuoto recovery-kit --vault <VAULT>
+       vuoto recover --vault <VAULT>
+       vuoto scratch --vault <VAULT> [--clear]
+       vuoto keychain add --vault <VAULT> [--biometric]
+       vuoto keychain remove --vault <VAULT>
+       vuoto 2fa enroll yubikey --vault <VAULT> [--slot <1|2>]
+       vuoto 2fa enroll fido2 --vault <VAULT> [--passwordless]
+       vuoto 2fa remove --vault <VAULT>
+       vuoto kdf bench --vault <VAULT> [--target <MS>]
+       vuoto vault rekdf --vault <VAULT> --kdf <argon2id|pbkdf2-sha256>
+       vuoto qr send --vault <VAULT> <NAME> [--interval <MS>] [--loops <N>]
+                     [--reason <TEXT>]
+       vuoto qr receive --vault <VAULT> [FILE]
+       vuoto share-once --vault <VAULT> <NAME> [--bind <ADDR>] [--timeout <SECS>]
+                        [--reason <TEXT>]
+       vuoto encrypt-file --vault <VAULT> <FILE> [--entry <NAME>] [--output <FILE>]
+       vuoto decrypt-file --vault <VAULT> <FILE> [--output <FILE>]
+       vuoto provision --unit <UNIT> --vault <VAULT> [--secret [CRED=]ENTRY[#FIELD]]...
+                       [--encrypt] [--dir <DIR>]
+       vuoto deprovision --unit <UNIT> [--encrypt] [--dir <DIR>]
+       vuoto migrate-device export|import <FILE>
+       vuoto demo
+       vuoto doctor
+
+Commands:
+  list              print the names of a vault's entries, one per line
+  get               print an entry's secret, or its FIELD (e.g. password,
+                    username, url or otp, the current TOTP code), and nothing
+                    else, like `lookup`
+  add               add a login entry, its password read from stdin when
+                    piped or w/ --password-stdin, else asked twice, w/
+                    --generate a random one, exits w/ 5 on an invalid name,
+                    username, URL or password and 6 if the entry exists
+  rm                delete an entry, asking first w/o --yes
+  vault list        print all vaults, archived ones marked
+  vault create      add a vault and set its master password
+  vault rename      rename a vault, its entries stay as they are
+  vault delete      delete a vault and all of its entries, asking to re-type
+                    its name first w/o --yes
+  vault default     set the vault used by commands w/o --vault, print it w/o
+                    a name, w/ --clear unset it
+  entry inspect     print a stored entry, w/ --raw dump its bytes as hex
+                    (works for entries which fail to decode), break-glass
+                    entries require a --reason which is logged
+  import            add entries from a file, on a terminal entries differing
+                    from existing ones of the same name show a field level
+                    diff (secrets masked unless shown) to keep or overwrite
+                    each, or all remaining, else existing entries are kept,
+                    w/ --from-browser the saved logins of the browser's last
+                    used profile (read w/ the `sqlite3` shell), asking for
+                    Firefox's primary password if one is set, w/
+                    --from-keychain the internet and app passwords picked
+                    from the macOS login keychain, w/
+                    --from-windows-credentials the generic and domain
+                    credentials picked from the Windows Credential Manager,
+                    w/ --from-secret-service the items picked from GNOME
+                    Keyring, KWallet or another Secret Service provider, w/
+                    --from-kwallet the picked entries of KWallet's Passwords
+                    folder (read w/ `kwallet-query`)
+  export            write all entries to a file, or stdout w/o one, w/
+                    --passphrase-protect encrypted under a passphrase, so it
+                    can be imported on another machine w/o the master password,
+                    unencrypted exports of the same entries are byte-identical,
+                    break-glass entries are only exported w/ a --reason which
+                    is logged
+  lookup            print a single secret for automation (Ansible lookup
+                    plugins, Terraform external data sources): the entry's
+                    secret or FIELD, raw w/ ansible (default) or as
+                    {\"value\": ...} w/ json, nothing else, uses the key of
+                    `vuoto unlock` or the keychain and only prompts on a
+                    terminal, exits w/ 2 on invalid arguments, 3 if the
+                    vault, entry or field doesn't exist, 4 if the vault is
+                    locked and 1 on any other error
+  history verify    check a vault's access history for tampering
+  history migrate   chain the records of an access history written by
+                    older releases, accepting them as they are
+  verify            compare a vault's entries against the hashes recorded
+                    on every write, catching bit-rot and lost writes, w/
+                    --reset accept its current state instead
+  audit             list a vault's issues, most urgent first, w/ --score
+                    also print its health score (0-100), w/ --reuse-graph
+                    print a Graphviz graph of logins sharing a password
+                    instead, e.g. for `dot -Tsvg`
+  passwd            change a vault's master password, re-encrypting all of
+                    its entries, also adds a newly configured keyfile, w/
+                    --decoy set a duress password instead, which unlocks a
+                    separate, initially empty set of decoy entries
+  unlock            keep a vault's key in a background agent, so following
+                    commands use it w/o asking for the master password,
+                    until `vuoto lock`, [session] lock_after_secs w/o use,
+                    [key_cache] agent_ttl_secs after unlocking or the screen
+                    locks or the machine sleeps (Unix only), refused unless
+                    [key_cache] policy is agent or keychain (the default)
+  lock              make the agent forget all keys, locking open sessions
+                    too, w/ --all also remove all keys from the OS keychain
+                    and clear the clipboard
+  status            print the key cache policy and which vault keys are
+                    cached where
+  recovery-kit      print an emergency kit for a vault to store offline: its
+                    details and a random recovery key (also as a QR code)
+                    which opens it w/o the master password, replacing any
+                    earlier kit, it stays valid through password changes
+  recover           set a new master password w/ the recovery kit's key,
+                    when the old one is forgotten
+  scratch           edit a vault's scratchpad in $EDITOR, an encrypted note for
+                    parking secrets copied from elsewhere, deleted [scratch]
+                    ttl_secs (default 3600) after its last save, w/ --clear
+                    delete it right away
+  keychain add      keep a vault's key in the OS keychain (macOS Keychain,
+                    Windows Credential Manager or Secret Service), so it
+                    unlocks w/o the master password on this machine, w/
+                    --biometric only after Touch ID (macOS) or Windows
+                    Hello, asking for the master password if they fail
+  keychain remove   forget the key kept by `keychain add`
+  2fa enroll        require a YubiKey's HMAC-SHA1 challenge-response (slot
+                    2 by default, via `ykchalresp`) or a FIDO2 security
+                    key's hmac-secret (via libfido2's tools, w/ PIN and
+                    touch) next to the master password, prints a recovery
+                    code unlocking w/o the key, w/ --passwordless the FIDO2
+                    key unlocks the vault on its own
+  2fa remove        stop requiring the hardware key
+  kdf bench         time key derivation on this machine and raise its cost
+                    to take about --target ms (default 500), applied the
+                    next time the vault is unlocked
+  vault rekdf       re-encrypt a vault under a key derived w/ another KDF,
+                    e.g. pbkdf2-sha256 for devices too constrained for
+                    Argon2's memory cost
+  qr send           show an entry as an animated sequence of QR codes, for
+                    offline transfer to another device
+  qr receive        reassemble scanned QR frames (one per line, from FILE or
+                    stdin), verify their checksum and add the entry
+  share-once        serve an entry's secrets exactly once over HTTPS, behind
+                    a random link and PIN, e.g. to a colleague on the LAN
+                    (default bind 0.0.0.0:0, timeout 300s)
+  encrypt-file      encrypt a file to FILE.vuoto, under the random key of a
+                    \"File key\" entry (default \"File key\", created if
+                    missing), the original is kept, changing the entry's
+                    key makes its files unreadable
+  decrypt-file      decrypt a file of `encrypt-file` to FILE w/o .vuoto,
+                    w/ the entry whose key it was encrypted w/, never
+                    overwriting a file
+  provision         write secrets of a vault as systemd credentials for a
+                    service, one file per --secret (picked interactively
+                    w/o one) in /run/vuoto/<UNIT> readable by root only, or
+                    w/ --encrypt sealed by `systemd-creds` in
+                    /etc/vuoto/credstore/<UNIT>, and print the lines to add
+                    to the unit, secrets provisioned before are replaced,
+                    break-glass entries are never provisioned
+  deprovision       remove the credentials provisioned for a unit
+  migrate-device    bundle config, vault index, master password and all
+                    vaults into a single passphrase encrypted file (export),
+                    or unpack it on a new machine (import), refusing to
+                    overwrite files which differ
+  demo              try vuoto on a throwaway vault w/ sample entries
+  doctor            run the crypto self-test and check the OS random source,
+                    passwords and keys are never generated while it looks
+                    broken
+
+JSON output of list, get, audit and vault commands:
+  --json            print a single JSON value instead, prompts fail rather
+                    than show up, so give the master password up front (see
+                    --password-file) or `vuoto unlock` the vault first
+  --show-secrets    include secret values in the JSON of list and get, list
+                    leaves those of break-glass entries out
+
+Options:
+  --profile <NAME>  use a separate data dir, e.g. for work, w/ its own
+                    config.toml unless there is a user config (also read
+                    from VUOTO_PROFILE)
+  --vault <VAULT>   vault used by commands w/o their own --vault, opened
+                    right away instead of showing the vault menu
+  --keyfile <FILE>  keyfile required next to the master password, for vaults
+                    set up w/ one (see [keyfiles] in the config)
+  --minimal         only ask for name and password when creating entries
+  --all-vaults      unlock several vaults and browse their entries in one
+                    list
+  --no-clipboard    show values instead of copying them, the default when
+                    running inside a container
+  --answers <FILE>  answer prompts from a JSON file instead of the terminal
+                    (also read from VUOTO_ANSWERS)
+  --password-file <FILE>
+                    read the master password from the first line of FILE
+                    instead of prompting, for CI and scripts, a wrong one
+                    fails right away (also read from VUOTO_PASSWORD)
+  --password-fd <FD>
+                    the same, from an open file descriptor (Unix only)
+  -h, --help        print this help
+
+Config:
+  config.toml in $VUOTO_HOME, else in the vuoto dir of $XDG_CONFIG_HOME or
+  ~/.config (~/Library/Application Support on macOS, %APPDATA% on Windows),
+  else in the data dir, sets e.g. data_dir, default_vault, theme (default, light
+  or plain), [clipboard] clear_after_secs, [session] lock_after_secs and
+  [generator] length and charset
+
+Environment:
+  VUOTO_HOME        dir of vuoto's config, data and state, instead of
+                    $XDG_CONFIG_HOME, $XDG_DATA_HOME and $XDG_STATE_HOME (or
+                    ~/.config, ~/.local/share and ~/.local/state), or of
+                    ~/Library/Application Support on macOS and %APPDATA% on
+                    Windows, an old ~/vuoto_cli is moved to the data dir
+  VUOTO_DATA_DIR    data dir used instead of the default or configured one
+  VUOTO_CONFIG      config file used instead of the user's or data dir's
+  VUOTO_VAULT       vault used by commands w/o --vault, before the one set
+                    w/ `vuoto vault default`
+  VUOTO_DEFAULT_VAULT, VUOTO_THEME, VUOTO_CLIPBOARD_CLEAR_SECS,
+  VUOTO_LOCK_AFTER_SECS, VUOTO_GENERATOR_LENGTH, VUOTO_GENERATOR_CHARSET
+                    override the config key of the same name
+  NO_COLOR          plain prompts, whatever the theme
+  VUOTO_PASSWORD    master password used instead of prompting, hidden from
+                    processes vuoto starts, prefer --password-fd where the
+                    environment of processes is visible to others";
+
+/// Command line flags
+#[derive(Debug, Default)]
+pub(crate) struct Args {
+    /// only ask for name and password when creating entries
+    pub minimal: bool,
+    /// never touch the system clipboard
+    pub no_clipboard: bool,
+    /// browse the entries of several vaults at once
+    pub all_vaults: bool,
+    /// scripted prompt answers, see [`crate::prompt::load_answers`]
+    pub answers: Option<String>,
+    /// separate data dir, see [`crate::get_app_dir`]
+    pub profile: Option<String>,
+    /// vault given before the command, for commands w/o their own --vault
+    /// and instead of the vault menu
+    pub vault: Option<String>,
+    /// keyfile for any vault, see [`crate::config::Config::keyfile_for`]
+    pub keyfile: Option<String>,
+    /// file holding the master password, `/dev/fd/N` for --password-fd
+    pub password_file: Option<String>,
+    /// non-interactive command, `None` runs the interactive menus
+    pub command: Option<Command>,
+}
+
+/// Non-interactive commands
+#[derive(Debug, PartialEq)]
+pub(crate) enum Command {
+    /// Print the names of a vault's entries, w/ `json` the entries
+    List {
+        vault: Option<String>,
+        json: bool,
+        show_secrets: bool,
+    },
+    /// Print an entry's secret, or one of its fields, w/ `json` the entry
+    Get {
+        vault: Option<String>,
+        name: String,
+        field: Option<String>,
+        json: bool,
+        show_secrets: bool,
+    },
+    /// Add a login entry, w/ a random password if `generate`
+    Add {
+        vault: Option<String>,
+        name: String,
+        username: Option<String>,
+        url: Option<String>,
+        generate: bool,
+        /// read the password from stdin, even on a terminal
+        password_stdin: bool,
+    },
+    /// Delete an entry, w/o asking if `yes`
+    Remove {
+        vault: Option<String>,
+        name: String,
+        yes: bool,
+    },
+    /// Print all vaults
+    VaultList { json: bool },
+    /// Add a vault and set its master password
+    VaultCreate { name: String, json: bool },
+    /// Rename a vault
+    VaultRename {
+        name: String,
+        new_name: String,
+        json: bool,
+    },
+    /// Delete a vault and its entries, w/o asking if `yes`
+    VaultDelete { name: String, yes: bool, json: bool },
+    /// Set the default vault, unset it if `clear`, print it w/o either
+    VaultDefault {
+        name: Option<String>,
+        clear: bool,
+        json: bool,
+    },
+    /// Print a stored entry, or dump its raw bytes
+    InspectEntry {
+        vault: Option<String>,
+        name: String,
+        raw: bool,
+        /// why a break-glass entry is accessed
+        reason: Option<String>,
+    },
+    /// Add entries from a file in given format, or a browser's saved logins
+    Import {
+        source: ImportSource,
+        vault: Option<String>,
+    },
+    /// Write all entries in given format, to stdout if `file` is `None`
+    Export {
+        format: String,
+        vault: Option<String>,
+        file: Option<String>,
+        /// encrypt the output under a passphrase, see [`crate::crypto::protect`]
+        protect: bool,
+        /// why break-glass entries are exported, they're left out w/o one
+        reason: Option<String>,
+    },
+    /// Print a single value of an entry, for scripts
+    Lookup {
+        /// `<VAULT>/<ENTRY>[#FIELD]`
+        path: String,
+        format: LookupFormat,
+    },
+    /// List import (or export) formats
+    ListFormats { export: bool },
+    /// Check the hash chain of a vault's history
+    VerifyHistory { vault: Option<String> },
+    /// Chain the records of a history written before they were chained
+    MigrateHistory { vault: Option<String> },
+    /// Check a vault's cache against its integrity manifest, or replace
+    /// the manifest w/ `reset`
+    VerifyVault { vault: Option<String>, reset: bool },
+    /// Print a vault's audit findings, optionally w/ its health score, or
+    /// the graph of reused passwords
+    Audit {
+        vault: Option<String>,
+        score: bool,
+        reuse_graph: bool,
+        json: bool,
+    },
+    /// Change a vault's master password, or set its decoy one
+    Passwd { vault: Option<String>, decoy: bool },
+    /// Re-derive a vault's key w/ another KDF
+    RekdfVault {
+        vault: Option<String>,
+        algorithm: KdfAlgorithm,
+    },
+    /// Pick KDF parameters for this machine, applied on the vault's next
+    /// unlock
+    KdfBench {
+        vault: Option<String>,
+        /// milliseconds deriving a key should take
+        target: Option<u64>,
+    },
+    /// Show an entry as a sequence of QR frames
+    QrSend {
+        vault: Option<String>,
+        name: String,
+        /// milliseconds each frame is shown
+        interval: Option<u64>,
+        /// times the sequence is repeated
+        loops: Option<u32>,
+        /// why a break-glass entry is accessed
+        reason: Option<String>,
+    },
+    /// Add an entry from scanned QR frames, read from stdin if `file` is
+    /// `None`
+    QrReceive {
+        vault: Option<String>,
+        file: Option<String>,
+    },
+    /// Serve an entry's secrets once over a local HTTPS link
+    ShareOnce {
+        vault: Option<String>,
+        name: String,
+        /// listen address, see [`crate::share::DEFAULT_BIND`]
+        bind: Option<String>,
+        /// seconds the link stays valid
+        timeout: Option<u64>,
+        /// why a break-glass entry is accessed
+        reason: Option<String>,
+    },
+    /// Encrypt a file under a key kept in a vault entry
+    EncryptFile {
+        vault: Option<String>,
+        path: String,
+        /// entry holding the key, created if missing, see
+        /// [`crate::FILE_KEY_ENTRY`]
+        entry: Option<String>,
+        /// `<path>.vuoto` by default
+        output: Option<String>,
+    },
+    /// Decrypt a file of `EncryptFile`
+    DecryptFile {
+        vault: Option<String>,
+        path: String,
+        /// `path` w/o `.vuoto` by default
+        output: Option<String>,
+    },
+    /// Write secrets as credentials of a systemd unit
+    Provision {
+        vault: Option<String>,
+        unit: String,
+        /// `[CRED=]ENTRY[#FIELD]`, picked interactively if empty
+        secrets: Vec<String>,
+        /// encrypt w/ `systemd-creds`
+        encrypt: bool,
+        /// parent of the unit's dir instead of the default one
+        dir: Option<String>,
+    },
+    /// Remove the credentials provisioned for a unit
+    Deprovision {
+        unit: String,
+        encrypt: bool,
+        dir: Option<String>,
+    },
+    /// Bundle the whole app dir into `file`, or unpack it from there
+    MigrateDevice { export: bool, file: String },
+    /// Interactive walkthrough on a throwaway vault
+    Demo,
+    /// Check the environment vuoto runs in, e.g. the OS random source
+    Doctor,
+    /// Cache a vault's key in the agent, starting one if needed
+    Unlock { vault: Option<String> },
+    /// Make the agent drop all keys and exit, w/ `all` also remove keys
+    /// from the OS keychain and clear the clipboard
+    Lock { all: bool },
+    /// Print where vault keys are cached
+    Status,
+    /// Print a recovery kit opening a vault w/o its master password
+    RecoveryKit { vault: Option<String> },
+    /// Set a new master password w/ the recovery kit
+    Recover { vault: Option<String> },
+    /// Edit a vault's expiring scratchpad, or delete it w/ `clear`
+    Scratch { vault: Option<String>, clear: bool },
+    /// Run the agent itself, spawned by `unlock`
+    Agent,
+    /// Keep a vault's key in the OS keychain, or behind biometrics
+    KeychainAdd {
+        vault: Option<String>,
+        biometric: bool,
+    },
+    /// Forget a vault's key kept in the OS keychain
+    KeychainRemove { vault: Option<String> },
+    /// Require a hardware key next to the master password
+    TwoFactorEnroll {
+        vault: Option<String>,
+        factor: SecondFactor,
+    },
+    /// Stop requiring the hardware key
+    TwoFactorRemove { vault: Option<String> },
+}
+
+impl Command {
+    /// Whether the command prints JSON, so prompts must not mix in
+    pub fn json(&self) -> bool {
+        match self {
+            Command::List { json, .. }
+            | Command::Get { json, .. }
+            | Command::Audit { json, .. }
+            | Command::VaultList { json }
+            | Command::VaultCreate { json, .. }
+            | Command::VaultRename { json, .. }
+            | Command::VaultDelete { json, .. }
+            | Command::VaultDefault { json, .. } => *json,
+            _ => false,
+        }
+    }
+}
+
+/// Where `vuoto import` reads entries from
+#[derive(Debug, PartialEq)]
+pub(crate) enum ImportSource {
+    /// file in a format of [`crate::plugins::Registry`]
+    File { format: String, file: String },
+    /// saved logins of the browser's last used profile
+    Browser(Browser),
+    /// picked items of the macOS login keychain
+    Keychain,
+    /// picked credentials of the Windows Credential Manager
+    WindowsCredentials,
+    /// picked items of the Secret Service, e.g. of GNOME Keyring
+    SecretService,
+    /// picked entries of KWallet's password folder
+    KWallet,
+}
+
+/// Output of `vuoto lookup`
+#[derive(Debug, PartialEq)]
+pub(crate) enum LookupFormat {
+    /// the value as is, w/o a trailing newline
+    Ansible,
+    /// `{"value": ...}`, as Terraform's external data source expects
+    Json,
+}
+
+/// Hardware key enrolled w/ `vuoto 2fa enroll`
+#[derive(Debug, PartialEq)]
+pub(crate) enum SecondFactor {
+    /// HMAC-SHA1 challenge-response in configuration slot 1 or 2
+    Yubikey { slot: u8 },
+    /// FIDO2 hmac-secret, w/ `passwordless` replacing the master password
+    Fido2 { passwordless: bool },
+}
+
+impl Args {
+    pub fn parse() -> InternalResult<Self> {
+        Self::parse_from(std::env::args().skip(1))
+    }
+
+    fn parse_from<I: IntoIterator<Item = String>>(args: I) -> InternalResult<Self> {
+        let mut parsed = Self::default();
+
+        let mut args = args.into_iter();
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "entry" if parsed.command.is_none() => {
+                    match args.next().as_deref() {
+                        Some("inspect") => {}
+                        Some(other) => {
+                            return Err(InternalError::IO(format!(
+                                "Unknown entry command: {other}"
+                            )));
+                        }
+                        None => return Err(InternalError::IO("entry requires a command".into())),
+                    }
+
+                    parsed.command = Some(Self::parse_inspect(&mut args)?);
+                }
+                "history" if parsed.command.is_none() => {
+                    let migrate = match args.next().as_deref() {
+                        Some("verify") => false,
+                        Some("migrate") => true,
+                        Some(other) => {
+                            return Err(InternalError::IO(format!(
+                                "Unknown history command: {other}"
+                            )));
+                        }
+                        None => {
+                            return Err(InternalError::IO("history requires a command".into()));
+                        }
+                    };
+
+                    let vault = Self::parse_history_vault(&mut args)?;
+                    parsed.command = Some(match migrate {
+                        true => Command::MigrateHistory { vault },
+                        false => Command::VerifyHistory { vault },
+                    });
+                }
+                "verify" if parsed.command.is_none() => {
+                    parsed.command = Some(Self::parse_verify_vault(&mut args)?);
+                }
+                "demo" if parsed.command.is_none() => parsed.command = Some(Command::Demo),
+                "doctor" if parsed.command.is_none() => parsed.command = Some(Command::Doctor),
+                "lock" if parsed.command.is_none() => {
+                    parsed.command = Some(Self::parse_lock(&mut args)?);
+                }
+                "status" if parsed.command.is_none() => parsed.command = Some(Command::Status),
+                "agent" if parsed.command.is_none() => parsed.command = Some(Command::Agent),
+                "unlock" if parsed.command.is_none() => {
+                    parsed.command = Some(Self::parse_unlock(&mut args)?);
+                }
+                "recovery-kit" | "recover" if parsed.command.is_none() => {
+                    parsed.command = Some(Self::parse_recovery_kit(&mut args, arg == "recover")?);
+                }
+                "scratch" if parsed.command.is_none() => {
+                    parsed.command = Some(Self::parse_scratch(&mut args)?);
+                }
+                "audit" if parsed.command.is_none() => {
+                    parsed.command = Some(Self::parse_audit(&mut args)?);
+                }
+                "passwd" if parsed.command.is_none() => {
+                    parsed.command = Some(Self::parse_passwd(&mut args)?);
+                }
+                "vault" if parsed.command.is_none() => {
+                    parsed.command = Some(match args.next().as_deref() {
+                        Some("rekdf") => Self::parse_rekdf(&mut args)?,
+                        Some(action @ ("list" | "create" | "rename" | "delete" | "default")) => {
+                            Self::parse_vault(&mut args, action)?
+                        }
+                        Some(other) => {
+                            return Err(InternalError::IO(format!(
+                                "Unknown vault command: {other}"
+                            )));
+                        }
+                        None => return Err(InternalError::IO("vault requires a command".into())),
+                    });
+                }
+                "list" if parsed.command.is_none() => {
+                    parsed.command = Some(Self::parse_list(&mut args)?);
+                }
+                "get" | "add" | "rm" if parsed.command.is_none() => {
+                    parsed.command = Some(Self::parse_entry(&mut args, &arg)?);
+                }
+                "keychain" if parsed.command.is_none() => {
+                    let remove = match args.next().as_deref() {
+                        Some("add") => false,
+                        Some("remove") => true,
+                        Some(other) => {
+                            return Err(InternalError::IO(format!(
+                                "Unknown keychain command: {other}"
+                            )));
+                        }
+                        None => {
+                            return Err(InternalError::IO("keychain requires a command".into()));
+                        }
+                    };
+
+                    parsed.command = Some(Self::parse_keychain(&mut args, remove)?);
+                }
+                "2fa" if parsed.command.is_none() => {
+                    let command = match args.next().as_deref() {
+                        Some("enroll") => match args.next().as_deref() {
+                            Some(factor @ ("yubikey" | "fido2")) => {
+                                let fido2 = factor == "fido2";
+                                Self::parse_two_factor(&mut args, Some(fido2))?
+                            }
+                            Some(other) => {
+                                return Err(InternalError::IO(format!(
+                                    "Unsupported second factor: {other}"
+                                )));
+                            }
+                            None => {
+                                return Err(InternalError::IO(
+                                    "2fa enroll requires yubikey or fido2".into(),
+                                ));
+                            }
+                        },
+                        Some("remove") => Self::parse_two_factor(&mut args, None)?,
+                        Some(other) => {
+                            return Err(InternalError::IO(format!("Unknown 2fa command: {other}")));
+                        }
+                        None => return Err(InternalError::IO("2fa requires a command".into())),
+                    };
+
+                    parsed.command = Some(command);
+                }
+                "kdf" if parsed.command.is_none() => {
+                    match args.next().as_deref() {
+                        Some("bench") => {}
+                        Some(other) => {
+                            return Err(InternalError::IO(format!("Unknown kdf command: {other}")));
+                        }
+                        None => return Err(InternalError::IO("kdf requires a command".into())),
+                    }
+
+                    parsed.command = Some(Self::parse_kdf_bench(&mut args)?);
+                }
+                "qr" if parsed.command.is_none() => {
+                    let command = match args.next().as_deref() {
+                        Some("send") => Self::parse_qr_send(&mut args)?,
+                        Some("receive") => Self::parse_qr_receive(&mut args)?,
+                        Some(other) => {
+                            return Err(InternalError::IO(format!("Unknown qr command: {other}")));
+                        }
+                        None => return Err(InternalError::IO("qr requires a command".into())),
+                    };
+
+                    parsed.command = Some(command);
+                }
+                "migrate-device" if parsed.command.is_none() => {
+                    parsed.command = Some(Self::parse_migrate(&mut args)?);
+                }
+                "lookup" if parsed.command.is_none() => {
+                    parsed.command = Some(Self::parse_lookup(&mut args)?);
+                }
+                "provision" | "deprovision" if parsed.command.is_none() => {
+                    parsed.command = Some(Self::parse_provision(&mut args, arg == "deprovision")?);
+                }
+                "share-once" if parsed.command.is_none() => {
+                    parsed.command = Some(Self::parse_share(&mut args)?);
+                }
+                "encrypt-file" | "decrypt-file" if parsed.command.is_none() => {
+                    parsed.command =
+                        Some(Self::parse_file_crypt(&mut args, arg == "decrypt-file")?);
+                }
+                "import" | "export" if parsed.command.is_none() => {
+                    parsed.command = Some(Self::parse_transfer(&mut args, arg == "export")?);
+                }
+                "--minimal" => parsed.minimal = true,
+                "--no-clipboard" => parsed.no_clipboard = true,
+                "--all-vaults" => parsed.all_vaults = true,
+                "--answers" => {
+                    let file = args
+                        .next()
+                        .ok_or_else(|| InternalError::IO("--answers requires a file".into()))?;
+
+                    parsed.answers = Some(file);
+                }
+                "--keyfile" => {
+                    let file = args
+                        .next()
+                        .ok_or_else(|| InternalError::IO("--keyfile requires a file".into()))?;
+
+                    parsed.keyfile = Some(file);
+                }
+                "--password-file" | "--password-fd" if parsed.password_file.is_some() => {
+                    return Err(InternalError::IO(
+                        "--password-file and --password-fd can't be combined".into(),
+                    ));
+                }
+                "--password-file" => {
+                    let file = args.next().ok_or_else(|| {
+                        InternalError::IO("--password-file requires a file".into())
+                    })?;
+
+                    parsed.password_file = Some(file);
+                }
+                "--password-fd" => {
+                    let fd = args
+                        .next()
+                        .and_then(|fd| fd.parse::<u32>().ok())
+                        .ok_or_else(|| {
+                            InternalError::IO("--password-fd requires a file descriptor".into())
+                        })?;
+
+                    parsed.password_file = Some(format!("/dev/fd/{fd}"));
+                }
+                "--vault" => {
+                    let name = args
+                        .next()
+                        .ok_or_else(|| InternalError::IO("--vault requires a vault name".into()))?;
+
+                    parsed.vault = Some(name);
+                }
+                "--profile" => {
+                    let name = args
+                        .next()
+                        .ok_or_else(|| InternalError::IO("--profile requires a name".into()))?;
+
+                    parsed.profile = Some(name);
+                }
+                "-h" | "--help" => {
+                    println!("{USAGE}");
+                    std::process::exit(0);
+                }
+                other => return Err(InternalError::IO(format!("Unknown argument: {other}"))),
+            }
+        }
+
+        // stdin can't hold both passwords
+        if let Some(Command::Add {
+            password_stdin: true,
+            ..
+        }) = &parsed.command
+            && matches!(
+                parsed.password_file.as_deref(),
+                Some("/dev/stdin" | "/dev/fd/0")
+            )
+        {
+            return Err(InternalError::IO(
+                "--password-stdin can't be combined w/ a master password read from stdin".into(),
+            ));
+        }
+
+        Ok(parsed)
+    }
+
+    fn parse_list<I: Iterator<Item = String>>(args: &mut I) -> InternalResult<Command> {
+        let mut vault = None;
+        let mut json = false;
+        let mut show_secrets = false;
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--vault" => {
+                    vault = Some(args.next().ok_or_else(|| {
+                        InternalError::IO("--vault requires a vault name".into())
+                    })?);
+                }
+                "--json" => json = true,
+                "--show-secrets" => show_secrets = true,
+                other => return Err(InternalError::IO(format!("Unknown argument: {other}"))),
+            }
+        }
+
+        Self::ensure_json(json, show_secrets)?;
+
+        Ok(Command::List {
+            vault,
+            json,
+            show_secrets,
+        })
+    }
+
+    /// --show-secrets only picks what --json includes
+    fn ensure_json(json: bool, show_secrets: bool) -> InternalResult<()> {
+        match show_secrets && !json {
+            true => Err(InternalError::IO("--show-secrets requires --json".into())),
+            false => Ok(()),
+        }
+    }
+
+    /// `get`, `add` or `rm` of a single entry, flags not taken by `cmd` are
+    /// rejected
+    fn parse_entry<I: Iterator<Item = String>>(args: &mut I, cmd: &str) -> InternalResult<Command> {
+        let mut vault = None;
+        let mut name = None;
+        let mut field = None;
+        let mut username = None;
+        let mut url = None;
+        let mut generate = false;
+        let mut password_stdin = false;
+        let mut yes = false;
+        let mut json = false;
+        let mut show_secrets = false;
+
+        while let Some(arg) = args.next() {
+            match (cmd, arg.as_str()) {
+                (_, "--vault") => {
+                    vault = Some(args.next().ok_or_else(|| {
+                        InternalError::IO("--vault requires a vault name".into())
+                    })?);
+                }
+                ("get", "--field") => {
+                    field = Some(
+                        args.next()
+                            .ok_or_else(|| InternalError::IO("--field requires a name".into()))?,
+                    );
+                }
+                ("add", "--username") => {
+                    username = Some(args.next().ok_or_else(|| {
+                        InternalError::IO("--username requires a username".into())
+                    })?);
+                }
+                ("add", "--url") => {
+                    url = Some(
+                        args.next()
+                            .ok_or_else(|| InternalError::IO("--url requires a URL".into()))?,
+                    );
+                }
+                ("get", "--json") => json = true,
+                ("get", "--show-secrets") => show_secrets = true,
+                ("add", "--generate") => generate = true,
+                ("add", "--password-stdin") => password_stdin = true,
+                ("rm", "--yes") => yes = true,
+                (_, other) if other.starts_with('-') || name.is_some() => {
+                    return Err(InternalError::IO(format!("Unknown argument: {other}")));
+                }
+                (_, other) => name = Some(other.to_string()),
+            }
+        }
+
+        let name =
+            name.ok_or_else(|| InternalError::IO(format!("{cmd} requires an entry name")))?;
+        Self::ensure_json(json, show_secrets)?;
+
+        if generate && password_stdin {
+            return Err(InternalError::IO(
+                "--generate and --password-stdin can't be combined".into(),
+            ));
+        }
+
+        Ok(match cmd {
+            "get" => Command::Get {
+                vault,
+                name,
+                field,
+                json,
+                show_secrets,
+            },
+            "add" => Command::Add {
+                vault,
+                name,
+                username,
+                url,
+                generate,
+                password_stdin,
+            },
+            _ => Command::Remove { vault, name, yes },
+        })
+    }
+
+    /// `vault list`, `create`, `rename`, `delete` or `default`
+    fn parse_vault<I: Iterator<Item = String>>(
+        args: &mut I,
+        action: &str,
+    ) -> InternalResult<Command> {
+        let mut names = Vec::new();
+        let mut yes = false;
+        let mut clear = false;
+        let mut json = false;
+
+        for arg in args {
+            match (action, arg.as_str()) {
+                (_, "--json") => json = true,
+                ("delete", "--yes") => yes = true,
+                ("default", "--clear") => clear = true,
+                (_, other) if other.starts_with('-') => {
+                    return Err(InternalError::IO(format!("Unknown argument: {other}")));
+                }
+                (_, other) => names.push(other.to_string()),
+            }
+        }
+
+        let expected = match action {
+            "list" => 0,
+            "rename" => 2,
+            "default" if clear => 0,
+            "default" => names.len().min(1),
+            _ => 1,
+        };
+
+        if names.len() != expected {
+            return Err(InternalError::IO(match action {
+                "list" | "default" => format!("Unknown argument: {}", names[expected]),
+                "rename" => "vault rename requires <NAME> <NEW_NAME>".into(),
+                _ => format!("vault {action} requires a vault name"),
+            }));
+        }
+
+        // the typed confirmation is a prompt
+        if action == "delete" && json && !yes {
+            return Err(InternalError::IO(
+                "vault delete --json requires --yes".into(),
+            ));
+        }
+
+        let mut names = names.into_iter();
+        let mut name = || names.next().unwrap_or_default();
+
+        Ok(match action {
+            "list" => Command::VaultList { json },
+            "create" => Command::VaultCreate { name: name(), json },
+            "rename" => Command::VaultRename {
+                name: name(),
+                new_name: name(),
+                json,
+            },
+            "default" => Command::VaultDefault {
+                name: (expected == 1).then(name),
+                clear,
+                json,
+            },
+            _ => Command::VaultDelete {
+                name: name(),
+                yes,
+                json,
+            },
+        })
+    }
+
+    fn parse_inspect<I: Iterator<Item = String>>(args: &mut I) -> InternalResult<Command> {
+        let mut vault = None;
+        let mut name = None;
+        let mut raw = false;
+        let mut reason = None;
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--raw" => raw = true,
+                "--reason" => {
+                    reason = Some(
+                        args.next()
+                            .ok_or_else(|| InternalError::IO("--reason requires a text".into()))?,
+                    );
+                }
+                "--vault" => {
+                    vault = Some(args.next().ok_or_else(|| {
+                        InternalError::IO("--vault requires a vault name".into())
+                    })?);
+                }
+                other if other.starts_with('-') || name.is_some() => {
+                    return Err(InternalError::IO(format!("Unknown argument: {other}")));
+                }
+                other => name = Some(other.to_string()),
+            }
+        }
+
+        Ok(Command::InspectEntry {
+            vault,
+            name: name.ok_or_else(|| InternalError::IO("entry inspect requires a name".into()))?,
+            raw,
+            reason,
+        })
+    }
+
+    fn parse_transfer<I: Iterator<Item = String>>(
+        args: &mut I,
+        export: bool,
+    ) -> InternalResult<Command> {
+        let mut format = None;
+        let mut vault = None;
+        let mut file = None;
+        let mut protect = false;
+        let mut reason = None;
+        // source other than a file, w/ the flag naming it
+        let mut store = None;
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--list-formats" => return Ok(Command::ListFormats { export }),
+                "--passphrase-protect" if export => protect = true,
+                "--reason" if export => {
+                    reason = Some(
+                        args.next()
+                            .ok_or_else(|| InternalError::IO("--reason requires a text".into()))?,
+                    );
+                }
+                "--from-browser" if !export => {
+                    let name = args.next().ok_or_else(|| {
+                        InternalError::IO("--from-browser requires chrome, firefox or edge".into())
+                    })?;
+                    store = Some((ImportSource::Browser(Browser::parse(&name)?), arg));
+                }
+                "--from-keychain" if !export => store = Some((ImportSource::Keychain, arg)),
+                "--from-windows-credentials" if !export => {
+                    store = Some((ImportSource::WindowsCredentials, arg));
+                }
+                "--from-secret-service" if !export => {
+                    store = Some((ImportSource::SecretService, arg));
+                }
+                "--from-kwallet" if !export => store = Some((ImportSource::KWallet, arg)),
+                "--format" => {
+                    format = Some(args.next().ok_or_else(|| {
+                        InternalError::IO("--format requires a format name".into())
+                    })?);
+                }
+                "--vault" => {
+                    vault = Some(args.next().ok_or_else(|| {
+                        InternalError::IO("--vault requires a vault name".into())
+                    })?);
+                }
+                other if other.starts_with('-') || file.is_some() => {
+                    return Err(InternalError::IO(format!("Unknown argument: {other}")));
+                }
+                other => file = Some(other.to_string()),
+            }
+        }
+
+        if let Some((source, flag)) = store {
+            if format.is_some() || file.is_some() {
+                return Err(InternalError::IO(format!(
+                    "{flag} takes no --format or file"
+                )));
+            }
+
+            return Ok(Command::Import { source, vault });
+        }
+
+        let cmd = if export { "export" } else { "import" };
+        let format = format.ok_or_else(|| InternalError::IO(format!("{cmd} requires --format")))?;
+
+        if export {
+            return Ok(Command::Export {
+                format,
+                vault,
+                file,
+                protect,
+                reason,
+            });
+        }
+
+        Ok(Command::Import {
+            source: ImportSource::File {
+                format,
+                file: file.ok_or_else(|| InternalError::IO("import requires a file".into()))?,
+            },
+            vault,
+        })
+    }
+
+    fn parse_qr_send<I: Iterator<Item = String>>(args: &mut I) -> InternalResult<Command> {
+        let mut vault = None;
+        let mut name = None;
+        let mut interval = None;
+        let mut loops = None;
+        let mut reason = None;
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--interval" => interval = Some(Self::parse_number(args, "--interval")?),
+                "--loops" => loops = Some(Self::parse_number(args, "--loops")?),
+                "--reason" => {
+                    reason = Some(
+                        args.next()
+                            .ok_or_else(|| InternalError::IO("--reason requires a text".into()))?,
+                    );
+                }
+                "--vault" => {
+                    vault = Some(args.next().ok_or_else(|| {
+                        InternalError::IO("--vault requires a vault name".into())
+                    })?);
+                }
+                other if other.starts_with('-') || name.is_some() => {
+                    return Err(InternalError::IO(format!("Unknown argument: {other}")));
+                }
+                other => name = Some(other.to_string()),
+            }
+        }
+
+        Ok(Command::QrSend {
+            vault,
+            name: name.ok_or_else(|| InternalError::IO("qr send requires a name".into()))?,
+            interval,
+            loops,
+            reason,
+        })
+    }
+
+    fn parse_lookup<I: Iterator<Item = String>>(args: &mut I) -> InternalResult<Command> {
+        let mut path = None;
+        let mut format = LookupFormat::Ansible;
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--format" => {
+                    format = match args.next().as_deref() {
+                        Some("ansible") => LookupFormat::Ansible,
+                        Some("json") => LookupFormat::Json,
+                        Some(other) => {
+                            return Err(InternalError::IO(format!(
+                                "Unknown lookup format '{other}', expected ansible or json"
+                            )));
+                        }
+                        None => {
+                            return Err(InternalError::IO("--format requires a format".into()));
+                        }
+                    };
+                }
+                other if other.starts_with('-') || path.is_some() => {
+                    return Err(InternalError::IO(format!("Unknown argument: {other}")));
+                }
+                other => path = Some(other.to_string()),
+            }
+        }
+
+        Ok(Command::Lookup {
+            path: path.ok_or_else(|| {
+                InternalError::IO("lookup requires <VAULT>/<ENTRY>[#FIELD]".into())
+            })?,
+            format,
+        })
+    }
+
+    fn parse_provision<I: Iterator<Item = String>>(
+        args: &mut I,
+        deprovision: bool,
+    ) -> InternalResult<Command> {
+        let mut vault = None;
+        let mut unit = None;
+        let mut secrets = Vec::new();
+        let mut encrypt = false;
+        let mut dir = None;
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--unit" => {
+                    unit =
+                        Some(args.next().ok_or_else(|| {
+                            InternalError::IO("--unit requires a unit name".into())
+                        })?);
+                }
+                "--secret" if !deprovision => {
+                    secrets.push(args.next().ok_or_else(|| {
+                        InternalError::IO("--secret requires an entry name".into())
+                    })?)
+                }
+                "--encrypt" => encrypt = true,
+                "--dir" => {
+                    dir = Some(
+                        args.next()
+                            .ok_or_else(|| InternalError::IO("--dir requires a path".into()))?,
+                    );
+                }
+                "--vault" if !deprovision => {
+                    vault = Some(args.next().ok_or_else(|| {
+                        InternalError::IO("--vault requires a vault name".into())
+                    })?);
+                }
+                other => return Err(InternalError::IO(format!("Unknown argument: {other}"))),
+            }
+        }
+
+        let cmd = if deprovision {
+            "deprovision"
+        } else {
+            "provision"
+        };
+        let unit = unit.ok_or_else(|| InternalError::IO(format!("{cmd} requires --unit")))?;
+
+        if deprovision {
+            return Ok(Command::Deprovision { unit, encrypt, dir });
+        }
+
+        Ok(Command::Provision {
+            vault,
+            unit,
+            secrets,
+            encrypt,
+            dir,
+        })
+    }
+
+    fn parse_file_crypt<I: Iterator<Item = String>>(
+        args: &mut I,
+        decrypt: bool,
+    ) -> InternalResult<Command> {
+        let mut vault = None;
+        let mut path = None;
+        let mut entry = None;
+        let mut output = None;
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--vault" => {
+                    vault = Some(args.next().ok_or_else(|| {
+                        InternalError::IO("--vault requires a vault name".into())
+                    })?);
+                }
+                "--entry" if !decrypt => {
+                    entry = Some(args.next().ok_or_else(|| {
+                        InternalError::IO("--entry requires an entry name".into())
+                    })?);
+                }
+                "--output" => {
+                    output = Some(
+                        args.next()
+                            .ok_or_else(|| InternalError::IO("--output requires a path".into()))?,
+                    );
+                }
+                other if other.starts_with('-') || path.is_some() => {
+                    return Err(InternalError::IO(format!("Unknown argument: {other}")));
+                }
+                other => path = Some(other.to_string()),
+            }
+        }
+
+        let cmd = if decrypt {
+            "decrypt-file"
+        } else {
+            "encrypt-file"
+        };
+        let path = path.ok_or_else(|| InternalError::IO(format!("{cmd} requires a file")))?;
+
+        Ok(if decrypt {
+            Command::DecryptFile {
+                vault,
+                path,
+                output,
+            }
+        } else {
+            Command::EncryptFile {
+                vault,
+                path,
+                entry,
+                output,
+            }
+        })
+    }
+
+    fn parse_qr_receive<I: Iterator<Item = String>>(args: &mut I) -> InternalResult<Command> {
+        let mut vault = None;
+        let mut file = None;
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--vault" => {
+                    vault = Some(args.next().ok_or_else(|| {
+                        InternalError::IO("--vault requires a vault name".into())
+                    })?);
+                }
+                other if other.starts_with('-') || file.is_some() => {
+                    return Err(InternalError::IO(format!("Unknown argument: {other}")));
+                }
+                other => file = Some(other.to_string()),
+            }
+        }
+
+        Ok(Command::QrReceive { vault, file })
+    }
+
+    fn parse_migrate<I: Iterator<Item = String>>(args: &mut I) -> InternalResult<Command> {
+        let export = match args.next().as_deref() {
+            Some("export") => true,
+            Some("import") => false,
+            Some(other) => {
+                return Err(InternalError::IO(format!(
+                    "Unknown migrate-device command: {other}"
+                )));
+            }
+            None => {
+                return Err(InternalError::IO(
+                    "migrate-device requires a command".into(),
+                ));
+            }
+        };
+
+        let mut file = None;
+
+        for arg in args.by_ref() {
+            match arg.as_str() {
+                other if other.starts_with('-') || file.is_some() => {
+                    return Err(InternalError::IO(format!("Unknown argument: {other}")));
+                }
+                other => file = Some(other.to_string()),
+            }
+        }
+
+        Ok(Command::MigrateDevice {
+            export,
+            file: file.ok_or_else(|| InternalError::IO("migrate-device requires a file".into()))?,
+        })
+    }
+
+    /// Numeric value following `flag`
+    fn parse_number<I: Iterator<Item = String>, T: std::str::FromStr>(
+        args: &mut I,
+        flag: &str,
+    ) -> InternalResult<T> {
+        let value = args
+            .next()
+            .ok_or_else(|| InternalError::IO(format!("{flag} requires a number")))?;
+
+        value
+            .parse()
+            .map_err(|_| InternalError::IO(format!("Invalid {flag}, expected a number: {value}")))
+    }
+
+    fn parse_share<I: Iterator<Item = String>>(args: &mut I) -> InternalResult<Command> {
+        let mut vault = None;
+        let mut name = None;
+        let mut bind = None;
+        let mut timeout = None;
+        let mut reason = None;
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--bind" => {
+                    bind =
+                        Some(args.next().ok_or_else(|| {
+                            InternalError::IO("--bind requires an address".into())
+                        })?);
+                }
+                "--timeout" => timeout = Some(Self::parse_number(args, "--timeout")?),
+                "--reason" => {
+                    reason = Some(
+                        args.next()
+                            .ok_or_else(|| InternalError::IO("--reason requires a text".into()))?,
+                    );
+                }
+                "--vault" => {
+                    vault = Some(args.next().ok_or_else(|| {
+                        InternalError::IO("--vault requires a vault name".into())
+                    })?);
+                }
+                other if other.starts_with('-') || name.is_some() => {
+                    return Err(InternalError::IO(format!("Unknown argument: {other}")));
+                }
+                other => name = Some(other.to_string()),
+            }
+        }
+
+        Ok(Command::ShareOnce {
+            vault,
+            name: name.ok_or_else(|| InternalError::IO("share-once requires a name".into()))?,
+            bind,
+            timeout,
+            reason,
+        })
+    }
+
+    fn parse_rekdf<I: Iterator<Item = String>>(args: &mut I) -> InternalResult<Command> {
+        let mut vault = None;
+        let mut algorithm = None;
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--kdf" => {
+                    let name = args
+                        .next()
+                        .ok_or_else(|| InternalError::IO("--kdf requires a name".into()))?;
+
+                    algorithm = Some(KdfAlgorithm::from_name(&name).ok_or_else(|| {
+                        let names: Vec<&str> = KdfAlgorithm::ALL.iter().map(|a| a.name()).collect();
+                        InternalError::IO(format!(
+                            "Unknown KDF '{name}', expected one of: {}",
+                            names.join(", ")
+                        ))
+                    })?);
+                }
+                "--vault" => {
+                    vault = Some(args.next().ok_or_else(|| {
+                        InternalError::IO("--vault requires a vault name".into())
+                    })?);
+                }
+                other => return Err(InternalError::IO(format!("Unknown argument: {other}"))),
+            }
+        }
+
+        Ok(Command::RekdfVault {
+            vault,
+            algorithm: algorithm
+                .ok_or_else(|| InternalError::IO("vault rekdf requires --kdf".into()))?,
+        })
+    }
+
+    fn parse_kdf_bench<I: Iterator<Item = String>>(args: &mut I) -> InternalResult<Command> {
+        let mut vault = None;
+        let mut target = None;
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--target" => target = Some(Self::parse_number(args, "--target")?),
+                "--vault" => {
+                    vault = Some(args.next().ok_or_else(|| {
+                        InternalError::IO("--vault requires a vault name".into())
+                    })?);
+                }
+                other => return Err(InternalError::IO(format!("Unknown argument: {other}"))),
+            }
+        }
+
+        Ok(Command::KdfBench { vault, target })
+    }
+
+    fn parse_audit<I: Iterator<Item = String>>(args: &mut I) -> InternalResult<Command> {
+        let mut vault = None;
+        let mut score = false;
+        let mut reuse_graph = false;
+        let mut json = false;
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--score" => score = true,
+                "--reuse-graph" => reuse_graph = true,
+                "--json" => json = true,
+                "--vault" => {
+                    vault = Some(args.next().ok_or_else(|| {
+                        InternalError::IO("--vault requires a vault name".into())
+                    })?);
+                }
+                other => return Err(InternalError::IO(format!("Unknown argument: {other}"))),
+            }
+        }
+
+        if json && reuse_graph {
+            return Err(InternalError::IO(
+                "--json and --reuse-graph can't be combined".into(),
+            ));
+        }
+
+        Ok(Command::Audit {
+            vault,
+            score,
+            reuse_graph,
+            json,
+        })
+    }
+
+    fn parse_history_vault<I: Iterator<Item = String>>(
+        args: &mut I,
+    ) -> InternalResult<Option<String>> {
+        let mut vault = None;
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--vault" => {
+                    vault = Some(args.next().ok_or_else(|| {
+                        InternalError::IO("--vault requires a vault name".into())
+                    })?);
+                }
+                other => return Err(InternalError::IO(format!("Unknown argument: {other}"))),
+            }
+        }
+
+        Ok(vault)
+    }
+
+    fn parse_unlock<I: Iterator<Item = String>>(args: &mut I) -> InternalResult<Command> {
+        let mut vault = None;
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--vault" => {
+                    vault = Some(args.next().ok_or_else(|| {
+                        InternalError::IO("--vault requires a vault name".into())
+                    })?);
+                }
+                other => return Err(InternalError::IO(format!("Unknown argument: {other}"))),
+            }
+        }
+
+        Ok(Command::Unlock { vault })
+    }
+
+    fn parse_lock<I: Iterator<Item = String>>(args: &mut I) -> InternalResult<Command> {
+        let mut all = false;
+
+        for arg in args {
+            match arg.as_str() {
+                "--all" => all = true,
+                other => return Err(InternalError::IO(format!("Unknown argument: {other}"))),
+            }
+        }
+
+        Ok(Command::Lock { all })
+    }
+
+    fn parse_recovery_kit<I: Iterator<Item = String>>(
+        args: &mut I,
+        recover: bool,
+    ) -> InternalResult<Command> {
+        let mut vault = None;
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--vault" => {
+                    vault = Some(args.next().ok_or_else(|| {
+                        InternalError::IO("--vault requires a vault name".into())
+                    })?);
+                }
+                other => return Err(InternalError::IO(format!("Unknown argument: {other}"))),
+            }
+        }
+
+        Ok(if recover {
+            Command::Recover { vault }
+        } else {
+            Command::RecoveryKit { vault }
+        })
+    }
+
+    fn parse_scratch<I: Iterator<Item = String>>(args: &mut I) -> InternalResult<Command> {
+        let mut vault = None;
+        let mut clear = false;
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--vault" => {
+                    vault = Some(args.next().ok_or_else(|| {
+                        InternalError::IO("--vault requires a vault name".into())
+                    })?);
+                }
+                "--clear" => clear = true,
+                other => return Err(InternalError::IO(format!("Unknown argument: {other}"))),
+            }
+        }
+
+        Ok(Command::Scratch { vault, clear })
+    }
+
+    fn parse_keychain<I: Iterator<Item = String>>(
+        args: &mut I,
+        remove: bool,
+    ) -> InternalResult<Command> {
+        let mut vault = None;
+        let mut biometric = false;
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--vault" => {
+                    vault = Some(args.next().ok_or_else(|| {
+                        InternalError::IO("--vault requires a vault name".into())
+                    })?);
+                }
+                "--biometric" if !remove => biometric = true,
+                other => return Err(InternalError::IO(format!("Unknown argument: {other}"))),
+            }
+        }
+
+        Ok(if remove {
+            Command::KeychainRemove { vault }
+        } else {
+            Command::KeychainAdd { vault, biometric }
+        })
+    }
+
+    /// `2fa enroll` of a FIDO2 key w/ `Some(true)` or a YubiKey w/
+    /// `Some(false)`, `2fa remove` w/ `None`
+    fn parse_two_factor<I: Iterator<Item = String>>(
+        args: &mut I,
+        fido2: Option<bool>,
+    ) -> InternalResult<Command> {
+        let mut vault = None;
+        let mut slot = yubikey::DEFAULT_SLOT;
+        let mut passwordless = false;
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--passwordless" if fido2 == Some(true) => passwordless = true,
+                "--slot" if fido2 == Some(false) => {
+                    slot = Self::parse_number(args, "--slot")?;
+
+                    if !matches!(slot, 1 | 2) {
+                        return Err(InternalError::IO("--slot must be 1 or 2".into()));
+                    }
+                }
+                "--vault" => {
+                    vault = Some(args.next().ok_or_else(|| {
+                        InternalError::IO("--vault requires a vault name".into())
+                    })?);
+                }
+                other => return Err(InternalError::IO(format!("Unknown argument: {other}"))),
+            }
+        }
+
+        let factor = match fido2 {
+            None => {
+                return Ok(Command::TwoFactorRemove { vault });
+            }
+            Some(true) => SecondFactor::Fido2 { passwordless },
+            Some(false) => SecondFactor::Yubikey { slot },
+        };
+
+        Ok(Command::TwoFactorEnroll { vault, factor })
+    }
+
+    fn parse_verify_vault<I: Iterator<Item = String>>(args: &mut I) -> InternalResult<Command> {
+        let mut vault = None;
+        let mut reset = false;
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--reset" => reset = true,
+                "--vault" => {
+                    vault = Some(args.next().ok_or_else(|| {
+                        InternalError::IO("--vault requires a vault name".into())
+                    })?);
+                }
+                other => return Err(InternalError::IO(format!("Unknown argument: {other}"))),
+            }
+        }
+
+        Ok(Command::VerifyVault { vault, reset })
+    }
+
+    fn parse_passwd<I: Iterator<Item = String>>(args: &mut I) -> InternalResult<Command> {
+        let mut vault = None;
+        let mut decoy = false;
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--decoy" => decoy = true,
+                "--vault" => {
+                    vault = Some(args.next().ok_or_else(|| {
+                        InternalError::IO("--vault requires a vault name".into())
+                    })?);
+                }
+                other => return Err(InternalError::IO(format!("Unknown argument: {other}"))),
+            }
+        }
+
+        Ok(Command::Passwd { vault, decoy })
+    }
+}
@@ -0,0 +1,133 @@
+use crate::{
+    types::{InternalError, InternalResult},
+    vaults::Vault,
+};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// Appended to the vault's slug
+pub(crate) const ATTEMPTS_EXT: &str = ".attempts";
+/// Wrong passwords let through w/o delay, for typos
+const FREE_ATTEMPTS: u32 = 3;
+/// Longest delay between attempts, it doubles w/ each failure up to this
+const MAX_DELAY_SECS: u64 = 15 * 60;
+
+/// Wrong master passwords entered for a vault since it was last unlocked,
+/// kept in the state dir so a fresh process starts no fresh count
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+pub(crate) struct Attempts {
+    pub failures: u32,
+    /// unix timestamp
+    pub last_failure: u64,
+}
+
+impl Attempts {
+    pub fn load<P: AsRef<Path>>(home_dir: &P, vault: &Vault) -> Self {
+        std::fs::read(path(home_dir, vault))
+            .ok()
+            .and_then(|raw| serde_json::from_slice(&raw).ok())
+            .unwrap_or_default()
+    }
+
+    /// Seconds left at `now` before the next attempt is allowed
+    pub fn wait(&self, now: u64) -> u64 {
+        let Some(doublings) = self.failures.checked_sub(FREE_ATTEMPTS) else {
+            return 0;
+        };
+
+        let delay = 1u64
+            .checked_shl(doublings)
+            .unwrap_or(u64::MAX)
+            .min(MAX_DELAY_SECS);
+
+        self.last_failure.saturating_add(delay).saturating_sub(now)
+    }
+
+    /// Count one more wrong password at `now`
+    pub fn record_failure<P: AsRef<Path>>(
+        &mut self,
+        home_dir: &P,
+        vault: &Vault,
+        now: u64,
+    ) -> InternalResult<()> {
+        self.failures = self.failures.saturating_add(1);
+        self.last_failure = now;
+
+        let raw = serde_json::to_vec(self)
+            .map_err(|e| InternalError::IO(format!("Failed to encode attempts: {e}")))?;
+
+        // write + rename, so a crash never resets the count
+        let path = path(home_dir, vault);
+        let tmp = path.with_extension("attempts.tmp");
+        std::fs::write(&tmp, raw)?;
+        std::fs::rename(&tmp, &path)?;
+
+        Ok(())
+    }
+
+    /// Start counting from zero again, after a successful unlock
+    pub fn reset<P: AsRef<Path>>(home_dir: &P, vault: &Vault) -> InternalResult<()> {
+        match std::fs::remove_file(path(home_dir, vault)) {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+fn path<P: AsRef<Path>>(home_dir: &P, vault: &Vault) -> PathBuf {
+    crate::dirs::state_dir(home_dir.as_ref()).join(format!("{}{ATTEMPTS_EXT}", vault.slug))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::vault;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_delay_doubles_after_free_attempts() {
+        let at = |failures| Attempts {
+            failures,
+            last_failure: 1000,
+        };
+
+        assert_eq!(at(0).wait(1000), 0);
+        assert_eq!(at(FREE_ATTEMPTS - 1).wait(1000), 0);
+        assert_eq!(at(FREE_ATTEMPTS).wait(1000), 1);
+        assert_eq!(at(FREE_ATTEMPTS + 3).wait(1000), 8);
+        assert_eq!(at(FREE_ATTEMPTS + 3).wait(1005), 3);
+        assert_eq!(at(FREE_ATTEMPTS + 3).wait(2000), 0);
+        assert_eq!(at(u32::MAX).wait(1000), MAX_DELAY_SECS);
+    }
+
+    #[test]
+    fn test_failures_persist_until_reset() {
+        let temp_dir = TempDir::new().unwrap();
+        let vault = vault();
+
+        let mut attempts = Attempts::load(&temp_dir.path(), &vault);
+        assert_eq!(attempts, Attempts::default());
+
+        attempts
+            .record_failure(&temp_dir.path(), &vault, 42)
+            .unwrap();
+        attempts
+            .record_failure(&temp_dir.path(), &vault, 43)
+            .unwrap();
+        assert_eq!(
+            Attempts::load(&temp_dir.path(), &vault),
+            Attempts {
+                failures: 2,
+                last_failure: 43,
+            }
+        );
+
+        Attempts::reset(&temp_dir.path(), &vault).unwrap();
+        assert_eq!(
+            Attempts::load(&temp_dir.path(), &vault),
+            Attempts::default()
+        );
+        Attempts::reset(&temp_dir.path(), &vault).unwrap();
+    }
+}
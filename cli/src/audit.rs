@@ -0,0 +1,474 @@
+use crate::{
+    clock,
+    entries::{Entry, LicenseEntry, Template},
+    history::{Event, History},
+};
+use std::collections::HashMap;
+
+/// Passwords w/ less estimated entropy are weak
+const MIN_PASSWORD_BITS: f64 = 60.0;
+/// Passwords below this are trivially guessable
+const CRITICAL_PASSWORD_BITS: f64 = 40.0;
+const PERFECT_SCORE: u32 = 100;
+/// Bundled list of domains known to support 2FA
+const TWO_FACTOR_SITES: &str = include_str!("two_factor_sites.txt");
+
+/// How urgent a finding is, ordered from least to most urgent
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum Severity {
+    /// worth knowing, doesn't affect the health score
+    Info,
+    Low,
+    Medium,
+    High,
+}
+
+impl Severity {
+    pub fn label(self) -> &'static str {
+        match self {
+            Severity::Info => "INFO",
+            Severity::Low => "LOW",
+            Severity::Medium => "MEDIUM",
+            Severity::High => "HIGH",
+        }
+    }
+
+    /// Points taken off the health score
+    fn penalty(self) -> u32 {
+        match self {
+            Severity::Info => 0,
+            Severity::Low => 3,
+            Severity::Medium => 8,
+            Severity::High => 15,
+        }
+    }
+}
+
+/// A single problem detected in a vault
+#[derive(Debug)]
+pub(crate) struct Finding {
+    pub entry: String,
+    pub message: String,
+    pub severity: Severity,
+}
+
+/// Vault health, combining all audit signals
+#[derive(Debug)]
+pub(crate) struct Health {
+    /// 0 (neglected) to 100 (nothing to do)
+    pub score: u32,
+    /// findings, most urgent first
+    pub actions: Vec<Finding>,
+}
+
+/// Score a vault from its entries and access history
+pub(crate) fn health(entries: &[Entry], history: &History) -> Health {
+    let mut actions = audit(entries);
+    actions.extend(access_findings(history));
+
+    // stable, so findings of the same severity keep their rule order
+    actions.sort_by_key(|f| std::cmp::Reverse(f.severity));
+
+    let penalty: u32 = actions.iter().map(|f| f.severity.penalty()).sum();
+
+    Health {
+        score: PERFECT_SCORE.saturating_sub(penalty),
+        actions,
+    }
+}
+
+/// Run all audit rules against given entries
+pub(crate) fn audit(entries: &[Entry]) -> Vec<Finding> {
+    let today = clock::today();
+    let mut findings = Vec::new();
+
+    for entry in entries {
+        if let Template::License(license) = &entry.template {
+            check_license_maintenance(license, today, &mut findings);
+        }
+    }
+
+    check_weak_passwords(entries, &mut findings);
+    check_reused_passwords(entries, &mut findings);
+    check_missing_2fa(entries, &mut findings);
+
+    findings
+}
+
+/// Access anomalies and break-glass accesses recorded in a vault's history
+pub(crate) fn access_findings(history: &History) -> Vec<Finding> {
+    history
+        .records()
+        .iter()
+        .filter_map(|r| match &r.event {
+            Event::Anomaly { entry, message } => Some(Finding {
+                entry: entry.clone(),
+                message: format!("{message} at {}", clock::format_timestamp(r.at)),
+                severity: Severity::Medium,
+            }),
+            Event::BreakGlass { entry, reason } => Some(Finding {
+                entry: entry.clone(),
+                message: format!(
+                    "break-glass access at {}: {reason}",
+                    clock::format_timestamp(r.at)
+                ),
+                severity: Severity::Info,
+            }),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Flag licenses whose maintenance period has already ended
+fn check_license_maintenance(license: &LicenseEntry, today: u64, findings: &mut Vec<Finding>) {
+    let Some(until) = license.maintenance_until.as_deref() else {
+        return;
+    };
+
+    match license.maintenance_expiry() {
+        Some(expiry) if expiry < today => findings.push(Finding {
+            entry: license.name.clone(),
+            message: format!("maintenance for '{}' expired on {until}", license.product),
+            severity: Severity::Low,
+        }),
+
+        Some(_) => {}
+
+        None => findings.push(Finding {
+            entry: license.name.clone(),
+            message: format!("invalid maintenance date '{until}', expected YYYY-MM-DD"),
+            severity: Severity::Low,
+        }),
+    }
+}
+
+fn login_passwords(entries: &[Entry]) -> impl Iterator<Item = (&str, &str)> {
+    entries.iter().filter_map(|e| match &e.template {
+        Template::Login(login) => Some((login.name.as_str(), login.password.expose())),
+        _ => None,
+    })
+}
+
+/// Flag login passwords w/ too little estimated entropy
+fn check_weak_passwords(entries: &[Entry], findings: &mut Vec<Finding>) {
+    for (name, password) in login_passwords(entries) {
+        let bits = password_bits(password);
+
+        if bits >= MIN_PASSWORD_BITS {
+            continue;
+        }
+
+        let severity = if bits < CRITICAL_PASSWORD_BITS {
+            Severity::High
+        } else {
+            Severity::Medium
+        };
+
+        findings.push(Finding {
+            entry: name.to_string(),
+            message: format!("weak password (~{bits:.0} bits), replace it w/ a longer one"),
+            severity,
+        });
+    }
+}
+
+/// Names of logins sharing a password, one group per password in order of
+/// first use
+pub(crate) fn reuse_groups(entries: &[Entry]) -> Vec<Vec<&str>> {
+    let mut groups: Vec<Vec<&str>> = Vec::new();
+    let mut by_password: HashMap<&str, usize> = HashMap::new();
+
+    for (name, password) in login_passwords(entries) {
+        let idx = *by_password.entry(password).or_insert_with(|| {
+            groups.push(Vec::new());
+            groups.len() - 1
+        });
+
+        groups[idx].push(name);
+    }
+
+    groups.retain(|g| g.len() > 1);
+    groups
+}
+
+/// Graphviz graph linking logins which share a password, w/ a cluster per
+/// password, e.g. for `dot -Tsvg`, passwords themselves are never included
+pub(crate) fn reuse_graph(entries: &[Entry]) -> String {
+    let quote = |name: &str| format!("\"{}\"", name.replace('\\', "\\\\").replace('"', "\\\""));
+    let mut dot = String::from("graph reuse {\n    node [shape=box];\n");
+
+    for (i, group) in reuse_groups(entries).iter().enumerate() {
+        dot.push_str(&format!(
+            "    subgraph cluster_{i} {{\n        label=\"shared password {} ({} entries)\";\n",
+            i + 1,
+            group.len()
+        ));
+
+        // every pair falls together if one of them is breached
+        for (j, a) in group.iter().enumerate() {
+            for b in &group[j + 1..] {
+                dot.push_str(&format!("        {} -- {};\n", quote(a), quote(b)));
+            }
+        }
+
+        dot.push_str("    }\n");
+    }
+
+    dot.push_str("}\n");
+    dot
+}
+
+/// Flag login passwords shared by several entries
+fn check_reused_passwords(entries: &[Entry], findings: &mut Vec<Finding>) {
+    for group in reuse_groups(entries) {
+        for name in &group {
+            let others: Vec<&str> = group.iter().copied().filter(|n| n != name).collect();
+
+            findings.push(Finding {
+                entry: name.to_string(),
+                message: format!("password reused by {}, change it", others.join(", ")),
+                severity: Severity::High,
+            });
+        }
+    }
+}
+
+/// Report logins w/o 2FA, more urgent when the site is known to support it
+fn check_missing_2fa(entries: &[Entry], findings: &mut Vec<Finding>) {
+    for entry in entries {
+        let Template::Login(login) = &entry.template else {
+            continue;
+        };
+
+        if entry.has_2fa || entry.has_totp() {
+            continue;
+        }
+
+        let host = login.url.as_deref().and_then(url_host);
+
+        match host {
+            Some(host) if supports_2fa(&host) => findings.push(Finding {
+                entry: login.name.clone(),
+                message: format!("{host} supports 2FA, enable it and mark the entry"),
+                severity: Severity::Medium,
+            }),
+
+            _ => findings.push(Finding {
+                entry: login.name.clone(),
+                message: "no 2FA".into(),
+                severity: Severity::Info,
+            }),
+        }
+    }
+}
+
+/// Whether `host` or one of its parent domains is in the bundled list
+fn supports_2fa(host: &str) -> bool {
+    TWO_FACTOR_SITES
+        .lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty() && !l.starts_with('#'))
+        .any(|site| host == site || host.ends_with(&format!(".{site}")))
+}
+
+/// Lowercase host of a URL, w/o scheme, credentials, port and path
+pub(crate) fn url_host(url: &str) -> Option<String> {
+    let rest = url.split_once("://").map_or(url, |(_, rest)| rest);
+    let authority = rest.split(['/', '?', '#']).next()?;
+    let host = authority.rsplit('@').next()?;
+    let host = host.split(':').next()?.trim_end_matches('.');
+
+    (!host.is_empty()).then(|| host.to_ascii_lowercase())
+}
+
+/// Rough entropy estimate, length times bits per char of the character
+/// classes used
+///
+/// NOTE: ignores dictionary words and patterns, so it overestimates e.g.
+/// `Password1!`, but reliably catches short and single-class passwords
+fn password_bits(password: &str) -> f64 {
+    let mut pool = 0;
+
+    if password.chars().any(|c| c.is_ascii_lowercase()) {
+        pool += 26;
+    }
+    if password.chars().any(|c| c.is_ascii_uppercase()) {
+        pool += 26;
+    }
+    if password.chars().any(|c| c.is_ascii_digit()) {
+        pool += 10;
+    }
+    if password.chars().any(|c| !c.is_ascii_alphanumeric()) {
+        pool += 33;
+    }
+
+    if pool == 0 {
+        return 0.0;
+    }
+
+    password.chars().count() as f64 * f64::from(pool).log2()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        entries::LoginEntry,
+        vaults::{Vault, VaultIndex},
+    };
+    use tempfile::TempDir;
+
+    fn vault(temp_dir: &TempDir) -> Vault {
+        let mut index = VaultIndex::open(&temp_dir.path()).unwrap();
+        index.add("main").unwrap();
+
+        index.get("main").cloned().unwrap()
+    }
+
+    fn license(maintenance_until: Option<&str>) -> Entry {
+        Entry::new(Template::License(LicenseEntry {
+            name: "editor".into(),
+            product: "Editor Pro".into(),
+            key: "AAAAA-BBBBB".into(),
+            purchase_date: Some("2020-01-01".into()),
+            maintenance_until: maintenance_until.map(Into::into),
+            machines: vec!["laptop".into()],
+        }))
+    }
+
+    #[test]
+    fn test_expired_maintenance_is_flagged() {
+        let findings = audit(&[license(Some("2001-01-01"))]);
+
+        assert_eq!(findings.len(), 1);
+        assert_eq!(findings[0].entry, "editor");
+    }
+
+    #[test]
+    fn test_active_or_missing_maintenance_is_ok() {
+        assert!(audit(&[license(Some("9999-12-31"))]).is_empty());
+        assert!(audit(&[license(None)]).is_empty());
+    }
+
+    #[test]
+    fn test_invalid_maintenance_date_is_flagged() {
+        let findings = audit(&[license(Some("2024-02-31"))]);
+        assert_eq!(findings.len(), 1);
+    }
+
+    fn login(name: &str, password: &str) -> Entry {
+        let mut entry = login_at(name, password, None);
+        entry.has_2fa = true;
+
+        entry
+    }
+
+    fn login_at(name: &str, password: &str, url: Option<&str>) -> Entry {
+        Entry::new(Template::Login(LoginEntry {
+            name: name.into(),
+            password: password.into(),
+            username: None,
+            url: url.map(Into::into),
+        }))
+    }
+
+    #[test]
+    fn test_missing_2fa_is_reported() {
+        let pw = "correct-horse-battery-staple";
+        let findings = audit(&[
+            login_at("gh", pw, Some("https://me@Gist.GitHub.com:443/login")),
+            login_at(
+                "intranet",
+                "another-Long-passphrase-1",
+                Some("intranet.local"),
+            ),
+            login("mail", "x-Unique-Passphrase-9"),
+        ]);
+
+        assert_eq!(findings.len(), 2);
+        assert_eq!(findings[0].entry, "gh");
+        assert_eq!(findings[0].severity, Severity::Medium);
+        assert!(findings[0].message.contains("gist.github.com"));
+        assert_eq!(findings[1].entry, "intranet");
+        assert_eq!(findings[1].severity, Severity::Info);
+    }
+
+    #[test]
+    fn test_url_host() {
+        assert_eq!(
+            url_host("https://a.example.com/x?y").as_deref(),
+            Some("a.example.com")
+        );
+        assert_eq!(url_host("example.com:8080").as_deref(), Some("example.com"));
+        assert_eq!(url_host("https://"), None);
+        assert!(!supports_2fa("notgithub.com"));
+    }
+
+    #[test]
+    fn test_weak_passwords_are_flagged() {
+        let findings = audit(&[
+            login("short", "abc123"),
+            login("medium", "abcdefgh12"),
+            login("strong", "correct-horse-battery-staple"),
+        ]);
+
+        assert_eq!(findings.len(), 2);
+        assert_eq!(findings[0].entry, "short");
+        assert_eq!(findings[0].severity, Severity::High);
+        assert_eq!(findings[1].entry, "medium");
+        assert_eq!(findings[1].severity, Severity::Medium);
+    }
+
+    #[test]
+    fn test_reused_passwords_are_flagged() {
+        let pw = "correct-horse-battery-staple";
+        let findings = audit(&[
+            login("a", pw),
+            login("b", pw),
+            login("c", "x-Unique-Passphrase-9"),
+        ]);
+
+        let entries: Vec<&str> = findings.iter().map(|f| f.entry.as_str()).collect();
+        assert_eq!(entries, vec!["a", "b"]);
+        assert!(findings[0].message.contains('b'));
+    }
+
+    #[test]
+    fn test_reuse_graph() {
+        let pw = "correct-horse-battery-staple";
+        let graph = reuse_graph(&[
+            login("a", pw),
+            login("say \"b\"", "x-Unique-Passphrase-9"),
+            login("c", pw),
+            login("d", pw),
+            login("e", "x-Unique-Passphrase-9"),
+        ]);
+
+        assert!(graph.starts_with("graph reuse {"));
+        assert!(graph.contains("shared password 1 (3 entries)"));
+        assert!(graph.contains("\"a\" -- \"c\";"));
+        assert!(graph.contains("\"c\" -- \"d\";"));
+        assert!(graph.contains("\"say \\\"b\\\"\" -- \"e\";"));
+        assert!(!graph.contains(pw));
+    }
+
+    #[test]
+    fn test_health_score_prioritizes_actions() {
+        let temp_dir = TempDir::new().unwrap();
+        let vault = vault(&temp_dir);
+        let history = History::open(&temp_dir.path(), &vault).unwrap();
+
+        let strong = login("mail", "correct-horse-battery-staple");
+        let perfect = health(&[strong], &history);
+        assert_eq!(perfect.score, 100);
+        assert!(perfect.actions.is_empty());
+
+        let report = health(
+            &[license(Some("2001-01-01")), login("bank", "1234")],
+            &history,
+        );
+        assert_eq!(report.score, 100 - 3 - 15);
+        assert_eq!(report.actions[0].entry, "bank");
+        assert_eq!(report.actions[1].entry, "editor");
+    }
+}
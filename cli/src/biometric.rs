@@ -0,0 +1,232 @@
+use crate::{
+    keystore::Keystore,
+    types::{InternalError, InternalResult},
+};
+
+/// Keystore behind the platform's biometrics, Touch ID on macOS and Windows
+/// Hello on Windows
+#[cfg(all(feature = "biometric", any(target_os = "macos", windows)))]
+pub(crate) fn os() -> InternalResult<Box<dyn Keystore>> {
+    Ok(Box::new(platform::Biometric))
+}
+
+#[cfg(all(feature = "biometric", not(any(target_os = "macos", windows))))]
+pub(crate) fn os() -> InternalResult<Box<dyn Keystore>> {
+    Err(InternalError::IO(
+        "Biometric unlock requires Touch ID on macOS or Windows Hello".into(),
+    ))
+}
+
+#[cfg(not(feature = "biometric"))]
+pub(crate) fn os() -> InternalResult<Box<dyn Keystore>> {
+    Err(InternalError::IO(
+        "vuoto was built w/o biometric support".into(),
+    ))
+}
+
+/// Secrets in the data protection keychain, which only hands them out after
+/// Touch ID (or Face ID) of a currently enrolled finger
+#[cfg(all(feature = "biometric", target_os = "macos"))]
+mod platform {
+    use super::*;
+    use security_framework::passwords::{self, AccessControlOptions, PasswordOptions};
+    use zeroize::Zeroizing;
+
+    /// Service name of vuoto's biometric items, apart from those of `vuoto
+    /// keychain add` w/o --biometric
+    const SERVICE: &str = "vuoto-biometric";
+    /// errSecItemNotFound, also returned once the enrolled fingers changed
+    const NOT_FOUND: i32 = -25300;
+    /// errSecMissingEntitlement
+    const MISSING_ENTITLEMENT: i32 = -34018;
+
+    pub(super) struct Biometric;
+
+    fn options(slug: &str) -> PasswordOptions {
+        let mut options = PasswordOptions::new_generic_password(SERVICE, slug);
+        // the login keychain ignores access control
+        options.use_protected_keychain();
+        options
+    }
+
+    impl Keystore for Biometric {
+        fn get(&self, slug: &str) -> InternalResult<Option<Zeroizing<Vec<u8>>>> {
+            match passwords::generic_password(options(slug)) {
+                Ok(secret) => Ok(Some(Zeroizing::new(secret))),
+                Err(e) if e.code() == NOT_FOUND => Ok(None),
+                Err(e) => Err(touch_id_error(e)),
+            }
+        }
+
+        fn set(&self, slug: &str, secret: &[u8]) -> InternalResult<()> {
+            // an existing item keeps its old access control on update
+            self.delete(slug)?;
+
+            let mut options = options(slug);
+            options.set_access_control_options(AccessControlOptions::BIOMETRY_CURRENT_SET);
+
+            passwords::set_generic_password_options(secret, options).map_err(touch_id_error)
+        }
+
+        fn delete(&self, slug: &str) -> InternalResult<()> {
+            match passwords::delete_generic_password_options(options(slug)) {
+                Ok(()) => Ok(()),
+                Err(e) if e.code() == NOT_FOUND => Ok(()),
+                Err(e) => Err(touch_id_error(e)),
+            }
+        }
+
+        fn biometric(&self) -> bool {
+            true
+        }
+    }
+
+    fn touch_id_error(err: security_framework::base::Error) -> InternalError {
+        match err.code() {
+            MISSING_ENTITLEMENT => InternalError::IO(
+                "Touch ID requires a signed build of vuoto w/ a keychain access group".into(),
+            ),
+            _ => InternalError::IO(format!("Touch ID failed: {err}")),
+        }
+    }
+}
+
+/// Secrets sealed under a key derived from a Windows Hello credential's
+/// signature, kept in the Credential Manager
+#[cfg(all(feature = "biometric", windows))]
+mod platform {
+    use super::*;
+    use crate::{
+        crypto::{self, VaultKey},
+        keystore,
+    };
+    use windows::{
+        Security::{
+            Credentials::{KeyCredentialCreationOption, KeyCredentialManager, KeyCredentialStatus},
+            Cryptography::CryptographicBuffer,
+        },
+        core::{Array, HSTRING},
+    };
+    use zeroize::Zeroizing;
+
+    /// Prefix of vuoto's Windows Hello credentials, and domain of the keys
+    /// derived from them
+    const PREFIX: &str = "vuoto-biometric";
+
+    pub(super) struct Biometric;
+
+    /// Key of the Windows Hello credential of `slug`, w/ a face, fingerprint
+    /// or PIN, `None` if there's none, a new one replaces it w/ `create`
+    fn hello_key(slug: &str, create: bool) -> InternalResult<Option<VaultKey>> {
+        let name = HSTRING::from(format!("{PREFIX}-{slug}"));
+
+        let retrieved = match create {
+            true => KeyCredentialManager::RequestCreateAsync(
+                &name,
+                KeyCredentialCreationOption::ReplaceExisting,
+            ),
+            false => KeyCredentialManager::OpenAsync(&name),
+        }
+        .and_then(|op| op.join())
+        .map_err(hello_error)?;
+
+        match retrieved.Status().map_err(hello_error)? {
+            KeyCredentialStatus::Success => {}
+            KeyCredentialStatus::NotFound => return Ok(None),
+            status => return Err(status_error(status)),
+        }
+
+        // RSA PKCS#1 v1.5 signatures are deterministic, so signing the
+        // same data yields the same key every time
+        let data = CryptographicBuffer::CreateFromByteArray(format!("{PREFIX}-{slug}").as_bytes())
+            .map_err(hello_error)?;
+        let signed = retrieved
+            .Credential()
+            .and_then(|c| c.RequestSignAsync(&data))
+            .and_then(|op| op.join())
+            .map_err(hello_error)?;
+
+        match signed.Status().map_err(hello_error)? {
+            KeyCredentialStatus::Success => {}
+            status => return Err(status_error(status)),
+        }
+
+        let mut signature = Array::<u8>::new();
+        CryptographicBuffer::CopyToByteArray(
+            &signed.Result().map_err(hello_error)?,
+            &mut signature,
+        )
+        .map_err(hello_error)?;
+        let signature = Zeroizing::new(signature.to_vec());
+
+        Ok(Some(VaultKey::hashed(PREFIX.as_bytes(), &signature)))
+    }
+
+    /// Account of the sealed secret in the Credential Manager
+    fn account(slug: &str) -> String {
+        format!("{slug}.biometric")
+    }
+
+    impl Keystore for Biometric {
+        fn get(&self, slug: &str) -> InternalResult<Option<Zeroizing<Vec<u8>>>> {
+            let Some(sealed) = keystore::os()?.get(&account(slug))? else {
+                return Ok(None);
+            };
+            let Some(key) = hello_key(slug, false)? else {
+                return Ok(None);
+            };
+
+            // a credential replaced on another enrollment fails authentication
+            Ok(crypto::open(&key, slug.as_bytes(), &sealed)
+                .ok()
+                .map(Zeroizing::new))
+        }
+
+        fn set(&self, slug: &str, secret: &[u8]) -> InternalResult<()> {
+            let supported = KeyCredentialManager::IsSupportedAsync()
+                .and_then(|op| op.join())
+                .map_err(hello_error)?;
+
+            if !supported {
+                return Err(InternalError::IO(
+                    "Windows Hello isn't set up on this machine".into(),
+                ));
+            }
+
+            let key = hello_key(slug, true)?
+                .ok_or_else(|| InternalError::IO("Windows Hello created no credential".into()))?;
+            let sealed = crypto::seal(&key, slug.as_bytes(), secret)?;
+
+            keystore::os()?.set(&account(slug), &sealed)
+        }
+
+        fn delete(&self, slug: &str) -> InternalResult<()> {
+            keystore::os()?.delete(&account(slug))?;
+
+            // fails if there's no credential, which is just as good
+            let _ = KeyCredentialManager::DeleteAsync(&HSTRING::from(format!("{PREFIX}-{slug}")))
+                .and_then(|op| op.join());
+
+            Ok(())
+        }
+
+        fn biometric(&self) -> bool {
+            true
+        }
+    }
+
+    fn hello_error(err: windows::core::Error) -> InternalError {
+        InternalError::IO(format!("Windows Hello failed: {err}"))
+    }
+
+    fn status_error(status: KeyCredentialStatus) -> InternalError {
+        InternalError::IO(match status {
+            KeyCredentialStatus::UserCanceled => "Windows Hello was canceled".into(),
+            KeyCredentialStatus::UserPrefersPassword => "Windows Hello was skipped".into(),
+            KeyCredentialStatus::SecurityDeviceLocked => {
+                "Windows Hello is locked out after too many attempts".into()
+            }
+            status => format!("Windows Hello failed w/ status {}", status.0),
+        })
+    }
+}
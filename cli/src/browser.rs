@@ -0,0 +1,856 @@
+use crate::{
+    logins::Login,
+    secret::SecretString,
+    types::{InternalError, InternalResult},
+};
+use aes::cipher::{BlockCipher, BlockDecryptMut, KeyInit, KeyIvInit, block_padding::Pkcs7};
+use base64::{Engine as _, engine::general_purpose};
+use ring::{digest, pbkdf2};
+use serde_json::{Map, Value};
+use std::{
+    num::NonZeroU32,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+use zeroize::Zeroizing;
+
+/// Tries at Firefox's primary password before giving up
+const PRIMARY_PASSWORD_ATTEMPTS: usize = 3;
+/// Plaintext of key4.db's check value, once decrypted w/ the right primary
+/// password
+const PASSWORD_CHECK: &[u8] = b"password-check";
+
+/// DER object identifiers NSS encrypts w/
+const OID_PBES2: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x05, 0x0d];
+const OID_PBKDF2: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x05, 0x0c];
+const OID_AES256_CBC: &[u8] = &[0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x01, 0x2a];
+const OID_DES3_CBC: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x03, 0x07];
+
+/// Browser whose saved logins `import --from-browser` reads
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Browser {
+    Chrome,
+    Firefox,
+    Edge,
+}
+
+impl Browser {
+    pub fn parse(name: &str) -> InternalResult<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "chrome" => Ok(Self::Chrome),
+            "firefox" => Ok(Self::Firefox),
+            "edge" => Ok(Self::Edge),
+            _ => Err(InternalError::IO(format!(
+                "Unknown browser '{name}', use chrome, firefox or edge"
+            ))),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Chrome => "Chrome",
+            Self::Firefox => "Firefox",
+            Self::Edge => "Edge",
+        }
+    }
+}
+
+/// Saved logins of the browser's last used profile, w/o a password if it
+/// couldn't be decrypted
+///
+/// `primary_password` is asked for if Firefox's logins are protected by one.
+pub(crate) fn logins(
+    browser: Browser,
+    primary_password: &mut dyn FnMut() -> InternalResult<SecretString>,
+) -> InternalResult<Vec<Login>> {
+    match browser {
+        Browser::Firefox => firefox_logins(primary_password),
+        Browser::Chrome | Browser::Edge => chromium_logins(browser),
+    }
+}
+
+fn chromium_logins(browser: Browser) -> InternalResult<Vec<Login>> {
+    let not_found = || InternalError::NotFound(format!("No {} profile found", browser.name()));
+    let user_data = platform::chromium_dir(browser)
+        .filter(|d| d.is_dir())
+        .ok_or_else(not_found)?;
+
+    let db = user_data
+        .join(last_used_profile(&user_data))
+        .join("Login Data");
+
+    if !db.is_file() {
+        return Err(not_found());
+    }
+
+    let rows = sqlite(
+        &db,
+        "SELECT origin_url, username_value, hex(password_value) AS password_value \
+         FROM logins WHERE blacklisted_by_user = 0",
+    )?;
+    let mut crypt = platform::ChromiumCrypt::new(browser, &user_data);
+
+    rows.iter()
+        .map(|row| {
+            let value = unhex(column(row, "password_value"))?;
+            let password = crypt
+                .decrypt(&value)?
+                .and_then(|p| String::from_utf8(p.to_vec()).ok())
+                .map(Zeroizing::new);
+
+            Ok(Login::web(
+                column(row, "origin_url"),
+                column(row, "username_value"),
+                password,
+            ))
+        })
+        .collect()
+}
+
+/// Profile dir the browser was last used w/, per its `Local State`
+fn last_used_profile(user_data: &Path) -> String {
+    std::fs::read(user_data.join("Local State"))
+        .ok()
+        .and_then(|raw| serde_json::from_slice::<Value>(&raw).ok())
+        .and_then(|state| state["profile"]["last_used"].as_str().map(str::to_string))
+        .filter(|p| !p.is_empty())
+        .unwrap_or_else(|| "Default".into())
+}
+
+fn firefox_logins(
+    primary_password: &mut dyn FnMut() -> InternalResult<SecretString>,
+) -> InternalResult<Vec<Login>> {
+    let not_found = || InternalError::NotFound("No Firefox profile found".into());
+    let dir = platform::firefox_dir()
+        .filter(|d| d.is_dir())
+        .ok_or_else(not_found)?;
+    let profile = firefox_profile(&dir).ok_or_else(not_found)?;
+
+    let raw = match std::fs::read(profile.join("logins.json")) {
+        Ok(raw) => raw,
+        // nothing was ever saved
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+    let json: Value = serde_json::from_slice(&raw)
+        .map_err(|e| InternalError::IO(format!("Invalid Firefox logins.json: {e}")))?;
+
+    let key = firefox_key(&profile.join("key4.db"), primary_password)?;
+    let logins = json["logins"].as_array().map_or(&[][..], Vec::as_slice);
+
+    Ok(logins
+        .iter()
+        .map(|login| {
+            let field = |name: &str| decrypt_login_field(&key, login[name].as_str().unwrap_or(""));
+
+            Login::web(
+                login["hostname"].as_str().unwrap_or_default(),
+                &field("encryptedUsername").map_or(String::new(), |u| u.to_string()),
+                field("encryptedPassword"),
+            )
+        })
+        .collect())
+}
+
+/// Default profile of `profiles.ini`, preferring the one of an install
+fn firefox_profile(dir: &Path) -> Option<PathBuf> {
+    let ini = std::fs::read_to_string(dir.join("profiles.ini")).ok()?;
+    let mut sections: Vec<(&str, Vec<(&str, &str)>)> = Vec::new();
+
+    for line in ini.lines().map(str::trim) {
+        if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            sections.push((name, Vec::new()));
+        } else if let (Some((key, value)), Some((_, values))) =
+            (line.split_once('='), sections.last_mut())
+        {
+            values.push((key.trim(), value.trim()));
+        }
+    }
+
+    fn get<'a>(values: &[(&str, &'a str)], key: &str) -> Option<&'a str> {
+        values.iter().find(|(k, _)| *k == key).map(|(_, v)| *v)
+    }
+
+    // installs name their profile relative to the dir
+    let install = sections
+        .iter()
+        .filter(|(name, _)| name.starts_with("Install"))
+        .find_map(|(_, values)| get(values, "Default"));
+
+    if let Some(path) = install {
+        return Some(dir.join(path));
+    }
+
+    let profiles: Vec<_> = sections
+        .iter()
+        .filter(|(name, _)| name.starts_with("Profile"))
+        .map(|(_, values)| values)
+        .collect();
+    let profile = profiles
+        .iter()
+        .find(|values| get(values, "Default") == Some("1"))
+        .or(profiles.first())?;
+
+    let path = get(profile, "Path")?;
+
+    Some(match get(profile, "IsRelative") {
+        Some("0") => PathBuf::from(path),
+        _ => dir.join(path),
+    })
+}
+
+/// Key of the logins, kept in key4.db under the primary password, which is
+/// empty unless one is set
+fn firefox_key(
+    db: &Path,
+    primary_password: &mut dyn FnMut() -> InternalResult<SecretString>,
+) -> InternalResult<Zeroizing<Vec<u8>>> {
+    let meta = sqlite(
+        db,
+        "SELECT hex(item1) AS salt, hex(item2) AS item2 FROM metaData WHERE id = 'password'",
+    )?;
+    let row = meta
+        .first()
+        .ok_or_else(|| InternalError::Corrupted("Firefox key4.db has no password".into()))?;
+    let salt = unhex(column(row, "salt"))?;
+    let check = unhex(column(row, "item2"))?;
+
+    let mut password = SecretString::from("");
+
+    for attempt in 0..=PRIMARY_PASSWORD_ATTEMPTS {
+        if attempt > 0 {
+            password = primary_password()?;
+        }
+
+        match pbe_decrypt(&check, &salt, password.expose())? {
+            Some(plain) if plain.as_slice() == PASSWORD_CHECK => {}
+            _ => continue,
+        }
+
+        for row in sqlite(db, "SELECT hex(a11) AS a11 FROM nssPrivate")? {
+            if let Some(key) = pbe_decrypt(&unhex(column(&row, "a11"))?, &salt, password.expose())?
+            {
+                return Ok(key);
+            }
+        }
+
+        return Err(InternalError::Corrupted(
+            "Firefox key4.db holds no usable key".into(),
+        ));
+    }
+
+    Err(InternalError::IO("Wrong Firefox primary password".into()))
+}
+
+/// Decrypt a PBES2 blob of key4.db, `None` if the password is wrong
+fn pbe_decrypt(
+    der: &[u8],
+    global_salt: &[u8],
+    password: &str,
+) -> InternalResult<Option<Zeroizing<Vec<u8>>>> {
+    let mut blob = Der(der).seq()?;
+    let mut algorithm = blob.seq()?;
+
+    // older profiles use 3DES w/ a SHA1 based KDF, Firefox upgrades them
+    // when opened
+    if algorithm.oid()? != OID_PBES2 {
+        return Err(InternalError::IO(
+            "Firefox profile uses an old key format, open it in a current Firefox first".into(),
+        ));
+    }
+
+    let mut params = algorithm.seq()?;
+    let mut kdf = params.seq()?;
+
+    if kdf.oid()? != OID_PBKDF2 {
+        return Err(InternalError::Corrupted("Unknown key4.db KDF".into()));
+    }
+
+    // key length and PRF follow, always 32 and HMAC-SHA256
+    let mut kdf_params = kdf.seq()?;
+    let salt = kdf_params.octets()?;
+    let iterations = NonZeroU32::new(kdf_params.uint()?)
+        .ok_or_else(|| InternalError::Corrupted("Invalid key4.db iterations".into()))?;
+
+    let mut cipher = params.seq()?;
+
+    if cipher.oid()? != OID_AES256_CBC {
+        return Err(InternalError::Corrupted("Unknown key4.db cipher".into()));
+    }
+
+    // NSS stores the IV w/o its own DER header
+    let mut iv = vec![0x04, 0x0e];
+    iv.extend_from_slice(cipher.octets()?);
+    let ciphertext = blob.octets()?;
+
+    let mut hash = digest::Context::new(&digest::SHA1_FOR_LEGACY_USE_ONLY);
+    hash.update(global_salt);
+    hash.update(password.as_bytes());
+
+    let mut key = Zeroizing::new([0u8; 32]);
+    pbkdf2::derive(
+        pbkdf2::PBKDF2_HMAC_SHA256,
+        iterations,
+        salt,
+        hash.finish().as_ref(),
+        &mut *key,
+    );
+
+    Ok(cbc_decrypt::<aes::Aes256>(&*key, &iv, ciphertext))
+}
+
+/// `encryptedUsername` or `encryptedPassword` of logins.json
+fn decrypt_login_field(key: &[u8], encrypted: &str) -> Option<Zeroizing<String>> {
+    let der = general_purpose::STANDARD.decode(encrypted).ok()?;
+    let mut blob = Der(&der).seq().ok()?;
+
+    // id of the key in key4.db, there is only one
+    blob.octets().ok()?;
+
+    let mut algorithm = blob.seq().ok()?;
+    let oid = algorithm.oid().ok()?;
+    let iv = algorithm.octets().ok()?;
+    let ciphertext = blob.octets().ok()?;
+
+    let plain = match oid {
+        OID_DES3_CBC => cbc_decrypt::<des::TdesEde3>(key.get(..24)?, iv, ciphertext),
+        OID_AES256_CBC => cbc_decrypt::<aes::Aes256>(key.get(..32)?, iv, ciphertext),
+        _ => None,
+    }?;
+
+    String::from_utf8(plain.to_vec()).ok().map(Zeroizing::new)
+}
+
+/// Reads the few DER types NSS uses, in order
+struct Der<'a>(&'a [u8]);
+
+impl<'a> Der<'a> {
+    fn read(&mut self, tag: u8) -> InternalResult<&'a [u8]> {
+        let invalid = || InternalError::Corrupted("Invalid DER in browser data".into());
+
+        let [found, len, rest @ ..] = self.0 else {
+            return Err(invalid());
+        };
+
+        if *found != tag {
+            return Err(invalid());
+        }
+
+        let (len, rest) = match *len {
+            len @ 0..=0x7f => (len as usize, rest),
+            0x81..=0x84 => {
+                let size = (*len & 0x7f) as usize;
+                let bytes = rest.get(..size).ok_or_else(invalid)?;
+                let len = bytes.iter().fold(0, |l, b| (l << 8) | *b as usize);
+
+                (len, &rest[size..])
+            }
+            _ => return Err(invalid()),
+        };
+
+        let value = rest.get(..len).ok_or_else(invalid)?;
+        self.0 = &rest[len..];
+
+        Ok(value)
+    }
+
+    fn seq(&mut self) -> InternalResult<Der<'a>> {
+        self.read(0x30).map(Der)
+    }
+
+    fn oid(&mut self) -> InternalResult<&'a [u8]> {
+        self.read(0x06)
+    }
+
+    fn octets(&mut self) -> InternalResult<&'a [u8]> {
+        self.read(0x04)
+    }
+
+    fn uint(&mut self) -> InternalResult<u32> {
+        let bytes = self.read(0x02)?;
+
+        if bytes.len() > 5 {
+            return Err(InternalError::Corrupted("DER integer too large".into()));
+        }
+
+        Ok(bytes.iter().fold(0, |n, b| (n << 8) | *b as u32))
+    }
+}
+
+/// PKCS#7 padded CBC, `None` if the key is wrong
+fn cbc_decrypt<C>(key: &[u8], iv: &[u8], data: &[u8]) -> Option<Zeroizing<Vec<u8>>>
+where
+    C: BlockCipher + BlockDecryptMut + KeyInit,
+{
+    let decryptor = cbc::Decryptor::<C>::new_from_slices(key, iv).ok()?;
+    let mut buf = Zeroizing::new(data.to_vec());
+    let len = decryptor.decrypt_padded_mut::<Pkcs7>(&mut buf).ok()?.len();
+
+    buf.truncate(len);
+    Some(buf)
+}
+
+/// Rows of a query on one of the browser's SQLite databases, read w/ the
+/// `sqlite3` shell
+fn sqlite(db: &Path, query: &str) -> InternalResult<Vec<Map<String, Value>>> {
+    let output = Command::new("sqlite3")
+        .args(["-readonly", "-json"])
+        .arg(sqlite_uri(db))
+        .arg(query)
+        .stdin(Stdio::null())
+        .output()
+        .map_err(|e| InternalError::IO(format!("Failed to run sqlite3, is it installed? {e}")))?;
+
+    if !output.status.success() {
+        return Err(InternalError::IO(format!(
+            "Failed to read '{}': {}",
+            db.display(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+
+    // no rows print nothing at all
+    if output.stdout.trim_ascii().is_empty() {
+        return Ok(Vec::new());
+    }
+
+    serde_json::from_slice(&output.stdout)
+        .map_err(|e| InternalError::IO(format!("Invalid sqlite3 output: {e}")))
+}
+
+/// URI of `db` opened as immutable, as the running browser may hold a lock
+/// on it
+fn sqlite_uri(db: &Path) -> String {
+    let path = db.to_string_lossy().replace('\\', "/");
+    let mut uri = String::from("file:");
+
+    // e.g. `C:/Users`
+    if !path.starts_with('/') {
+        uri.push('/');
+    }
+
+    for c in path.chars() {
+        match c {
+            '%' | '?' | '#' => uri.push_str(&format!("%{:02X}", c as u32)),
+            c => uri.push(c),
+        }
+    }
+
+    uri.push_str("?immutable=1");
+    uri
+}
+
+fn column<'a>(row: &'a Map<String, Value>, name: &str) -> &'a str {
+    row.get(name).and_then(Value::as_str).unwrap_or_default()
+}
+
+fn unhex(hex: &str) -> InternalResult<Vec<u8>> {
+    let invalid = || InternalError::IO("Invalid hex in sqlite3 output".into());
+
+    if !hex.len().is_multiple_of(2) {
+        return Err(invalid());
+    }
+
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| {
+            hex.get(i..i + 2)
+                .and_then(|b| u8::from_str_radix(b, 16).ok())
+                .ok_or_else(invalid)
+        })
+        .collect()
+}
+
+/// Key of `v10`/`v11` values of Chromium on Linux and macOS, AES-128
+/// derived from its "Safe Storage" password
+#[cfg(unix)]
+fn chromium_key(password: &[u8], iterations: u32) -> Zeroizing<[u8; 16]> {
+    let mut key = Zeroizing::new([0u8; 16]);
+
+    pbkdf2::derive(
+        pbkdf2::PBKDF2_HMAC_SHA1,
+        NonZeroU32::new(iterations).unwrap_or(NonZeroU32::MIN),
+        b"saltysalt",
+        password,
+        &mut *key,
+    );
+
+    key
+}
+
+/// AES-128-CBC w/ an IV of spaces
+#[cfg(unix)]
+fn chromium_decrypt(key: &[u8; 16], value: &[u8]) -> Option<Zeroizing<Vec<u8>>> {
+    cbc_decrypt::<aes::Aes128>(key, &[b' '; 16], value.get(3..)?)
+}
+
+/// Chromium encrypts `v10` values under "peanuts", `v11` ones under the
+/// "Safe Storage" password it keeps in the Secret Service
+#[cfg(target_os = "linux")]
+mod platform {
+    use super::*;
+
+    pub(super) fn chromium_dir(browser: Browser) -> Option<PathBuf> {
+        let config = match std::env::var_os("XDG_CONFIG_HOME").filter(|d| !d.is_empty()) {
+            Some(dir) => PathBuf::from(dir),
+            None => env_home::env_home_dir()?.join(".config"),
+        };
+
+        Some(config.join(match browser {
+            Browser::Edge => "microsoft-edge",
+            _ => "google-chrome",
+        }))
+    }
+
+    pub(super) fn firefox_dir() -> Option<PathBuf> {
+        Some(env_home::env_home_dir()?.join(".mozilla/firefox"))
+    }
+
+    pub(super) struct ChromiumCrypt {
+        browser: Browser,
+        /// looked up on the first `v11` value
+        v11: Option<Zeroizing<[u8; 16]>>,
+    }
+
+    impl ChromiumCrypt {
+        pub fn new(browser: Browser, _user_data: &Path) -> Self {
+            Self { browser, v11: None }
+        }
+
+        pub fn decrypt(&mut self, value: &[u8]) -> InternalResult<Option<Zeroizing<Vec<u8>>>> {
+            let key = match value.get(..3) {
+                Some(b"v10") => chromium_key(b"peanuts", 1),
+                Some(b"v11") => match &self.v11 {
+                    Some(key) => key.clone(),
+                    None => self
+                        .v11
+                        .insert(chromium_key(&self.safe_storage()?, 1))
+                        .clone(),
+                },
+                _ => return Ok(None),
+            };
+
+            Ok(chromium_decrypt(&key, value))
+        }
+
+        fn safe_storage(&self) -> InternalResult<Zeroizing<Vec<u8>>> {
+            let application = match self.browser {
+                Browser::Edge => "microsoft-edge",
+                _ => "chrome",
+            };
+
+            let output = Command::new("secret-tool")
+                .args(["lookup", "application", application])
+                .stdin(Stdio::null())
+                .stderr(Stdio::null())
+                .output()
+                .map_err(|e| InternalError::IO(format!("Failed to run secret-tool: {e}")))?;
+            let password = Zeroizing::new(output.stdout);
+
+            if !output.status.success() || password.is_empty() {
+                return Err(InternalError::Locked(format!(
+                    "{} Safe Storage isn't in the Secret Service, is the keyring unlocked?",
+                    self.browser.name()
+                )));
+            }
+
+            Ok(password)
+        }
+    }
+}
+
+/// Chromium encrypts `v10` values under the "Safe Storage" password it
+/// keeps in the login keychain, reading it asks the user for access
+#[cfg(target_os = "macos")]
+mod platform {
+    use super::*;
+
+    /// PBKDF2 iterations of the key on macOS
+    const ITERATIONS: u32 = 1003;
+
+    fn app_support() -> Option<PathBuf> {
+        Some(env_home::env_home_dir()?.join("Library/Application Support"))
+    }
+
+    pub(super) fn chromium_dir(browser: Browser) -> Option<PathBuf> {
+        Some(app_support()?.join(match browser {
+            Browser::Edge => "Microsoft Edge",
+            _ => "Google/Chrome",
+        }))
+    }
+
+    pub(super) fn firefox_dir() -> Option<PathBuf> {
+        Some(app_support()?.join("Firefox"))
+    }
+
+    pub(super) struct ChromiumCrypt {
+        browser: Browser,
+        /// looked up on the first value
+        key: Option<Zeroizing<[u8; 16]>>,
+    }
+
+    impl ChromiumCrypt {
+        pub fn new(browser: Browser, _user_data: &Path) -> Self {
+            Self { browser, key: None }
+        }
+
+        pub fn decrypt(&mut self, value: &[u8]) -> InternalResult<Option<Zeroizing<Vec<u8>>>> {
+            if value.get(..3) != Some(b"v10") {
+                return Ok(None);
+            }
+
+            let key = match &self.key {
+                Some(key) => key.clone(),
+                None => {
+                    let key = chromium_key(&self.safe_storage()?, ITERATIONS);
+                    self.key.insert(key).clone()
+                }
+            };
+
+            Ok(chromium_decrypt(&key, value))
+        }
+
+        fn safe_storage(&self) -> InternalResult<Zeroizing<Vec<u8>>> {
+            let service = match self.browser {
+                Browser::Edge => "Microsoft Edge Safe Storage",
+                _ => "Chrome Safe Storage",
+            };
+
+            let output = Command::new("security")
+                .args(["find-generic-password", "-w", "-s", service])
+                .stdin(Stdio::null())
+                .stderr(Stdio::null())
+                .output()
+                .map_err(|e| InternalError::IO(format!("Failed to run security: {e}")))?;
+            let mut password = Zeroizing::new(output.stdout);
+
+            // denying the keychain prompt fails the lookup too
+            if !output.status.success() || password.is_empty() {
+                return Err(InternalError::Locked(format!(
+                    "No access to '{service}' in the keychain, allow it when asked"
+                )));
+            }
+
+            password.pop_if(|c| *c == b'\n');
+            Ok(password)
+        }
+    }
+}
+
+/// Chromium encrypts values w/ AES-256-GCM under a key protected by DPAPI,
+/// kept in `Local State`, older values w/ DPAPI directly
+///
+/// NOTE: `v20` values are bound to the browser itself, they can't be
+/// decrypted by any other app
+#[cfg(windows)]
+mod platform {
+    use super::*;
+    use ring::aead;
+    use std::ffi::c_void;
+
+    /// Prefix of the key in `Local State`
+    const DPAPI_PREFIX: &[u8] = b"DPAPI";
+    const NONCE_SIZE: usize = 12;
+
+    #[repr(C)]
+    struct Blob {
+        len: u32,
+        data: *mut u8,
+    }
+
+    #[link(name = "crypt32")]
+    unsafe extern "system" {
+        fn CryptUnprotectData(
+            data_in: *const Blob,
+            description: *mut *mut u16,
+            entropy: *const Blob,
+            reserved: *const c_void,
+            prompt: *const c_void,
+            flags: u32,
+            data_out: *mut Blob,
+        ) -> i32;
+    }
+
+    #[link(name = "kernel32")]
+    unsafe extern "system" {
+        fn LocalFree(mem: *mut c_void) -> *mut c_void;
+    }
+
+    pub(super) fn chromium_dir(browser: Browser) -> Option<PathBuf> {
+        let local = PathBuf::from(std::env::var_os("LOCALAPPDATA")?);
+
+        Some(local.join(match browser {
+            Browser::Edge => "Microsoft/Edge/User Data",
+            _ => "Google/Chrome/User Data",
+        }))
+    }
+
+    pub(super) fn firefox_dir() -> Option<PathBuf> {
+        Some(PathBuf::from(std::env::var_os("APPDATA")?).join("Mozilla/Firefox"))
+    }
+
+    pub(super) struct ChromiumCrypt {
+        local_state: PathBuf,
+        /// read on the first `v10` value
+        key: Option<Zeroizing<Vec<u8>>>,
+    }
+
+    impl ChromiumCrypt {
+        pub fn new(_browser: Browser, user_data: &Path) -> Self {
+            Self {
+                local_state: user_data.join("Local State"),
+                key: None,
+            }
+        }
+
+        pub fn decrypt(&mut self, value: &[u8]) -> InternalResult<Option<Zeroizing<Vec<u8>>>> {
+            match value.get(..3) {
+                Some(b"v10" | b"v11") => {}
+                Some(b"v20") => return Ok(None),
+                _ => return Ok(unprotect(value)),
+            }
+
+            if self.key.is_none() {
+                self.key = Some(self.read_key()?);
+            }
+
+            let Some(key) = self.key.as_ref() else {
+                return Ok(None);
+            };
+            let Some((nonce, sealed)) = value[3..].split_at_checked(NONCE_SIZE) else {
+                return Ok(None);
+            };
+
+            let key = aead::LessSafeKey::new(
+                aead::UnboundKey::new(&aead::AES_256_GCM, key)
+                    .map_err(|_| InternalError::Corrupted("Invalid Local State key".into()))?,
+            );
+            let nonce = aead::Nonce::try_assume_unique_for_key(nonce)
+                .map_err(|_| InternalError::Corrupted("Invalid nonce".into()))?;
+
+            let mut buf = Zeroizing::new(sealed.to_vec());
+            let Ok(plain) = key.open_in_place(nonce, aead::Aad::empty(), &mut buf) else {
+                return Ok(None);
+            };
+
+            Ok(Some(Zeroizing::new(plain.to_vec())))
+        }
+
+        fn read_key(&self) -> InternalResult<Zeroizing<Vec<u8>>> {
+            let invalid = || InternalError::Corrupted("Invalid key in Local State".into());
+
+            let state: Value = serde_json::from_slice(&std::fs::read(&self.local_state)?)
+                .map_err(|_| invalid())?;
+            let encrypted = state["os_crypt"]["encrypted_key"]
+                .as_str()
+                .and_then(|k| general_purpose::STANDARD.decode(k).ok())
+                .ok_or_else(invalid)?;
+
+            encrypted
+                .strip_prefix(DPAPI_PREFIX)
+                .and_then(unprotect)
+                .ok_or_else(invalid)
+        }
+    }
+
+    /// Decrypt w/ the user's DPAPI key, never prompting
+    fn unprotect(data: &[u8]) -> Option<Zeroizing<Vec<u8>>> {
+        /// CRYPTPROTECT_UI_FORBIDDEN
+        const UI_FORBIDDEN: u32 = 0x1;
+
+        let input = Blob {
+            len: u32::try_from(data.len()).ok()?,
+            data: data.as_ptr().cast_mut(),
+        };
+        let mut output = Blob {
+            len: 0,
+            data: std::ptr::null_mut(),
+        };
+
+        // SAFETY: the input is only read, the output is allocated by DPAPI
+        // and freed once copied
+        unsafe {
+            let ok = CryptUnprotectData(
+                &input,
+                std::ptr::null_mut(),
+                std::ptr::null(),
+                std::ptr::null(),
+                std::ptr::null(),
+                UI_FORBIDDEN,
+                &mut output,
+            );
+
+            if ok == 0 || output.data.is_null() {
+                return None;
+            }
+
+            let plain = std::slice::from_raw_parts(output.data, output.len as usize);
+            let copy = Zeroizing::new(plain.to_vec());
+
+            std::ptr::write_bytes(output.data, 0, output.len as usize);
+            LocalFree(output.data.cast());
+
+            Some(copy)
+        }
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", windows)))]
+mod platform {
+    use super::*;
+
+    pub(super) fn chromium_dir(_browser: Browser) -> Option<PathBuf> {
+        None
+    }
+
+    pub(super) fn firefox_dir() -> Option<PathBuf> {
+        None
+    }
+
+    pub(super) struct ChromiumCrypt;
+
+    impl ChromiumCrypt {
+        pub fn new(_browser: Browser, _user_data: &Path) -> Self {
+            Self
+        }
+
+        pub fn decrypt(&mut self, _value: &[u8]) -> InternalResult<Option<Zeroizing<Vec<u8>>>> {
+            Ok(None)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use aes::cipher::BlockEncryptMut;
+
+    #[cfg(unix)]
+    #[test]
+    fn test_chromium_v10_values() {
+        let key = chromium_key(b"peanuts", 1);
+        let mut value = b"v10".to_vec();
+        value.extend(
+            cbc::Encryptor::<aes::Aes128>::new_from_slices(&*key, &[b' '; 16])
+                .unwrap()
+                .encrypt_padded_vec_mut::<Pkcs7>(b"hunter2"),
+        );
+
+        let plain = chromium_decrypt(&key, &value).unwrap();
+        assert_eq!(plain.as_slice(), b"hunter2");
+        let wrong = chromium_decrypt(&chromium_key(b"other", 1), &value);
+        assert!(wrong.is_none_or(|p| p.as_slice() != b"hunter2"));
+    }
+
+    #[test]
+    fn test_der_reads_in_order() {
+        // SEQUENCE { OCTET STRING 01 02, INTEGER 10000 }
+        let der = [0x30, 0x08, 0x04, 0x02, 0x01, 0x02, 0x02, 0x02, 0x27, 0x10];
+        let mut seq = Der(&der).seq().unwrap();
+
+        assert_eq!(seq.octets().unwrap(), &[0x01, 0x02]);
+        assert_eq!(seq.uint().unwrap(), 10_000);
+        assert!(seq.octets().is_err());
+        assert!(Der(&der[..5]).seq().is_err());
+    }
+}
@@ -0,0 +1,511 @@
+use crate::{
+    config::CacheConfig,
+    crypto::{EntryCodec, VaultKey},
+    manifest::{Manifest, Report},
+    types::{InternalError, InternalResult},
+    vaults::Vault,
+};
+use std::{
+    cell::RefCell,
+    path::{Path, PathBuf},
+};
+use turbocache::TurboCache;
+
+/// A vault's cache, w/ its integrity manifest kept in step on every write
+pub(crate) struct VaultCache {
+    cache: TurboCache,
+    home_dir: PathBuf,
+    vault: Vault,
+    /// see [`EntryCodec::manifest_key`]
+    key: VaultKey,
+    /// `None` while unreadable, writes then leave it for `vuoto verify`
+    manifest: RefCell<Option<Manifest>>,
+}
+
+impl VaultCache {
+    /// Track writes to `cache` of `vault`, recording its current contents
+    /// if it has no manifest yet
+    pub fn new<P: AsRef<Path>>(
+        home_dir: &P,
+        vault: &Vault,
+        cache: TurboCache,
+        codec: &EntryCodec,
+    ) -> InternalResult<Self> {
+        let key = codec.manifest_key();
+
+        let manifest = match Manifest::load(home_dir, vault, &key) {
+            Ok(Some(manifest)) => Some(manifest),
+            Ok(None) => {
+                let manifest = Manifest::of(&cache)?;
+                manifest.save(home_dir, vault, &key)?;
+
+                Some(manifest)
+            }
+            Err(err) => {
+                eprintln!("[WARN]: {err}, see `vuoto verify`.");
+                None
+            }
+        };
+
+        Ok(Self {
+            cache,
+            home_dir: home_dir.as_ref().to_path_buf(),
+            vault: vault.clone(),
+            key,
+            manifest: RefCell::new(manifest),
+        })
+    }
+
+    pub fn get(&self, key: &[u8]) -> InternalResult<Option<Vec<u8>>> {
+        Ok(self.cache.get(key)?)
+    }
+
+    pub fn iter(
+        &self,
+    ) -> InternalResult<impl Iterator<Item = turbocache::TurboResult<(Vec<u8>, Vec<u8>)>>> {
+        Ok(self.cache.iter()?)
+    }
+
+    pub fn set(&self, key: &[u8], value: &[u8]) -> InternalResult<()> {
+        self.cache.set(key, value)?;
+        self.record(key, Some(value))
+    }
+
+    pub fn del(&self, key: &[u8]) -> InternalResult<Option<Vec<u8>>> {
+        let old = self.cache.del(key)?;
+        self.record(key, None)?;
+
+        Ok(old)
+    }
+
+    /// Compare the cache against its manifest
+    pub fn verify(&self) -> InternalResult<Report> {
+        Manifest::load(&self.home_dir, &self.vault, &self.key)?
+            .unwrap_or_default()
+            .verify(&self.cache)
+    }
+
+    /// Record the cache's current contents as its manifest, returning how
+    /// many values it holds
+    pub fn reset_manifest(&self) -> InternalResult<usize> {
+        let manifest = Manifest::of(&self.cache)?;
+        manifest.save(&self.home_dir, &self.vault, &self.key)?;
+
+        let len = manifest.len();
+        *self.manifest.borrow_mut() = Some(manifest);
+
+        Ok(len)
+    }
+
+    fn record(&self, key: &[u8], value: Option<&[u8]>) -> InternalResult<()> {
+        let mut manifest = self.manifest.borrow_mut();
+
+        let Some(manifest) = manifest.as_mut() else {
+            return Ok(());
+        };
+
+        manifest.record(key, value);
+        manifest.save(&self.home_dir, &self.vault, &self.key)
+    }
+}
+
+/// Capacity used for a vault's cache
+pub(crate) fn capacity(vault: &Vault, config: &CacheConfig) -> u32 {
+    vault.capacity.unwrap_or(config.capacity).max(1)
+}
+
+/// Open a vault's cache w/ its configured capacity
+pub(crate) fn open<P: AsRef<Path>>(
+    home_dir: &P,
+    vault: &Vault,
+    config: &CacheConfig,
+) -> InternalResult<TurboCache> {
+    let path = home_dir.as_ref().join(&vault.slug);
+    recover(&path)?;
+
+    Ok(TurboCache::new(path, capacity(vault, config) as usize)?)
+}
+
+/// Open a vault's cache to read and write its entries, see [`VaultCache`]
+pub(crate) fn open_tracked<P: AsRef<Path>>(
+    home_dir: &P,
+    vault: &Vault,
+    config: &CacheConfig,
+    codec: &EntryCodec,
+) -> InternalResult<VaultCache> {
+    VaultCache::new(home_dir, vault, open(home_dir, vault, config)?, codec)
+}
+
+/// Whether `len` entries fill a cache of `capacity` past `grow_at_percent`
+pub(crate) fn is_full(len: usize, capacity: u32, grow_at_percent: u8) -> bool {
+    len * 100 >= capacity as usize * usize::from(grow_at_percent.clamp(1, 100))
+}
+
+/// Open a vault's cache, rebuilding it w/ doubled capacity when it's full,
+/// also returns the new capacity if it grew
+pub(crate) fn open_or_grow<P: AsRef<Path>>(
+    home_dir: &P,
+    vault: &Vault,
+    config: &CacheConfig,
+) -> InternalResult<(TurboCache, Option<u32>)> {
+    open_to_fit(home_dir, vault, config, 0)
+}
+
+/// Like [`open_or_grow`], but counting `incoming` entries about to be
+/// written at once, doubling the capacity until they all fit
+pub(crate) fn open_to_fit<P: AsRef<Path>>(
+    home_dir: &P,
+    vault: &Vault,
+    config: &CacheConfig,
+    incoming: usize,
+) -> InternalResult<(TurboCache, Option<u32>)> {
+    let cache = open(home_dir, vault, config)?;
+    let len = cache.iter()?.count() + incoming;
+    let current = capacity(vault, config);
+
+    let mut capacity = current;
+
+    while is_full(len, capacity, config.grow_at_percent) && capacity < u32::MAX {
+        capacity = capacity.saturating_mul(2);
+    }
+
+    if capacity == current {
+        return Ok((cache, None));
+    }
+
+    let cache = rebuild(home_dir, vault, cache, capacity)?;
+
+    Ok((cache, Some(capacity)))
+}
+
+/// Copy all entries into a fresh cache of given capacity, which then
+/// replaces the vault's current one
+pub(crate) fn rebuild<P: AsRef<Path>>(
+    home_dir: &P,
+    vault: &Vault,
+    cache: TurboCache,
+    capacity: u32,
+) -> InternalResult<TurboCache> {
+    rewrite(home_dir, vault, cache, capacity, None, |key, value| {
+        Ok((key.to_vec(), value.to_vec()))
+    })
+}
+
+/// Like [`rebuild`], but every key and value is passed through `map`, the
+/// vault's cache is left untouched if it fails
+///
+/// `staged` is a file in `home_dir` and its new contents, replaced along w/
+/// the cache, so e.g. a rekeyed vault never ends up w/ the old header
+pub(crate) fn rewrite<P, F>(
+    home_dir: &P,
+    vault: &Vault,
+    cache: TurboCache,
+    capacity: u32,
+    staged: Option<(&str, &[u8])>,
+    mut map: F,
+) -> InternalResult<TurboCache>
+where
+    P: AsRef<Path>,
+    F: FnMut(&[u8], &[u8]) -> InternalResult<(Vec<u8>, Vec<u8>)>,
+{
+    let path = home_dir.as_ref().join(&vault.slug);
+    let fresh_path = path.with_extension("rebuild");
+    recover(&path)?;
+
+    let fresh = TurboCache::new(&fresh_path, capacity as usize)?;
+
+    for item in cache.iter()? {
+        let (key, value) = item?;
+        let (key, value) = map(&key, &value)?;
+        fresh.set(&key, &value)?;
+    }
+
+    drop(fresh);
+    drop(cache);
+
+    if let Some((name, contents)) = staged {
+        let pending = path.with_extension("pending");
+        let tmp = path.with_extension("pending.tmp");
+
+        std::fs::write(&tmp, [name.as_bytes(), b"\n", contents].concat())?;
+        std::fs::rename(&tmp, &pending)?;
+    }
+
+    // once complete, the rewrite is carried out even if interrupted
+    std::fs::rename(&fresh_path, path.with_extension("new"))?;
+    finish(&path)?;
+
+    Ok(TurboCache::new(&path, capacity as usize)?)
+}
+
+/// Bring the cache at `path` back in step after an interrupted rewrite,
+/// finishing it if it was complete, else dropping it
+///
+/// NOTE: see [`rewrite`], a rewrite's files are, in order, `.rebuild`
+/// while written, the staged file in `.pending`, and `.new` once complete,
+/// the current cache is then moved to `.old` and replaced
+pub(crate) fn recover(path: &Path) -> InternalResult<()> {
+    if path.with_extension("new").exists() {
+        return finish(path);
+    }
+
+    remove_path(&path.with_extension("rebuild"))?;
+    remove_path(&path.with_extension("pending"))?;
+
+    let old_path = path.with_extension("old");
+
+    // never drop the old cache while it's the only one
+    match path.exists() {
+        true => remove_path(&old_path),
+        false if old_path.exists() => Ok(std::fs::rename(&old_path, path)?),
+        false => Ok(()),
+    }
+}
+
+/// Replace the staged file and the cache w/ those of a complete rewrite,
+/// each step is safe to repeat after a crash
+fn finish(path: &Path) -> InternalResult<()> {
+    let pending = path.with_extension("pending");
+    let new_path = path.with_extension("new");
+    let old_path = path.with_extension("old");
+
+    // the staged file first, `.new` marks the rewrite as complete till then
+    match std::fs::read(&pending) {
+        Ok(raw) => {
+            let split = raw.iter().position(|b| *b == b'\n');
+            let name = split.and_then(|i| std::str::from_utf8(&raw[..i]).ok());
+
+            // only ever a file next to the cache
+            let (name, contents) = match (name, split) {
+                (Some(name), Some(i)) if Path::new(name).file_name() == Some(name.as_ref()) => {
+                    (name, &raw[i + 1..])
+                }
+                _ => {
+                    return Err(InternalError::IO(format!(
+                        "Invalid pending file {}",
+                        pending.display()
+                    )));
+                }
+            };
+            let target = path.with_file_name(name);
+            let tmp = target.with_extension("tmp");
+
+            std::fs::write(&tmp, contents)?;
+            std::fs::rename(&tmp, &target)?;
+            remove_path(&pending)?;
+        }
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+        Err(err) => return Err(err.into()),
+    }
+
+    if new_path.exists() {
+        // keep the old cache until the new one is in place
+        if path.exists() {
+            remove_path(&old_path)?;
+            std::fs::rename(path, &old_path)?;
+        }
+
+        std::fs::rename(&new_path, path)?;
+    }
+
+    remove_path(&old_path)
+}
+
+fn remove_path(path: &Path) -> InternalResult<()> {
+    let res = if path.is_dir() {
+        std::fs::remove_dir_all(path)
+    } else {
+        std::fs::remove_file(path)
+    };
+
+    match res {
+        Ok(()) => Ok(()),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        Err(err) => Err(err.into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::vault;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_capacity_falls_back_to_config() {
+        let config = CacheConfig::default();
+
+        assert_eq!(capacity(&vault(), &config), config.capacity);
+        assert_eq!(
+            capacity(
+                &Vault {
+                    capacity: Some(64),
+                    ..vault()
+                },
+                &config
+            ),
+            64
+        );
+    }
+
+    #[test]
+    fn test_is_full() {
+        assert!(!is_full(0, 512, 75));
+        assert!(!is_full(383, 512, 75));
+        assert!(is_full(384, 512, 75));
+
+        // out of range percentages are clamped
+        assert!(is_full(10, 10, 200));
+        assert!(!is_full(5, 512, 0));
+        assert!(is_full(6, 512, 0));
+    }
+
+    #[test]
+    fn test_grow_keeps_entries() {
+        let temp_dir = TempDir::new().unwrap();
+        let config = CacheConfig {
+            capacity: 4,
+            grow_at_percent: 50,
+        };
+        let vault = vault();
+
+        let cache = open(&temp_dir.path(), &vault, &config).unwrap();
+        cache.set(b"a", b"1").unwrap();
+        drop(cache);
+
+        let (_, grown) = open_or_grow(&temp_dir.path(), &vault, &config).unwrap();
+        assert!(grown.is_none());
+
+        let cache = open(&temp_dir.path(), &vault, &config).unwrap();
+        cache.set(b"b", b"2").unwrap();
+        drop(cache);
+
+        let (cache, grown) = open_or_grow(&temp_dir.path(), &vault, &config).unwrap();
+        assert_eq!(grown, Some(8));
+        assert_eq!(cache.get(b"a").unwrap().unwrap(), b"1");
+        assert_eq!(cache.get(b"b").unwrap().unwrap(), b"2");
+
+        assert!(!temp_dir.path().join("0123456789abcdef.rebuild").exists());
+        assert!(!temp_dir.path().join("0123456789abcdef.old").exists());
+    }
+
+    #[test]
+    fn test_grow_to_fit_incoming() {
+        let temp_dir = TempDir::new().unwrap();
+        let config = CacheConfig {
+            capacity: 4,
+            grow_at_percent: 50,
+        };
+        let vault = vault();
+
+        let cache = open(&temp_dir.path(), &vault, &config).unwrap();
+        cache.set(b"a", b"1").unwrap();
+        drop(cache);
+
+        let (_, grown) = open_to_fit(&temp_dir.path(), &vault, &config, 0).unwrap();
+        assert!(grown.is_none());
+
+        // 1 + 20 entries fill 50% of 32, not of 64
+        let (cache, grown) = open_to_fit(&temp_dir.path(), &vault, &config, 20).unwrap();
+        assert_eq!(grown, Some(64));
+        assert_eq!(cache.get(b"a").unwrap().unwrap(), b"1");
+    }
+
+    #[test]
+    fn test_failed_rewrite_keeps_cache() {
+        let temp_dir = TempDir::new().unwrap();
+        let config = CacheConfig::default();
+        let vault = vault();
+
+        let cache = open(&temp_dir.path(), &vault, &config).unwrap();
+        cache.set(b"a", b"1").unwrap();
+
+        let failed = rewrite(&temp_dir.path(), &vault, cache, 8, None, |_, _| {
+            Err(crate::types::InternalError::IO("nope".into()))
+        });
+        assert!(failed.is_err());
+
+        let cache = open(&temp_dir.path(), &vault, &config).unwrap();
+        assert_eq!(cache.get(b"a").unwrap().unwrap(), b"1");
+
+        let cache = rewrite(&temp_dir.path(), &vault, cache, 8, None, |k, v| {
+            Ok((k.to_vec(), [v, v].concat()))
+        })
+        .unwrap();
+        assert_eq!(cache.get(b"a").unwrap().unwrap(), b"11");
+    }
+
+    #[test]
+    fn test_staged_file_is_replaced_w_cache() {
+        let temp_dir = TempDir::new().unwrap();
+        let config = CacheConfig::default();
+        let vault = vault();
+        let header = temp_dir.path().join("0123456789abcdef.master");
+        std::fs::write(&header, "old").unwrap();
+
+        let cache = open(&temp_dir.path(), &vault, &config).unwrap();
+        cache.set(b"a", b"1").unwrap();
+
+        let staged = Some(("0123456789abcdef.master", b"new".as_slice()));
+        let cache = rewrite(&temp_dir.path(), &vault, cache, 8, staged, |k, v| {
+            Ok((k.to_vec(), [v, v].concat()))
+        })
+        .unwrap();
+
+        assert_eq!(cache.get(b"a").unwrap().unwrap(), b"11");
+        assert_eq!(std::fs::read(&header).unwrap(), b"new");
+        assert!(!temp_dir.path().join("0123456789abcdef.pending").exists());
+    }
+
+    #[test]
+    fn test_interrupted_rewrite_is_recovered() {
+        let temp_dir = TempDir::new().unwrap();
+        let config = CacheConfig::default();
+        let vault = vault();
+        let path = temp_dir.path().join(&vault.slug);
+        let header = temp_dir.path().join("0123456789abcdef.master");
+        std::fs::write(&header, "old").unwrap();
+
+        let cache = open(&temp_dir.path(), &vault, &config).unwrap();
+        cache.set(b"a", b"1").unwrap();
+        drop(cache);
+
+        // incomplete, so dropped
+        TurboCache::new(path.with_extension("rebuild"), 8).unwrap();
+        std::fs::write(
+            path.with_extension("pending"),
+            "0123456789abcdef.master\nnew",
+        )
+        .unwrap();
+
+        let cache = open(&temp_dir.path(), &vault, &config).unwrap();
+        assert_eq!(cache.get(b"a").unwrap().unwrap(), b"1");
+        assert_eq!(std::fs::read(&header).unwrap(), b"old");
+        assert!(!path.with_extension("rebuild").exists());
+        drop(cache);
+
+        // complete, and crashed w/ the old cache moved aside
+        let fresh = TurboCache::new(path.with_extension("new"), 8).unwrap();
+        fresh.set(b"a", b"2").unwrap();
+        drop(fresh);
+        std::fs::write(
+            path.with_extension("pending"),
+            "0123456789abcdef.master\nnew",
+        )
+        .unwrap();
+        std::fs::rename(&path, path.with_extension("old")).unwrap();
+
+        let cache = open(&temp_dir.path(), &vault, &config).unwrap();
+        assert_eq!(cache.get(b"a").unwrap().unwrap(), b"2");
+        assert_eq!(std::fs::read(&header).unwrap(), b"new");
+        assert!(!path.with_extension("old").exists());
+        assert!(!path.with_extension("new").exists());
+        drop(cache);
+
+        // the old cache is never dropped while it's the only one
+        std::fs::rename(&path, path.with_extension("old")).unwrap();
+
+        let cache = open(&temp_dir.path(), &vault, &config).unwrap();
+        assert_eq!(cache.get(b"a").unwrap().unwrap(), b"2");
+    }
+}
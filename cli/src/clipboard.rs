@@ -0,0 +1,80 @@
+use crate::types::{InternalError, InternalResult};
+use arboard::Clipboard;
+use std::{
+    sync::atomic::{AtomicBool, AtomicU64, Ordering},
+    time::Duration,
+};
+use zeroize::Zeroizing;
+
+/// Files container runtimes (docker, podman) leave in the root fs
+const CONTAINER_MARKERS: &[&str] = &["/.dockerenv", "/run/.containerenv"];
+
+static DISABLED: AtomicBool = AtomicBool::new(false);
+/// Seconds until copied text is cleared, `0` never
+static CLEAR_AFTER_SECS: AtomicU64 = AtomicU64::new(0);
+
+/// Never touch the system clipboard, copies fail so callers show the value
+pub(crate) fn disable() {
+    DISABLED.store(true, Ordering::Relaxed);
+}
+
+/// Clear copied text after `after`, unless replaced by then, see
+/// [`crate::config::ClipboardConfig`]
+pub(crate) fn clear_after(after: Option<Duration>) {
+    CLEAR_AFTER_SECS.store(after.map_or(0, |a| a.as_secs()), Ordering::Relaxed);
+}
+
+/// Best guess whether we run inside a container, where no clipboard exists
+pub(crate) fn in_container() -> bool {
+    std::env::var_os("container").is_some()
+        || CONTAINER_MARKERS
+            .iter()
+            .any(|p| std::path::Path::new(p).exists())
+}
+
+/// Copy given text into the system clipboard
+pub(crate) fn copy(text: &str) -> InternalResult<()> {
+    if DISABLED.load(Ordering::Relaxed) {
+        return Err(InternalError::IO("Clipboard disabled".into()));
+    }
+
+    let mut clipboard =
+        Clipboard::new().map_err(|e| InternalError::IO(format!("Clipboard unavailable: {e}")))?;
+
+    clipboard
+        .set_text(text)
+        .map_err(|e| InternalError::IO(format!("Failed to copy to clipboard: {e}")))?;
+
+    let secs = CLEAR_AFTER_SECS.load(Ordering::Relaxed);
+
+    if secs > 0 {
+        let copied = Zeroizing::new(text.to_string());
+
+        // dies w/ the process, which may exit before
+        std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_secs(secs));
+
+            let current = Clipboard::new()
+                .and_then(|mut c| c.get_text())
+                .map(Zeroizing::new);
+
+            if current.is_ok_and(|c| c == copied) {
+                let _ = clear();
+            }
+        });
+    }
+
+    Ok(())
+}
+
+/// Empty the system clipboard, e.g. of a secret copied before the screen
+/// locked
+pub(crate) fn clear() -> InternalResult<()> {
+    if DISABLED.load(Ordering::Relaxed) {
+        return Ok(());
+    }
+
+    Clipboard::new()
+        .and_then(|mut clipboard| clipboard.clear())
+        .map_err(|e| InternalError::IO(format!("Failed to clear the clipboard: {e}")))
+}
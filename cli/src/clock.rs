@@ -0,0 +1,100 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const SECS_PER_DAY: u64 = 86_400;
+
+/// Current unix timestamp in seconds
+pub(crate) fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// Format unix timestamp as `YYYY-MM-DD HH:MM UTC`
+pub(crate) fn format_timestamp(ts: u64) -> String {
+    let (y, m, d) = civil_from_days(ts / SECS_PER_DAY);
+    let secs = ts % SECS_PER_DAY;
+
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02} UTC",
+        y,
+        m,
+        d,
+        secs / 3600,
+        (secs % 3600) / 60
+    )
+}
+
+/// Format unix timestamp as ASN.1 `UTCTime`, i.e. `YYMMDDHHMMSSZ`
+pub(crate) fn format_utc_time(ts: u64) -> String {
+    let (y, m, d) = civil_from_days(ts / SECS_PER_DAY);
+    let secs = ts % SECS_PER_DAY;
+
+    format!(
+        "{:02}{:02}{:02}{:02}{:02}{:02}Z",
+        y % 100,
+        m,
+        d,
+        secs / 3600,
+        (secs % 3600) / 60,
+        secs % 60
+    )
+}
+
+/// Days since unix epoch for today (UTC)
+pub(crate) fn today() -> u64 {
+    now() / SECS_PER_DAY
+}
+
+/// Parse a `YYYY-MM-DD` date into days since unix epoch
+pub(crate) fn parse_date(s: &str) -> Option<u64> {
+    let mut parts = s.trim().splitn(3, '-');
+
+    let y: u64 = parts.next()?.parse().ok()?;
+    let m: u64 = parts.next()?.parse().ok()?;
+    let d: u64 = parts.next()?.parse().ok()?;
+
+    if !(1970..=9999).contains(&y) || !(1..=12).contains(&m) || !(1..=31).contains(&d) {
+        return None;
+    }
+
+    let days = days_from_civil(y, m, d);
+
+    // reject overflowing days like `2024-02-31`
+    if civil_from_days(days) != (y, m, d) {
+        return None;
+    }
+
+    Some(days)
+}
+
+/// Convert a (year, month, day) triple into days since unix epoch
+///
+/// NOTE: Howard Hinnant's `days_from_civil` algorithm
+fn days_from_civil(y: u64, m: u64, d: u64) -> u64 {
+    let y = if m <= 2 { y - 1 } else { y };
+    let era = y / 400;
+    let yoe = y - era * 400;
+    let mp = if m > 2 { m - 3 } else { m + 9 };
+    let doy = (153 * mp + 2) / 5 + d - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+
+    era * 146_097 + doe - 719_468
+}
+
+/// Convert days since unix epoch into a (year, month, day) triple
+///
+/// NOTE: Howard Hinnant's `civil_from_days` algorithm
+fn civil_from_days(days: u64) -> (u64, u64, u64) {
+    let z = days + 719_468;
+    let era = z / 146_097;
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let d = doy - (153 * mp + 2) / 5 + 1;
+    let m = if mp < 10 { mp + 3 } else { mp - 9 };
+    let y = yoe + era * 400 + u64::from(m <= 2);
+
+    (y, m, d)
+}
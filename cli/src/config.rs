@@ -0,0 +1,652 @@
+use crate::{
+    generator::GeneratorPolicy,
+    secret::SecretString,
+    types::{InternalError, InternalResult},
+};
+use serde::Deserialize;
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+const CONFIG_PATH: &str = "config.toml";
+/// Config file used instead of the user's one
+pub(crate) const CONFIG_ENV: &str = "VUOTO_CONFIG";
+
+/// Env vars overriding config keys, their values parsed as TOML or taken
+/// as strings if they aren't, e.g. `VUOTO_THEME=plain`
+const ENV_OVERRIDES: &[(&str, &str)] = &[
+    ("VUOTO_DEFAULT_VAULT", "default_vault"),
+    ("VUOTO_THEME", "theme"),
+    ("VUOTO_CLIPBOARD_CLEAR_SECS", "clipboard.clear_after_secs"),
+    ("VUOTO_LOCK_AFTER_SECS", "session.lock_after_secs"),
+    ("VUOTO_GENERATOR_LENGTH", "generator.length"),
+    ("VUOTO_GENERATOR_CHARSET", "generator.charset"),
+];
+
+/// Templates offered on entry creation, custom ones can't reuse these names
+pub(crate) const BUILTIN_TEMPLATES: &[&str] = &["Login", "Recovery codes", "License"];
+
+/// User configuration, loaded once at startup from `VUOTO_CONFIG`, else
+/// `~/.config/vuoto/config.toml`, else `config.toml` in the app dir, w/
+/// [`ENV_OVERRIDES`] applied on top
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct Config {
+    /// app dir holding vaults, the platform's data dir by default,
+    /// `VUOTO_DATA_DIR` wins, see [`crate::get_app_dir`]
+    pub data_dir: Option<PathBuf>,
+    /// vault of commands w/o --vault, unless `vuoto vault default` set one
+    pub default_vault: Option<String>,
+    pub theme: Theme,
+    pub clipboard: ClipboardConfig,
+    /// passwords generated w/o a template's own policy, e.g. by
+    /// `vuoto add --generate`
+    pub generator: GeneratorPolicy,
+    pub prompt: PromptConfig,
+    pub cache: CacheConfig,
+    pub history: HistoryConfig,
+    /// custom entry templates, offered next to the built-in ones
+    pub templates: Vec<TemplateConfig>,
+    pub approval: ApprovalConfig,
+    pub session: SessionConfig,
+    pub key_cache: KeyCacheConfig,
+    pub unlock: UnlockConfig,
+    pub scratch: ScratchConfig,
+    pub yubikey: YubikeyConfig,
+    pub fido2: Fido2Config,
+    /// keyfiles needed next to the master password, by vault name, e.g.
+    /// `work = "/media/usb/work.key"`
+    pub keyfiles: HashMap<String, PathBuf>,
+    /// keyfile given w/ --keyfile, used for any vault
+    #[serde(skip)]
+    pub keyfile: Option<PathBuf>,
+    /// master password given up front, see [`crate::prompt::given_password`]
+    #[serde(skip)]
+    pub master_password: Option<SecretString>,
+    /// vault of commands w/o their own --vault, see [`crate::given_vault`]
+    #[serde(skip)]
+    pub vault: Option<String>,
+}
+
+/// Colors of prompts, `NO_COLOR` always turns them off
+#[derive(Debug, Default, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum Theme {
+    #[default]
+    Default,
+    /// darker colors, readable on light backgrounds
+    Light,
+    /// no colors at all
+    Plain,
+}
+
+/// Secrets copied to the system clipboard
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct ClipboardConfig {
+    /// seconds after which a copied secret is cleared, if it's still there
+    /// and vuoto still runs, `0` never clears
+    pub clear_after_secs: u64,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct PromptConfig {
+    /// optional fields never prompted for on entry creation,
+    /// e.g. `["url", "username"]`
+    pub skip_fields: Vec<String>,
+    /// only ask for name and password when creating entries
+    pub minimal: bool,
+}
+
+/// TurboCache tuning, vaults may override the capacity
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct CacheConfig {
+    /// capacity for vaults which don't have their own
+    pub capacity: u32,
+    /// grow a vault's cache once entries fill this share of its capacity
+    pub grow_at_percent: u8,
+}
+
+/// Where vault history events are mirrored to, in addition to the
+/// per-vault history file
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct HistoryConfig {
+    /// append every event to this JSON lines file
+    pub jsonl_path: Option<PathBuf>,
+    /// send every event to the local syslog daemon (Unix only)
+    pub syslog: bool,
+}
+
+/// When non-interactive commands (`entry inspect`, `export`, ...) need a
+/// confirmation on the terminal before releasing secrets
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct ApprovalConfig {
+    /// vaults whose secrets always need approval
+    pub vaults: Vec<String>,
+    /// break-glass entries need approval in every vault
+    pub break_glass: bool,
+}
+
+/// Interactive sessions and the agent, after unlocking a vault
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct SessionConfig {
+    /// seconds w/o use after which vault keys are dropped and the master
+    /// password asked again, `0` never locks
+    pub lock_after_secs: u64,
+    /// also lock, and clear the clipboard, once the screen locks or the
+    /// machine goes to sleep
+    pub lock_on_sleep: bool,
+}
+
+/// Where vault keys may be kept once unlocked, and for how long
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct KeyCacheConfig {
+    pub policy: CachePolicy,
+    /// seconds a key stays in the agent after `vuoto unlock`, used or not,
+    /// `0` until it locks otherwise
+    pub agent_ttl_secs: u64,
+}
+
+/// Places vault keys may be kept in, each allowing the ones before
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum CachePolicy {
+    /// only in the process which unlocked the vault, interactive sessions
+    /// keep their key to themselves
+    Never,
+    /// interactive sessions may keep the key sealing theirs in the agent
+    Session,
+    /// `vuoto unlock` may keep keys in the agent
+    Agent,
+    /// `vuoto keychain add` may keep keys in the OS keychain
+    #[default]
+    Keychain,
+}
+
+/// Guards against guessing master passwords, wrong ones are always
+/// slowed down exponentially after the first few
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct UnlockConfig {
+    /// permanently delete a vault and all of its entries after this many
+    /// wrong master passwords in a row, `0` never does
+    pub wipe_after_failures: u32,
+    /// also accept the master password w/ trailing whitespace, the first
+    /// letter's case flipped or Caps Lock on, see
+    /// [`crate::secret::SecretString::typo_variants`]
+    ///
+    /// NOTE: each attempt then tests up to 4 passwords, so guessing through
+    /// vuoto gets that much faster and every wrong attempt takes up to 4
+    /// times as long, offline attacks on the data dir are unaffected
+    pub typo_tolerant: bool,
+}
+
+/// Scratchpads of `vuoto scratch`
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct ScratchConfig {
+    /// seconds after its last save a scratchpad is deleted
+    pub ttl_secs: u64,
+}
+
+/// YubiKey answering the challenge of vaults enrolled w/
+/// `vuoto 2fa enroll yubikey`
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct YubikeyConfig {
+    /// `ykchalresp` of yubikey-personalization, or anything taking the same
+    /// arguments and printing the response as hex
+    pub command: PathBuf,
+}
+
+/// FIDO2 security key of vaults enrolled w/ `vuoto 2fa enroll fido2`,
+/// driven through the tools of libfido2
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct Fido2Config {
+    /// device path, e.g. `/dev/hidraw3`, the first one `fido2-token -L`
+    /// lists by default
+    pub device: Option<String>,
+    /// dir of `fido2-cred`, `fido2-assert` and `fido2-token`, found in
+    /// PATH by default
+    pub tools_dir: Option<PathBuf>,
+    /// ask for the key's PIN, not just a touch, fixed for a vault once it
+    /// is enrolled
+    pub pin: bool,
+}
+
+/// User defined entry template, e.g.
+///
+/// ```toml
+/// [[templates]]
+/// name = "Server"
+/// fields = [
+///     { name = "Host" },
+///     { name = "Root password", secret = true, generate = { length = 32 } },
+/// ]
+/// ```
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct TemplateConfig {
+    pub name: String,
+    pub fields: Vec<FieldConfig>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct FieldConfig {
+    pub name: String,
+    /// hidden when the entry is shown, copied to the clipboard instead
+    #[serde(default)]
+    pub secret: bool,
+    /// may be left empty on entry creation
+    #[serde(default)]
+    pub optional: bool,
+    /// offer a generated value on entry creation
+    pub generate: Option<GeneratorPolicy>,
+}
+
+impl Default for ClipboardConfig {
+    fn default() -> Self {
+        Self {
+            clear_after_secs: 45,
+        }
+    }
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        Self {
+            capacity: 512,
+            grow_at_percent: 75,
+        }
+    }
+}
+
+impl Default for SessionConfig {
+    fn default() -> Self {
+        Self {
+            lock_after_secs: 5 * 60,
+            lock_on_sleep: true,
+        }
+    }
+}
+
+impl Default for ScratchConfig {
+    fn default() -> Self {
+        Self { ttl_secs: 60 * 60 }
+    }
+}
+
+impl Default for YubikeyConfig {
+    fn default() -> Self {
+        Self {
+            command: PathBuf::from("ykchalresp"),
+        }
+    }
+}
+
+impl Default for Fido2Config {
+    fn default() -> Self {
+        Self {
+            device: None,
+            tools_dir: None,
+            pin: true,
+        }
+    }
+}
+
+impl Config {
+    /// Load the config in `VUOTO_CONFIG`, which then must exist, else the
+    /// user's `~/.config/vuoto/config.toml`, `None` if there is neither
+    ///
+    /// Loaded before the app dir is known, as it may set the data dir.
+    pub fn load_user() -> InternalResult<Option<Self>> {
+        if let Some(path) = std::env::var_os(CONFIG_ENV).filter(|p| !p.is_empty()) {
+            let path = PathBuf::from(path);
+            let raw = read(&path)?
+                .ok_or_else(|| InternalError::IO(format!("Config {} not found", path.display())))?;
+
+            return Self::parse(&raw, &path, &env_var).map(Some);
+        }
+
+        let Some(path) = user_config_path() else {
+            return Ok(None);
+        };
+
+        read(&path)?
+            .map(|raw| Self::parse(&raw, &path, &env_var))
+            .transpose()
+    }
+
+    /// Load the config in given app dir, used w/o a user config, falling
+    /// back to defaults if missing
+    pub fn load<P: AsRef<Path>>(dir_path: &P) -> InternalResult<Self> {
+        let path = dir_path.as_ref().join(CONFIG_PATH);
+        let config = Self::parse(&read(&path)?.unwrap_or_default(), &path, &env_var)?;
+
+        if config.data_dir.is_some() {
+            return Err(InternalError::IO(format!(
+                "data_dir in {} can't move the dir it's in, set it in {} instead",
+                path.display(),
+                user_config_path().map_or("VUOTO_CONFIG".into(), |p| p.display().to_string())
+            )));
+        }
+
+        Ok(config)
+    }
+
+    /// Parse and validate the config read from `path`, w/ the overrides
+    /// `env` has
+    fn parse(raw: &str, path: &Path, env: &dyn Fn(&str) -> Option<String>) -> InternalResult<Self> {
+        let invalid =
+            |e: String| InternalError::IO(format!("Invalid config at {}: {e}", path.display()));
+
+        let mut table: toml::Table = toml::from_str(raw).map_err(|e| invalid(e.to_string()))?;
+
+        for (name, key) in ENV_OVERRIDES {
+            if let Some(value) = env(name) {
+                set_key(&mut table, key, env_value(&value));
+            }
+        }
+
+        let config: Self = toml::Value::Table(table)
+            .try_into()
+            .map_err(|e: toml::de::Error| invalid(e.to_string()))?;
+
+        config.validate().map_err(invalid)?;
+
+        Ok(config)
+    }
+
+    /// Keyfile to unlock `vault` w/, --keyfile wins over the configured one
+    pub fn keyfile_for(&self, vault: &str) -> Option<&Path> {
+        self.keyfile
+            .as_deref()
+            .or(self.keyfiles.get(vault).map(|p| p.as_path()))
+    }
+
+    /// Checks serde can't express, e.g. duplicate template names
+    fn validate(&self) -> Result<(), String> {
+        for (i, t) in self.templates.iter().enumerate() {
+            if BUILTIN_TEMPLATES.contains(&t.name.as_str()) {
+                return Err(format!("template '{}' shadows a built-in one", t.name));
+            }
+
+            if self.templates[..i].iter().any(|o| o.name == t.name) {
+                return Err(format!("template '{}' is defined twice", t.name));
+            }
+
+            if t.fields.is_empty() {
+                return Err(format!("template '{}' has no fields", t.name));
+            }
+
+            for (j, f) in t.fields.iter().enumerate() {
+                if t.fields[..j].iter().any(|o| o.name == f.name) {
+                    return Err(format!(
+                        "field '{}' of template '{}' is defined twice",
+                        f.name, t.name
+                    ));
+                }
+
+                if f.generate.as_ref().is_some_and(|g| g.length == 0) {
+                    return Err(format!(
+                        "field '{}' of template '{}' generates empty values",
+                        f.name, t.name
+                    ));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Custom template by name
+    pub fn template(&self, name: &str) -> Option<&TemplateConfig> {
+        self.templates.iter().find(|t| t.name == name)
+    }
+}
+
+impl ApprovalConfig {
+    /// Whether releasing a secret from `vault` needs approval
+    pub fn required(&self, vault: &str, break_glass: bool) -> bool {
+        (self.break_glass && break_glass) || self.vaults.iter().any(|v| v == vault)
+    }
+}
+
+impl SessionConfig {
+    /// Idle period before locking, `None` if sessions never lock
+    pub fn lock_after(&self) -> Option<Duration> {
+        (self.lock_after_secs > 0).then(|| Duration::from_secs(self.lock_after_secs))
+    }
+}
+
+impl KeyCacheConfig {
+    /// Fail unless the policy allows keeping keys in `place`
+    pub fn ensure_allows(&self, place: CachePolicy) -> InternalResult<()> {
+        if self.policy >= place {
+            return Ok(());
+        }
+
+        Err(InternalError::IO(format!(
+            "Keeping vault keys in the {} is disabled by [key_cache] policy = \"{}\"",
+            place.name(),
+            self.policy.name()
+        )))
+    }
+
+    /// Lifetime of keys kept by the agent, `None` if they never expire
+    pub fn agent_ttl(&self) -> Option<Duration> {
+        (self.agent_ttl_secs > 0).then(|| Duration::from_secs(self.agent_ttl_secs))
+    }
+}
+
+impl CachePolicy {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Never => "never",
+            Self::Session => "session",
+            Self::Agent => "agent",
+            Self::Keychain => "keychain",
+        }
+    }
+}
+
+impl ClipboardConfig {
+    /// Time a copied secret is left in the clipboard, `None` if forever
+    pub fn clear_after(&self) -> Option<Duration> {
+        (self.clear_after_secs > 0).then(|| Duration::from_secs(self.clear_after_secs))
+    }
+}
+
+impl PromptConfig {
+    /// Whether an optional field should be prompted for
+    pub fn asks(&self, field: &str) -> bool {
+        !self.minimal && !self.skip_fields.iter().any(|f| f == field)
+    }
+}
+
+/// `config.toml` in the user's config dir, see [`crate::dirs::config_dir`]
+fn user_config_path() -> Option<PathBuf> {
+    Some(crate::dirs::config_dir()?.join(CONFIG_PATH))
+}
+
+/// Contents of the file at `path`, `None` if missing
+fn read(path: &Path) -> InternalResult<Option<String>> {
+    match std::fs::read_to_string(path) {
+        Ok(raw) => Ok(Some(raw)),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(err.into()),
+    }
+}
+
+fn env_var(name: &str) -> Option<String> {
+    std::env::var(name).ok().filter(|v| !v.is_empty())
+}
+
+/// Env var value as TOML, e.g. `30` as a number, else as a string
+fn env_value(raw: &str) -> toml::Value {
+    toml::from_str::<toml::Table>(&format!("value = {raw}"))
+        .ok()
+        .and_then(|mut t| t.remove("value"))
+        .unwrap_or_else(|| toml::Value::String(raw.to_string()))
+}
+
+/// Set a dotted `key` of `table`, creating the sections on its way
+fn set_key(table: &mut toml::Table, key: &str, value: toml::Value) {
+    let Some((section, rest)) = key.split_once('.') else {
+        table.insert(key.to_string(), value);
+        return;
+    };
+
+    let section = table
+        .entry(section)
+        .or_insert_with(|| toml::Value::Table(toml::Table::new()));
+
+    // a non-table is left for serde to reject
+    if let toml::Value::Table(section) = section {
+        set_key(section, rest, value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn load(raw: &str) -> InternalResult<Config> {
+        Config::parse(raw, Path::new(CONFIG_PATH), &|_| None)
+    }
+
+    #[test]
+    fn test_load_templates() {
+        let config = load(
+            r#"
+            [[templates]]
+            name = "Server"
+            fields = [
+                { name = "Host" },
+                { name = "Root password", secret = true, generate = { length = 32, charset = "hex" } },
+            ]
+            "#,
+        )
+        .unwrap();
+
+        let template = config.template("Server").unwrap();
+        assert_eq!(template.fields.len(), 2);
+        assert!(!template.fields[0].secret);
+        assert_eq!(template.fields[1].generate.as_ref().unwrap().length, 32);
+    }
+
+    #[test]
+    fn test_reject_invalid_templates() {
+        assert!(load("[[templates]]\nname = \"Login\"\nfields = [{ name = \"a\" }]").is_err());
+        assert!(load("[[templates]]\nname = \"x\"\nfields = []").is_err());
+        assert!(
+            load("[[templates]]\nname = \"x\"\nfields = [{ name = \"a\" }, { name = \"a\" }]")
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_approval_required() {
+        let config = load("[approval]\nvaults = [\"work\"]\nbreak_glass = true").unwrap();
+
+        assert!(config.approval.required("work", false));
+        assert!(config.approval.required("personal", true));
+        assert!(!config.approval.required("personal", false));
+
+        // off by default
+        assert!(!Config::default().approval.required("work", true));
+    }
+
+    #[test]
+    fn test_key_cache_policy() {
+        let config = load("[key_cache]\npolicy = \"session\"\nagent_ttl_secs = 60").unwrap();
+
+        assert!(config.key_cache.ensure_allows(CachePolicy::Session).is_ok());
+        assert!(config.key_cache.ensure_allows(CachePolicy::Agent).is_err());
+        assert_eq!(config.key_cache.agent_ttl(), Some(Duration::from_secs(60)));
+
+        // everything allowed by default
+        let config = Config::default();
+        assert!(
+            config
+                .key_cache
+                .ensure_allows(CachePolicy::Keychain)
+                .is_ok()
+        );
+        assert_eq!(config.key_cache.agent_ttl(), None);
+
+        assert!(load("[key_cache]\npolicy = \"forever\"").is_err());
+    }
+
+    #[test]
+    fn test_keyfile_for() {
+        let mut config = load(
+            "[keyfiles]
+work = \"/media/usb/work.key\"",
+        )
+        .unwrap();
+
+        assert_eq!(
+            config.keyfile_for("work"),
+            Some(Path::new("/media/usb/work.key"))
+        );
+        assert_eq!(config.keyfile_for("personal"), None);
+
+        config.keyfile = Some("other.key".into());
+        assert_eq!(config.keyfile_for("work"), Some(Path::new("other.key")));
+    }
+
+    #[test]
+    fn test_env_overrides() {
+        let env = |name: &str| match name {
+            "VUOTO_THEME" => Some("plain".to_string()),
+            "VUOTO_CLIPBOARD_CLEAR_SECS" => Some("0".to_string()),
+            "VUOTO_GENERATOR_LENGTH" => Some("40".to_string()),
+            _ => None,
+        };
+        let raw = "default_vault = \"work\"\n[clipboard]\nclear_after_secs = 10";
+        let config = Config::parse(raw, Path::new(CONFIG_PATH), &env).unwrap();
+
+        assert_eq!(config.theme, Theme::Plain);
+        assert_eq!(config.clipboard.clear_after(), None);
+        assert_eq!(config.generator.length, 40);
+        assert_eq!(config.default_vault.as_deref(), Some("work"));
+
+        let env = |name: &str| (name == "VUOTO_LOCK_AFTER_SECS").then(|| "soon".to_string());
+        assert!(Config::parse("", Path::new(CONFIG_PATH), &env).is_err());
+    }
+
+    #[test]
+    fn test_data_dir_only_in_user_config() {
+        let temp_dir = TempDir::new().unwrap();
+        std::fs::write(temp_dir.path().join(CONFIG_PATH), "data_dir = \"/srv\"").unwrap();
+
+        assert!(Config::load(&temp_dir.path()).is_err());
+        assert!(load("data_dir = \"/srv\"").unwrap().data_dir.is_some());
+    }
+
+    #[test]
+    fn test_missing_config_is_default() {
+        let temp_dir = TempDir::new().unwrap();
+        let config = Config::load(&temp_dir.path()).unwrap();
+
+        assert!(config.templates.is_empty());
+        assert_eq!(config.cache.capacity, 512);
+        assert_eq!(config.session.lock_after(), Some(Duration::from_secs(300)));
+        assert_eq!(
+            config.clipboard.clear_after(),
+            Some(Duration::from_secs(45))
+        );
+    }
+}
@@ -0,0 +1,2113 @@
+use crate::{
+    entries::{Entry, EntryFormat},
+    rng,
+    types::{InternalError, InternalResult},
+    vaults::{self, Vault},
+};
+use argon2::{Algorithm, Argon2, Params, Version};
+use base64::{Engine as _, engine::general_purpose};
+use chacha20poly1305::{
+    ChaCha20Poly1305, Key, Nonce, XChaCha20Poly1305, XNonce,
+    aead::{Aead, KeyInit, Payload},
+};
+use ring::{hmac, pbkdf2};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    num::NonZeroU32,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+use zeroize::{Zeroize, Zeroizing};
+
+/// App wide header of older versions, shared by all vaults
+const LEGACY_HEADER_PATH: &str = "master.vuoto";
+const HEADER_VERSION: u8 = 1;
+/// First byte of sealed values, never starts a plain entry (JSON, msgpack
+/// map or compressed frame)
+pub(crate) const SEALED_FLAG: u8 = 0xc7;
+/// Layout of sealed values: `[flag][version][nonce][ciphertext + tag]`,
+/// v2 is XChaCha20-Poly1305 w/ the entry key as associated data
+const SEAL_VERSION: u8 = 2;
+const NONCE_SIZE: usize = 24;
+/// v1 was ChaCha20-Poly1305 w/o associated data, still read
+const V1_SEAL_VERSION: u8 = 1;
+const V1_NONCE_SIZE: usize = 12;
+const KEY_SIZE: usize = 32;
+const SALT_SIZE: usize = 16;
+/// Sealed w/ the derived key to tell wrong passwords apart
+const VERIFIER: &[u8] = b"vuoto master key";
+const HEADER_AAD: &[u8] = b"master header";
+/// Context the entry name key is derived under, from the vault key
+const NAMES_CONTEXT: &[u8] = b"vuoto entry names";
+/// Context the integrity manifest key is derived under, from the vault key
+const MANIFEST_CONTEXT: &[u8] = b"vuoto manifest";
+/// Context the scratchpad key is derived under, from the vault key
+const SCRATCH_CONTEXT: &[u8] = b"vuoto scratchpad";
+/// Start of cache keys hashed from entry names, never part of base64
+const HASHED_KEY_PREFIX: char = '#';
+/// Start of passphrase protected exports, followed by a version byte, the
+/// KDF parameters (u32 LE each), the salt and the sealed export
+const PROTECTED_MAGIC: &[u8] = b"vuotoexp";
+const PROTECTED_VERSION: u8 = 1;
+/// Prefix of files encrypted w/ `vuoto encrypt-file`
+const FILE_MAGIC: &[u8] = b"vuotofile";
+const FILE_VERSION: u8 = 2;
+/// Files naming their entry in the clear
+const FILE_V1_VERSION: u8 = 1;
+/// Size of the tag of a file's entry, see [`file_tag`]
+const FILE_TAG_SIZE: usize = 32;
+/// Context decoy data slugs are hashed under, from the vault's slug
+const DECOY_CONTEXT: &[u8] = b"vuoto decoy";
+/// Associated data of a token's response sealed w/ its recovery code
+const RECOVERY_AAD: &[u8] = b"vuoto token recovery";
+/// Associated data of the master key sealed w/ a token's response
+const PASSWORDLESS_AAD: &[u8] = b"vuoto passwordless";
+/// Random bytes of a recovery code, printed as hex in groups of four
+const RECOVERY_SIZE: usize = 16;
+/// Associated data of the master key sealed w/ a recovery kit's key
+const KIT_AAD: &[u8] = b"vuoto recovery kit";
+/// Associated data of a recovery kit's key sealed w/ the master key
+const KIT_ESCROW_AAD: &[u8] = b"vuoto recovery kit escrow";
+/// Random bytes of a recovery kit's key, too many to guess w/o a slow KDF
+const KIT_KEY_SIZE: usize = 20;
+/// Upper bound of [`bench_kdf`], as a multiple of the base iterations
+const MAX_BENCH_FACTOR: u32 = 20;
+/// OWASP recommendation for PBKDF2-HMAC-SHA256
+const PBKDF2_ITERATIONS: u32 = 600_000;
+/// Upper bound of costs read from untrusted files, as a multiple of the
+/// recommended ones
+const MAX_UNTRUSTED_KDF_FACTOR: u32 = 4;
+
+/// Password hashing scheme of a vault
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) enum KdfAlgorithm {
+    #[default]
+    Argon2id,
+    /// for platforms too constrained for Argon2's memory cost, only uses
+    /// `t_cost`
+    Pbkdf2Sha256,
+}
+
+impl KdfAlgorithm {
+    pub const ALL: &[Self] = &[Self::Argon2id, Self::Pbkdf2Sha256];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Argon2id => "argon2id",
+            Self::Pbkdf2Sha256 => "pbkdf2-sha256",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.iter().copied().find(|a| a.name() == name)
+    }
+}
+
+/// KDF cost parameters
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub(crate) struct KdfParams {
+    /// Argon2id for headers written before it was recorded
+    #[serde(default)]
+    pub algorithm: KdfAlgorithm,
+    /// memory in KiB
+    pub m_cost: u32,
+    /// iterations
+    pub t_cost: u32,
+    /// lanes
+    pub p_cost: u32,
+}
+
+/// Current recommendation for Argon2id
+impl Default for KdfParams {
+    fn default() -> Self {
+        Self::recommended(KdfAlgorithm::Argon2id)
+    }
+}
+
+impl std::fmt::Display for KdfParams {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.algorithm {
+            KdfAlgorithm::Argon2id => write!(
+                f,
+                "Argon2id w/ {} MiB, {} iterations and {} lanes",
+                self.m_cost / 1024,
+                self.t_cost,
+                self.p_cost
+            ),
+            KdfAlgorithm::Pbkdf2Sha256 => {
+                write!(f, "PBKDF2-HMAC-SHA256 w/ {} iterations", self.t_cost)
+            }
+        }
+    }
+}
+
+impl KdfParams {
+    /// Current recommendation, vaults w/ weaker parameters are upgraded on
+    /// unlock
+    pub fn recommended(algorithm: KdfAlgorithm) -> Self {
+        match algorithm {
+            KdfAlgorithm::Argon2id => Self {
+                algorithm,
+                m_cost: 64 * 1024,
+                t_cost: 3,
+                p_cost: 1,
+            },
+            KdfAlgorithm::Pbkdf2Sha256 => Self {
+                algorithm,
+                m_cost: 0,
+                t_cost: PBKDF2_ITERATIONS,
+                p_cost: 0,
+            },
+        }
+    }
+
+    /// Whether all costs are within [`MAX_UNTRUSTED_KDF_FACTOR`] times the
+    /// recommended ones, higher costs of a crafted file could exhaust memory
+    /// or hang deriving the key
+    pub fn is_bounded(&self) -> bool {
+        let max = Self::recommended(self.algorithm);
+        let within =
+            |cost: u32, max: u32| cost <= max.max(1).saturating_mul(MAX_UNTRUSTED_KDF_FACTOR);
+
+        within(self.m_cost, max.m_cost)
+            && within(self.t_cost, max.t_cost)
+            && within(self.p_cost, max.p_cost)
+    }
+
+    /// Whether memory or iterations fall short of `other`, parameters of
+    /// different algorithms don't compare
+    pub fn weaker_than(&self, other: &Self) -> bool {
+        self.algorithm == other.algorithm
+            && (self.m_cost < other.m_cost || self.t_cost < other.t_cost)
+    }
+}
+
+/// Raise the iterations of `base` until deriving a key takes about
+/// `target` on this machine, returning the parameters and time taken
+pub(crate) fn bench_kdf(
+    base: KdfParams,
+    target: Duration,
+) -> InternalResult<(KdfParams, Duration)> {
+    let salt: [u8; SALT_SIZE] = random_bytes()?;
+    let measure = |params: &KdfParams| -> InternalResult<Duration> {
+        let start = Instant::now();
+        VaultKey::derive("benchmark", None, &salt, params)?;
+
+        Ok(start.elapsed())
+    };
+
+    let mut params = base;
+    let elapsed = measure(&params)?;
+
+    if elapsed >= target {
+        return Ok((params, elapsed));
+    }
+
+    // time grows linearly w/ iterations
+    let scale = target.as_secs_f64() / elapsed.as_secs_f64().max(f64::EPSILON);
+    let max_t_cost = base.t_cost.saturating_mul(MAX_BENCH_FACTOR);
+    params.t_cost = ((base.t_cost as f64 * scale).ceil() as u32).clamp(base.t_cost, max_t_cost);
+
+    if params.t_cost == base.t_cost {
+        return Ok((params, elapsed));
+    }
+
+    Ok((params, measure(&params)?))
+}
+
+/// Key entries are encrypted w/, derived from the master password, wiped
+/// from memory on drop
+#[derive(Clone)]
+pub(crate) struct VaultKey([u8; KEY_SIZE]);
+
+impl Drop for VaultKey {
+    fn drop(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+impl std::fmt::Debug for VaultKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("VaultKey(..)")
+    }
+}
+
+impl VaultKey {
+    /// Hash of the password, or a keyfile along w/ it, under the given
+    /// KDF
+    pub fn derive(
+        password: &str,
+        keyfile: Option<&[u8]>,
+        salt: &[u8],
+        params: &KdfParams,
+    ) -> InternalResult<Self> {
+        let err = |e: String| InternalError::IO(format!("Key derivation failed: {e}"));
+
+        // hashes of both factors, as KeePass composes them
+        let secret = Zeroizing::new(match keyfile {
+            None => password.as_bytes().to_vec(),
+            Some(keyfile) => [Sha256::digest(password), Sha256::digest(keyfile)].concat(),
+        });
+
+        let mut key = Self([0u8; KEY_SIZE]);
+
+        match params.algorithm {
+            KdfAlgorithm::Argon2id => {
+                let params =
+                    Params::new(params.m_cost, params.t_cost, params.p_cost, Some(KEY_SIZE))
+                        .map_err(|e| err(e.to_string()))?;
+
+                Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+                    .hash_password_into(&secret, salt, &mut key.0)
+                    .map_err(|e| err(e.to_string()))?;
+            }
+            KdfAlgorithm::Pbkdf2Sha256 => {
+                let iterations =
+                    NonZeroU32::new(params.t_cost).ok_or_else(|| err("no iterations".into()))?;
+
+                pbkdf2::derive(
+                    pbkdf2::PBKDF2_HMAC_SHA256,
+                    iterations,
+                    salt,
+                    &secret,
+                    &mut key.0,
+                );
+            }
+        }
+
+        Ok(key)
+    }
+
+    /// Throwaway key, e.g. for the demo vault
+    pub fn random() -> InternalResult<Self> {
+        Ok(Self(random_bytes()?))
+    }
+
+    /// Raw key as base64, e.g. to hand it to the agent
+    pub fn to_base64(&self) -> Zeroizing<String> {
+        Zeroizing::new(general_purpose::STANDARD.encode(self.0))
+    }
+
+    /// SHA-256 of `secret`, separated from other uses of it by `domain`
+    pub fn hashed(domain: &[u8], secret: &[u8]) -> Self {
+        Self(
+            Sha256::new()
+                .chain_update(domain)
+                .chain_update(secret)
+                .finalize()
+                .into(),
+        )
+    }
+
+    pub fn from_base64(encoded: &str) -> InternalResult<Self> {
+        let bytes = Zeroizing::new(
+            general_purpose::STANDARD
+                .decode(encoded)
+                .map_err(|_| InternalError::IO("Invalid vault key".into()))?,
+        );
+
+        if bytes.len() != KEY_SIZE {
+            return Err(InternalError::IO("Invalid vault key".into()));
+        }
+
+        let mut key = Self([0u8; KEY_SIZE]);
+        key.0.copy_from_slice(&bytes);
+
+        Ok(key)
+    }
+}
+
+/// Encrypt `plaintext` under a fresh random nonce, `aad` is authenticated
+/// but not stored, so it must be passed again to open the value
+pub(crate) fn seal(key: &VaultKey, aad: &[u8], plaintext: &[u8]) -> InternalResult<Vec<u8>> {
+    seal_with_nonce(key, &random_bytes()?, aad, plaintext)
+}
+
+fn seal_with_nonce(
+    key: &VaultKey,
+    nonce: &[u8; NONCE_SIZE],
+    aad: &[u8],
+    plaintext: &[u8],
+) -> InternalResult<Vec<u8>> {
+    let payload = Payload {
+        msg: plaintext,
+        aad,
+    };
+
+    let ciphertext = XChaCha20Poly1305::new(Key::from_slice(&key.0))
+        .encrypt(XNonce::from_slice(nonce), payload)
+        .map_err(|_| InternalError::IO("Encryption failed".into()))?;
+
+    let mut out = Vec::with_capacity(2 + NONCE_SIZE + ciphertext.len());
+    out.push(SEALED_FLAG);
+    out.push(SEAL_VERSION);
+    out.extend_from_slice(nonce);
+    out.extend(ciphertext);
+
+    Ok(out)
+}
+
+/// Decrypt a value produced by [`seal`] w/ the same `aad`
+pub(crate) fn open(key: &VaultKey, aad: &[u8], sealed: &[u8]) -> InternalResult<Vec<u8>> {
+    let key = Key::from_slice(&key.0);
+
+    let plaintext = match sealed {
+        [SEALED_FLAG, SEAL_VERSION, body @ ..] if body.len() >= NONCE_SIZE => {
+            let (nonce, msg) = body.split_at(NONCE_SIZE);
+
+            XChaCha20Poly1305::new(key).decrypt(XNonce::from_slice(nonce), Payload { msg, aad })
+        }
+        // not bound to `aad`, see `EntryCodec::decode_legacy`
+        [SEALED_FLAG, V1_SEAL_VERSION, ..] => {
+            return Err(InternalError::Corrupted(
+                "Entry is sealed by an older version, unlock its vault to migrate it".into(),
+            ));
+        }
+        [SEALED_FLAG, version, ..] if *version > SEAL_VERSION => {
+            return Err(InternalError::IO(format!(
+                "Entry is encrypted w/ a newer version ({version}), please update vuoto"
+            )));
+        }
+        _ => return Err(InternalError::Corrupted("Entry is not encrypted".into())),
+    };
+
+    plaintext.map_err(|_| {
+        InternalError::Corrupted("Entry failed authentication, wrong key or tampered".into())
+    })
+}
+
+/// Decrypt a value sealed by older versions, which authenticated no `aad`
+fn open_v1(key: &VaultKey, sealed: &[u8]) -> InternalResult<Vec<u8>> {
+    let [SEALED_FLAG, V1_SEAL_VERSION, body @ ..] = sealed else {
+        return Err(InternalError::Corrupted("Entry is not encrypted".into()));
+    };
+
+    if body.len() < V1_NONCE_SIZE {
+        return Err(InternalError::Corrupted("Entry is not encrypted".into()));
+    }
+
+    let (nonce, msg) = body.split_at(V1_NONCE_SIZE);
+
+    ChaCha20Poly1305::new(Key::from_slice(&key.0))
+        .decrypt(Nonce::from_slice(nonce), msg)
+        .map_err(|_| {
+            InternalError::Corrupted("Entry failed authentication, wrong key or tampered".into())
+        })
+}
+
+/// Like [`open`], also for values sealed by older versions, only for those
+/// w/ a single use under their key, e.g. a header's verifier, which can't
+/// be swapped for another value
+fn open_single_use(key: &VaultKey, aad: &[u8], sealed: &[u8]) -> InternalResult<Vec<u8>> {
+    match is_v1_sealed(sealed) {
+        true => open_v1(key, sealed),
+        false => open(key, aad, sealed),
+    }
+}
+
+pub(crate) fn is_sealed(bytes: &[u8]) -> bool {
+    bytes.first() == Some(&SEALED_FLAG)
+}
+
+/// Whether `bytes` were sealed by older versions, w/o binding them to their
+/// cache key
+pub(crate) fn is_v1_sealed(bytes: &[u8]) -> bool {
+    bytes.starts_with(&[SEALED_FLAG, V1_SEAL_VERSION])
+}
+
+/// Encodes entries in a vault's format and seals them, under cache keys
+/// which don't reveal entry names
+#[derive(Debug, Clone)]
+pub(crate) struct EntryCodec {
+    pub format: EntryFormat,
+    key: VaultKey,
+    names: hmac::Key,
+}
+
+impl EntryCodec {
+    pub fn new(format: EntryFormat, key: VaultKey) -> Self {
+        let derived = hmac::sign(&hmac::Key::new(hmac::HMAC_SHA256, &key.0), NAMES_CONTEXT);
+        let names = hmac::Key::new(hmac::HMAC_SHA256, derived.as_ref());
+
+        Self { format, key, names }
+    }
+
+    /// Key the entries are sealed w/
+    pub fn vault_key(&self) -> &VaultKey {
+        &self.key
+    }
+
+    /// Cache key for an entry name, a keyed hash so names can't be
+    /// enumerated from disk
+    pub fn key_for(&self, name: &str) -> String {
+        let tag = hmac::sign(&self.names, name.as_bytes());
+        format!("{HASHED_KEY_PREFIX}{}", hex(tag.as_ref()))
+    }
+
+    /// Cache key for this entry
+    pub fn key(&self, entry: &Entry) -> String {
+        self.key_for(entry.name())
+    }
+
+    /// Whether `key` was made by [`Self::key_for`] rather than derived from
+    /// the plain name, as older versions did
+    pub fn is_hashed_key(key: &[u8]) -> bool {
+        key.first() == Some(&(HASHED_KEY_PREFIX as u8))
+    }
+
+    /// Same key, other format
+    pub fn with_format(&self, format: EntryFormat) -> Self {
+        Self::new(format, self.key.clone())
+    }
+
+    /// Key sealing the vault's integrity manifest, it can't open entries so
+    /// it may outlive an idle session
+    pub fn manifest_key(&self) -> VaultKey {
+        self.subkey(MANIFEST_CONTEXT)
+    }
+
+    /// Key sealing the vault's scratchpad, apart from its entries
+    pub fn scratch_key(&self) -> VaultKey {
+        self.subkey(SCRATCH_CONTEXT)
+    }
+
+    fn subkey(&self, context: &[u8]) -> VaultKey {
+        let tag = hmac::sign(&hmac::Key::new(hmac::HMAC_SHA256, &self.key.0), context);
+
+        let mut key = VaultKey([0u8; KEY_SIZE]);
+        key.0.copy_from_slice(tag.as_ref());
+
+        key
+    }
+
+    /// Sealed bytes of `entry`, bound to its cache key
+    pub fn encode(&self, entry: &Entry) -> InternalResult<Vec<u8>> {
+        let plain = Zeroizing::new(entry.encode(self.format)?);
+
+        seal(&self.key, self.key(entry).as_bytes(), &plain)
+    }
+
+    /// Entry stored under `key`, a value moved to another key fails w/
+    /// [`InternalError::Corrupted`]
+    pub fn decode(&self, key: &[u8], bytes: &[u8]) -> InternalResult<Entry> {
+        Entry::decode(&Zeroizing::new(self.open(key, bytes)?))
+    }
+
+    /// Plain entry bytes of a sealed value stored under `key`
+    pub fn open(&self, key: &[u8], bytes: &[u8]) -> InternalResult<Vec<u8>> {
+        open(&self.key, key, bytes)
+    }
+
+    /// Like [`Self::decode`], also for values sealed by older versions,
+    /// which are only accepted under the key of their own name, as they
+    /// could be moved to any other
+    ///
+    /// NOTE: only for migrating them, see `open_unlocked`
+    pub fn decode_legacy(&self, key: &[u8], bytes: &[u8]) -> InternalResult<Entry> {
+        let plain = match is_v1_sealed(bytes) {
+            true => open_v1(&self.key, bytes)?,
+            false => open(&self.key, key, bytes)?,
+        };
+        let entry = Entry::decode(&Zeroizing::new(plain))?;
+
+        let own_keys = [self.key(&entry), crate::entries::key_for(entry.name())];
+
+        if !own_keys.iter().any(|k| k.as_bytes() == key) {
+            return Err(InternalError::Corrupted(
+                "Entry was moved from another key".into(),
+            ));
+        }
+
+        Ok(entry)
+    }
+}
+
+/// KDF parameters, salt and verifier of a vault's master password, kept
+/// next to its data
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct MasterHeader {
+    version: u8,
+    kdf: KdfParams,
+    /// base64
+    salt: String,
+    /// [`VERIFIER`] sealed w/ the derived key, base64
+    verifier: String,
+    /// key is derived from a keyfile too
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    keyfile: bool,
+    /// parameters picked by `vuoto kdf bench`, applied on next unlock
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    tuned: Option<KdfParams>,
+    /// duress password, opening a decoy set of entries, an unused one if
+    /// none is set so the header never tells, `None` only in older headers
+    #[serde(default)]
+    decoy: Option<DecoySlot>,
+    /// hardware token, a second factor of the master password
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    token: Option<TokenSlot>,
+    /// token's answer to the challenge, never stored
+    #[serde(skip)]
+    response: Option<Response>,
+    /// printed recovery kit, opening the master slot w/o its password
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    kit: Option<KitSlot>,
+}
+
+/// Second wrapped copy of the master key, under the key of a recovery kit,
+/// which is kept sealed w/ the master key to follow it through password
+/// changes
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct KitSlot {
+    /// unix timestamp, printed on the kit
+    created_at: u64,
+    /// master key sealed w/ the kit's key, base64
+    wrapped: String,
+    /// kit's key sealed w/ the master key, base64
+    escrow: String,
+}
+
+/// Hardware token answering a fixed challenge, its response is mixed into
+/// the master key like a keyfile
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct TokenSlot {
+    #[serde(flatten)]
+    device: TokenDevice,
+    /// base64
+    challenge: String,
+    recovery_kdf: KdfParams,
+    /// base64
+    recovery_salt: String,
+    /// the response sealed w/ a key derived from the recovery code, base64
+    recovery: String,
+    /// master key sealed w/ the response, so the token alone unlocks the
+    /// vault, base64
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    passwordless: Option<String>,
+}
+
+/// Kind of token and what it needs to answer the challenge
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub(crate) enum TokenDevice {
+    /// HMAC-SHA1 challenge-response of a YubiKey, in configuration slot 1
+    /// or 2
+    Yubikey { slot: u8 },
+    /// hmac-secret extension of a FIDO2 credential
+    Fido2 {
+        /// base64
+        credential: String,
+        /// user verification (PIN) was required, the secret differs w/o
+        uv: bool,
+    },
+}
+
+impl TokenDevice {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Yubikey { .. } => "YubiKey",
+            Self::Fido2 { .. } => "security key",
+        }
+    }
+}
+
+/// Response of a token, kept only while the header is in use
+struct Response {
+    bytes: Zeroizing<Vec<u8>>,
+    /// restored from the recovery code rather than given by the token
+    recovered: bool,
+}
+
+impl std::fmt::Debug for Response {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Response(..)")
+    }
+}
+
+impl Response {
+    /// Key sealing the master key of passwordless tokens
+    fn key(&self) -> VaultKey {
+        VaultKey::hashed(PASSWORDLESS_AAD, &self.bytes)
+    }
+}
+
+/// Second password of a vault, derived like the master one
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct DecoySlot {
+    kdf: KdfParams,
+    /// base64
+    salt: String,
+    /// base64
+    verifier: String,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    keyfile: bool,
+}
+
+impl DecoySlot {
+    /// Slot no password opens, w/ a verifier sealed under a random key, so
+    /// it reads like a set one
+    fn unused(kdf: KdfParams, keyfile: bool) -> InternalResult<Self> {
+        let salt: [u8; SALT_SIZE] = random_bytes()?;
+        let verifier = seal(&VaultKey::random()?, HEADER_AAD, VERIFIER)?;
+
+        Ok(Self {
+            kdf,
+            salt: general_purpose::STANDARD.encode(salt),
+            verifier: general_purpose::STANDARD.encode(verifier),
+            keyfile,
+        })
+    }
+}
+
+/// Which password of a vault unlocked it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum Slot {
+    /// master password, opening the real entries
+    Master,
+    /// duress password, opening the decoy entries
+    Decoy,
+}
+
+impl Slot {
+    /// `vault` w/ the slug this slot's data lives under, decoy data gets a
+    /// slug hashed from the real one, shaped like any other vault's
+    pub fn data_vault(self, vault: &Vault) -> Vault {
+        match self {
+            Self::Master => vault.clone(),
+            Self::Decoy => {
+                let hash = Sha256::digest([DECOY_CONTEXT, vault.slug.as_bytes()].concat());
+                Vault {
+                    slug: hex(&hash[..vaults::SLUG_SIZE / 2]),
+                    ..vault.clone()
+                }
+            }
+        }
+    }
+}
+
+/// Salt and verifier (base64) of a fresh key derived from `password`
+fn new_slot(
+    password: &str,
+    keyfile: Option<&[u8]>,
+    kdf: &KdfParams,
+) -> InternalResult<(String, String, VaultKey)> {
+    let salt: [u8; SALT_SIZE] = random_bytes()?;
+    let key = VaultKey::derive(password, keyfile, &salt, kdf)?;
+    let verifier = seal(&key, HEADER_AAD, VERIFIER)?;
+
+    Ok((
+        general_purpose::STANDARD.encode(salt),
+        general_purpose::STANDARD.encode(verifier),
+        key,
+    ))
+}
+
+/// Key of a slot made by [`new_slot`], `None` if `password` is wrong
+fn open_slot(
+    password: &str,
+    keyfile: Option<&[u8]>,
+    kdf: &KdfParams,
+    salt: &str,
+    verifier: &str,
+) -> InternalResult<Option<VaultKey>> {
+    let invalid = || InternalError::IO("Invalid master header".into());
+
+    let salt = general_purpose::STANDARD
+        .decode(salt)
+        .map_err(|_| invalid())?;
+    let verifier = general_purpose::STANDARD
+        .decode(verifier)
+        .map_err(|_| invalid())?;
+
+    let key = VaultKey::derive(password, keyfile, &salt, kdf)?;
+
+    match open_single_use(&key, HEADER_AAD, &verifier) {
+        Ok(v) if v == VERIFIER => Ok(Some(key)),
+        _ => Ok(None),
+    }
+}
+
+impl MasterHeader {
+    pub fn exists<P: AsRef<Path>>(home_dir: &P, vault: &Vault) -> bool {
+        header_path(home_dir, vault).exists()
+    }
+
+    /// Set up the vault's master password, and keyfile if given, returning
+    /// the derived key
+    pub fn create<P: AsRef<Path>>(
+        home_dir: &P,
+        vault: &Vault,
+        password: &str,
+        keyfile: Option<&[u8]>,
+        kdf: KdfParams,
+    ) -> InternalResult<VaultKey> {
+        let (header, key) = Self::new(password, keyfile, kdf)?;
+        header.save(home_dir, vault)?;
+
+        Ok(key)
+    }
+
+    /// Header for `password` (and `keyfile`) under a fresh salt, along w/
+    /// the derived key
+    pub fn new(
+        password: &str,
+        keyfile: Option<&[u8]>,
+        kdf: KdfParams,
+    ) -> InternalResult<(Self, VaultKey)> {
+        let (salt, verifier, key) = new_slot(password, keyfile, &kdf)?;
+
+        let header = Self {
+            version: HEADER_VERSION,
+            kdf,
+            salt,
+            verifier,
+            keyfile: keyfile.is_some(),
+            tuned: None,
+            decoy: Some(DecoySlot::unused(kdf, keyfile.is_some())?),
+            token: None,
+            response: None,
+            kit: None,
+        };
+
+        Ok((header, key))
+    }
+
+    /// Replace the password (and keyfile, KDF) of `slot` w/ a fresh salt,
+    /// keeping the other one, returning the new key
+    pub fn rewrap(
+        &mut self,
+        slot: Slot,
+        password: &str,
+        keyfile: Option<&[u8]>,
+        kdf: KdfParams,
+    ) -> InternalResult<VaultKey> {
+        let (salt, verifier, key) = match slot {
+            Slot::Master => new_slot(
+                password,
+                self.master_factor(keyfile)?.as_deref().map(Vec::as_slice),
+                &kdf,
+            )?,
+            Slot::Decoy => new_slot(password, keyfile, &kdf)?,
+        };
+
+        match slot {
+            Slot::Master => {
+                self.kdf = kdf;
+                self.salt = salt;
+                self.verifier = verifier;
+                self.keyfile = keyfile.is_some();
+                self.tuned = None;
+
+                // the old master key sealed for the token is stale now
+                if let (Some(token), Some(response)) = (&mut self.token, &self.response)
+                    && token.passwordless.is_some()
+                {
+                    let sealed = seal(
+                        &response.key(),
+                        PASSWORDLESS_AAD,
+                        key.to_base64().as_bytes(),
+                    )?;
+                    token.passwordless = Some(general_purpose::STANDARD.encode(sealed));
+                }
+            }
+            Slot::Decoy => {
+                self.decoy = Some(DecoySlot {
+                    kdf,
+                    salt,
+                    verifier,
+                    keyfile: keyfile.is_some(),
+                });
+            }
+        }
+
+        Ok(key)
+    }
+
+    /// Write the header, replacing the vault's current one
+    pub fn save<P: AsRef<Path>>(&self, home_dir: &P, vault: &Vault) -> InternalResult<()> {
+        let raw = self.encode()?;
+
+        // write + rename, so a crash never leaves a half written header
+        let path = header_path(home_dir, vault);
+        let tmp = path.with_extension("tmp");
+        std::fs::write(&tmp, raw)?;
+        std::fs::rename(&tmp, &path)?;
+
+        Ok(())
+    }
+
+    /// Header as saved, for writing it along w/ other files, see
+    /// [`crate::cache::rewrite`]
+    pub fn encode(&self) -> InternalResult<Vec<u8>> {
+        serde_json::to_vec_pretty(self)
+            .map_err(|e| InternalError::IO(format!("Failed to encode master header: {e}")))
+    }
+
+    pub fn kdf(&self, slot: Slot) -> KdfParams {
+        match (slot, &self.decoy) {
+            (Slot::Decoy, Some(decoy)) => decoy.kdf,
+            _ => self.kdf,
+        }
+    }
+
+    pub fn requires_keyfile(&self) -> bool {
+        self.keyfile
+    }
+
+    /// Parameters the key should be derived w/, benchmarked ones if stronger
+    /// than the default
+    pub fn recommended_kdf(&self) -> KdfParams {
+        let recommended = KdfParams::recommended(self.kdf.algorithm);
+
+        match self.tuned {
+            Some(tuned)
+                if tuned.algorithm == recommended.algorithm && !tuned.weaker_than(&recommended) =>
+            {
+                tuned
+            }
+            _ => recommended,
+        }
+    }
+
+    /// Remember benchmarked parameters, see [`Self::recommended_kdf`]
+    pub fn set_tuned(&mut self, kdf: KdfParams) {
+        self.tuned = Some(kdf);
+    }
+
+    /// Whether the key should be re-derived w/ [`Self::recommended_kdf`]
+    pub fn needs_upgrade(&self) -> bool {
+        self.kdf.weaker_than(&self.recommended_kdf())
+    }
+
+    pub fn load<P: AsRef<Path>>(home_dir: &P, vault: &Vault) -> InternalResult<Self> {
+        // an interrupted rekey may still have to write the header
+        for slot in [Slot::Master, Slot::Decoy] {
+            crate::cache::recover(&home_dir.as_ref().join(slot.data_vault(vault).slug))?;
+        }
+
+        let path = header_path(home_dir, vault);
+        let raw = std::fs::read(&path)?;
+
+        let mut header: Self = serde_json::from_slice(&raw).map_err(|e| {
+            InternalError::IO(format!("Invalid master header {}: {e}", path.display()))
+        })?;
+
+        if header.version > HEADER_VERSION {
+            return Err(InternalError::IO(format!(
+                "Master header version {} is newer than supported, please update vuoto",
+                header.version
+            )));
+        }
+
+        // older headers only had the slot once a decoy password was set
+        if header.decoy.is_none() {
+            header.decoy = Some(DecoySlot::unused(header.kdf, header.keyfile)?);
+            header.save(home_dir, vault)?;
+        }
+
+        Ok(header)
+    }
+
+    /// Derive the key from `password` and `keyfile`, trying the master
+    /// password first, `None` if neither slot matches
+    pub fn unlock(
+        &self,
+        password: &str,
+        keyfile: Option<&[u8]>,
+    ) -> InternalResult<Option<(VaultKey, Slot)>> {
+        if self.keyfile && keyfile.is_none() {
+            return Err(InternalError::IO(
+                "Vault requires a keyfile next to the master password".into(),
+            ));
+        }
+
+        // both slots are always derived, so the time it takes never tells
+        // which password was typed
+        let master = if self.token.is_none() || self.response.is_some() {
+            let master = self.master_factor(keyfile.filter(|_| self.keyfile))?;
+
+            open_slot(
+                password,
+                master.as_deref().map(Vec::as_slice),
+                &self.kdf,
+                &self.salt,
+                &self.verifier,
+            )?
+        } else {
+            // w/o its token only the decoy password can match
+            open_slot(password, None, &self.kdf, &self.salt, &self.verifier)?;
+            None
+        };
+
+        let decoy = match &self.decoy {
+            Some(decoy) => open_slot(
+                password,
+                keyfile.filter(|_| decoy.keyfile),
+                &decoy.kdf,
+                &decoy.salt,
+                &decoy.verifier,
+            )?
+            .filter(|_| !decoy.keyfile || keyfile.is_some()),
+            None => None,
+        };
+
+        Ok(master
+            .map(|key| (key, Slot::Master))
+            .or(decoy.map(|key| (key, Slot::Decoy))))
+    }
+
+    /// Keyfile and token response the master key is derived from, hashed
+    /// together like KeePassXC does, just the keyfile w/o a token
+    fn master_factor(&self, keyfile: Option<&[u8]>) -> InternalResult<Option<Zeroizing<Vec<u8>>>> {
+        if self.token.is_none() {
+            return Ok(keyfile.map(|k| Zeroizing::new(k.to_vec())));
+        }
+
+        let Some(response) = &self.response else {
+            return Err(InternalError::IO(
+                "Vault requires its hardware token next to the master password".into(),
+            ));
+        };
+
+        Ok(Some(Zeroizing::new(
+            [
+                Sha256::digest(keyfile.unwrap_or_default()),
+                Sha256::digest(&*response.bytes),
+            ]
+            .concat(),
+        )))
+    }
+
+    /// Token and challenge the master key needs the response to
+    pub fn token_challenge(&self) -> InternalResult<Option<(TokenDevice, Vec<u8>)>> {
+        let Some(token) = &self.token else {
+            return Ok(None);
+        };
+
+        let challenge = general_purpose::STANDARD
+            .decode(&token.challenge)
+            .map_err(|_| InternalError::IO("Invalid master header".into()))?;
+
+        Ok(Some((token.device.clone(), challenge)))
+    }
+
+    /// Provide the token's answer to [`Self::token_challenge`], used until
+    /// the header is dropped
+    pub fn set_response(&mut self, response: &[u8]) {
+        self.response = Some(Response {
+            bytes: Zeroizing::new(response.to_vec()),
+            recovered: false,
+        });
+    }
+
+    /// Master key unlocked by the token's response alone, for tokens
+    /// enrolled as passwordless, never w/ the recovery code
+    pub fn passwordless_key(&self) -> Option<VaultKey> {
+        let sealed = self.token.as_ref()?.passwordless.as_ref()?;
+        let response = self.response.as_ref().filter(|r| !r.recovered)?;
+
+        let sealed = general_purpose::STANDARD.decode(sealed).ok()?;
+        let encoded = Zeroizing::new(open(&response.key(), PASSWORDLESS_AAD, &sealed).ok()?);
+        let key = VaultKey::from_base64(std::str::from_utf8(&encoded).ok()?).ok()?;
+
+        self.accepts(&key, Slot::Master).then_some(key)
+    }
+
+    /// Restore the token's response from its recovery code, `false` if the
+    /// code is wrong
+    pub fn recover(&mut self, code: &str) -> InternalResult<bool> {
+        let Some(token) = &self.token else {
+            return Ok(false);
+        };
+
+        let invalid = || InternalError::IO("Invalid master header".into());
+        let salt = general_purpose::STANDARD
+            .decode(&token.recovery_salt)
+            .map_err(|_| invalid())?;
+        let sealed = general_purpose::STANDARD
+            .decode(&token.recovery)
+            .map_err(|_| invalid())?;
+
+        let key = VaultKey::derive(
+            &normalize_recovery_code(code),
+            None,
+            &salt,
+            &token.recovery_kdf,
+        )?;
+
+        match open(&key, RECOVERY_AAD, &sealed) {
+            Ok(response) => {
+                self.response = Some(Response {
+                    bytes: Zeroizing::new(response),
+                    recovered: true,
+                });
+                Ok(true)
+            }
+            Err(_) => Ok(false),
+        }
+    }
+
+    pub fn has_token(&self) -> bool {
+        self.token.is_some()
+    }
+
+    /// Require `device` answering `challenge` w/ `response`, returning a
+    /// fresh recovery code to use in its place, takes effect once the master
+    /// slot is rewrapped, w/ `passwordless` the token alone unlocks it then
+    pub fn set_token(
+        &mut self,
+        device: TokenDevice,
+        challenge: &[u8],
+        response: &[u8],
+        kdf: KdfParams,
+        passwordless: bool,
+    ) -> InternalResult<Zeroizing<String>> {
+        let code = recovery_code(&random_bytes::<RECOVERY_SIZE>()?);
+
+        let salt: [u8; SALT_SIZE] = random_bytes()?;
+        let key = VaultKey::derive(&normalize_recovery_code(&code), None, &salt, &kdf)?;
+
+        self.token = Some(TokenSlot {
+            device,
+            challenge: general_purpose::STANDARD.encode(challenge),
+            recovery_kdf: kdf,
+            recovery_salt: general_purpose::STANDARD.encode(salt),
+            recovery: general_purpose::STANDARD.encode(seal(&key, RECOVERY_AAD, response)?),
+            // filled in by `rewrap`, once the master key is known
+            passwordless: passwordless.then(String::new),
+        });
+        self.set_response(response);
+
+        Ok(code)
+    }
+
+    /// Stop requiring a token, takes effect once the master slot is
+    /// rewrapped
+    pub fn remove_token(&mut self) {
+        self.token = None;
+        self.response = None;
+    }
+
+    /// Replace the recovery kit of the master slot, opened by `key`, w/ a
+    /// fresh one, returning its key to print
+    pub fn set_kit(
+        &mut self,
+        key: &VaultKey,
+        created_at: u64,
+    ) -> InternalResult<Zeroizing<String>> {
+        if !self.accepts(key, Slot::Master) {
+            return Err(InternalError::IO(
+                "Recovery kits only stand in for the master password".into(),
+            ));
+        }
+
+        let code = recovery_code(&random_bytes::<KIT_KEY_SIZE>()?);
+        let kit_key = kit_key(&code);
+
+        self.kit = Some(KitSlot {
+            created_at,
+            wrapped: general_purpose::STANDARD.encode(seal(
+                &kit_key,
+                KIT_AAD,
+                key.to_base64().as_bytes(),
+            )?),
+            escrow: general_purpose::STANDARD.encode(seal(
+                key,
+                KIT_ESCROW_AAD,
+                kit_key.to_base64().as_bytes(),
+            )?),
+        });
+
+        Ok(code)
+    }
+
+    /// When the recovery kit was created, if there is one
+    pub fn kit_created_at(&self) -> Option<u64> {
+        self.kit.as_ref().map(|k| k.created_at)
+    }
+
+    /// Master key opened w/ the key of the recovery kit, `None` if it's
+    /// wrong or there's no kit
+    pub fn open_kit(&self, code: &str) -> InternalResult<Option<VaultKey>> {
+        let Some(kit) = &self.kit else {
+            return Ok(None);
+        };
+
+        let sealed = general_purpose::STANDARD
+            .decode(&kit.wrapped)
+            .map_err(|_| InternalError::IO("Invalid master header".into()))?;
+        let Ok(encoded) = open(&kit_key(code), KIT_AAD, &sealed) else {
+            return Ok(None);
+        };
+        let encoded = Zeroizing::new(encoded);
+        let key = VaultKey::from_base64(std::str::from_utf8(&encoded).unwrap_or_default())?;
+
+        // a kit left behind by an older master key
+        Ok(self.accepts(&key, Slot::Master).then_some(key))
+    }
+
+    /// Seal the master key replacing `old_key` under the recovery kit, so
+    /// the printed kit stays valid
+    pub fn rewrap_kit(&mut self, old_key: &VaultKey, new_key: &VaultKey) -> InternalResult<()> {
+        let Some(kit) = &mut self.kit else {
+            return Ok(());
+        };
+
+        let invalid = || InternalError::IO("Invalid master header".into());
+        let escrow = general_purpose::STANDARD
+            .decode(&kit.escrow)
+            .map_err(|_| invalid())?;
+        let encoded = Zeroizing::new(open(old_key, KIT_ESCROW_AAD, &escrow)?);
+        let kit_key = VaultKey::from_base64(std::str::from_utf8(&encoded).map_err(|_| invalid())?)?;
+
+        kit.wrapped = general_purpose::STANDARD.encode(seal(
+            &kit_key,
+            KIT_AAD,
+            new_key.to_base64().as_bytes(),
+        )?);
+        kit.escrow = general_purpose::STANDARD.encode(seal(
+            new_key,
+            KIT_ESCROW_AAD,
+            kit_key.to_base64().as_bytes(),
+        )?);
+
+        Ok(())
+    }
+
+    /// Whether `key` still opens `slot`, e.g. a cached one after the
+    /// password got changed
+    pub fn accepts(&self, key: &VaultKey, slot: Slot) -> bool {
+        let verifier = match slot {
+            Slot::Master => Some(&self.verifier),
+            Slot::Decoy => self.decoy.as_ref().map(|d| &d.verifier),
+        };
+
+        verifier
+            .and_then(|v| general_purpose::STANDARD.decode(v).ok())
+            .is_some_and(|v| open(key, HEADER_AAD, &v).is_ok_and(|p| p == VERIFIER))
+    }
+}
+
+/// Encrypt an export under a key derived from `passphrase`, everything
+/// needed to decrypt it besides the passphrase is stored alongside
+///
+/// NOTE: the format only has room for Argon2id parameters
+pub(crate) fn protect(data: &[u8], passphrase: &str, kdf: KdfParams) -> InternalResult<Vec<u8>> {
+    if kdf.algorithm != KdfAlgorithm::Argon2id {
+        return Err(InternalError::IO(
+            "Protected exports are always derived w/ Argon2id".into(),
+        ));
+    }
+
+    let salt: [u8; SALT_SIZE] = random_bytes()?;
+    let key = VaultKey::derive(passphrase, None, &salt, &kdf)?;
+
+    let mut out = PROTECTED_MAGIC.to_vec();
+    out.push(PROTECTED_VERSION);
+
+    for cost in [kdf.m_cost, kdf.t_cost, kdf.p_cost] {
+        out.extend_from_slice(&cost.to_le_bytes());
+    }
+
+    out.extend_from_slice(&salt);
+    out.extend(seal(&key, PROTECTED_MAGIC, data)?);
+
+    Ok(out)
+}
+
+pub(crate) fn is_protected(bytes: &[u8]) -> bool {
+    bytes.starts_with(PROTECTED_MAGIC)
+}
+
+/// Decrypt an export produced by [`protect`]
+pub(crate) fn unprotect(bytes: &[u8], passphrase: &str) -> InternalResult<Vec<u8>> {
+    let invalid = || InternalError::IO("Invalid passphrase protected export".into());
+
+    let rest = bytes.strip_prefix(PROTECTED_MAGIC).ok_or_else(invalid)?;
+    let (&version, rest) = rest.split_first().ok_or_else(invalid)?;
+
+    if version > PROTECTED_VERSION {
+        return Err(InternalError::IO(format!(
+            "Export is protected w/ a newer version ({version}), please update vuoto"
+        )));
+    }
+
+    if rest.len() < 12 + SALT_SIZE {
+        return Err(invalid());
+    }
+
+    let (costs, rest) = rest.split_at(12);
+    let (salt, sealed) = rest.split_at(SALT_SIZE);
+
+    let cost = |i: usize| u32::from_le_bytes(costs[i * 4..i * 4 + 4].try_into().unwrap());
+    let kdf = KdfParams {
+        algorithm: KdfAlgorithm::Argon2id,
+        m_cost: cost(0),
+        t_cost: cost(1),
+        p_cost: cost(2),
+    };
+
+    if !kdf.is_bounded() {
+        return Err(InternalError::IO(
+            "Export's key derivation costs are implausibly high, refusing to derive".into(),
+        ));
+    }
+
+    let key = VaultKey::derive(passphrase, None, salt, &kdf)?;
+
+    open_single_use(&key, PROTECTED_MAGIC, sealed)
+        .map_err(|_| InternalError::IO("Wrong passphrase or tampered export".into()))
+}
+
+/// Encrypt a file's `data` under a key derived from `secret`, the random
+/// key of the entry `entry`, which is only stored as a tag so
+/// [`is_file_of`] finds it again
+pub(crate) fn encrypt_file(data: &[u8], entry: &str, secret: &str) -> InternalResult<Vec<u8>> {
+    let salt: [u8; SALT_SIZE] = random_bytes()?;
+
+    let mut out = FILE_MAGIC.to_vec();
+    out.push(FILE_VERSION);
+    out.extend_from_slice(file_tag(secret, &salt, entry).as_ref());
+    out.extend_from_slice(&salt);
+
+    // the header is authenticated along w/ the data
+    let key = file_key(secret, &salt);
+    let sealed = seal(&key, &out, data)?;
+    out.extend(sealed);
+
+    Ok(out)
+}
+
+/// Fail unless `bytes` are a file produced by [`encrypt_file`]
+pub(crate) fn validate_file(bytes: &[u8]) -> InternalResult<()> {
+    EncryptedFile::parse(bytes).map(|_| ())
+}
+
+/// Whether a file produced by [`encrypt_file`] is encrypted w/ the key of
+/// the entry `entry` holding `secret`
+pub(crate) fn is_file_of(bytes: &[u8], entry: &str, secret: &str) -> InternalResult<bool> {
+    let file = EncryptedFile::parse(bytes)?;
+
+    Ok(match file.entry {
+        FileEntry::Named(name) => name == entry,
+        FileEntry::Tagged(tag) => {
+            let key = hmac::Key::new(hmac::HMAC_SHA256, secret.as_bytes());
+            hmac::verify(&key, &[file.salt, entry.as_bytes()].concat(), tag).is_ok()
+        }
+    })
+}
+
+/// Decrypt a file produced by [`encrypt_file`] w/ its entry's `secret`
+pub(crate) fn decrypt_file(bytes: &[u8], secret: &str) -> InternalResult<Vec<u8>> {
+    let file = EncryptedFile::parse(bytes)?;
+
+    open(&file_key(secret, file.salt), file.header, file.sealed).map_err(|_| {
+        InternalError::IO("File failed authentication, its entry changed or it was tampered".into())
+    })
+}
+
+fn file_key(secret: &str, salt: &[u8]) -> VaultKey {
+    VaultKey::hashed(&[FILE_MAGIC, salt].concat(), secret.as_bytes())
+}
+
+/// HMAC of a file's entry name under its key, so the file doesn't tell
+/// which entry it belongs to
+fn file_tag(secret: &str, salt: &[u8], entry: &str) -> hmac::Tag {
+    let key = hmac::Key::new(hmac::HMAC_SHA256, secret.as_bytes());
+    hmac::sign(&key, &[salt, entry.as_bytes()].concat())
+}
+
+/// Entry of a file, by its name in v1 files
+enum FileEntry<'a> {
+    Named(&'a str),
+    Tagged(&'a [u8]),
+}
+
+/// Parts of a file produced by [`encrypt_file`]
+struct EncryptedFile<'a> {
+    /// everything before `sealed`, authenticated
+    header: &'a [u8],
+    entry: FileEntry<'a>,
+    salt: &'a [u8],
+    sealed: &'a [u8],
+}
+
+impl<'a> EncryptedFile<'a> {
+    fn parse(bytes: &'a [u8]) -> InternalResult<Self> {
+        let invalid = || InternalError::IO("Not a file encrypted by vuoto".into());
+
+        let rest = bytes.strip_prefix(FILE_MAGIC).ok_or_else(invalid)?;
+        let (&version, rest) = rest.split_first().ok_or_else(invalid)?;
+
+        if version > FILE_VERSION {
+            return Err(InternalError::IO(format!(
+                "File is encrypted w/ a newer version ({version}), please update vuoto"
+            )));
+        }
+
+        let (entry, rest) = match version {
+            FILE_V1_VERSION => {
+                let (entry_len, rest) = rest.split_first_chunk::<2>().ok_or_else(invalid)?;
+                let entry_len = u16::from_le_bytes(*entry_len) as usize;
+
+                if rest.len() < entry_len {
+                    return Err(invalid());
+                }
+
+                let (entry, rest) = rest.split_at(entry_len);
+                let entry = std::str::from_utf8(entry).map_err(|_| invalid())?;
+
+                (FileEntry::Named(entry), rest)
+            }
+            FILE_VERSION if rest.len() >= FILE_TAG_SIZE => {
+                let (tag, rest) = rest.split_at(FILE_TAG_SIZE);
+                (FileEntry::Tagged(tag), rest)
+            }
+            _ => return Err(invalid()),
+        };
+
+        if rest.len() < SALT_SIZE {
+            return Err(invalid());
+        }
+
+        let (salt, sealed) = rest.split_at(SALT_SIZE);
+
+        Ok(Self {
+            header: &bytes[..bytes.len() - sealed.len()],
+            entry,
+            salt,
+            sealed,
+        })
+    }
+}
+
+impl MasterHeader {
+    pub fn legacy_exists<P: AsRef<Path>>(home_dir: &P) -> bool {
+        home_dir.as_ref().join(LEGACY_HEADER_PATH).exists()
+    }
+
+    /// Give `vault` its own copy of the app wide header, so entries sealed
+    /// under it stay readable w/ the same password
+    pub fn adopt_legacy<P: AsRef<Path>>(home_dir: &P, vault: &Vault) -> InternalResult<()> {
+        let path = header_path(home_dir, vault);
+        let tmp = path.with_extension("tmp");
+
+        std::fs::copy(home_dir.as_ref().join(LEGACY_HEADER_PATH), &tmp)?;
+        std::fs::rename(&tmp, &path)?;
+
+        Ok(())
+    }
+
+    pub fn remove<P: AsRef<Path>>(home_dir: &P, vault: &Vault) -> InternalResult<()> {
+        match std::fs::remove_file(header_path(home_dir, vault)) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+}
+
+fn header_path<P: AsRef<Path>>(home_dir: &P, vault: &Vault) -> PathBuf {
+    home_dir.as_ref().join(header_file(vault))
+}
+
+/// File name of a vault's master header
+pub(crate) fn header_file(vault: &Vault) -> String {
+    format!("{}.master", vault.slug)
+}
+
+/// Random `bytes` as hex in groups of four, e.g. `3f2a-91c0-...`
+fn recovery_code(bytes: &[u8]) -> Zeroizing<String> {
+    Zeroizing::new(
+        hex(bytes)
+            .as_bytes()
+            .chunks(4)
+            .map(|c| String::from_utf8_lossy(c).into_owned())
+            .collect::<Vec<_>>()
+            .join("-"),
+    )
+}
+
+fn kit_key(code: &str) -> VaultKey {
+    VaultKey::hashed(KIT_AAD, normalize_recovery_code(code).as_bytes())
+}
+
+/// Recovery code as typed by the user, w/o dashes, spaces or upper case
+fn normalize_recovery_code(code: &str) -> Zeroizing<String> {
+    Zeroizing::new(
+        code.chars()
+            .filter(|c| !c.is_whitespace() && *c != '-')
+            .map(|c| c.to_ascii_lowercase())
+            .collect(),
+    )
+}
+
+fn random_bytes<const N: usize>() -> InternalResult<[u8; N]> {
+    let mut bytes = [0u8; N];
+    rng::fill(&mut bytes)?;
+
+    Ok(bytes)
+}
+
+pub(crate) fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// Plaintext of the AEAD vectors, from RFC 8439 and draft-irtf-cfrg-xchacha
+const KAT_PLAINTEXT: &[u8] = b"Ladies and Gentlemen of the class of '99: If I could offer you only one tip for the future, sunscreen would be it.";
+const KAT_AAD: &[u8] = &[
+    0x50, 0x51, 0x52, 0x53, 0xc0, 0xc1, 0xc2, 0xc3, 0xc4, 0xc5, 0xc6, 0xc7,
+];
+const KAT_XCHACHA: &str = "bd6d179d3e83d43b9576579493c0e939572a1700252bfaccbed2902c21396cbb731c7f1b0b4aa6440bf3a82f4eda7e39ae64c6708c54c216cb96b72e1213b4522f8c9ba40db5d945b11b69b982c1bb9e3f3fac2bc369488f76b2383565d3fff921f9664c97637da9768812f615c68b13b52ec0875924c1c7987947deafd8780acf49";
+/// w/o aad, as v1 seals never had one
+const KAT_CHACHA: &str = "d31a8d34648e60db7b86afbc53ef7ec2a4aded51296e08fea9e2b5a736ee62d63dbea45e8ca9671282fafb69da92728b1a71de0a9e060b2905d6a5b67ecd3b3692ddbd7f2d778b8c9803aee328091b58fab324e4fad675945585808b4831d7bc3ff4def08e4b7a9de576d26586cec64b61166a23a4681fd59456aea1d29f82477216";
+
+/// Run known-answer tests of every primitive vaults depend on, so a
+/// miscompiled or broken build refuses to touch any data
+pub(crate) fn self_test() -> InternalResult<()> {
+    let fail = |primitive: &str| Err(InternalError::SelfTest(primitive.into()));
+
+    if hex(&Sha256::digest(b"abc"))
+        != "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+    {
+        return fail("SHA-256");
+    }
+
+    // RFC 4231, test case 2
+    let tag = hmac::sign(
+        &hmac::Key::new(hmac::HMAC_SHA256, b"Jefe"),
+        b"what do ya want for nothing?",
+    );
+    if hex(tag.as_ref()) != "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843" {
+        return fail("HMAC-SHA256");
+    }
+
+    let kdfs = [
+        (
+            KdfParams {
+                algorithm: KdfAlgorithm::Argon2id,
+                m_cost: 64,
+                t_cost: 1,
+                p_cost: 1,
+            },
+            "73cba7a192f57cc8935bd573b08cc8e1788b2237989a1b24d9d088520db54696",
+        ),
+        (
+            KdfParams {
+                algorithm: KdfAlgorithm::Pbkdf2Sha256,
+                t_cost: 1000,
+                ..KdfParams::recommended(KdfAlgorithm::Pbkdf2Sha256)
+            },
+            "8514638175a45bc45eb1f22f04ff7d27f4f8be480498c455ff4b494ce8d1e7d2",
+        ),
+    ];
+
+    for (kdf, expected) in kdfs {
+        match VaultKey::derive("password", None, b"0123456789abcdef", &kdf) {
+            Ok(key) if hex(&key.0) == expected => {}
+            _ => return fail(kdf.algorithm.name()),
+        }
+    }
+
+    let mut key = VaultKey([0u8; KEY_SIZE]);
+    for (i, b) in key.0.iter_mut().enumerate() {
+        *b = 0x80 + i as u8;
+    }
+
+    let mut nonce = [0u8; NONCE_SIZE];
+    for (i, b) in nonce.iter_mut().enumerate() {
+        *b = 0x40 + i as u8;
+    }
+
+    let sealed = seal_with_nonce(&key, &nonce, KAT_AAD, KAT_PLAINTEXT)?;
+    if hex(&sealed[2 + NONCE_SIZE..]) != KAT_XCHACHA
+        || open(&key, KAT_AAD, &sealed).ok().as_deref() != Some(KAT_PLAINTEXT)
+    {
+        return fail("XChaCha20-Poly1305");
+    }
+
+    // legacy entries are still opened w/ it
+    let mut v1_nonce = [0u8; V1_NONCE_SIZE];
+    v1_nonce[0] = 0x07;
+    v1_nonce[4..].copy_from_slice(&nonce[..V1_NONCE_SIZE - 4]);
+
+    let ciphertext = ChaCha20Poly1305::new(Key::from_slice(&key.0))
+        .encrypt(Nonce::from_slice(&v1_nonce), KAT_PLAINTEXT)
+        .unwrap_or_default();
+    let sealed = [&[SEALED_FLAG, V1_SEAL_VERSION], &v1_nonce[..], &ciphertext].concat();
+
+    if hex(&ciphertext) != KAT_CHACHA
+        || open_v1(&key, &sealed).ok().as_deref() != Some(KAT_PLAINTEXT)
+    {
+        return fail("ChaCha20-Poly1305");
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        entries::{LoginEntry, Template},
+        testing::{TEST_KDF, vault},
+    };
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_self_test_passes() {
+        self_test().unwrap();
+    }
+
+    #[test]
+    fn test_seal_roundtrip() {
+        let key = VaultKey::random().unwrap();
+        let sealed = seal(&key, b"mail", b"hunter2").unwrap();
+
+        assert!(is_sealed(&sealed));
+        assert!(!sealed.windows(7).any(|w| w == b"hunter2"));
+        assert_eq!(open(&key, b"mail", &sealed).unwrap(), b"hunter2");
+
+        // fresh nonce every time
+        assert_ne!(seal(&key, b"mail", b"hunter2").unwrap(), sealed);
+    }
+
+    #[test]
+    fn test_open_rejects_tampering_and_wrong_keys() {
+        let key = VaultKey::random().unwrap();
+        let mut sealed = seal(&key, b"mail", b"hunter2").unwrap();
+
+        let corrupted =
+            |res: InternalResult<Vec<u8>>| matches!(res, Err(InternalError::Corrupted(_)));
+
+        assert!(corrupted(open(
+            &VaultKey::random().unwrap(),
+            b"mail",
+            &sealed
+        )));
+
+        // swapped between entries
+        assert!(corrupted(open(&key, b"bank", &sealed)));
+
+        let last = sealed.len() - 1;
+        sealed[last] ^= 1;
+        assert!(corrupted(open(&key, b"mail", &sealed)));
+
+        assert!(corrupted(open(&key, b"mail", br#"{"name":"plain"}"#)));
+    }
+
+    #[test]
+    fn test_open_v1() {
+        let key = VaultKey::random().unwrap();
+        let nonce = [7u8; V1_NONCE_SIZE];
+
+        let mut sealed = vec![SEALED_FLAG, V1_SEAL_VERSION];
+        sealed.extend_from_slice(&nonce);
+        sealed.extend(
+            ChaCha20Poly1305::new(Key::from_slice(&key.0))
+                .encrypt(Nonce::from_slice(&nonce), &b"hunter2"[..])
+                .unwrap(),
+        );
+
+        assert_eq!(open_v1(&key, &sealed).unwrap(), b"hunter2");
+        assert!(matches!(
+            open(&key, b"any", &sealed),
+            Err(InternalError::Corrupted(_))
+        ));
+    }
+
+    #[test]
+    fn test_v1_entry_only_under_own_key() {
+        let codec = EntryCodec::new(EntryFormat::Json, VaultKey::random().unwrap());
+        let entry = Entry::new(Template::Login(LoginEntry {
+            name: "mail".into(),
+            password: "hunter2".into(),
+            username: None,
+            url: None,
+        }));
+        let plain = entry.encode(EntryFormat::Json).unwrap();
+        let nonce = [7u8; V1_NONCE_SIZE];
+
+        let mut sealed = vec![SEALED_FLAG, V1_SEAL_VERSION];
+        sealed.extend_from_slice(&nonce);
+        sealed.extend(
+            ChaCha20Poly1305::new(Key::from_slice(&codec.key.0))
+                .encrypt(Nonce::from_slice(&nonce), plain.as_slice())
+                .unwrap(),
+        );
+
+        let legacy_key = crate::entries::key_for("mail");
+        let decoded = codec.decode_legacy(legacy_key.as_bytes(), &sealed).unwrap();
+        assert_eq!(decoded.name(), "mail");
+        assert!(
+            codec
+                .decode_legacy(codec.key(&entry).as_bytes(), &sealed)
+                .is_ok()
+        );
+
+        // moved under another entry's key
+        let bank = crate::entries::key_for("bank");
+        assert!(codec.decode_legacy(bank.as_bytes(), &sealed).is_err());
+        assert!(codec.decode(legacy_key.as_bytes(), &sealed).is_err());
+    }
+
+    #[test]
+    fn test_master_header_unlock() {
+        let temp_dir = TempDir::new().unwrap();
+        let vault = vault();
+        assert!(!MasterHeader::exists(&temp_dir.path(), &vault));
+
+        let key = MasterHeader::create(&temp_dir.path(), &vault, "correct horse", None, TEST_KDF)
+            .unwrap();
+        assert!(MasterHeader::exists(&temp_dir.path(), &vault));
+
+        let header = MasterHeader::load(&temp_dir.path(), &vault).unwrap();
+        assert!(header.unlock("wrong", None).unwrap().is_none());
+
+        let (unlocked, slot) = header.unlock("correct horse", None).unwrap().unwrap();
+        assert_eq!(unlocked.0, key.0);
+        assert_eq!(slot, Slot::Master);
+    }
+
+    #[test]
+    fn test_keyfile_is_required() {
+        let temp_dir = TempDir::new().unwrap();
+        let vault = vault();
+
+        let keyfile: &[u8] = b"random keyfile contents";
+        let key =
+            MasterHeader::create(&temp_dir.path(), &vault, "pw", Some(keyfile), TEST_KDF).unwrap();
+
+        let header = MasterHeader::load(&temp_dir.path(), &vault).unwrap();
+        assert!(header.requires_keyfile());
+
+        assert!(header.unlock("pw", None).is_err());
+        assert!(header.unlock("pw", Some(b"other")).unwrap().is_none());
+        assert!(header.unlock("wrong", Some(keyfile)).unwrap().is_none());
+        assert_eq!(
+            header.unlock("pw", Some(keyfile)).unwrap().unwrap().0.0,
+            key.0
+        );
+    }
+
+    #[test]
+    fn test_decoy_password() {
+        let (mut header, master_key) = MasterHeader::new("real", None, TEST_KDF).unwrap();
+        assert_eq!(header.unlock("", None).unwrap().map(|(_, s)| s), None);
+
+        // a set decoy slot reads just like the unused one
+        let shape = |header: &MasterHeader| {
+            let raw: serde_json::Value = serde_json::from_slice(&header.encode().unwrap()).unwrap();
+            let decoy = raw["decoy"].as_object().unwrap().clone();
+
+            decoy
+                .into_iter()
+                .map(|(k, v)| (k, v.as_str().map(str::len).unwrap_or_default()))
+                .collect::<Vec<_>>()
+        };
+        let unused = shape(&header);
+
+        let decoy_key = header
+            .rewrap(Slot::Decoy, "duress", None, TEST_KDF)
+            .unwrap();
+        assert_ne!(master_key.0, decoy_key.0);
+        assert_eq!(shape(&header), unused);
+
+        let (key, slot) = header.unlock("duress", None).unwrap().unwrap();
+        assert_eq!((key.0, slot), (decoy_key.0, Slot::Decoy));
+
+        // changing the master password keeps the decoy
+        header
+            .rewrap(Slot::Master, "new real", None, TEST_KDF)
+            .unwrap();
+        assert_eq!(
+            header.unlock("duress", None).unwrap().unwrap().1,
+            Slot::Decoy
+        );
+        assert_eq!(
+            header.unlock("new real", None).unwrap().unwrap().1,
+            Slot::Master
+        );
+        assert!(header.unlock("real", None).unwrap().is_none());
+
+        // either password derives both slots, a slot failing to derive
+        // fails the other one too
+        let mut broken = header.decoy.clone().unwrap();
+        broken.kdf.t_cost = 0;
+        let decoy = header.decoy.replace(broken).unwrap();
+        assert!(header.unlock("new real", None).is_err());
+
+        header.decoy = Some(decoy);
+        let kdf = header.kdf;
+        header.kdf.t_cost = 0;
+        assert!(header.unlock("duress", None).is_err());
+        header.kdf = kdf;
+
+        // cached keys go stale w/ their password
+        assert!(!header.accepts(&master_key, Slot::Master));
+        assert!(header.accepts(&decoy_key, Slot::Decoy));
+        assert!(!header.accepts(&decoy_key, Slot::Master));
+        assert_eq!(
+            VaultKey::from_base64(&decoy_key.to_base64()).unwrap().0,
+            decoy_key.0
+        );
+
+        // decoy data lives apart, under a slug like any other
+        let vault = vault();
+        let decoy = Slot::Decoy.data_vault(&vault);
+        assert_ne!(decoy.slug, vault.slug);
+        assert_eq!(decoy.slug.len(), vault.slug.len());
+        assert_eq!(decoy.name, vault.name);
+        assert_eq!(Slot::Master.data_vault(&vault), vault);
+
+        // even for names of v1 vaults
+        let decoy = Slot::Decoy.data_vault(&Vault {
+            slug: "work".into(),
+            ..vault.clone()
+        });
+        assert_eq!(decoy.slug.len(), vaults::SLUG_SIZE);
+        assert!(decoy.slug.bytes().all(|b| b.is_ascii_hexdigit()));
+    }
+
+    #[test]
+    fn test_token_response_is_required() {
+        let temp_dir = TempDir::new().unwrap();
+        let vault = vault();
+
+        let (mut header, _) = MasterHeader::new("pw", None, TEST_KDF).unwrap();
+        header
+            .rewrap(Slot::Decoy, "duress", None, TEST_KDF)
+            .unwrap();
+        let code = header
+            .set_token(
+                TokenDevice::Yubikey { slot: 2 },
+                b"challenge",
+                b"response",
+                TEST_KDF,
+                false,
+            )
+            .unwrap();
+        let key = header.rewrap(Slot::Master, "pw", None, TEST_KDF).unwrap();
+        header.save(&temp_dir.path(), &vault).unwrap();
+
+        // the response is never stored
+        let mut header = MasterHeader::load(&temp_dir.path(), &vault).unwrap();
+        assert_eq!(
+            header.token_challenge().unwrap(),
+            Some((TokenDevice::Yubikey { slot: 2 }, b"challenge".to_vec()))
+        );
+        assert!(header.unlock("pw", None).unwrap().is_none());
+        assert_eq!(
+            header.unlock("duress", None).unwrap().unwrap().1,
+            Slot::Decoy
+        );
+
+        header.set_response(b"other");
+        assert!(header.unlock("pw", None).unwrap().is_none());
+
+        header.set_response(b"response");
+        assert_eq!(header.unlock("pw", None).unwrap().unwrap().0.0, key.0);
+
+        let mut header = MasterHeader::load(&temp_dir.path(), &vault).unwrap();
+        assert!(!header.recover("0000-0000").unwrap());
+        assert!(
+            header
+                .recover(&code.to_uppercase().replace('-', " "))
+                .unwrap()
+        );
+        assert_eq!(header.unlock("pw", None).unwrap().unwrap().0.0, key.0);
+
+        header.remove_token();
+        let key = header.rewrap(Slot::Master, "pw", None, TEST_KDF).unwrap();
+        assert!(header.token_challenge().unwrap().is_none());
+        assert_eq!(header.unlock("pw", None).unwrap().unwrap().0.0, key.0);
+    }
+
+    #[test]
+    fn test_passwordless_token() {
+        let temp_dir = TempDir::new().unwrap();
+        let vault = vault();
+        let device = TokenDevice::Fido2 {
+            credential: "Y3JlZA==".into(),
+            uv: true,
+        };
+
+        let (mut header, _) = MasterHeader::new("pw", None, TEST_KDF).unwrap();
+        let code = header
+            .set_token(device, b"salt", b"secret", TEST_KDF, true)
+            .unwrap();
+        let key = header.rewrap(Slot::Master, "pw", None, TEST_KDF).unwrap();
+        header.save(&temp_dir.path(), &vault).unwrap();
+
+        let mut header = MasterHeader::load(&temp_dir.path(), &vault).unwrap();
+        assert!(header.passwordless_key().is_none());
+
+        header.set_response(b"secret");
+        assert_eq!(header.passwordless_key().unwrap().0, key.0);
+
+        // a new password reseals the key for the token
+        let key = header
+            .rewrap(Slot::Master, "new pw", None, TEST_KDF)
+            .unwrap();
+        assert_eq!(header.passwordless_key().unwrap().0, key.0);
+
+        // the recovery code stands in for the token, not the password
+        let mut header = MasterHeader::load(&temp_dir.path(), &vault).unwrap();
+        assert!(header.recover(&code).unwrap());
+        assert!(header.passwordless_key().is_none());
+    }
+
+    #[test]
+    fn test_recovery_kit_follows_master_key() {
+        let (mut header, key) = MasterHeader::new("pw", None, TEST_KDF).unwrap();
+        assert!(header.open_kit("0000").unwrap().is_none());
+
+        let code = header.set_kit(&key, 42).unwrap();
+        assert_eq!(header.kit_created_at(), Some(42));
+        assert_eq!(header.open_kit(&code).unwrap().unwrap().0, key.0);
+        assert!(header.open_kit("0000-1111").unwrap().is_none());
+
+        // spaces and case don't matter when typed in
+        let typed = code.replace('-', " ").to_uppercase();
+        assert_eq!(header.open_kit(&typed).unwrap().unwrap().0, key.0);
+
+        // stale once the master key changes, until rewrapped
+        let new_key = header
+            .rewrap(Slot::Master, "new pw", None, TEST_KDF)
+            .unwrap();
+        assert!(header.open_kit(&code).unwrap().is_none());
+
+        header.rewrap_kit(&key, &new_key).unwrap();
+        assert_eq!(header.open_kit(&code).unwrap().unwrap().0, new_key.0);
+
+        let decoy_key = header
+            .rewrap(Slot::Decoy, "duress", None, TEST_KDF)
+            .unwrap();
+        assert!(header.set_kit(&decoy_key, 42).is_err());
+    }
+
+    #[test]
+    fn test_vaults_have_independent_keys() {
+        let temp_dir = TempDir::new().unwrap();
+        let work = vault();
+        let personal = Vault {
+            name: "personal".into(),
+            slug: "fedcba9876543210".into(),
+            ..vault()
+        };
+
+        let work_key =
+            MasterHeader::create(&temp_dir.path(), &work, "same", None, TEST_KDF).unwrap();
+        let personal_key =
+            MasterHeader::create(&temp_dir.path(), &personal, "same", None, TEST_KDF).unwrap();
+
+        // same password, different salts
+        assert_ne!(work_key.0, personal_key.0);
+        assert!(
+            open(
+                &personal_key,
+                b"",
+                &seal(&work_key, b"", b"secret").unwrap()
+            )
+            .is_err()
+        );
+
+        let header = MasterHeader::load(&temp_dir.path(), &personal).unwrap();
+        assert_eq!(
+            header.unlock("same", None).unwrap().unwrap().0.0,
+            personal_key.0
+        );
+    }
+
+    #[test]
+    fn test_kdf_upgrade() {
+        let (mut header, _) = MasterHeader::new("pw", None, KdfParams::default()).unwrap();
+        assert!(!header.needs_upgrade());
+
+        let mut tuned = KdfParams::default();
+        tuned.t_cost += 2;
+        header.set_tuned(tuned);
+        assert!(header.needs_upgrade());
+        assert_eq!(header.recommended_kdf(), tuned);
+
+        // benchmarks never lower the default
+        header.set_tuned(TEST_KDF);
+        assert!(!header.needs_upgrade());
+
+        let (weak, _) = MasterHeader::new("pw", None, TEST_KDF).unwrap();
+        assert!(weak.needs_upgrade());
+    }
+
+    #[test]
+    fn test_pbkdf2_unlock() {
+        let kdf = KdfParams {
+            algorithm: KdfAlgorithm::Pbkdf2Sha256,
+            m_cost: 0,
+            t_cost: 1000,
+            p_cost: 0,
+        };
+
+        let (header, key) = MasterHeader::new("pw", None, kdf).unwrap();
+        let raw = serde_json::to_string(&header).unwrap();
+        assert!(raw.contains("\"pbkdf2-sha256\""));
+
+        let header: MasterHeader = serde_json::from_str(&raw).unwrap();
+        assert!(header.unlock("wrong", None).unwrap().is_none());
+        assert_eq!(header.unlock("pw", None).unwrap().unwrap().0.0, key.0);
+
+        // fewer iterations than recommended
+        assert!(header.needs_upgrade());
+        assert_eq!(
+            header.recommended_kdf().algorithm,
+            KdfAlgorithm::Pbkdf2Sha256
+        );
+
+        // same password, other KDF
+        let argon = VaultKey::derive("pw", None, b"0123456789abcdef", &TEST_KDF).unwrap();
+        let pbkdf2 = VaultKey::derive("pw", None, b"0123456789abcdef", &kdf).unwrap();
+        assert_ne!(argon.0, pbkdf2.0);
+    }
+
+    #[test]
+    fn test_legacy_kdf_is_argon2() {
+        let kdf: KdfParams =
+            serde_json::from_str(r#"{"m_cost":65536,"t_cost":3,"p_cost":1}"#).unwrap();
+        assert_eq!(kdf, KdfParams::default());
+    }
+
+    #[test]
+    fn test_older_header_gets_decoy_slot() {
+        let temp_dir = TempDir::new().unwrap();
+        let vault = vault();
+
+        let (mut header, _) = MasterHeader::new("pw", None, TEST_KDF).unwrap();
+        header.decoy = None;
+        header.save(&temp_dir.path(), &vault).unwrap();
+
+        let header = MasterHeader::load(&temp_dir.path(), &vault).unwrap();
+        assert!(header.decoy.is_some());
+        assert!(header.unlock("", None).unwrap().is_none());
+
+        let raw = std::fs::read_to_string(temp_dir.path().join(header_file(&vault))).unwrap();
+        assert!(raw.contains("\"decoy\""));
+    }
+
+    #[test]
+    fn test_bench_kdf() {
+        let (params, _) = bench_kdf(TEST_KDF, Duration::ZERO).unwrap();
+        assert_eq!(params, TEST_KDF);
+
+        let (params, _) = bench_kdf(TEST_KDF, Duration::from_millis(20)).unwrap();
+        assert!(params.t_cost >= TEST_KDF.t_cost);
+        assert!(params.t_cost <= TEST_KDF.t_cost * MAX_BENCH_FACTOR);
+    }
+
+    #[test]
+    fn test_protect_roundtrip() {
+        let protected = protect(b"name,password", "passphrase", TEST_KDF).unwrap();
+
+        assert!(is_protected(&protected));
+        assert!(!is_protected(b"name,password"));
+
+        assert!(unprotect(&protected, "wrong").is_err());
+        assert!(unprotect(&protected[..20], "passphrase").is_err());
+        assert_eq!(
+            unprotect(&protected, "passphrase").unwrap(),
+            b"name,password"
+        );
+    }
+
+    #[test]
+    fn test_unprotect_bounds_costs() {
+        let protected = protect(b"name,password", "passphrase", TEST_KDF).unwrap();
+        let costs = PROTECTED_MAGIC.len() + 1;
+
+        // ~4 TiB of memory, derived at once w/o the bound
+        let mut crafted = protected.clone();
+        crafted[costs..costs + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+        let err = unprotect(&crafted, "passphrase").unwrap_err();
+        assert!(err.to_string().contains("implausibly high"));
+
+        let mut crafted = protected;
+        crafted[costs + 4..costs + 8].copy_from_slice(&1_000_000u32.to_le_bytes());
+        assert!(unprotect(&crafted, "passphrase").is_err());
+
+        assert!(KdfParams::recommended(KdfAlgorithm::Argon2id).is_bounded());
+        assert!(KdfParams::recommended(KdfAlgorithm::Pbkdf2Sha256).is_bounded());
+    }
+
+    #[test]
+    fn test_file_roundtrip() {
+        let encrypted = encrypt_file(b"tax return", "Files", "s3cret").unwrap();
+
+        assert!(is_file_of(&encrypted, "Files", "s3cret").unwrap());
+        assert!(!is_file_of(&encrypted, "files", "s3cret").unwrap());
+        assert!(!is_file_of(&encrypted, "Files", "changed").unwrap());
+        assert_eq!(decrypt_file(&encrypted, "s3cret").unwrap(), b"tax return");
+        assert!(decrypt_file(&encrypted, "changed").is_err());
+
+        // the entry name isn't stored in the clear
+        let name = b"Files";
+        assert!(!encrypted.windows(name.len()).any(|w| w == name));
+
+        // the entry's tag is authenticated too
+        let mut retagged = encrypted.clone();
+        retagged[FILE_MAGIC.len() + 1] ^= 1;
+        assert!(decrypt_file(&retagged, "s3cret").is_err());
+
+        assert!(is_file_of(b"tax return", "Files", "s3cret").is_err());
+        assert!(is_file_of(&encrypted[..FILE_MAGIC.len() + 4], "Files", "s3cret").is_err());
+    }
+
+    #[test]
+    fn test_v1_file_names_its_entry() {
+        let salt = [7u8; SALT_SIZE];
+        let mut v1 = FILE_MAGIC.to_vec();
+        v1.push(FILE_V1_VERSION);
+        v1.extend_from_slice(&5u16.to_le_bytes());
+        v1.extend_from_slice(b"Files");
+        v1.extend_from_slice(&salt);
+        let sealed = seal(&file_key("s3cret", &salt), &v1, b"tax return").unwrap();
+        v1.extend(sealed);
+
+        assert!(is_file_of(&v1, "Files", "other").unwrap());
+        assert!(!is_file_of(&v1, "Bank", "s3cret").unwrap());
+        assert_eq!(decrypt_file(&v1, "s3cret").unwrap(), b"tax return");
+    }
+
+    #[test]
+    fn test_codec_roundtrip() {
+        let codec = EntryCodec::new(EntryFormat::MessagePack, VaultKey::random().unwrap());
+        let entry = Entry::new(Template::Login(LoginEntry {
+            name: "mail".into(),
+            password: "hunter2".into(),
+            username: None,
+            url: None,
+        }));
+
+        let key = codec.key(&entry);
+        let bytes = codec.encode(&entry).unwrap();
+        assert_eq!(codec.decode(key.as_bytes(), &bytes).unwrap().name(), "mail");
+        assert!(codec.decode(b"other", &bytes).is_err());
+
+        let json = codec.with_format(EntryFormat::Json);
+        let bytes = json.encode(&entry).unwrap();
+        assert_eq!(json.open(key.as_bytes(), &bytes).unwrap()[0], b'{');
+    }
+
+    #[test]
+    fn test_keys_hide_names() {
+        let codec = EntryCodec::new(EntryFormat::Json, VaultKey::random().unwrap());
+        let key = codec.key_for("github");
+
+        assert!(EntryCodec::is_hashed_key(key.as_bytes()));
+        assert!(!EntryCodec::is_hashed_key(b"Z2l0aHVi"));
+        assert_eq!(codec.key_for("github"), key);
+        assert_ne!(codec.key_for("gitlab"), key);
+
+        // other vaults hash names differently
+        let other = EntryCodec::new(EntryFormat::Json, VaultKey::random().unwrap());
+        assert_ne!(other.key_for("github"), key);
+    }
+}
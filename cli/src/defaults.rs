@@ -0,0 +1,94 @@
+use crate::types::{InternalError, InternalResult};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+pub(crate) const DEFAULTS_PATH: &str = "defaults.toml";
+
+/// Settings changed by commands, kept in `defaults.toml` in the state dir
+/// apart from the hand-edited `config.toml`
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct Defaults {
+    /// vault used by commands w/o --vault
+    pub vault: Option<String>,
+}
+
+impl Defaults {
+    /// Defaults of the app dir, all unset if it has none
+    pub fn load<P: AsRef<Path>>(home_dir: &P) -> InternalResult<Self> {
+        let path = path(home_dir);
+
+        let raw = match std::fs::read_to_string(&path) {
+            Ok(raw) => raw,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => return Err(e.into()),
+        };
+
+        toml::from_str(&raw)
+            .map_err(|e| InternalError::IO(format!("Invalid {}: {e}", path.display())))
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, home_dir: &P) -> InternalResult<()> {
+        let raw = toml::to_string(self)
+            .map_err(|e| InternalError::IO(format!("Failed to encode defaults: {e}")))?;
+
+        // write + rename, so a crash never leaves a half written file
+        let path = path(home_dir);
+        let tmp = path.with_extension("toml.tmp");
+        std::fs::write(&tmp, raw)?;
+        std::fs::rename(&tmp, &path)?;
+
+        Ok(())
+    }
+
+    /// Follow the default vault being renamed to `new_name`, or unset it
+    /// when deleted w/o one
+    pub fn vault_moved<P: AsRef<Path>>(
+        home_dir: &P,
+        name: &str,
+        new_name: Option<&str>,
+    ) -> InternalResult<()> {
+        let mut defaults = Self::load(home_dir)?;
+
+        if defaults.vault.as_deref() != Some(name) {
+            return Ok(());
+        }
+
+        defaults.vault = new_name.map(str::to_string);
+        defaults.save(home_dir)
+    }
+}
+
+fn path<P: AsRef<Path>>(home_dir: &P) -> PathBuf {
+    crate::dirs::state_dir(home_dir.as_ref()).join(DEFAULTS_PATH)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_default_vault_follows_renames() {
+        let temp_dir = TempDir::new().unwrap();
+
+        assert_eq!(
+            Defaults::load(&temp_dir.path()).unwrap(),
+            Defaults::default()
+        );
+
+        let defaults = Defaults {
+            vault: Some("work".into()),
+        };
+        defaults.save(&temp_dir.path()).unwrap();
+        assert_eq!(Defaults::load(&temp_dir.path()).unwrap(), defaults);
+
+        Defaults::vault_moved(&temp_dir.path(), "home", None).unwrap();
+        Defaults::vault_moved(&temp_dir.path(), "work", Some("job")).unwrap();
+        let vault = Defaults::load(&temp_dir.path()).unwrap().vault;
+        assert_eq!(vault.as_deref(), Some("job"));
+
+        Defaults::vault_moved(&temp_dir.path(), "job", None).unwrap();
+        assert!(Defaults::load(&temp_dir.path()).unwrap().vault.is_none());
+    }
+}
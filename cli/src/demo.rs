@@ -0,0 +1,123 @@
+use crate::{
+    entries::{Entry, LicenseEntry, LoginEntry, RecoveryCodesEntry, Template},
+    types::InternalResult,
+    vaults::{Vault, VaultIndex},
+};
+use std::path::{Path, PathBuf};
+
+const DEMO_VAULT: &str = "Demo";
+
+/// Steps shown before the demo vault is opened
+pub(crate) const TUTORIAL: &[&str] = &[
+    "Pick an entry to view it, e.g. 'Email' shows a login w/ its notes.",
+    "'Backup codes' is a recovery codes entry, try consuming a code.",
+    "'< Organize entries >' pins entries to the top or sets an icon.",
+    "'< Audit vault >' reports issues, the demo license has expired.",
+    "'< Quit >' leaves the demo, nothing you do here is kept.",
+];
+
+/// Throwaway app dir, removed again on drop
+pub(crate) struct DemoDir {
+    path: PathBuf,
+}
+
+impl DemoDir {
+    /// Create a fresh app dir in the temp dir w/ a populated demo vault
+    pub fn create() -> InternalResult<(Self, Vault)> {
+        let path = std::env::temp_dir().join(format!("vuoto_demo_{}", std::process::id()));
+
+        // leftovers of a crashed run w/ the same pid
+        if path.exists() {
+            std::fs::remove_dir_all(&path)?;
+        }
+
+        std::fs::create_dir_all(&path)?;
+        let dir = Self { path };
+
+        let mut index = VaultIndex::open(&dir.path())?;
+        index.add(DEMO_VAULT)?;
+
+        let vault = index
+            .get(DEMO_VAULT)
+            .cloned()
+            .expect("vault was just added");
+
+        Ok((dir, vault))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for DemoDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.path);
+    }
+}
+
+/// One sample entry of each template
+pub(crate) fn sample_entries() -> Vec<Entry> {
+    let mut email = Entry::new(Template::Login(LoginEntry {
+        name: "Email".into(),
+        password: "correct-horse-battery-staple".into(),
+        username: Some("demo@example.com".into()),
+        url: Some("https://mail.example.com".into()),
+    }));
+    email.icon = Some("📧".into());
+    email.set_notes("Recovery phone ends in 42.".into());
+
+    let codes = Entry::new(Template::RecoveryCodes(RecoveryCodesEntry::from_input(
+        "Backup codes".into(),
+        "1111-2222 3333-4444 5555-6666",
+    )));
+
+    let license = Entry::new(Template::License(LicenseEntry {
+        name: "Photo editor".into(),
+        product: "Photo Editor Pro".into(),
+        key: "ABCDE12345FGHIJ67890".into(),
+        purchase_date: Some("2020-01-15".into()),
+        maintenance_until: Some("2021-01-15".into()),
+        machines: vec!["laptop".into()],
+    }));
+
+    vec![email, codes, license]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_samples_cover_every_template() {
+        let samples = sample_entries();
+
+        assert!(
+            samples
+                .iter()
+                .any(|e| matches!(e.template, Template::Login(_)))
+        );
+        assert!(
+            samples
+                .iter()
+                .any(|e| matches!(e.template, Template::RecoveryCodes(_)))
+        );
+        assert!(
+            samples
+                .iter()
+                .any(|e| matches!(e.template, Template::License(_)))
+        );
+    }
+
+    #[test]
+    fn test_demo_dir_is_removed_on_drop() {
+        let (dir, vault) = DemoDir::create().unwrap();
+        let path = dir.path().to_path_buf();
+
+        assert_eq!(vault.name, DEMO_VAULT);
+        assert!(path.join("index.vuoto").exists());
+
+        drop(dir);
+        assert!(!path.exists());
+    }
+}
@@ -0,0 +1,217 @@
+use crate::entries::FieldValue;
+
+/// Number of unchanged lines shown around each change
+const CONTEXT: usize = 3;
+/// Shown instead of secret values, the same for any length
+const MASK: &str = "********";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Op {
+    Keep,
+    Delete,
+    Insert,
+}
+
+/// Render a unified diff between two texts, empty when they are equal
+pub(crate) fn unified(old: &str, new: &str) -> String {
+    let a: Vec<&str> = old.lines().collect();
+    let b: Vec<&str> = new.lines().collect();
+    let ops = line_ops(&a, &b);
+
+    if ops.iter().all(|(op, _, _)| *op == Op::Keep) {
+        return String::new();
+    }
+
+    let mut out = String::from("--- before\n+++ after\n");
+    let mut i = 0;
+
+    while i < ops.len() {
+        if ops[i].0 == Op::Keep {
+            i += 1;
+            continue;
+        }
+
+        // extend hunk until the gap between changes exceeds the context
+        let start = i.saturating_sub(CONTEXT);
+        let mut end = i;
+        let mut keeps = 0;
+
+        while end < ops.len() && keeps <= 2 * CONTEXT {
+            keeps = if ops[end].0 == Op::Keep { keeps + 1 } else { 0 };
+            end += 1;
+        }
+
+        let end = end - keeps.saturating_sub(CONTEXT);
+        let hunk = &ops[start..end];
+
+        let old_len = hunk.iter().filter(|(op, _, _)| *op != Op::Insert).count();
+        let new_len = hunk.iter().filter(|(op, _, _)| *op != Op::Delete).count();
+        let (old_start, new_start) = (hunk[0].1, hunk[0].2);
+
+        out.push_str(&format!(
+            "@@ -{},{} +{},{} @@\n",
+            old_start + usize::from(old_len > 0),
+            old_len,
+            new_start + usize::from(new_len > 0),
+            new_len
+        ));
+
+        for (op, ai, bi) in hunk {
+            match op {
+                Op::Keep => out.push_str(&format!(" {}\n", a[*ai])),
+                Op::Delete => out.push_str(&format!("-{}\n", a[*ai])),
+                Op::Insert => out.push_str(&format!("+{}\n", b[*bi])),
+            }
+        }
+
+        i = end;
+    }
+
+    out
+}
+
+/// Render a field by field comparison of two entries, unchanged fields
+/// prefixed w/ ` `, changed ones w/ `-` and `+`, secrets masked unless
+/// `reveal`
+pub(crate) fn fields(old: &[FieldValue], new: &[FieldValue], reveal: bool) -> String {
+    let mut out = String::new();
+    let mut line = |prefix: char, field: &FieldValue| {
+        let value = match field.secret && !reveal {
+            true => MASK,
+            false => field.value.as_str(),
+        };
+        let mut lines = value.lines();
+
+        out.push_str(&format!(
+            "{prefix} {}: {}\n",
+            field.name,
+            lines.next().unwrap_or_default()
+        ));
+
+        for rest in lines {
+            out.push_str(&format!("{prefix}   {rest}\n"));
+        }
+    };
+
+    for field in old {
+        match new.iter().find(|f| f.name == field.name) {
+            Some(same) if same.value == field.value => line(' ', field),
+            Some(changed) => {
+                line('-', field);
+                line('+', changed);
+            }
+            None => line('-', field),
+        }
+    }
+
+    for field in new.iter().filter(|f| !old.iter().any(|o| o.name == f.name)) {
+        line('+', field);
+    }
+
+    out
+}
+
+/// Line level edit script computed via LCS, each op carries the line
+/// indices into `a` and `b` at that point
+fn line_ops(a: &[&str], b: &[&str]) -> Vec<(Op, usize, usize)> {
+    let (n, m) = (a.len(), b.len());
+    let mut lcs = vec![vec![0usize; m + 1]; n + 1];
+
+    for i in (0..n).rev() {
+        for j in (0..m).rev() {
+            lcs[i][j] = if a[i] == b[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let mut ops = Vec::with_capacity(n + m);
+    let (mut i, mut j) = (0, 0);
+
+    while i < n || j < m {
+        if i < n && j < m && a[i] == b[j] {
+            ops.push((Op::Keep, i, j));
+            i += 1;
+            j += 1;
+        } else if i < n && (j == m || lcs[i + 1][j] >= lcs[i][j + 1]) {
+            ops.push((Op::Delete, i, j));
+            i += 1;
+        } else {
+            ops.push((Op::Insert, i, j));
+            j += 1;
+        }
+    }
+
+    ops
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_equal_texts_have_no_diff() {
+        assert_eq!(unified("a\nb\n", "a\nb\n"), "");
+    }
+
+    #[test]
+    fn test_single_line_change() {
+        let diff = unified("one\ntwo\nthree", "one\n2\nthree");
+
+        assert_eq!(
+            diff,
+            "--- before\n+++ after\n@@ -1,3 +1,3 @@\n one\n-two\n+2\n three\n"
+        );
+    }
+
+    #[test]
+    fn test_distant_changes_split_into_hunks() {
+        let old: Vec<String> = (0..20).map(|i| i.to_string()).collect();
+        let mut new = old.clone();
+        new[1] = "x".into();
+        new[18] = "y".into();
+
+        let diff = unified(&old.join("\n"), &new.join("\n"));
+
+        assert_eq!(diff.matches("@@ -").count(), 2);
+        assert!(diff.contains("-1\n+x\n"));
+        assert!(diff.contains("-18\n+y\n"));
+        assert!(!diff.contains(" 10\n"));
+    }
+
+    #[test]
+    fn test_field_diff_masks_secrets() {
+        let field = |name: &str, value: &str, secret: bool| FieldValue {
+            name: name.into(),
+            value: value.into(),
+            secret,
+        };
+        let old = [
+            field("Username", "me", false),
+            field("Password", "old", true),
+        ];
+        let new = [
+            field("Username", "me", false),
+            field("Password", "new", true),
+            field("URL", "https://a", false),
+        ];
+
+        let diff = fields(&old, &new, false);
+        assert_eq!(
+            diff,
+            "  Username: me\n- Password: ********\n+ Password: ********\n+ URL: https://a\n"
+        );
+        assert!(!diff.contains("old") && !diff.contains("new"));
+
+        let diff = fields(&old, &new, true);
+        assert!(diff.contains("- Password: old\n+ Password: new\n"));
+    }
+
+    #[test]
+    fn test_added_to_empty() {
+        let diff = unified("", "hello");
+        assert!(diff.contains("@@ -0,0 +1,1 @@\n+hello\n"));
+    }
+}
@@ -0,0 +1,227 @@
+use crate::{
+    attempts, defaults,
+    types::{InternalError, InternalResult},
+};
+use std::{
+    path::{Path, PathBuf},
+    sync::OnceLock,
+};
+
+/// Dir holding all of vuoto's files, its config included, instead of the
+/// platform's dirs
+pub(crate) const HOME_ENV: &str = "VUOTO_HOME";
+/// Dir of vuoto in each of the platform's dirs
+const APP_DIR: &str = "vuoto";
+/// Data dir in the home dir of releases before the platform's dirs
+const LEGACY_DIR: &str = "vuoto_cli";
+
+/// Dir of state files, when not kept in the data dir
+static STATE_DIR: OnceLock<PathBuf> = OnceLock::new();
+
+/// Default dirs of the default profile
+#[derive(Debug, PartialEq)]
+pub(crate) struct AppDirs {
+    pub data: PathBuf,
+    /// files which aren't worth a backup, e.g. the default vault and
+    /// counts of wrong master passwords
+    pub state: PathBuf,
+    /// data dir of older releases, moved to `data` if still there
+    pub legacy: Option<PathBuf>,
+}
+
+/// Dir of the user's config file, `VUOTO_HOME` if set
+pub(crate) fn config_dir() -> Option<PathBuf> {
+    if let Some(home) = env_dir(HOME_ENV) {
+        return Some(home);
+    }
+
+    Some(platform_dirs(&env_dir)?.config.join(APP_DIR))
+}
+
+/// `VUOTO_HOME` if set, else the platform's data and state dirs, XDG ones
+/// on Linux, `Application Support` on macOS and `%APPDATA%` on Windows
+///
+/// NOTE: debug builds use a temp dir, so they never touch real vaults
+pub(crate) fn app_dirs() -> InternalResult<AppDirs> {
+    let single = |dir: PathBuf| AppDirs {
+        data: dir.clone(),
+        state: dir,
+        legacy: None,
+    };
+
+    if let Some(home) = env_dir(HOME_ENV) {
+        return Ok(single(home));
+    }
+
+    if cfg!(debug_assertions) {
+        return Ok(single(std::env::temp_dir().join(LEGACY_DIR)));
+    }
+
+    let dirs = platform_dirs(&env_dir)
+        .ok_or_else(|| InternalError::IO("Unable to read home dir".into()))?;
+
+    Ok(AppDirs {
+        data: dirs.data.join(APP_DIR),
+        state: dirs.state.join(APP_DIR),
+        legacy: env_home::env_home_dir().map(|home| home.join(LEGACY_DIR)),
+    })
+}
+
+/// Keep state files in `dir` instead of the data dir
+pub(crate) fn use_state_dir(dir: PathBuf) -> InternalResult<()> {
+    std::fs::create_dir_all(&dir)
+        .map_err(|e| InternalError::IO(format!("Failed to create state dir: {e}")))?;
+
+    let _ = STATE_DIR.set(dir);
+    Ok(())
+}
+
+/// Dir of state files, the data dir `home_dir` unless the platform has one
+/// for them
+pub(crate) fn state_dir(home_dir: &Path) -> PathBuf {
+    STATE_DIR
+        .get()
+        .cloned()
+        .unwrap_or_else(|| home_dir.to_path_buf())
+}
+
+/// Move the data dir of an older release to `data`, its state files to
+/// `state`, unless `data` already exists
+pub(crate) fn migrate(legacy: &Path, data: &Path, state: &Path) -> InternalResult<bool> {
+    if !legacy.is_dir() {
+        return Ok(false);
+    }
+
+    if data.exists() {
+        eprintln!(
+            "[WARN]: {} is no longer used, vaults are in {}",
+            legacy.display(),
+            data.display()
+        );
+        return Ok(false);
+    }
+
+    let failed = |e: std::io::Error| {
+        InternalError::IO(format!(
+            "Failed to move {} to {}: {e}, move it there yourself",
+            legacy.display(),
+            data.display()
+        ))
+    };
+
+    if let Some(parent) = data.parent() {
+        std::fs::create_dir_all(parent).map_err(failed)?;
+    }
+    std::fs::rename(legacy, data).map_err(failed)?;
+
+    if state != data {
+        std::fs::create_dir_all(state)?;
+
+        for file in std::fs::read_dir(data)? {
+            let file = file?;
+            let name = file.file_name();
+
+            if is_state_file(&name.to_string_lossy()) {
+                std::fs::rename(file.path(), state.join(name))?;
+            }
+        }
+    }
+
+    Ok(true)
+}
+
+fn is_state_file(name: &str) -> bool {
+    name == defaults::DEFAULTS_PATH || name.ends_with(attempts::ATTEMPTS_EXT)
+}
+
+/// Base dirs of the platform, w/o vuoto's own
+struct PlatformDirs {
+    config: PathBuf,
+    data: PathBuf,
+    state: PathBuf,
+}
+
+fn platform_dirs(env: &dyn Fn(&str) -> Option<PathBuf>) -> Option<PlatformDirs> {
+    if cfg!(windows) {
+        let app_data = env("APPDATA")?;
+
+        return Some(PlatformDirs {
+            config: app_data.clone(),
+            data: app_data.clone(),
+            state: app_data,
+        });
+    }
+
+    let home = env_home::env_home_dir()?;
+
+    if cfg!(target_os = "macos") {
+        let support = home.join("Library/Application Support");
+
+        return Some(PlatformDirs {
+            config: support.clone(),
+            data: support.clone(),
+            state: support,
+        });
+    }
+
+    Some(PlatformDirs {
+        config: env("XDG_CONFIG_HOME").unwrap_or_else(|| home.join(".config")),
+        data: env("XDG_DATA_HOME").unwrap_or_else(|| home.join(".local/share")),
+        state: env("XDG_STATE_HOME").unwrap_or_else(|| home.join(".local/state")),
+    })
+}
+
+/// Dir in env var `name`, relative ones are ignored as the XDG spec asks
+fn env_dir(name: &str) -> Option<PathBuf> {
+    std::env::var_os(name)
+        .map(PathBuf::from)
+        .filter(|d| d.is_absolute())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_xdg_dirs() {
+        let env = |name: &str| match name {
+            "XDG_DATA_HOME" => Some(PathBuf::from("/xdg/data")),
+            _ => None,
+        };
+        let dirs = platform_dirs(&env).unwrap();
+        let home = env_home::env_home_dir().unwrap();
+
+        assert_eq!(dirs.data, PathBuf::from("/xdg/data"));
+        assert_eq!(dirs.state, home.join(".local/state"));
+        assert_eq!(dirs.config, home.join(".config"));
+    }
+
+    #[test]
+    fn test_migrate_moves_state_apart() {
+        let temp_dir = TempDir::new().unwrap();
+        let legacy = temp_dir.path().join(LEGACY_DIR);
+        let data = temp_dir.path().join("share/vuoto");
+        let state = temp_dir.path().join("state/vuoto");
+
+        assert!(!migrate(&legacy, &data, &state).unwrap());
+
+        std::fs::create_dir(&legacy).unwrap();
+        std::fs::write(legacy.join("index.vuoto"), "").unwrap();
+        std::fs::write(legacy.join("0123456789abcdef.attempts"), "").unwrap();
+        std::fs::write(legacy.join(defaults::DEFAULTS_PATH), "").unwrap();
+
+        assert!(migrate(&legacy, &data, &state).unwrap());
+        assert!(!legacy.exists());
+        assert!(data.join("index.vuoto").exists());
+        assert!(state.join("0123456789abcdef.attempts").exists());
+        assert!(state.join(defaults::DEFAULTS_PATH).exists());
+        assert!(!data.join(defaults::DEFAULTS_PATH).exists());
+
+        // never merged into an existing data dir
+        std::fs::create_dir(&legacy).unwrap();
+        assert!(!migrate(&legacy, &data, &state).unwrap());
+        assert!(legacy.exists());
+    }
+}
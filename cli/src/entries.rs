@@ -0,0 +1,1069 @@
+use crate::{
+    audit, clock,
+    secret::SecretString,
+    types::{InternalError, InternalResult},
+};
+use base64::{Engine as _, engine::general_purpose};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::borrow::Cow;
+
+const KEY_CHUNK_SIZE: usize = 5;
+const HEX_DUMP_WIDTH: usize = 16;
+
+/// Encoded entries above this size get compressed
+const COMPRESS_THRESHOLD: usize = 1024;
+const COMPRESS_LEVEL: i32 = 3;
+/// Upper bound for decompressed entries, guards against zip bombs
+const MAX_DECOMPRESSED_SIZE: usize = 16 * 1024 * 1024;
+
+/// Flag byte prefixing zstd compressed values, never a valid first byte of
+/// JSON or MessagePack (`0xc1` is unused in MessagePack)
+const COMPRESSED_FLAG: u8 = 0xc1;
+
+/// Schema version written into encoded entries
+///
+/// History:
+/// - `0`: bare login entry, written before templates existed
+/// - `1`: tagged w/ `template`, no version field
+/// - `2`: same as `1` plus the `version` field
+pub(crate) const ENTRY_VERSION: u64 = 2;
+
+/// On-disk encoding of entries, chosen per vault
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum EntryFormat {
+    Json,
+    /// compact binary encoding, smaller and faster for big vaults
+    MessagePack,
+}
+
+/// A single vault entry w/ its user facing metadata
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct Entry {
+    #[serde(flatten)]
+    pub template: Template,
+    /// short emoji/icon shown in entry lists
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub icon: Option<String>,
+    /// manual ordering index, pinned entries are listed first
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub position: Option<u32>,
+    /// free-form multi-line notes, not masked
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub notes: Option<String>,
+    /// previous versions of `notes`, oldest first
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub history: Vec<NotesRevision>,
+    /// emergency credential, viewing it requires a logged reason
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub break_glass: bool,
+    /// account is protected by a second factor, set manually or when the
+    /// entry holds a TOTP secret
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub has_2fa: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct NotesRevision {
+    pub notes: String,
+    /// when this version got replaced
+    pub replaced_at: u64,
+}
+
+/// Entry data, tagged w/ the template it was created from
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "template", rename_all = "snake_case")]
+pub(crate) enum Template {
+    Login(LoginEntry),
+    RecoveryCodes(RecoveryCodesEntry),
+    License(LicenseEntry),
+    /// created from a template defined in the user's config
+    Custom(CustomEntry),
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct LoginEntry {
+    pub name: String,
+    pub password: SecretString,
+    pub username: Option<String>,
+    pub url: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct RecoveryCodesEntry {
+    pub name: String,
+    pub codes: Vec<RecoveryCode>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct LicenseEntry {
+    pub name: String,
+    pub product: String,
+    pub key: String,
+    /// `YYYY-MM-DD`
+    pub purchase_date: Option<String>,
+    /// `YYYY-MM-DD`
+    pub maintenance_until: Option<String>,
+    /// free-form notes on where the license is in use
+    pub machines: Vec<String>,
+}
+
+/// Entry of a user defined template, fields are stored along w/ their
+/// secrecy so the entry stays readable if the template is removed
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct CustomEntry {
+    pub name: String,
+    /// name of the template the entry was created from
+    pub kind: String,
+    pub fields: Vec<CustomField>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct CustomField {
+    pub name: String,
+    pub value: String,
+    #[serde(default)]
+    pub secret: bool,
+}
+
+/// Labeled value of an entry, as compared by [`crate::diff::fields`]
+#[derive(Debug, PartialEq)]
+pub(crate) struct FieldValue {
+    pub name: String,
+    pub value: String,
+    pub secret: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct RecoveryCode {
+    pub code: String,
+    pub used_at: Option<u64>,
+}
+
+impl Entry {
+    pub fn new(template: Template) -> Self {
+        Self {
+            template,
+            icon: None,
+            position: None,
+            notes: None,
+            history: Vec::new(),
+            break_glass: false,
+            has_2fa: false,
+        }
+    }
+
+    /// Whether the entry holds a TOTP secret, i.e. a custom field w/ an
+    /// `otpauth://` URI or named like one
+    pub fn has_totp(&self) -> bool {
+        self.totp_secret().is_some()
+    }
+
+    /// Value of the first custom field holding a TOTP secret
+    pub fn totp_secret(&self) -> Option<&str> {
+        let Template::Custom(custom) = &self.template else {
+            return None;
+        };
+
+        custom
+            .fields
+            .iter()
+            .find(|f| {
+                let name = f.name.to_ascii_lowercase();
+
+                !f.value.is_empty()
+                    && (f.value.starts_with("otpauth://") || name.contains("totp") || name == "otp")
+            })
+            .map(|f| f.value.as_str())
+    }
+
+    /// Flag `has_2fa` if a TOTP secret exists, never clears it
+    pub fn detect_2fa(&mut self) {
+        self.has_2fa |= self.has_totp();
+    }
+
+    pub fn name(&self) -> &str {
+        match &self.template {
+            Template::Login(e) => &e.name,
+            Template::RecoveryCodes(e) => &e.name,
+            Template::License(e) => &e.name,
+            Template::Custom(e) => &e.name,
+        }
+    }
+
+    /// Value of `field` (case-insensitive), or w/o one the entry's secret:
+    /// a login's password, a license key, all unused recovery codes (one
+    /// per line) or a custom entry's first secret field
+    pub fn field(&self, field: Option<&str>) -> Option<String> {
+        let lower = field.map(str::to_ascii_lowercase);
+
+        match (&self.template, lower.as_deref()) {
+            (Template::Login(e), None | Some("password")) => Some(e.password.expose().to_string()),
+            (Template::Login(e), Some("username")) => e.username.clone(),
+            (Template::Login(e), Some("url")) => e.url.clone(),
+            (Template::RecoveryCodes(e), None | Some("codes")) => Some(
+                e.codes
+                    .iter()
+                    .filter(|c| c.used_at.is_none())
+                    .map(|c| c.code.as_str())
+                    .collect::<Vec<_>>()
+                    .join("\n"),
+            ),
+            (Template::License(e), None | Some("key")) => Some(e.formatted_key()),
+            (Template::License(e), Some("product")) => Some(e.product.clone()),
+            (Template::License(e), Some("purchase_date")) => e.purchase_date.clone(),
+            (Template::License(e), Some("maintenance_until")) => e.maintenance_until.clone(),
+            (Template::Custom(e), _) => e
+                .fields
+                .iter()
+                .find(|f| match field {
+                    None => f.secret,
+                    Some(name) => f.name.eq_ignore_ascii_case(name),
+                })
+                .map(|f| f.value.clone()),
+            _ => None,
+        }
+    }
+
+    /// Whether [`Entry::field`] of `field` is a secret, as is the entry's
+    /// secret picked w/o one
+    pub fn is_secret_field(&self, field: Option<&str>) -> bool {
+        let Some(field) = field else {
+            return true;
+        };
+
+        match (&self.template, field.to_ascii_lowercase().as_str()) {
+            (_, "otp")
+            | (Template::Login(_), "password")
+            | (Template::RecoveryCodes(_), "codes")
+            | (Template::License(_), "key") => true,
+            (Template::Custom(e), _) => e
+                .fields
+                .iter()
+                .any(|f| f.secret && f.name.eq_ignore_ascii_case(field)),
+            _ => false,
+        }
+    }
+
+    /// All set values w/ their display labels, secrets flagged
+    pub fn field_values(&self) -> Vec<FieldValue> {
+        let field = |name: &str, value: &str, secret: bool| FieldValue {
+            name: name.to_string(),
+            value: value.to_string(),
+            secret,
+        };
+        let mut fields = vec![field("Template", self.kind(), false)];
+
+        match &self.template {
+            Template::Login(e) => {
+                fields.extend(e.username.as_deref().map(|u| field("Username", u, false)));
+                fields.push(field("Password", e.password.expose(), true));
+                fields.extend(e.url.as_deref().map(|u| field("URL", u, false)));
+            }
+            Template::RecoveryCodes(e) => {
+                let codes: Vec<String> = e
+                    .codes
+                    .iter()
+                    .map(|c| match c.used_at {
+                        Some(_) => format!("{} (used)", c.code),
+                        None => c.code.clone(),
+                    })
+                    .collect();
+
+                fields.push(field("Recovery codes", &codes.join("\n"), true));
+            }
+            Template::License(e) => {
+                fields.push(field("Product", &e.product, false));
+                fields.push(field("Key", &e.formatted_key(), true));
+                fields.extend(
+                    e.purchase_date
+                        .as_deref()
+                        .map(|d| field("Purchased", d, false)),
+                );
+                fields.extend(
+                    e.maintenance_until
+                        .as_deref()
+                        .map(|d| field("Maintenance until", d, false)),
+                );
+
+                if !e.machines.is_empty() {
+                    fields.push(field("Assigned machines", &e.machines.join("\n"), false));
+                }
+            }
+            Template::Custom(e) => {
+                for f in &e.fields {
+                    fields.push(field(&f.name, &f.value, f.secret));
+                }
+            }
+        }
+
+        fields.extend(self.icon.as_deref().map(|i| field("Icon", i, false)));
+        fields.extend(self.notes.as_deref().map(|n| field("Notes", n, false)));
+
+        if self.break_glass {
+            fields.push(field("Break-glass", "yes", false));
+        }
+        if self.has_2fa {
+            fields.push(field("2FA", "yes", false));
+        }
+
+        fields
+    }
+
+    /// Template name, as shown to the user
+    fn kind(&self) -> &str {
+        match &self.template {
+            Template::Login(_) => "Login",
+            Template::RecoveryCodes(_) => "Recovery codes",
+            Template::License(_) => "License",
+            Template::Custom(e) => &e.kind,
+        }
+    }
+
+    /// Label used in entry lists
+    pub fn label(&self) -> String {
+        let label = match &self.icon {
+            Some(icon) => format!("{icon} {}", self.name()),
+            None => self.name().to_string(),
+        };
+
+        match self.break_glass {
+            true => format!("{label} [break-glass]"),
+            false => label,
+        }
+    }
+
+    /// Replace notes, keeping the previous version in history
+    pub fn set_notes(&mut self, notes: String) {
+        let notes = (!notes.trim().is_empty()).then_some(notes);
+
+        if notes == self.notes {
+            return;
+        }
+
+        if let Some(prev) = self.notes.take() {
+            self.history.push(NotesRevision {
+                notes: prev,
+                replaced_at: clock::now(),
+            });
+        }
+
+        self.notes = notes;
+    }
+
+    /// Encode w/ the current [`ENTRY_VERSION`], compressing large entries
+    pub fn encode(&self, format: EntryFormat) -> InternalResult<Vec<u8>> {
+        let envelope = Envelope {
+            version: ENTRY_VERSION,
+            entry: self,
+        };
+
+        let res = match format {
+            EntryFormat::Json => serde_json::to_vec(&envelope).map_err(|e| e.to_string()),
+            EntryFormat::MessagePack => {
+                rmp_serde::to_vec_named(&envelope).map_err(|e| e.to_string())
+            }
+        };
+
+        let bytes =
+            res.map_err(|e| InternalError::IO(format!("Failed to serialize entry: {e}")))?;
+
+        compress(bytes)
+    }
+
+    /// Decode an entry of any schema version and format, upgrading older
+    /// ones on read
+    pub fn decode(bytes: &[u8]) -> InternalResult<Self> {
+        let bytes = match bytes.split_first() {
+            Some((&COMPRESSED_FLAG, compressed)) => Cow::Owned(
+                zstd::bulk::decompress(compressed, MAX_DECOMPRESSED_SIZE)
+                    .map_err(|e| InternalError::IO(format!("Failed to decompress entry: {e}")))?,
+            ),
+            _ => Cow::Borrowed(bytes),
+        };
+        let bytes = &bytes[..];
+
+        let res = match EntryFormat::detect(bytes) {
+            EntryFormat::Json => serde_json::from_slice::<Value>(bytes).map_err(|e| e.to_string()),
+            EntryFormat::MessagePack => {
+                rmp_serde::from_slice::<Value>(bytes).map_err(|e| e.to_string())
+            }
+        };
+
+        let value = res.map_err(|e| InternalError::IO(format!("Failed to decode entry: {e}")))?;
+
+        Self::from_value(value)
+    }
+
+    /// Versioned representation, as written by [`Entry::encode`]
+    pub fn to_value(&self) -> InternalResult<Value> {
+        let envelope = Envelope {
+            version: ENTRY_VERSION,
+            entry: self,
+        };
+
+        serde_json::to_value(envelope)
+            .map_err(|e| InternalError::IO(format!("Failed to serialize entry: {e}")))
+    }
+
+    /// Build entry from a decoded value of any schema version
+    pub fn from_value(mut value: Value) -> InternalResult<Self> {
+        let Value::Object(fields) = &mut value else {
+            return Err(InternalError::IO(
+                "Failed to decode entry: expected a JSON object".into(),
+            ));
+        };
+
+        let version = match fields.remove("version") {
+            Some(v) => v.as_u64().ok_or_else(|| {
+                InternalError::IO(format!("Failed to decode entry: invalid version {v}"))
+            })?,
+            None if fields.contains_key("template") => 1,
+            None => 0,
+        };
+
+        if version > ENTRY_VERSION {
+            return Err(InternalError::IO(format!(
+                "Entry schema version {version} is newer than supported ({ENTRY_VERSION})"
+            )));
+        }
+
+        upgrade(fields, version);
+
+        serde_json::from_value(value)
+            .map_err(|e| InternalError::IO(format!("Failed to decode entry: {e}")))
+    }
+}
+
+impl EntryFormat {
+    /// Guess format of encoded bytes, entries are always maps so JSON starts
+    /// w/ `{` while MessagePack starts w/ a map marker
+    fn detect(bytes: &[u8]) -> Self {
+        match bytes.iter().find(|b| !b.is_ascii_whitespace()) {
+            Some(0x80..=0x8f | 0xde | 0xdf) => Self::MessagePack,
+            _ => Self::Json,
+        }
+    }
+}
+
+/// Compress encoded entry if it's large enough and compression pays off
+fn compress(bytes: Vec<u8>) -> InternalResult<Vec<u8>> {
+    if bytes.len() <= COMPRESS_THRESHOLD {
+        return Ok(bytes);
+    }
+
+    let compressed = zstd::bulk::compress(&bytes, COMPRESS_LEVEL)
+        .map_err(|e| InternalError::IO(format!("Failed to compress entry: {e}")))?;
+
+    if compressed.len() + 1 >= bytes.len() {
+        return Ok(bytes);
+    }
+
+    let mut out = Vec::with_capacity(compressed.len() + 1);
+    out.push(COMPRESSED_FLAG);
+    out.extend_from_slice(&compressed);
+
+    Ok(out)
+}
+
+/// On-disk wrapper adding the schema version to an entry
+#[derive(Serialize)]
+struct Envelope<'a> {
+    version: u64,
+    #[serde(flatten)]
+    entry: &'a Entry,
+}
+
+/// Upgrade raw entry fields from `version` to the current schema, one
+/// version at a time
+fn upgrade(fields: &mut Map<String, Value>, version: u64) {
+    // v1 -> v2 only added the version field, nothing to migrate
+    for from in version..ENTRY_VERSION {
+        // every pre-template entry was a login
+        if from == 0 {
+            fields.insert("template".into(), Value::String("login".into()));
+        }
+    }
+}
+
+impl LoginEntry {
+    /// Check fields given on the command line, which no prompt checked,
+    /// all but the password which is read later
+    pub fn validate(&self) -> InternalResult<()> {
+        let invalid = |msg: String| Err(InternalError::Invalid(msg));
+
+        if self.name.trim().is_empty() {
+            return invalid("Entry name must be non-empty".into());
+        }
+        if self.name.trim() != self.name {
+            return invalid("Entry name can't start or end w/ whitespace".into());
+        }
+
+        let fields = [
+            ("Entry name", Some(&self.name)),
+            ("Username", self.username.as_ref()),
+            ("URL", self.url.as_ref()),
+        ];
+
+        for (field, value) in fields {
+            if let Some(c) = value.and_then(|v| v.chars().find(|c| c.is_control())) {
+                return invalid(format!("{field} can't contain control character {c:?}"));
+            }
+        }
+
+        if let Some(url) = &self.url {
+            let scheme = url.split_once("://").map(|(scheme, _)| scheme);
+            let valid_scheme = scheme.is_some_and(|s| {
+                s.starts_with(|c: char| c.is_ascii_alphabetic())
+                    && s.chars()
+                        .all(|c| c.is_ascii_alphanumeric() || "+-.".contains(c))
+            });
+
+            if !valid_scheme || url.contains(' ') || audit::url_host(url).is_none() {
+                return invalid(format!(
+                    "Invalid URL '{url}', expected e.g. https://example.com"
+                ));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl RecoveryCodesEntry {
+    /// Build from raw user input, one code per whitespace separated token
+    pub fn from_input(name: String, input: &str) -> Self {
+        let codes = input
+            .split(|c: char| c.is_whitespace() || c == ',')
+            .filter(|c| !c.is_empty())
+            .map(|c| RecoveryCode {
+                code: c.to_string(),
+                used_at: None,
+            })
+            .collect();
+
+        Self { name, codes }
+    }
+
+    pub fn unused_count(&self) -> usize {
+        self.codes.iter().filter(|c| c.used_at.is_none()).count()
+    }
+
+    /// Mark the next unused code as used and return it
+    pub fn consume(&mut self) -> Option<&str> {
+        let code = self.codes.iter_mut().find(|c| c.used_at.is_none())?;
+        code.used_at = Some(clock::now());
+
+        Some(&code.code)
+    }
+}
+
+impl LicenseEntry {
+    /// License key split into dash separated chunks of [`KEY_CHUNK_SIZE`]
+    pub fn formatted_key(&self) -> String {
+        let chars: Vec<char> = self
+            .key
+            .chars()
+            .filter(|c| !c.is_whitespace() && *c != '-')
+            .collect();
+
+        chars
+            .chunks(KEY_CHUNK_SIZE)
+            .map(|c| c.iter().collect::<String>())
+            .collect::<Vec<_>>()
+            .join("-")
+    }
+
+    /// Maintenance expiry date as days since unix epoch
+    pub fn maintenance_expiry(&self) -> Option<u64> {
+        self.maintenance_until
+            .as_deref()
+            .and_then(clock::parse_date)
+    }
+}
+
+/// Sort entries for display, pinned entries first by their position and
+/// the rest alphabetically
+pub(crate) fn sort_entries(entries: &mut [Entry]) {
+    entries.sort_by(compare_entries);
+}
+
+/// Order of [`sort_entries`], total as long as names are unique so it
+/// never depends on the order entries were loaded in
+pub(crate) fn compare_entries(a: &Entry, b: &Entry) -> std::cmp::Ordering {
+    let pinned = match (a.position, b.position) {
+        (Some(x), Some(y)) => x.cmp(&y),
+        (Some(_), None) => std::cmp::Ordering::Less,
+        (None, Some(_)) => std::cmp::Ordering::Greater,
+        (None, None) => std::cmp::Ordering::Equal,
+    };
+
+    pinned.then_with(|| a.name().cmp(b.name()))
+}
+
+/// Pin entry at `idx` to given slot among pinned entries (or unpin it w/
+/// `None`), renumbering all pinned entries so positions stay contiguous
+///
+/// NOTE: `entries` must already be sorted w/ [`sort_entries`]
+pub(crate) fn reposition(entries: &mut [Entry], idx: usize, slot: Option<usize>) {
+    let mut pinned: Vec<usize> = (0..entries.len())
+        .filter(|&i| i != idx && entries[i].position.is_some())
+        .collect();
+
+    entries[idx].position = None;
+
+    if let Some(slot) = slot {
+        pinned.insert(slot.min(pinned.len()), idx);
+    }
+
+    for (pos, i) in pinned.into_iter().enumerate() {
+        entries[i].position = Some(pos as u32);
+    }
+}
+
+/// Cache key for an entry name
+pub(crate) fn key_for(name: &str) -> String {
+    general_purpose::STANDARD.encode(name)
+}
+
+/// Entry name for a cache key, `None` if key was not created by [`key_for`]
+pub(crate) fn name_for_key(key: &[u8]) -> Option<String> {
+    let bytes = general_purpose::STANDARD.decode(key).ok()?;
+
+    String::from_utf8(bytes).ok()
+}
+
+/// Render bytes as `offset  hex  |ascii|` lines, like `hexdump -C`
+pub(crate) fn hex_dump(bytes: &[u8]) -> String {
+    let mut out = String::new();
+
+    for (i, chunk) in bytes.chunks(HEX_DUMP_WIDTH).enumerate() {
+        let hex: Vec<String> = chunk.iter().map(|b| format!("{b:02x}")).collect();
+        let ascii: String = chunk
+            .iter()
+            .map(|&b| match b {
+                0x20..=0x7e => b as char,
+                _ => '.',
+            })
+            .collect();
+
+        out.push_str(&format!(
+            "{:08x}  {:<width$}  |{}|\n",
+            i * HEX_DUMP_WIDTH,
+            hex.join(" "),
+            ascii,
+            width = HEX_DUMP_WIDTH * 3 - 1
+        ));
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_legacy_login_entry() {
+        let legacy = br#"{"name":"mail","password":"hunter2","username":"me","url":null}"#;
+
+        match Entry::decode(legacy).unwrap().template {
+            Template::Login(e) => {
+                assert_eq!(e.name, "mail");
+                assert_eq!(e.password.expose(), "hunter2");
+                assert_eq!(e.username.as_deref(), Some("me"));
+                assert!(e.url.is_none());
+            }
+
+            other => panic!("unexpected entry: {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_roundtrip_recovery_codes() {
+        let entry = Entry::new(Template::RecoveryCodes(RecoveryCodesEntry::from_input(
+            "github".into(),
+            "aaaa-1111 bbbb-2222\ncccc-3333",
+        )));
+
+        let decoded = Entry::decode(&entry.encode(EntryFormat::Json).unwrap()).unwrap();
+
+        match decoded.template {
+            Template::RecoveryCodes(e) => {
+                assert_eq!(e.name, "github");
+                assert_eq!(e.codes.len(), 3);
+                assert_eq!(e.unused_count(), 3);
+            }
+
+            other => panic!("unexpected entry: {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_field_lookup() {
+        let login = Entry::new(Template::Login(LoginEntry {
+            name: "mail".into(),
+            password: SecretString::from("hunter2"),
+            username: Some("me".into()),
+            url: None,
+        }));
+
+        assert_eq!(login.field(None).as_deref(), Some("hunter2"));
+        assert_eq!(login.field(Some("Username")).as_deref(), Some("me"));
+        assert!(login.field(Some("url")).is_none());
+        assert!(login.field(Some("other")).is_none());
+
+        let custom = Entry::new(Template::Custom(CustomEntry {
+            name: "db".into(),
+            kind: "Server".into(),
+            fields: vec![
+                CustomField {
+                    name: "Host".into(),
+                    value: "db.local".into(),
+                    secret: false,
+                },
+                CustomField {
+                    name: "Root password".into(),
+                    value: "s3cret".into(),
+                    secret: true,
+                },
+            ],
+        }));
+
+        assert_eq!(custom.field(None).as_deref(), Some("s3cret"));
+        assert_eq!(custom.field(Some("host")).as_deref(), Some("db.local"));
+    }
+
+    #[test]
+    fn test_roundtrip_custom_entry() {
+        let entry = Entry::new(Template::Custom(CustomEntry {
+            name: "db".into(),
+            kind: "Server".into(),
+            fields: vec![
+                CustomField {
+                    name: "Host".into(),
+                    value: "db.local".into(),
+                    secret: false,
+                },
+                CustomField {
+                    name: "Root password".into(),
+                    value: "hunter2".into(),
+                    secret: true,
+                },
+            ],
+        }));
+
+        let decoded = Entry::decode(&entry.encode(EntryFormat::MessagePack).unwrap()).unwrap();
+
+        match decoded.template {
+            Template::Custom(e) => {
+                assert_eq!(e.name, "db");
+                assert_eq!(e.kind, "Server");
+                assert!(!e.fields[0].secret);
+                assert!(e.fields[1].secret);
+                assert_eq!(e.fields[1].value, "hunter2");
+            }
+
+            other => panic!("unexpected entry: {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_login_validation() {
+        let login = |name: &str, url: Option<&str>| LoginEntry {
+            name: name.into(),
+            password: "pw".into(),
+            username: Some("me".into()),
+            url: url.map(str::to_string),
+        };
+
+        assert!(
+            login("mail", Some("https://mail.example.com/inbox"))
+                .validate()
+                .is_ok()
+        );
+        assert!(login("mail", None).validate().is_ok());
+
+        for invalid in [
+            login(" mail", None),
+            login("", None),
+            login("mail\n", None),
+            login("mail", Some("mail.example.com")),
+            login("mail", Some("https://")),
+            login("mail", Some("https://exa mple.com")),
+        ] {
+            assert!(matches!(invalid.validate(), Err(InternalError::Invalid(_))));
+        }
+    }
+
+    #[test]
+    fn test_secret_fields() {
+        let login = Entry::new(Template::Login(LoginEntry {
+            name: "mail".into(),
+            password: "hunter2".into(),
+            username: Some("me".into()),
+            url: None,
+        }));
+
+        assert!(login.is_secret_field(None));
+        assert!(login.is_secret_field(Some("Password")));
+        assert!(!login.is_secret_field(Some("username")));
+
+        let values = login.field_values();
+        assert!(values.iter().any(|f| f.name == "Password" && f.secret));
+        assert!(values.iter().any(|f| f.name == "Username" && !f.secret));
+    }
+
+    #[test]
+    fn test_detect_2fa_from_totp_field() {
+        let custom = |field: &str, value: &str| {
+            Entry::new(Template::Custom(CustomEntry {
+                name: "vpn".into(),
+                kind: "Account".into(),
+                fields: vec![CustomField {
+                    name: field.into(),
+                    value: value.into(),
+                    secret: true,
+                }],
+            }))
+        };
+
+        let mut entry = custom("Seed", "otpauth://totp/vpn?secret=ABC");
+        entry.detect_2fa();
+        assert!(entry.has_2fa);
+
+        let mut entry = custom("TOTP secret", "JBSWY3DPEHPK3PXP");
+        entry.detect_2fa();
+        assert!(entry.has_2fa);
+
+        let mut entry = custom("Password", "hunter2");
+        entry.detect_2fa();
+        assert!(!entry.has_2fa);
+
+        // manual flag is kept
+        entry.has_2fa = true;
+        entry.detect_2fa();
+        assert!(entry.has_2fa);
+    }
+
+    #[test]
+    fn test_consume_marks_codes_in_order() {
+        let mut entry = RecoveryCodesEntry::from_input("github".into(), "one, two");
+
+        assert_eq!(entry.consume(), Some("one"));
+        assert!(entry.codes[0].used_at.is_some());
+        assert_eq!(entry.unused_count(), 1);
+
+        assert_eq!(entry.consume(), Some("two"));
+        assert_eq!(entry.consume(), None);
+        assert_eq!(entry.unused_count(), 0);
+    }
+
+    #[test]
+    fn test_license_key_grouping() {
+        let mut entry = LicenseEntry {
+            name: "editor".into(),
+            product: "Editor Pro".into(),
+            key: "abcde12345FGHIJ678".into(),
+            purchase_date: None,
+            maintenance_until: None,
+            machines: Vec::new(),
+        };
+
+        assert_eq!(entry.formatted_key(), "abcde-12345-FGHIJ-678");
+
+        // existing separators are normalized
+        entry.key = "ABC-DE1 2345".into();
+        assert_eq!(entry.formatted_key(), "ABCDE-12345");
+    }
+
+    fn login(name: &str) -> Entry {
+        Entry::new(Template::Login(LoginEntry {
+            name: name.into(),
+            password: "secret".into(),
+            username: None,
+            url: None,
+        }))
+    }
+
+    fn names(entries: &[Entry]) -> Vec<&str> {
+        entries.iter().map(|e| e.name()).collect()
+    }
+
+    #[test]
+    fn test_roundtrip_icon_and_position() {
+        let mut entry = login("mail");
+        entry.icon = Some("📧".into());
+        entry.position = Some(3);
+
+        let decoded = Entry::decode(&entry.encode(EntryFormat::Json).unwrap()).unwrap();
+        assert_eq!(decoded.icon.as_deref(), Some("📧"));
+        assert_eq!(decoded.position, Some(3));
+        assert_eq!(decoded.label(), "📧 mail");
+    }
+
+    #[test]
+    fn test_sort_pinned_first() {
+        let mut entries = vec![login("c"), login("a"), login("b"), login("d")];
+        entries[2].position = Some(1);
+        entries[3].position = Some(0);
+
+        sort_entries(&mut entries);
+        assert_eq!(names(&entries), vec!["d", "b", "a", "c"]);
+    }
+
+    #[test]
+    fn test_reposition_pin_and_unpin() {
+        let mut entries = vec![login("a"), login("b"), login("c")];
+
+        // pin `c` then `a` on top of it
+        reposition(&mut entries, 2, Some(0));
+        sort_entries(&mut entries);
+        assert_eq!(names(&entries), vec!["c", "a", "b"]);
+
+        reposition(&mut entries, 1, Some(0));
+        sort_entries(&mut entries);
+        assert_eq!(names(&entries), vec!["a", "c", "b"]);
+        assert_eq!(entries[0].position, Some(0));
+        assert_eq!(entries[1].position, Some(1));
+
+        // unpin `a`, positions are renumbered
+        reposition(&mut entries, 0, None);
+        sort_entries(&mut entries);
+        assert_eq!(names(&entries), vec!["c", "a", "b"]);
+        assert_eq!(entries[0].position, Some(0));
+        assert!(entries[1].position.is_none());
+    }
+
+    #[test]
+    fn test_set_notes_keeps_history() {
+        let mut entry = login("mail");
+
+        entry.set_notes("first".into());
+        assert_eq!(entry.notes.as_deref(), Some("first"));
+        assert!(entry.history.is_empty());
+
+        // unchanged notes don't create a revision
+        entry.set_notes("first".into());
+        assert!(entry.history.is_empty());
+
+        entry.set_notes("second\nline".into());
+        entry.set_notes("".into());
+        assert!(entry.notes.is_none());
+        assert_eq!(entry.history.len(), 2);
+        assert_eq!(entry.history[0].notes, "first");
+        assert_eq!(entry.history[1].notes, "second\nline");
+    }
+
+    #[test]
+    fn test_decode_garbage() {
+        assert!(Entry::decode(b"not json").is_err());
+    }
+
+    #[test]
+    fn test_name_for_key_roundtrip() {
+        assert_eq!(
+            name_for_key(key_for("github").as_bytes()).unwrap(),
+            "github"
+        );
+        assert!(name_for_key(b"not base64!").is_none());
+    }
+
+    #[test]
+    fn test_hex_dump() {
+        let dump = hex_dump(b"{\"name\":\x00\xff}");
+        let lines: Vec<&str> = dump.lines().collect();
+
+        assert_eq!(lines.len(), 1);
+        assert!(lines[0].starts_with("00000000  7b 22 6e 61 6d 65 22 3a 00 ff 7d"));
+        assert!(lines[0].ends_with("|{\"name\":..}|"));
+
+        assert_eq!(hex_dump(&[0u8; 17]).lines().count(), 2);
+        assert_eq!(hex_dump(&[]), "");
+    }
+
+    #[test]
+    fn test_encode_writes_current_version() {
+        let bytes = login("mail").encode(EntryFormat::Json).unwrap();
+        let value: Value = serde_json::from_slice(&bytes).unwrap();
+
+        assert_eq!(value["version"], ENTRY_VERSION);
+        assert_eq!(value["template"], "login");
+    }
+
+    #[test]
+    fn test_decode_every_schema_version() {
+        let v0 = br#"{"name":"mail","password":"p","username":null,"url":null}"#.to_vec();
+        let v1 = br#"{"template":"login","name":"mail","password":"p","username":null,"url":null,"icon":"x"}"#.to_vec();
+        let v2 = login("mail").encode(EntryFormat::Json).unwrap();
+
+        for (version, bytes) in [&v0, &v1, &v2].into_iter().enumerate() {
+            let entry = Entry::decode(bytes).unwrap();
+
+            assert_eq!(entry.name(), "mail", "version {version}");
+            assert!(matches!(entry.template, Template::Login(_)));
+        }
+
+        // metadata survives the upgrade
+        assert_eq!(Entry::decode(&v1).unwrap().icon.as_deref(), Some("x"));
+    }
+
+    #[test]
+    fn test_decode_rejects_newer_version() {
+        let future = format!(
+            r#"{{"version":{},"template":"login","name":"mail","password":"p","username":null,"url":null}}"#,
+            ENTRY_VERSION + 1
+        );
+
+        let err = Entry::decode(future.as_bytes()).unwrap_err();
+        assert!(err.to_string().contains("newer than supported"));
+    }
+
+    #[test]
+    fn test_roundtrip_msgpack() {
+        let mut entry = login("mail");
+        entry.set_notes("hello".into());
+
+        let json = entry.encode(EntryFormat::Json).unwrap();
+        let msgpack = entry.encode(EntryFormat::MessagePack).unwrap();
+        assert!(msgpack.len() < json.len());
+
+        let decoded = Entry::decode(&msgpack).unwrap();
+        assert_eq!(decoded.name(), "mail");
+        assert_eq!(decoded.notes.as_deref(), Some("hello"));
+    }
+
+    #[test]
+    fn test_large_notes_are_compressed() {
+        let mut entry = login("mail");
+        entry.set_notes("recovery text\n".repeat(500));
+
+        for format in [EntryFormat::Json, EntryFormat::MessagePack] {
+            let bytes = entry.encode(format).unwrap();
+
+            assert_eq!(bytes[0], COMPRESSED_FLAG);
+            assert!(bytes.len() < 1000);
+            assert_eq!(Entry::decode(&bytes).unwrap().notes, entry.notes);
+        }
+
+        // small entries stay plain
+        let small = login("mail").encode(EntryFormat::Json).unwrap();
+        assert_eq!(small[0], b'{');
+    }
+
+    #[test]
+    fn test_break_glass_roundtrip() {
+        let mut entry = login("root");
+        assert!(
+            !String::from_utf8(entry.encode(EntryFormat::Json).unwrap())
+                .unwrap()
+                .contains("break_glass")
+        );
+
+        entry.break_glass = true;
+        assert_eq!(entry.label(), "root [break-glass]");
+
+        let decoded = Entry::decode(&entry.encode(EntryFormat::Json).unwrap()).unwrap();
+        assert!(decoded.break_glass);
+    }
+}
@@ -0,0 +1,221 @@
+use crate::{
+    config::Fido2Config,
+    crypto::TokenDevice,
+    rng,
+    types::{InternalError, InternalResult},
+};
+use base64::{Engine as _, engine::general_purpose};
+use std::{
+    io::Write,
+    path::PathBuf,
+    process::{Command, Stdio},
+};
+use zeroize::Zeroizing;
+
+/// Relying party vuoto's credentials are created for
+const RP_ID: &str = "vuoto";
+/// Salt sent to the hmac-secret extension, it takes exactly 32 bytes
+pub(crate) const SALT_SIZE: usize = 32;
+/// HMAC-SHA256 output
+const SECRET_SIZE: usize = 32;
+/// Line of `fido2-cred -M` output holding the credential id
+const CREDENTIAL_LINE: usize = 4;
+
+/// New hmac-secret credential on the security key, asking for its PIN (if
+/// configured) and a touch
+pub(crate) fn enroll(config: &Fido2Config) -> InternalResult<TokenDevice> {
+    let user_id: [u8; 16] = random()?;
+    let input = format!(
+        "{}\n{RP_ID}\nvuoto\n{}\n",
+        general_purpose::STANDARD.encode(random::<32>()?),
+        general_purpose::STANDARD.encode(user_id),
+    );
+
+    let mut args = vec!["-M", "-h"];
+
+    if config.pin {
+        args.push("-v");
+    }
+
+    let output = run(config, "fido2-cred", &args, &input)?;
+    let credential = output
+        .lines()
+        .nth(CREDENTIAL_LINE)
+        .filter(|c| general_purpose::STANDARD.decode(c).is_ok())
+        .ok_or_else(|| InternalError::IO("fido2-cred returned no credential".into()))?;
+
+    Ok(TokenDevice::Fido2 {
+        credential: credential.to_string(),
+        uv: config.pin,
+    })
+}
+
+/// Output of the hmac-secret extension of `credential` for `salt`
+pub(crate) fn respond(
+    config: &Fido2Config,
+    credential: &str,
+    uv: bool,
+    salt: &[u8],
+) -> InternalResult<Zeroizing<Vec<u8>>> {
+    let input = format!(
+        "{}\n{RP_ID}\n{credential}\n{}\n",
+        general_purpose::STANDARD.encode(random::<32>()?),
+        general_purpose::STANDARD.encode(salt),
+    );
+
+    // the secret differs w/ and w/o user verification
+    let mut args = vec!["-G", "-h", "-p"];
+
+    if uv {
+        args.push("-v");
+    }
+
+    let output = run(config, "fido2-assert", &args, &input)?;
+    let secret = Zeroizing::new(
+        output
+            .lines()
+            .last()
+            .and_then(|s| general_purpose::STANDARD.decode(s).ok())
+            .unwrap_or_default(),
+    );
+
+    if secret.len() != SECRET_SIZE {
+        return Err(InternalError::IO(
+            "fido2-assert returned no hmac-secret, does the security key support it?".into(),
+        ));
+    }
+
+    Ok(secret)
+}
+
+/// Configured device, or the first one libfido2 finds
+fn device(config: &Fido2Config) -> InternalResult<String> {
+    if let Some(device) = &config.device {
+        return Ok(device.clone());
+    }
+
+    let output = Command::new(tool(config, "fido2-token"))
+        .arg("-L")
+        .stdin(Stdio::null())
+        .stderr(Stdio::inherit())
+        .output()
+        .map_err(|e| InternalError::IO(format!("Failed to run fido2-token: {e}")))?;
+
+    // e.g. `/dev/hidraw3: vendor=0x1050, product=0x0407 (Yubico YubiKey)`
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .find_map(|l| l.split_once(": ").map(|(path, _)| path.to_string()))
+        .ok_or_else(|| InternalError::IO("No FIDO2 security key found".into()))
+}
+
+/// Run a libfido2 tool on the device w/ `input` on stdin, it prompts for
+/// the PIN on the terminal itself
+fn run(
+    config: &Fido2Config,
+    name: &str,
+    args: &[&str],
+    input: &str,
+) -> InternalResult<Zeroizing<String>> {
+    let err = |e: std::io::Error| InternalError::IO(format!("Failed to run {name}: {e}"));
+
+    let mut child = Command::new(tool(config, name))
+        .args(args)
+        .arg(device(config)?)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::inherit())
+        .spawn()
+        .map_err(err)?;
+
+    // input is a few short lines, never filling the pipe
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(input.as_bytes()).map_err(err)?;
+    }
+
+    let output = child.wait_with_output().map_err(err)?;
+    let stdout = Zeroizing::new(String::from_utf8_lossy(&output.stdout).into_owned());
+
+    if !output.status.success() {
+        return Err(InternalError::IO(format!(
+            "{name} exited w/ {}, was the PIN wrong or the key not touched?",
+            output.status
+        )));
+    }
+
+    Ok(stdout)
+}
+
+fn tool(config: &Fido2Config, name: &str) -> PathBuf {
+    match &config.tools_dir {
+        Some(dir) => dir.join(name),
+        None => PathBuf::from(name),
+    }
+}
+
+fn random<const N: usize>() -> InternalResult<[u8; N]> {
+    let mut bytes = [0u8; N];
+    rng::fill(&mut bytes)?;
+
+    Ok(bytes)
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+    use tempfile::TempDir;
+
+    fn fake_tool(temp_dir: &TempDir, name: &str, script: &str) {
+        let path = temp_dir.path().join(name);
+        std::fs::write(&path, format!("#!/bin/sh\n{script}\n")).unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+    }
+
+    #[test]
+    fn test_enroll_and_respond_w_libfido2_tools() {
+        let temp_dir = TempDir::new().unwrap();
+        let config = Fido2Config {
+            device: None,
+            tools_dir: Some(temp_dir.path().to_path_buf()),
+            pin: true,
+        };
+
+        fake_tool(
+            &temp_dir,
+            "fido2-token",
+            r#"echo "/dev/hidraw3: vendor=0x1050, product=0x0407 (Yubico YubiKey)""#,
+        );
+        fake_tool(
+            &temp_dir,
+            "fido2-cred",
+            r#"[ "$*" = "-M -h -v /dev/hidraw3" ] || exit 1
+read cdh; read rp; read user; read id
+printf '%s\n%s\npacked\nYXV0aA==\nY3JlZA==\nc2ln\n' "$cdh" "$rp""#,
+        );
+        fake_tool(
+            &temp_dir,
+            "fido2-assert",
+            r#"[ "$*" = "-G -h -p -v /dev/hidraw3" ] || exit 1
+read cdh; read rp; read cred; read salt
+[ "$cred" = "Y3JlZA==" ] || exit 1
+printf '%s\n%s\nYXV0aA==\nc2ln\n%s\n' "$cdh" "$rp" "$salt""#,
+        );
+
+        let device = enroll(&config).unwrap();
+        assert_eq!(
+            device,
+            TokenDevice::Fido2 {
+                credential: "Y3JlZA==".into(),
+                uv: true,
+            }
+        );
+
+        // the fake echoes the salt as its secret
+        let salt = [7u8; SALT_SIZE];
+        assert_eq!(*respond(&config, "Y3JlZA==", true, &salt).unwrap(), salt);
+
+        assert!(respond(&config, "b3RoZXI=", true, &salt).is_err());
+        assert!(respond(&config, "Y3JlZA==", false, &salt).is_err());
+        assert!(respond(&config, "Y3JlZA==", true, &[7u8; 16]).is_err());
+    }
+}
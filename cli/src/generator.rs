@@ -0,0 +1,113 @@
+use crate::{rng, types::InternalResult};
+use serde::Deserialize;
+
+const LOWER: &str = "abcdefghijklmnopqrstuvwxyz";
+const UPPER: &str = "ABCDEFGHIJKLMNOPQRSTUVWXYZ";
+const DIGITS: &str = "0123456789";
+const SYMBOLS: &str = "!#$%&*+-=?@^_~";
+const HEX: &str = "0123456789abcdef";
+
+/// Characters a generated value is drawn from
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum Charset {
+    #[default]
+    Alphanumeric,
+    /// alphanumeric plus shell-safe symbols
+    Symbols,
+    Digits,
+    Hex,
+}
+
+/// How a secret field's value is generated, e.g.
+/// `generate = { length = 32, charset = "symbols" }`
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct GeneratorPolicy {
+    pub length: usize,
+    pub charset: Charset,
+}
+
+impl Default for GeneratorPolicy {
+    fn default() -> Self {
+        Self {
+            length: 24,
+            charset: Charset::default(),
+        }
+    }
+}
+
+impl Charset {
+    fn chars(self) -> Vec<u8> {
+        let parts: &[&str] = match self {
+            Charset::Alphanumeric => &[LOWER, UPPER, DIGITS],
+            Charset::Symbols => &[LOWER, UPPER, DIGITS, SYMBOLS],
+            Charset::Digits => &[DIGITS],
+            Charset::Hex => &[HEX],
+        };
+
+        parts.iter().flat_map(|p| p.bytes()).collect()
+    }
+}
+
+/// Random value following `policy`
+pub(crate) fn generate(policy: &GeneratorPolicy) -> InternalResult<String> {
+    let chars = policy.charset.chars();
+
+    // largest multiple of the charset size fitting in a byte, bytes above
+    // it are rejected so every char is equally likely
+    let limit = 256 - (256 % chars.len());
+    let mut out = String::with_capacity(policy.length);
+
+    while out.len() < policy.length {
+        let mut bytes = [0u8; 64];
+        rng::fill(&mut bytes)?;
+
+        for b in bytes {
+            if out.len() == policy.length {
+                break;
+            }
+
+            if (b as usize) < limit {
+                out.push(chars[b as usize % chars.len()] as char);
+            }
+        }
+    }
+
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_generate_respects_policy() {
+        let policy = GeneratorPolicy {
+            length: 40,
+            charset: Charset::Hex,
+        };
+
+        let value = generate(&policy).unwrap();
+
+        assert_eq!(value.len(), 40);
+        assert!(value.bytes().all(|b| HEX.as_bytes().contains(&b)));
+    }
+
+    #[test]
+    fn test_generate_is_random() {
+        let policy = GeneratorPolicy::default();
+
+        assert_ne!(generate(&policy).unwrap(), generate(&policy).unwrap());
+    }
+
+    #[test]
+    fn test_generate_empty() {
+        let policy = GeneratorPolicy {
+            length: 0,
+            charset: Charset::Digits,
+        };
+
+        assert_eq!(generate(&policy).unwrap(), "");
+    }
+}
@@ -0,0 +1,745 @@
+use crate::{
+    clock,
+    config::HistoryConfig,
+    types::{InternalError, InternalResult},
+    vaults::Vault,
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    fs::OpenOptions,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+/// Views of a single entry within this window count towards a burst
+const BURST_WINDOW_SECS: u64 = 60;
+const BURST_LIMIT: usize = 50;
+
+/// `prev` hash of the first record
+const GENESIS_HASH: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+/// Head of the chain is checkpointed every this many records
+const CHECKPOINT_INTERVAL: usize = 64;
+
+/// `LOG_AUTHPRIV | LOG_INFO`
+#[cfg(unix)]
+const SYSLOG_PRIORITY: u8 = 10 << 3 | 6;
+#[cfg(unix)]
+const SYSLOG_SOCKETS: &[&str] = &["/dev/log", "/var/run/syslog", "/var/run/log"];
+
+/// Where an entry was accessed from
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum Source {
+    /// interactive menus
+    Interactive,
+    /// non-interactive commands, e.g. `vuoto entry inspect`
+    Command,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub(crate) enum Event {
+    Viewed {
+        entry: String,
+        source: Source,
+    },
+    Anomaly {
+        entry: String,
+        message: String,
+    },
+    /// a break-glass entry was opened
+    BreakGlass {
+        entry: String,
+        reason: String,
+    },
+}
+
+/// A single line of the history file
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct Record {
+    pub at: u64,
+    #[serde(flatten)]
+    pub event: Event,
+    /// hash of the previous record, [`GENESIS_HASH`] for the first one
+    #[serde(default)]
+    pub prev: String,
+    /// hash over `prev` and this record's content
+    #[serde(default)]
+    pub hash: String,
+}
+
+/// Record as mirrored to external logs, tagged w/ its vault
+#[derive(Serialize)]
+struct MirroredRecord<'a> {
+    vault: &'a str,
+    #[serde(flatten)]
+    record: &'a Record,
+}
+
+/// Hashed part of a record
+#[derive(Serialize)]
+struct RecordBody<'a> {
+    at: u64,
+    #[serde(flatten)]
+    event: &'a Event,
+}
+
+/// Chain head as of some record, detects truncation of older records
+#[derive(Debug, Serialize, Deserialize)]
+struct Checkpoint {
+    /// number of records covered
+    count: usize,
+    /// hash of the last covered record
+    hash: String,
+}
+
+/// Result of a successful [`verify`]
+#[derive(Debug, PartialEq)]
+pub(crate) struct Verification {
+    pub records: usize,
+    /// records covered by the latest checkpoint
+    pub checkpointed: usize,
+}
+
+/// How often an entry was viewed
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct ViewStats {
+    pub count: usize,
+    pub last: Option<u64>,
+}
+
+/// Append-only per-vault log of entry accesses, stored as JSON lines next
+/// to the vault's cache
+///
+/// Records form a hash chain, so modifying or removing a record breaks all
+/// following links. The chain head is periodically checkpointed into a
+/// separate file so cutting records off the end is detected as well, up to
+/// the latest checkpoint.
+pub(crate) struct History {
+    vault: String,
+    path: PathBuf,
+    records: Vec<Record>,
+    mirror: HistoryConfig,
+}
+
+impl History {
+    /// Load a vault's history, empty if there is none yet
+    pub fn open<P: AsRef<Path>>(home_dir: &P, vault: &Vault) -> InternalResult<Self> {
+        let path = path_for(home_dir, vault);
+
+        let raw = match std::fs::read_to_string(&path) {
+            Ok(raw) => raw,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(err) => return Err(err.into()),
+        };
+
+        let records = raw
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .map(|(i, line)| {
+                serde_json::from_str(line).map_err(|e| {
+                    InternalError::IO(format!("Invalid history record on line {}: {e}", i + 1))
+                })
+            })
+            .collect::<InternalResult<Vec<Record>>>()?;
+
+        Ok(Self {
+            vault: vault.name.clone(),
+            path,
+            records,
+            mirror: HistoryConfig::default(),
+        })
+    }
+
+    /// Hash all records into a fresh chain and rewrite the file
+    ///
+    /// NOTE: this accepts the records as they are, so it's only run when
+    /// the user asks for it, see [`migrate`]
+    fn rechain(&mut self) -> InternalResult<()> {
+        let mut prev = GENESIS_HASH.to_string();
+        let mut out = String::new();
+
+        for record in &mut self.records {
+            record.hash = chain_hash(&prev, record.at, &record.event)?;
+            record.prev = std::mem::replace(&mut prev, record.hash.clone());
+
+            out.push_str(&encode_line(record)?);
+        }
+
+        let tmp = self.path.with_extension("history.tmp");
+        std::fs::write(&tmp, out)?;
+        std::fs::rename(&tmp, &self.path)?;
+
+        write_checkpoint(&self.path, &self.records)
+    }
+
+    /// Also send newly appended events to the configured external logs
+    pub fn with_mirror(mut self, config: &HistoryConfig) -> Self {
+        self.mirror = config.clone();
+        self
+    }
+
+    pub fn records(&self) -> &[Record] {
+        &self.records
+    }
+
+    /// Append and persist an event
+    pub fn append(&mut self, event: Event) -> InternalResult<()> {
+        self.append_at(event, clock::now())
+    }
+
+    fn append_at(&mut self, event: Event, at: u64) -> InternalResult<()> {
+        let prev = match self.records.last() {
+            Some(last) => last.hash.clone(),
+            None => GENESIS_HASH.to_string(),
+        };
+        let hash = chain_hash(&prev, at, &event)?;
+        let record = Record {
+            at,
+            event,
+            prev,
+            hash,
+        };
+
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        file.write_all(encode_line(&record)?.as_bytes())?;
+        file.sync_data()?;
+
+        // the history file is the source of truth, an unreachable log
+        // pipeline shouldn't lock users out of their vault
+        if let Err(err) = self.mirror_record(&record) {
+            eprintln!("[WARN]: Failed to mirror history event: {err}");
+        }
+
+        self.records.push(record);
+
+        if self.records.len().is_multiple_of(CHECKPOINT_INTERVAL) {
+            write_checkpoint(&self.path, &self.records)?;
+        }
+
+        Ok(())
+    }
+
+    fn mirror_record(&self, record: &Record) -> InternalResult<()> {
+        if self.mirror.jsonl_path.is_none() && !self.mirror.syslog {
+            return Ok(());
+        }
+
+        let line = serde_json::to_string(&MirroredRecord {
+            vault: &self.vault,
+            record,
+        })
+        .map_err(|e| InternalError::IO(format!("Failed to serialize history: {e}")))?;
+
+        if let Some(path) = &self.mirror.jsonl_path {
+            let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+            file.write_all(format!("{line}\n").as_bytes())?;
+        }
+
+        if self.mirror.syslog {
+            send_syslog(&line)?;
+        }
+
+        Ok(())
+    }
+
+    /// Record a view, returns a warning if the entry is viewed suspiciously
+    /// often
+    pub fn record_view(&mut self, entry: &str, source: Source) -> InternalResult<Option<String>> {
+        self.record_view_at(entry, source, clock::now())
+    }
+
+    fn record_view_at(
+        &mut self,
+        entry: &str,
+        source: Source,
+        now: u64,
+    ) -> InternalResult<Option<String>> {
+        self.append_at(
+            Event::Viewed {
+                entry: entry.into(),
+                source,
+            },
+            now,
+        )?;
+
+        let since = now.saturating_sub(BURST_WINDOW_SECS);
+        let recent = self.records.iter().filter(|r| r.at >= since);
+
+        let mut views = 0;
+        let mut flagged = false;
+
+        for r in recent {
+            match &r.event {
+                Event::Viewed { entry: e, .. } if e == entry => views += 1,
+                Event::Anomaly { entry: e, .. } if e == entry => flagged = true,
+                _ => {}
+            }
+        }
+
+        // flag each burst once instead of on every following view
+        if views < BURST_LIMIT || flagged {
+            return Ok(None);
+        }
+
+        let message = format!("viewed {views} times within {BURST_WINDOW_SECS}s");
+
+        self.append_at(
+            Event::Anomaly {
+                entry: entry.into(),
+                message: message.clone(),
+            },
+            now,
+        )?;
+
+        Ok(Some(format!("'{entry}' was {message}")))
+    }
+
+    pub fn view_stats(&self, entry: &str) -> ViewStats {
+        let mut stats = ViewStats::default();
+
+        for r in &self.records {
+            if let Event::Viewed { entry: e, .. } = &r.event
+                && e == entry
+            {
+                stats.count += 1;
+                stats.last = Some(r.at);
+            }
+        }
+
+        stats
+    }
+}
+
+/// Check the hash chain of a vault's history against its latest checkpoint
+pub(crate) fn verify<P: AsRef<Path>>(home_dir: &P, vault: &Vault) -> InternalResult<Verification> {
+    let history = History::open(home_dir, vault)?;
+    let records = &history.records;
+    let tampered = |msg: String| Err(InternalError::IO(format!("History was tampered w/: {msg}")));
+
+    if let Some(i) = records.iter().position(|r| r.hash.is_empty()) {
+        return tampered(format!(
+            "record {} isn't chained, if written before histories were chained run \
+             `vuoto history migrate`",
+            i + 1
+        ));
+    }
+
+    let mut prev = GENESIS_HASH;
+
+    for (i, r) in records.iter().enumerate() {
+        if r.prev != prev {
+            return tampered(format!("record {} doesn't link to the one before", i + 1));
+        }
+
+        if r.hash != chain_hash(&r.prev, r.at, &r.event)? {
+            return tampered(format!("record {} was modified", i + 1));
+        }
+
+        prev = &r.hash;
+    }
+
+    let checkpointed = match read_checkpoint(&history.path)? {
+        // one is written once there are enough records
+        None if records.len() >= CHECKPOINT_INTERVAL => {
+            return tampered("the checkpoint is missing".into());
+        }
+        None => 0,
+        Some(cp) if cp.count > records.len() => {
+            return tampered(format!(
+                "{} records were checkpointed, only {} are left",
+                cp.count,
+                records.len()
+            ));
+        }
+        Some(cp) if cp.count > 0 && records[cp.count - 1].hash != cp.hash => {
+            return tampered(format!("record {} doesn't match the checkpoint", cp.count));
+        }
+        Some(cp) => cp.count,
+    };
+
+    Ok(Verification {
+        records: records.len(),
+        checkpointed,
+    })
+}
+
+/// Chain the records of a history written before records were chained,
+/// returns how many there are
+pub(crate) fn migrate<P: AsRef<Path>>(home_dir: &P, vault: &Vault) -> InternalResult<usize> {
+    let mut history = History::open(home_dir, vault)?;
+
+    if history.records.iter().all(|r| !r.hash.is_empty()) {
+        return Err(InternalError::IO(
+            "History is already chained, nothing to migrate".into(),
+        ));
+    }
+
+    history.rechain()?;
+    Ok(history.records.len())
+}
+
+/// Remove a vault's history and its checkpoint
+pub(crate) fn remove<P: AsRef<Path>>(home_dir: &P, vault: &Vault) -> InternalResult<()> {
+    let path = path_for(home_dir, vault);
+
+    for p in [checkpoint_path(&path), path] {
+        match std::fs::remove_file(p) {
+            Ok(()) => {}
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+            Err(err) => return Err(err.into()),
+        }
+    }
+
+    Ok(())
+}
+
+/// History file of a vault
+pub(crate) fn path_for<P: AsRef<Path>>(home_dir: &P, vault: &Vault) -> PathBuf {
+    home_dir.as_ref().join(format!("{}.history", vault.slug))
+}
+
+/// Send a message to the local syslog daemon
+#[cfg(unix)]
+fn send_syslog(message: &str) -> InternalResult<()> {
+    use std::os::unix::net::UnixDatagram;
+
+    let socket = UnixDatagram::unbound()?;
+    let msg = format!(
+        "<{SYSLOG_PRIORITY}>vuoto[{}]: {message}",
+        std::process::id()
+    );
+
+    let mut last_err = None;
+
+    for path in SYSLOG_SOCKETS {
+        match socket.send_to(msg.as_bytes(), path) {
+            Ok(_) => return Ok(()),
+            Err(err) => last_err = Some(err),
+        }
+    }
+
+    Err(InternalError::IO(format!(
+        "No syslog socket reachable: {}",
+        last_err.map(|e| e.to_string()).unwrap_or_default()
+    )))
+}
+
+#[cfg(not(unix))]
+fn send_syslog(_message: &str) -> InternalResult<()> {
+    Err(InternalError::IO("syslog is only supported on Unix".into()))
+}
+
+fn checkpoint_path(path: &Path) -> PathBuf {
+    path.with_extension("history.checkpoint")
+}
+
+fn chain_hash(prev: &str, at: u64, event: &Event) -> InternalResult<String> {
+    let body = serde_json::to_vec(&RecordBody { at, event })
+        .map_err(|e| InternalError::IO(format!("Failed to serialize history: {e}")))?;
+
+    let mut hasher = Sha256::new();
+    hasher.update(prev.as_bytes());
+    hasher.update(b"\n");
+    hasher.update(&body);
+
+    Ok(hasher
+        .finalize()
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect())
+}
+
+fn encode_line(record: &Record) -> InternalResult<String> {
+    let mut line = serde_json::to_string(record)
+        .map_err(|e| InternalError::IO(format!("Failed to serialize history: {e}")))?;
+    line.push('\n');
+
+    Ok(line)
+}
+
+fn write_checkpoint(path: &Path, records: &[Record]) -> InternalResult<()> {
+    let Some(last) = records.last() else {
+        return Ok(());
+    };
+
+    let checkpoint = Checkpoint {
+        count: records.len(),
+        hash: last.hash.clone(),
+    };
+    let raw = serde_json::to_vec(&checkpoint)
+        .map_err(|e| InternalError::IO(format!("Failed to serialize checkpoint: {e}")))?;
+
+    // replace atomically, a torn checkpoint would look like tampering
+    let path = checkpoint_path(path);
+    let tmp = path.with_extension("checkpoint.tmp");
+    std::fs::write(&tmp, raw)?;
+    std::fs::rename(&tmp, &path)?;
+
+    Ok(())
+}
+
+fn read_checkpoint(path: &Path) -> InternalResult<Option<Checkpoint>> {
+    let raw = match std::fs::read(checkpoint_path(path)) {
+        Ok(raw) => raw,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err.into()),
+    };
+
+    serde_json::from_slice(&raw)
+        .map(Some)
+        .map_err(|e| InternalError::IO(format!("Invalid history checkpoint: {e}")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::vault;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_views_are_persisted() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut history = History::open(&temp_dir.path(), &vault()).unwrap();
+
+        history
+            .record_view_at("mail", Source::Interactive, 100)
+            .unwrap();
+        history
+            .record_view_at("mail", Source::Command, 200)
+            .unwrap();
+        history
+            .record_view_at("bank", Source::Interactive, 300)
+            .unwrap();
+
+        let history = History::open(&temp_dir.path(), &vault()).unwrap();
+
+        assert_eq!(history.records().len(), 3);
+        assert_eq!(
+            history.view_stats("mail"),
+            ViewStats {
+                count: 2,
+                last: Some(200)
+            }
+        );
+        assert_eq!(history.view_stats("none"), ViewStats::default());
+    }
+
+    #[test]
+    fn test_burst_is_flagged_once() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut history = History::open(&temp_dir.path(), &vault()).unwrap();
+
+        for i in 0..BURST_LIMIT - 1 {
+            let warning = history
+                .record_view_at("mail", Source::Command, 1000 + i as u64 % 30)
+                .unwrap();
+            assert!(warning.is_none());
+        }
+
+        let warning = history
+            .record_view_at("mail", Source::Command, 1030)
+            .unwrap();
+        assert!(warning.unwrap().contains("50 times"));
+
+        // same burst isn't reported again
+        let warning = history
+            .record_view_at("mail", Source::Command, 1031)
+            .unwrap();
+        assert!(warning.is_none());
+
+        let anomalies = history
+            .records()
+            .iter()
+            .filter(|r| matches!(r.event, Event::Anomaly { .. }))
+            .count();
+        assert_eq!(anomalies, 1);
+    }
+
+    #[test]
+    fn test_spread_out_views_are_fine() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut history = History::open(&temp_dir.path(), &vault()).unwrap();
+
+        for i in 0..BURST_LIMIT * 2 {
+            let warning = history
+                .record_view_at("mail", Source::Interactive, i as u64 * 10)
+                .unwrap();
+            assert!(warning.is_none());
+        }
+    }
+
+    fn history_lines(temp_dir: &TempDir) -> Vec<String> {
+        let raw = std::fs::read_to_string(path_for(&temp_dir.path(), &vault())).unwrap();
+        raw.lines().map(String::from).collect()
+    }
+
+    fn write_lines(temp_dir: &TempDir, lines: &[String]) {
+        let mut raw = lines.join("\n");
+        raw.push('\n');
+        std::fs::write(path_for(&temp_dir.path(), &vault()), raw).unwrap();
+    }
+
+    fn record_views(temp_dir: &TempDir, count: usize) {
+        let mut history = History::open(&temp_dir.path(), &vault()).unwrap();
+
+        for i in 0..count {
+            history
+                .record_view_at("mail", Source::Interactive, i as u64 * 100)
+                .unwrap();
+        }
+    }
+
+    #[test]
+    fn test_verify_intact_chain() {
+        let temp_dir = TempDir::new().unwrap();
+        record_views(&temp_dir, CHECKPOINT_INTERVAL + 3);
+
+        let res = verify(&temp_dir.path(), &vault()).unwrap();
+        assert_eq!(
+            res,
+            Verification {
+                records: CHECKPOINT_INTERVAL + 3,
+                checkpointed: CHECKPOINT_INTERVAL,
+            }
+        );
+    }
+
+    #[test]
+    fn test_verify_detects_modification() {
+        let temp_dir = TempDir::new().unwrap();
+        record_views(&temp_dir, 5);
+
+        let mut lines = history_lines(&temp_dir);
+        lines[2] = lines[2].replace("\"mail\"", "\"bank\"");
+        write_lines(&temp_dir, &lines);
+
+        let err = verify(&temp_dir.path(), &vault()).unwrap_err();
+        assert!(err.to_string().contains("record 3 was modified"));
+    }
+
+    #[test]
+    fn test_verify_detects_removed_record() {
+        let temp_dir = TempDir::new().unwrap();
+        record_views(&temp_dir, 5);
+
+        let mut lines = history_lines(&temp_dir);
+        lines.remove(1);
+        write_lines(&temp_dir, &lines);
+
+        let err = verify(&temp_dir.path(), &vault()).unwrap_err();
+        assert!(err.to_string().contains("record 2 doesn't link"));
+    }
+
+    #[test]
+    fn test_verify_detects_truncation() {
+        let temp_dir = TempDir::new().unwrap();
+        record_views(&temp_dir, CHECKPOINT_INTERVAL + 1);
+
+        let lines = history_lines(&temp_dir);
+        write_lines(&temp_dir, &lines[..CHECKPOINT_INTERVAL - 1]);
+
+        let err = verify(&temp_dir.path(), &vault()).unwrap_err();
+        assert!(err.to_string().contains("checkpointed"));
+    }
+
+    #[test]
+    fn test_unchained_history_is_migrated() {
+        let temp_dir = TempDir::new().unwrap();
+        let legacy = [
+            r#"{"at":1,"event":"viewed","entry":"mail","source":"interactive"}"#.to_string(),
+            r#"{"at":2,"event":"viewed","entry":"mail","source":"command"}"#.to_string(),
+        ];
+        write_lines(&temp_dir, &legacy);
+
+        let mut history = History::open(&temp_dir.path(), &vault()).unwrap();
+        history.record_view_at("mail", Source::Command, 3).unwrap();
+
+        // never accepted unless migrated on purpose
+        let err = verify(&temp_dir.path(), &vault()).unwrap_err();
+        assert!(err.to_string().contains("record 1 isn't chained"));
+
+        assert_eq!(migrate(&temp_dir.path(), &vault()).unwrap(), 3);
+        assert!(migrate(&temp_dir.path(), &vault()).is_err());
+
+        let res = verify(&temp_dir.path(), &vault()).unwrap();
+        assert_eq!(res.records, 3);
+        assert_eq!(res.checkpointed, 3);
+    }
+
+    #[test]
+    fn test_stripped_hashes_are_not_rechained() {
+        let temp_dir = TempDir::new().unwrap();
+        record_views(&temp_dir, 3);
+
+        let lines: Vec<String> = history_lines(&temp_dir)
+            .iter()
+            .map(|line| {
+                let mut record: Record = serde_json::from_str(line).unwrap();
+                record.prev.clear();
+                record.hash.clear();
+                serde_json::to_string(&record).unwrap()
+            })
+            .collect();
+        write_lines(&temp_dir, &lines);
+
+        assert!(verify(&temp_dir.path(), &vault()).is_err());
+        History::open(&temp_dir.path(), &vault()).unwrap();
+        assert!(verify(&temp_dir.path(), &vault()).is_err());
+    }
+
+    #[test]
+    fn test_verify_detects_removed_checkpoint() {
+        let temp_dir = TempDir::new().unwrap();
+        record_views(&temp_dir, CHECKPOINT_INTERVAL);
+
+        let path = path_for(&temp_dir.path(), &vault());
+        std::fs::remove_file(checkpoint_path(&path)).unwrap();
+
+        let err = verify(&temp_dir.path(), &vault()).unwrap_err();
+        assert!(err.to_string().contains("checkpoint is missing"));
+    }
+
+    #[test]
+    fn test_mirror_to_jsonl() {
+        let temp_dir = TempDir::new().unwrap();
+        let jsonl_path = temp_dir.path().join("mirror.jsonl");
+        let config = HistoryConfig {
+            jsonl_path: Some(jsonl_path.clone()),
+            syslog: false,
+        };
+
+        let mut history = History::open(&temp_dir.path(), &vault())
+            .unwrap()
+            .with_mirror(&config);
+        history
+            .record_view_at("mail", Source::Interactive, 1)
+            .unwrap();
+        history
+            .append_at(
+                Event::BreakGlass {
+                    entry: "root".into(),
+                    reason: "outage".into(),
+                },
+                2,
+            )
+            .unwrap();
+
+        let raw = std::fs::read_to_string(jsonl_path).unwrap();
+        let lines: Vec<serde_json::Value> = raw
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect();
+
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["vault"], "work");
+        assert_eq!(lines[1]["event"], "break_glass");
+        assert_eq!(lines[1]["reason"], "outage");
+        assert_eq!(lines[1]["hash"], history.records()[1].hash.as_str());
+    }
+}
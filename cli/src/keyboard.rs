@@ -0,0 +1,167 @@
+use std::path::Path;
+
+/// LEDs of all keyboards, the Caps Lock ones named `<device>::capslock`
+#[cfg(target_os = "linux")]
+const LEDS_DIR: &str = "/sys/class/leds";
+
+/// Keyboard state likely to garble a typed password, `None` if it looks
+/// fine or the platform can't tell
+pub(crate) fn warning() -> Option<&'static str> {
+    // the state of the local keyboard says nothing about a remote one
+    if std::env::var_os("SSH_CONNECTION").is_some() {
+        return None;
+    }
+
+    match (platform::caps_lock(), platform::foreign_layout()) {
+        (true, true) => Some("Caps Lock is on and the keyboard layout isn't the default one"),
+        (true, false) => Some("Caps Lock is on"),
+        (false, true) => Some("The keyboard layout isn't the default one"),
+        (false, false) => None,
+    }
+}
+
+/// Whether any keyboard whose LEDs are in `dir` has Caps Lock lit
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+fn caps_lock_led<P: AsRef<Path>>(dir: &P) -> bool {
+    let Ok(leds) = std::fs::read_dir(dir) else {
+        return false;
+    };
+
+    leds.filter_map(Result::ok)
+        .filter(|led| led.file_name().to_string_lossy().ends_with("::capslock"))
+        .filter_map(|led| std::fs::read_to_string(led.path().join("brightness")).ok())
+        .any(|brightness| brightness.trim().parse::<u32>().is_ok_and(|b| b > 0))
+}
+
+/// Caps Lock from the keyboard LEDs in sysfs, no layout, which X11 and
+/// Wayland keep to themselves
+#[cfg(target_os = "linux")]
+mod platform {
+    pub(super) fn caps_lock() -> bool {
+        super::caps_lock_led(&super::LEDS_DIR)
+    }
+
+    pub(super) fn foreign_layout() -> bool {
+        false
+    }
+}
+
+/// Caps Lock from the flags of the current session, the layout would need
+/// the Text Input Sources API
+#[cfg(target_os = "macos")]
+mod platform {
+    /// kCGEventSourceStateCombinedSessionState
+    const COMBINED_SESSION_STATE: i32 = 0;
+    /// kCGEventFlagMaskAlphaShift
+    const ALPHA_SHIFT: u64 = 0x0001_0000;
+
+    #[link(name = "CoreGraphics", kind = "framework")]
+    unsafe extern "C" {
+        fn CGEventSourceFlagsState(state_id: i32) -> u64;
+    }
+
+    pub(super) fn caps_lock() -> bool {
+        // SAFETY: takes a plain state id and returns plain flags
+        let flags = unsafe { CGEventSourceFlagsState(COMBINED_SESSION_STATE) };
+
+        flags & ALPHA_SHIFT != 0
+    }
+
+    pub(super) fn foreign_layout() -> bool {
+        false
+    }
+}
+
+/// Caps Lock's toggle state, and the console's layout compared to the
+/// default input language
+#[cfg(windows)]
+mod platform {
+    use std::ffi::c_void;
+
+    const VK_CAPITAL: i32 = 0x14;
+    const SPI_GETDEFAULTINPUTLANG: u32 = 0x0059;
+    /// KL_NAMELENGTH, including the nul
+    const LAYOUT_NAME_LEN: usize = 9;
+
+    #[link(name = "user32")]
+    unsafe extern "system" {
+        fn GetKeyState(virt_key: i32) -> i16;
+        fn SystemParametersInfoW(action: u32, param: u32, value: *mut c_void, ini: u32) -> i32;
+    }
+
+    #[link(name = "kernel32")]
+    unsafe extern "system" {
+        fn GetConsoleKeyboardLayoutNameW(name: *mut u16) -> i32;
+    }
+
+    pub(super) fn caps_lock() -> bool {
+        // SAFETY: takes a plain virtual key code
+        let state = unsafe { GetKeyState(VK_CAPITAL) };
+
+        state & 1 != 0
+    }
+
+    pub(super) fn foreign_layout() -> bool {
+        let mut default: isize = 0;
+        let mut name = [0u16; LAYOUT_NAME_LEN];
+
+        // SAFETY: both write at most the size of the buffers given
+        let ok = unsafe {
+            SystemParametersInfoW(
+                SPI_GETDEFAULTINPUTLANG,
+                0,
+                (&mut default as *mut isize).cast(),
+                0,
+            ) != 0
+                && GetConsoleKeyboardLayoutNameW(name.as_mut_ptr()) != 0
+        };
+
+        if !ok {
+            return false;
+        }
+
+        // e.g. `00000407`, its low word is the layout's language
+        let name = String::from_utf16_lossy(&name[..LAYOUT_NAME_LEN - 1]);
+        let Ok(layout) = u32::from_str_radix(&name, 16) else {
+            return false;
+        };
+
+        layout & 0xffff != default as u32 & 0xffff
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", windows)))]
+mod platform {
+    pub(super) fn caps_lock() -> bool {
+        false
+    }
+
+    pub(super) fn foreign_layout() -> bool {
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_caps_lock_led_of_any_keyboard() {
+        let temp_dir = TempDir::new().unwrap();
+        let led = |name: &str, brightness: &str| {
+            let dir = temp_dir.path().join(name);
+            std::fs::create_dir_all(&dir).unwrap();
+            std::fs::write(dir.join("brightness"), brightness).unwrap();
+        };
+
+        assert!(!caps_lock_led(&temp_dir.path().join("missing")));
+
+        led("input3::capslock", "0\n");
+        led("input7::numlock", "1\n");
+        assert!(!caps_lock_led(&temp_dir.path()));
+
+        led("input9::capslock", "1\n");
+        assert!(caps_lock_led(&temp_dir.path()));
+    }
+}
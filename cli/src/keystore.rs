@@ -0,0 +1,291 @@
+use crate::{
+    crypto::{self, MasterHeader, Slot, VaultKey},
+    types::{InternalError, InternalResult},
+    vaults::Vault,
+};
+use base64::{Engine as _, engine::general_purpose};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use zeroize::Zeroizing;
+
+/// Service name of vuoto's items in the OS keychain
+pub(crate) const SERVICE: &str = "vuoto";
+
+/// Secret storage of the OS, holding one secret per vault slug
+pub(crate) trait Keystore {
+    fn get(&self, slug: &str) -> InternalResult<Option<Zeroizing<Vec<u8>>>>;
+    fn set(&self, slug: &str, secret: &[u8]) -> InternalResult<()>;
+    fn delete(&self, slug: &str) -> InternalResult<()>;
+
+    /// Whether `get` asks for biometrics, see [`crate::biometric`]
+    fn biometric(&self) -> bool {
+        false
+    }
+}
+
+/// macOS Keychain, Windows Credential Manager or Secret Service on Linux
+#[cfg(feature = "keychain")]
+struct OsKeystore;
+
+#[cfg(feature = "keychain")]
+impl OsKeystore {
+    fn entry(slug: &str) -> InternalResult<keyring::Entry> {
+        keyring::Entry::new(SERVICE, slug).map_err(keychain_error)
+    }
+}
+
+#[cfg(feature = "keychain")]
+impl Keystore for OsKeystore {
+    fn get(&self, slug: &str) -> InternalResult<Option<Zeroizing<Vec<u8>>>> {
+        match Self::entry(slug)?.get_secret() {
+            Ok(secret) => Ok(Some(Zeroizing::new(secret))),
+            Err(keyring::Error::NoEntry) => Ok(None),
+            Err(e) => Err(keychain_error(e)),
+        }
+    }
+
+    fn set(&self, slug: &str, secret: &[u8]) -> InternalResult<()> {
+        Self::entry(slug)?
+            .set_secret(secret)
+            .map_err(keychain_error)
+    }
+
+    fn delete(&self, slug: &str) -> InternalResult<()> {
+        match Self::entry(slug)?.delete_credential() {
+            Ok(()) | Err(keyring::Error::NoEntry) => Ok(()),
+            Err(e) => Err(keychain_error(e)),
+        }
+    }
+}
+
+#[cfg(feature = "keychain")]
+fn keychain_error(err: keyring::Error) -> InternalError {
+    InternalError::IO(format!("OS keychain failed: {err}"))
+}
+
+/// Keystore of this platform
+#[cfg(feature = "keychain")]
+pub(crate) fn os() -> InternalResult<Box<dyn Keystore>> {
+    Ok(Box::new(OsKeystore))
+}
+
+#[cfg(not(feature = "keychain"))]
+pub(crate) fn os() -> InternalResult<Box<dyn Keystore>> {
+    Err(InternalError::IO(
+        "vuoto was built w/o keychain support".into(),
+    ))
+}
+
+/// Vault key sealed under a secret kept in the keystore, so neither the
+/// data dir nor the keystore alone reveal it
+#[derive(Debug, Serialize, Deserialize)]
+struct WrappedKey {
+    slot: Slot,
+    /// base64
+    wrapped: String,
+    /// secret kept behind biometrics instead of the plain keystore
+    #[serde(default)]
+    biometric: bool,
+}
+
+/// Slot whose key `vault` keeps in the keystore on this machine, checked
+/// w/o touching the keystore itself
+pub(crate) fn enrolled_slot<P: AsRef<Path>>(home_dir: &P, vault: &Vault) -> Option<Slot> {
+    stored(home_dir, vault).map(|w| w.slot)
+}
+
+/// Whether `vault` was enrolled w/ the biometric keystore
+pub(crate) fn enrolled_biometric<P: AsRef<Path>>(home_dir: &P, vault: &Vault) -> bool {
+    stored(home_dir, vault).is_some_and(|w| w.biometric)
+}
+
+fn stored<P: AsRef<Path>>(home_dir: &P, vault: &Vault) -> Option<WrappedKey> {
+    let raw = std::fs::read(wrapped_path(home_dir, vault)).ok()?;
+
+    serde_json::from_slice(&raw).ok()
+}
+
+/// Wrap `key` under a fresh secret stored in `store`, replacing any earlier
+/// one of `vault`
+pub(crate) fn enroll<P: AsRef<Path>>(
+    store: &dyn Keystore,
+    home_dir: &P,
+    vault: &Vault,
+    key: &VaultKey,
+    slot: Slot,
+) -> InternalResult<()> {
+    let secret = VaultKey::random()?;
+    let wrapped = crypto::seal(&secret, vault.slug.as_bytes(), key.to_base64().as_bytes())?;
+
+    store.set(&vault.slug, secret.to_base64().as_bytes())?;
+
+    let raw = serde_json::to_vec_pretty(&WrappedKey {
+        slot,
+        wrapped: general_purpose::STANDARD.encode(wrapped),
+        biometric: store.biometric(),
+    })
+    .map_err(|e| InternalError::IO(format!("Failed to encode wrapped key: {e}")))?;
+
+    // write + rename, so a crash never leaves a half written key
+    let path = wrapped_path(home_dir, vault);
+    let tmp = path.with_extension("tmp");
+    std::fs::write(&tmp, raw)?;
+    std::fs::rename(&tmp, &path)?;
+
+    Ok(())
+}
+
+/// Key of `vault` unwrapped w/ its secret in `store`, `None` if either half
+/// is missing or the key no longer opens the vault, e.g. after `vuoto passwd`
+pub(crate) fn unlock<P: AsRef<Path>>(
+    store: &dyn Keystore,
+    home_dir: &P,
+    vault: &Vault,
+) -> InternalResult<Option<(VaultKey, Slot)>> {
+    let raw = match std::fs::read(wrapped_path(home_dir, vault)) {
+        Ok(raw) => raw,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+
+    let invalid = || InternalError::IO("Invalid wrapped key".into());
+    let stored: WrappedKey = serde_json::from_slice(&raw).map_err(|_| invalid())?;
+    let wrapped = general_purpose::STANDARD
+        .decode(&stored.wrapped)
+        .map_err(|_| invalid())?;
+
+    let Some(secret) = store.get(&vault.slug)? else {
+        return Ok(None);
+    };
+    let secret = std::str::from_utf8(&secret).map_err(|_| invalid())?;
+    let secret = VaultKey::from_base64(secret)?;
+
+    // a secret replaced on another enrollment fails authentication
+    let Ok(encoded) = crypto::open(&secret, vault.slug.as_bytes(), &wrapped) else {
+        return Ok(None);
+    };
+    let encoded = Zeroizing::new(encoded);
+    let key = VaultKey::from_base64(std::str::from_utf8(&encoded).map_err(|_| invalid())?)?;
+
+    let header = MasterHeader::load(home_dir, vault)?;
+
+    Ok(header
+        .accepts(&key, stored.slot)
+        .then_some((key, stored.slot)))
+}
+
+/// Forget `vault`'s key in `store` and the data dir, `false` if it wasn't
+/// enrolled
+pub(crate) fn remove<P: AsRef<Path>>(
+    store: &dyn Keystore,
+    home_dir: &P,
+    vault: &Vault,
+) -> InternalResult<bool> {
+    store.delete(&vault.slug)?;
+
+    match std::fs::remove_file(wrapped_path(home_dir, vault)) {
+        Ok(()) => Ok(true),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(false),
+        Err(e) => Err(e.into()),
+    }
+}
+
+fn wrapped_path<P: AsRef<Path>>(home_dir: &P, vault: &Vault) -> PathBuf {
+    home_dir.as_ref().join(format!("{}.keychain", vault.slug))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{TEST_KDF, vault};
+    use std::{cell::RefCell, collections::HashMap};
+    use tempfile::TempDir;
+
+    #[derive(Default)]
+    struct MemoryKeystore(RefCell<HashMap<String, Vec<u8>>>, bool);
+
+    impl Keystore for MemoryKeystore {
+        fn get(&self, slug: &str) -> InternalResult<Option<Zeroizing<Vec<u8>>>> {
+            Ok(self.0.borrow().get(slug).cloned().map(Zeroizing::new))
+        }
+
+        fn set(&self, slug: &str, secret: &[u8]) -> InternalResult<()> {
+            self.0.borrow_mut().insert(slug.into(), secret.to_vec());
+            Ok(())
+        }
+
+        fn delete(&self, slug: &str) -> InternalResult<()> {
+            self.0.borrow_mut().remove(slug);
+            Ok(())
+        }
+
+        fn biometric(&self) -> bool {
+            self.1
+        }
+    }
+
+    #[test]
+    fn test_keystore_unlocks_until_removed() {
+        let temp_dir = TempDir::new().unwrap();
+        let store = MemoryKeystore::default();
+        let vault = vault();
+
+        let key = MasterHeader::create(&temp_dir.path(), &vault, "pw", None, TEST_KDF).unwrap();
+        assert!(unlock(&store, &temp_dir.path(), &vault).unwrap().is_none());
+
+        enroll(&store, &temp_dir.path(), &vault, &key, Slot::Master).unwrap();
+        assert_eq!(enrolled_slot(&temp_dir.path(), &vault), Some(Slot::Master));
+        assert!(!enrolled_biometric(&temp_dir.path(), &vault));
+
+        // the keystore alone only holds the wrapping secret
+        let secret = store.get(&vault.slug).unwrap().unwrap();
+        assert_ne!(secret.as_slice(), key.to_base64().as_bytes());
+
+        let (unlocked, slot) = unlock(&store, &temp_dir.path(), &vault).unwrap().unwrap();
+        assert_eq!(unlocked.to_base64(), key.to_base64());
+        assert_eq!(slot, Slot::Master);
+
+        assert!(remove(&store, &temp_dir.path(), &vault).unwrap());
+        assert!(enrolled_slot(&temp_dir.path(), &vault).is_none());
+        assert!(store.get(&vault.slug).unwrap().is_none());
+        assert!(!remove(&store, &temp_dir.path(), &vault).unwrap());
+    }
+
+    #[test]
+    fn test_keystore_key_goes_stale_w_password() {
+        let temp_dir = TempDir::new().unwrap();
+        let store = MemoryKeystore::default();
+        let vault = vault();
+
+        let key = MasterHeader::create(&temp_dir.path(), &vault, "pw", None, TEST_KDF).unwrap();
+        enroll(&store, &temp_dir.path(), &vault, &key, Slot::Master).unwrap();
+
+        MasterHeader::create(&temp_dir.path(), &vault, "new pw", None, TEST_KDF).unwrap();
+        assert!(unlock(&store, &temp_dir.path(), &vault).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_biometric_enrollment_is_recorded() {
+        let temp_dir = TempDir::new().unwrap();
+        let store = MemoryKeystore(RefCell::default(), true);
+        let vault = vault();
+
+        let key = MasterHeader::create(&temp_dir.path(), &vault, "pw", None, TEST_KDF).unwrap();
+        enroll(&store, &temp_dir.path(), &vault, &key, Slot::Master).unwrap();
+        assert!(enrolled_biometric(&temp_dir.path(), &vault));
+
+        let (unlocked, _) = unlock(&store, &temp_dir.path(), &vault).unwrap().unwrap();
+        assert_eq!(unlocked.to_base64(), key.to_base64());
+
+        // re-enrolled w/o biometrics
+        enroll(
+            &MemoryKeystore::default(),
+            &temp_dir.path(),
+            &vault,
+            &key,
+            Slot::Master,
+        )
+        .unwrap();
+        assert!(!enrolled_biometric(&temp_dir.path(), &vault));
+    }
+}
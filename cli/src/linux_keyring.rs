@@ -0,0 +1,327 @@
+use crate::{
+    logins::Login,
+    types::{InternalError, InternalResult},
+};
+use std::{
+    collections::HashMap,
+    process::{Command, Stdio},
+};
+use zeroize::Zeroizing;
+
+/// Wallet KDE keeps the user's passwords in
+const KWALLET: &str = "kdewallet";
+/// Folder of plain passwords, apps keep maps and blobs in others
+const KWALLET_FOLDER: &str = "Passwords";
+/// Schemas of keys apps encrypt their own data w/, not the user's passwords
+const APP_KEY_SCHEMAS: [&str; 2] = [
+    "chrome_libsecret_os_crypt_password_v1",
+    "chrome_libsecret_os_crypt_password_v2",
+];
+
+/// Attributes naming an item's URL, username and name, first set one wins,
+/// compared w/o case
+const URL_ATTRIBUTES: [&str; 3] = ["origin_url", "url", "uri"];
+const USERNAME_ATTRIBUTES: [&str; 5] = ["username_value", "username", "user", "account", "login"];
+const NAME_ATTRIBUTES: [&str; 3] = ["title", "service", "application"];
+
+/// Picks the items to import by their labels, returning their indices
+pub(crate) type Pick<'a> = &'a mut dyn FnMut(Vec<String>) -> InternalResult<Vec<usize>>;
+
+/// Logins of the Secret Service items picked, e.g. of GNOME Keyring or of
+/// KWallet, w/ one unlock prompt of the keyring for those which are locked
+pub(crate) fn secret_service_logins(pick: Pick) -> InternalResult<Vec<Login>> {
+    platform::secret_service_logins(pick)
+}
+
+/// Logins of the picked entries of KWallet's password folder, read w/
+/// `kwallet-query` for KWallet w/o the Secret Service
+///
+/// NOTE: KWallet asks the user to allow access to the wallet, and for its
+/// password if it's closed
+pub(crate) fn kwallet_logins(pick: Pick) -> InternalResult<Vec<Login>> {
+    let output = kwallet_query(&["-l", "-f", KWALLET_FOLDER, KWALLET])?;
+
+    if !output.status.success() {
+        return Err(InternalError::IO(format!(
+            "Failed to open the {KWALLET} wallet, is KWallet running and access allowed?"
+        )));
+    }
+
+    let keys: Vec<String> = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter(|k| !k.is_empty())
+        .map(str::to_string)
+        .collect();
+    let picked = pick(keys.clone())?;
+
+    keys.into_iter()
+        .enumerate()
+        .filter(|(i, _)| picked.contains(i))
+        .map(|(_, key)| {
+            let output = kwallet_query(&["-r", &key, "-f", KWALLET_FOLDER, KWALLET])?;
+            let stdout = Zeroizing::new(output.stdout);
+
+            let password = output
+                .status
+                .success()
+                .then(|| String::from_utf8(stdout.to_vec()).ok())
+                .flatten()
+                .map(|p| Zeroizing::new(p.strip_suffix('\n').unwrap_or(&p).to_string()));
+
+            Ok(kwallet_login(&key, password))
+        })
+        .collect()
+}
+
+fn kwallet_query(args: &[&str]) -> InternalResult<std::process::Output> {
+    Command::new("kwallet-query")
+        .args(args)
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()
+        .map_err(|e| InternalError::IO(format!("Failed to run kwallet-query: {e}")))
+}
+
+/// Login of a KWallet entry, keyed by a URL, `user@host` or a name
+fn kwallet_login(key: &str, password: Option<Zeroizing<String>>) -> Login {
+    if key.contains("://") {
+        return Login::web(key, "", password);
+    }
+
+    match key.rsplit_once('@') {
+        Some((user, host)) if !user.is_empty() && host.contains('.') => Login {
+            name: host.to_string(),
+            url: None,
+            username: Some(user.to_string()),
+            password,
+        },
+        _ => Login {
+            name: key.to_string(),
+            url: None,
+            username: None,
+            password,
+        },
+    }
+}
+
+/// Item as shown when picking the ones to import
+fn item_label(label: &str, attributes: &HashMap<String, String>) -> String {
+    let login = into_login(label, attributes, None);
+
+    match (label.is_empty(), login.username) {
+        (true, Some(username)) => format!("{} ({username})", login.name),
+        (true, None) => login.name,
+        (false, _) => label.to_string(),
+    }
+}
+
+/// Whether an item holds a key of an app rather than a password, vuoto's
+/// own included
+fn is_app_key(attributes: &HashMap<String, String>) -> bool {
+    let attribute = |name: &str| attributes.get(name).map(String::as_str);
+
+    attribute("xdg:schema").is_some_and(|s| APP_KEY_SCHEMAS.contains(&s))
+        || attribute("service") == Some(crate::keystore::SERVICE)
+}
+
+/// Login of a Secret Service item, named after the host of its URL, else
+/// after the service it's for or its label
+fn into_login(
+    label: &str,
+    attributes: &HashMap<String, String>,
+    password: Option<Zeroizing<String>>,
+) -> Login {
+    let get = |names: &[&str]| {
+        names.iter().find_map(|name| {
+            attributes
+                .iter()
+                .find(|(k, v)| k.eq_ignore_ascii_case(name) && !v.is_empty())
+                .map(|(_, v)| v.clone())
+        })
+    };
+
+    let username = get(&USERNAME_ATTRIBUTES).unwrap_or_default();
+    // e.g. of `org.gnome.keyring.NetworkPassword`, used by git
+    let network_url = || {
+        let mut url = get(&["server"])?;
+
+        if let Some(protocol) = get(&["protocol"]) {
+            url = format!("{protocol}://{url}");
+        }
+        if let Some(port) = get(&["port"]).filter(|p| p != "0") {
+            url.push_str(&format!(":{port}"));
+        }
+        if let Some(object) = get(&["object"]) {
+            url.push('/');
+            url.push_str(object.trim_start_matches('/'));
+        }
+
+        Some(url)
+    };
+
+    match get(&URL_ATTRIBUTES).or_else(network_url) {
+        Some(url) => Login::web(&url, &username, password),
+        None => Login {
+            name: get(&NAME_ATTRIBUTES)
+                .or_else(|| Some(label.to_string()).filter(|l| !l.is_empty()))
+                .unwrap_or_else(|| "Secret Service item".into()),
+            url: None,
+            username: Some(username).filter(|u| !u.is_empty()),
+            password,
+        },
+    }
+}
+
+#[cfg(all(target_os = "linux", feature = "secret-service"))]
+mod platform {
+    use super::*;
+    use dbus_secret_service::{EncryptionType, Error, Item, SecretService};
+
+    pub(super) fn secret_service_logins(pick: Pick) -> InternalResult<Vec<Login>> {
+        // w/o a session bus there's no keyring either
+        let service = SecretService::connect(EncryptionType::Dh).map_err(|err| match err {
+            Error::Dbus(_) => error(Error::Unavailable),
+            err => error(err),
+        })?;
+        let mut listed = Vec::new();
+
+        // labels and attributes are readable while locked
+        for collection in service.get_all_collections().map_err(error)? {
+            for item in collection.get_all_items().map_err(error)? {
+                let attributes = item.get_attributes().map_err(error)?;
+
+                if !is_app_key(&attributes) {
+                    let label = item.get_label().map_err(error)?;
+                    listed.push((label, attributes, Item::new(&service, item.path)));
+                }
+            }
+        }
+
+        let labels = listed
+            .iter()
+            .map(|(label, attributes, _)| item_label(label, attributes))
+            .collect();
+        let picked = pick(labels)?;
+        let listed: Vec<_> = listed
+            .into_iter()
+            .enumerate()
+            .filter(|(i, _)| picked.contains(i))
+            .map(|(_, item)| item)
+            .collect();
+
+        let locked: Vec<&Item> = listed
+            .iter()
+            .map(|(_, _, item)| item)
+            .filter(|item| item.is_locked().unwrap_or(true))
+            .collect();
+
+        // a dismissed prompt leaves them locked, their secrets unread
+        if !locked.is_empty() {
+            match service.unlock_all(&locked) {
+                Ok(()) | Err(Error::Prompt) => {}
+                Err(err) => return Err(error(err)),
+            }
+        }
+
+        Ok(listed
+            .into_iter()
+            .map(|(label, attributes, item)| {
+                let password = item
+                    .get_secret()
+                    .ok()
+                    .map(Zeroizing::new)
+                    .and_then(|s| String::from_utf8(s.to_vec()).ok())
+                    .map(Zeroizing::new);
+
+                into_login(&label, &attributes, password)
+            })
+            .collect())
+    }
+
+    fn error(err: Error) -> InternalError {
+        match err {
+            Error::Unavailable => {
+                InternalError::IO("No Secret Service is running, is the keyring started?".into())
+            }
+            err => InternalError::IO(format!("Secret Service failed: {err}")),
+        }
+    }
+}
+
+#[cfg(not(all(target_os = "linux", feature = "secret-service")))]
+mod platform {
+    use super::*;
+
+    pub(super) fn secret_service_logins(_pick: Pick) -> InternalResult<Vec<Login>> {
+        Err(InternalError::IO(
+            "The Secret Service can only be imported on Linux, w/ the secret-service feature"
+                .into(),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn attributes(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_attributes_into_logins() {
+        let git = attributes(&[
+            ("xdg:schema", "org.gnome.keyring.NetworkPassword"),
+            ("protocol", "https"),
+            ("server", "git.example.org"),
+            ("port", "0"),
+            ("object", "team/repo.git"),
+            ("user", "alice"),
+        ]);
+        let login = into_login("Git: https://git.example.org/", &git, None);
+        assert_eq!(login.name, "git.example.org");
+        assert_eq!(
+            login.url.as_deref(),
+            Some("https://git.example.org/team/repo.git")
+        );
+        assert_eq!(login.username.as_deref(), Some("alice"));
+
+        let keepassxc = attributes(&[("Title", "Bank"), ("UserName", "bob"), ("URL", "")]);
+        let login = into_login("Bank", &keepassxc, None);
+        assert_eq!(login.name, "Bank");
+        assert!(login.url.is_none());
+        assert_eq!(login.username.as_deref(), Some("bob"));
+
+        assert_eq!(
+            into_login("", &HashMap::new(), None).name,
+            "Secret Service item"
+        );
+        assert_eq!(item_label("", &keepassxc), "Bank (bob)");
+
+        assert!(is_app_key(&attributes(&[(
+            "xdg:schema",
+            "chrome_libsecret_os_crypt_password_v2"
+        )])));
+        assert!(is_app_key(&attributes(&[(
+            "service",
+            crate::keystore::SERVICE
+        )])));
+    }
+
+    #[test]
+    fn test_kwallet_keys_into_logins() {
+        let login = kwallet_login("alice@mail.example.com", None);
+        assert_eq!(login.name, "mail.example.com");
+        assert_eq!(login.username.as_deref(), Some("alice"));
+
+        let login = kwallet_login("https://example.org/login", None);
+        assert_eq!(login.url.as_deref(), Some("https://example.org/login"));
+
+        let login = kwallet_login("Nextcloud", None);
+        assert_eq!(login.name, "Nextcloud");
+        assert!(login.url.is_none());
+    }
+}
@@ -0,0 +1,111 @@
+use crate::{
+    audit,
+    entries::{Entry, LoginEntry, Template},
+};
+use std::collections::HashSet;
+use zeroize::Zeroizing;
+
+/// A login read from a browser or an OS credential store, to be imported
+pub(crate) struct Login {
+    /// entry name, made unique on import
+    pub name: String,
+    pub url: Option<String>,
+    pub username: Option<String>,
+    /// `None` if it couldn't be decrypted or access to it was denied
+    pub password: Option<Zeroizing<String>>,
+}
+
+impl Login {
+    /// Login of a site, named after its host
+    pub fn web(url: &str, username: &str, password: Option<Zeroizing<String>>) -> Self {
+        Self {
+            name: audit::url_host(url).unwrap_or_else(|| url.to_string()),
+            url: Some(url.to_string()).filter(|u| !u.is_empty()),
+            username: Some(username.to_string()).filter(|u| !u.is_empty()),
+            password,
+        }
+    }
+}
+
+/// Login entries of `logins`, w/ the number of those skipped for lack of a
+/// password
+pub(crate) fn into_entries(mut logins: Vec<Login>) -> (Vec<Entry>, usize) {
+    // same names on every import, so importing again is a no-op
+    logins.sort_by(|a, b| (&a.name, &a.url, &a.username).cmp(&(&b.name, &b.url, &b.username)));
+
+    let mut names = HashSet::new();
+    let mut entries = Vec::new();
+    let mut skipped = 0;
+
+    for login in logins {
+        let Some(password) = login.password else {
+            skipped += 1;
+            continue;
+        };
+
+        // e.g. a site the browser was told to never save for
+        if password.is_empty() {
+            continue;
+        }
+
+        entries.push(Entry::new(Template::Login(LoginEntry {
+            name: unique_name(&mut names, &login.name, login.username.as_deref()),
+            password: password.as_str().into(),
+            username: login.username,
+            url: login.url,
+        })));
+    }
+
+    (entries, skipped)
+}
+
+/// `base`, `base (username)` once it is taken, numbered if even that is
+fn unique_name(names: &mut HashSet<String>, base: &str, username: Option<&str>) -> String {
+    let base = match (names.contains(base), username) {
+        (true, Some(username)) => format!("{base} ({username})"),
+        _ => base.to_string(),
+    };
+
+    let mut name = base.clone();
+    let mut n = 2;
+
+    while names.contains(&name) {
+        name = format!("{base} {n}");
+        n += 1;
+    }
+
+    names.insert(name.clone());
+    name
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unique_names() {
+        let login = |url: &str, username: &str| {
+            Login::web(url, username, Some(Zeroizing::new("pw".into())))
+        };
+
+        let (entries, skipped) = into_entries(vec![
+            login("https://github.com/login", "alice"),
+            login("https://github.com/", "bob"),
+            login("https://github.com/", ""),
+            login("https://github.com/", "bob"),
+            Login::web("https://example.org", "eve", None),
+        ]);
+        let names: Vec<_> = entries.iter().map(Entry::name).collect();
+
+        assert_eq!(
+            names,
+            [
+                "github.com",
+                "github.com (bob)",
+                "github.com (bob) 2",
+                "github.com (alice)"
+            ]
+        );
+        assert_eq!(skipped, 1);
+    }
+}
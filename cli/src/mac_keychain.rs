@@ -0,0 +1,331 @@
+use crate::{
+    logins::Login,
+    types::{InternalError, InternalResult},
+};
+use std::{
+    collections::HashMap,
+    process::{Command, Stdio},
+};
+use zeroize::Zeroizing;
+
+/// Keychain read, resolved by `security` in `~/Library/Keychains`
+const KEYCHAIN: &str = "login.keychain";
+/// Services macOS and its own apps keep their secrets under
+const APPLE_SERVICE_PREFIX: &str = "com.apple.";
+/// Attribute of an item's label, shown by Keychain Access as its name
+const LABEL_ATTRIBUTE: &str = "0x00000007";
+
+/// Password item of the login keychain, w/o its password
+#[derive(Debug, PartialEq)]
+pub(crate) enum Item {
+    /// e.g. saved by Safari
+    Internet {
+        server: String,
+        account: String,
+        /// four char code, e.g. `htps`
+        protocol: Option<String>,
+        port: Option<u16>,
+        path: Option<String>,
+    },
+    /// saved by an app for its own account
+    Application {
+        service: String,
+        account: String,
+        label: Option<String>,
+    },
+}
+
+/// Password items of the login keychain, listing them never asks for
+/// access
+pub(crate) fn items() -> InternalResult<Vec<Item>> {
+    if !cfg!(target_os = "macos") {
+        return Err(InternalError::IO(
+            "The Keychain can only be imported on macOS".into(),
+        ));
+    }
+
+    let output = Command::new("security")
+        .args(["dump-keychain", KEYCHAIN])
+        .stdin(Stdio::null())
+        .stderr(Stdio::inherit())
+        .output()
+        .map_err(|e| InternalError::IO(format!("Failed to run security: {e}")))?;
+
+    if !output.status.success() {
+        return Err(InternalError::IO(
+            "Failed to list the login keychain".into(),
+        ));
+    }
+
+    Ok(parse_dump(&String::from_utf8_lossy(&output.stdout)))
+}
+
+/// Password of `item`, `None` if access to it was denied
+///
+/// NOTE: macOS asks the user to allow access to each item, unless `security`
+/// was always allowed before
+pub(crate) fn password(item: &Item) -> InternalResult<Option<Zeroizing<String>>> {
+    let mut command = Command::new("security");
+
+    match item {
+        Item::Internet {
+            server,
+            account,
+            protocol,
+            path,
+            ..
+        } => {
+            command.args(["find-internet-password", "-s", server, "-a", account]);
+
+            if let Some(protocol) = protocol {
+                command.args(["-r", protocol]);
+            }
+            if let Some(path) = path {
+                command.args(["-p", path]);
+            }
+        }
+        Item::Application {
+            service, account, ..
+        } => {
+            command.args(["find-generic-password", "-s", service, "-a", account]);
+        }
+    }
+
+    let output = command
+        .args(["-w", KEYCHAIN])
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()
+        .map_err(|e| InternalError::IO(format!("Failed to run security: {e}")))?;
+    let stdout = Zeroizing::new(output.stdout);
+
+    // denied, or gone since listed
+    if !output.status.success() {
+        return Ok(None);
+    }
+
+    let password = String::from_utf8_lossy(&stdout);
+    Ok(Some(Zeroizing::new(
+        password.strip_suffix('\n').unwrap_or(&password).to_string(),
+    )))
+}
+
+impl Item {
+    /// Item as shown when picking the ones to import
+    pub fn label(&self) -> String {
+        match self {
+            Self::Internet {
+                server, account, ..
+            } if !account.is_empty() => format!("{server} ({account})"),
+            Self::Internet { server, .. } => server.clone(),
+            Self::Application {
+                service,
+                account,
+                label,
+            } => {
+                let name = label.as_deref().unwrap_or(service);
+
+                match account.is_empty() {
+                    true => format!("{name} [app]"),
+                    false => format!("{name} ({account}) [app]"),
+                }
+            }
+        }
+    }
+
+    pub fn into_login(self, password: Option<Zeroizing<String>>) -> Login {
+        match self {
+            Self::Internet {
+                server,
+                account,
+                protocol,
+                port,
+                path,
+            } => {
+                let mut url = match protocol.as_deref().and_then(scheme) {
+                    Some(scheme) => format!("{scheme}://{server}"),
+                    None => server,
+                };
+
+                if let Some(port) = port {
+                    url.push_str(&format!(":{port}"));
+                }
+                if let Some(path) = path.filter(|p| p.starts_with('/')) {
+                    url.push_str(&path);
+                }
+
+                Login::web(&url, &account, password)
+            }
+            Self::Application {
+                service,
+                account,
+                label,
+            } => Login {
+                name: label.unwrap_or(service),
+                url: None,
+                username: Some(account).filter(|a| !a.is_empty()),
+                password,
+            },
+        }
+    }
+
+    /// Item of a record of given class, `None` for anything but passwords
+    /// and secrets of macOS itself
+    fn new(class: &str, attributes: &HashMap<String, String>) -> Option<Self> {
+        let get = |name: &str| attributes.get(name).filter(|v| !v.is_empty()).cloned();
+        let account = get("acct").unwrap_or_default();
+
+        match class {
+            "inet" => Some(Self::Internet {
+                server: get("srvr")?,
+                account,
+                protocol: get("ptcl").filter(|p| p != "0x00000000"),
+                port: get("port").and_then(|p| {
+                    let port = u32::from_str_radix(p.trim_start_matches("0x"), 16).ok()?;
+                    u16::try_from(port).ok().filter(|p| *p > 0)
+                }),
+                path: get("path"),
+            }),
+            "genp" => {
+                let service = get("svce").filter(|s| !s.starts_with(APPLE_SERVICE_PREFIX))?;
+
+                Some(Self::Application {
+                    service,
+                    account,
+                    label: get(LABEL_ATTRIBUTE),
+                })
+            }
+            _ => None,
+        }
+    }
+}
+
+/// URL scheme of a keychain protocol code
+fn scheme(protocol: &str) -> Option<&'static str> {
+    Some(match protocol.trim() {
+        "htps" => "https",
+        "http" => "http",
+        "ftps" => "ftps",
+        "ftp" => "ftp",
+        "ssh" => "ssh",
+        "smb" => "smb",
+        "imap" => "imap",
+        "imps" => "imaps",
+        "smtp" => "smtp",
+        _ => return None,
+    })
+}
+
+/// Items of `security dump-keychain`'s output, records of a class followed
+/// by their attributes
+fn parse_dump(dump: &str) -> Vec<Item> {
+    let mut items = Vec::new();
+    let mut class: Option<&str> = None;
+    let mut attributes = HashMap::new();
+
+    for line in dump.lines() {
+        if let Some(next) = line.strip_prefix("class: ") {
+            items.extend(class.and_then(|c| Item::new(c, &attributes)));
+            attributes.clear();
+            class = Some(next.trim().trim_matches('"'));
+        } else if let Some((name, value)) = attribute(line) {
+            attributes.insert(name, value);
+        }
+    }
+
+    items.extend(class.and_then(|c| Item::new(c, &attributes)));
+    items
+}
+
+/// Name and value of an attribute line, e.g. `"srvr"<blob>="github.com"`,
+/// w/ `<NULL>` values left out
+fn attribute(line: &str) -> Option<(String, String)> {
+    let (name, rest) = line.trim().split_once('<')?;
+    let (kind, value) = rest.split_once(">=")?;
+    let name = name.trim().trim_matches('"').to_string();
+
+    let value = match value {
+        "<NULL>" => return None,
+        // blobs w/ unprintable bytes are hex, w/ a lossy string after
+        hex if kind == "blob" && hex.starts_with("0x") => {
+            let hex = hex.trim_start_matches("0x");
+            let hex = hex.split_whitespace().next().unwrap_or_default();
+            let bytes = (0..hex.len() / 2)
+                .map(|i| u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).ok())
+                .collect::<Option<Vec<u8>>>()?;
+
+            String::from_utf8(bytes).ok()?
+        }
+        quoted if quoted.starts_with('"') => quoted.get(1..quoted.rfind('"')?)?.to_string(),
+        other => other.to_string(),
+    };
+
+    Some((name, value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DUMP: &str = r#"keychain: "/Users/me/Library/Keychains/login.keychain-db"
+version: 512
+class: "inet"
+attributes:
+    0x00000007 <blob>="github.com"
+    "acct"<blob>="alice"
+    "path"<blob>="/login"
+    "port"<uint32>=0x00000000
+    "ptcl"<uint32>="htps"
+    "srvr"<blob>="github.com"
+keychain: "/Users/me/Library/Keychains/login.keychain-db"
+version: 512
+class: "genp"
+attributes:
+    0x00000007 <blob>="Slack Safe Storage"
+    "acct"<blob>=0x736C61636B  "slack"
+    "svce"<blob>="Slack Safe Storage"
+keychain: "/Users/me/Library/Keychains/login.keychain-db"
+version: 512
+class: "genp"
+attributes:
+    "acct"<blob>="me"
+    "svce"<blob>="com.apple.account.IdentityServices.token"
+keychain: "/Users/me/Library/Keychains/login.keychain-db"
+version: 512
+class: 0x80001000
+attributes:
+    "alis"<blob>="Apple Root CA"
+"#;
+
+    #[test]
+    fn test_parse_dump() {
+        let items = parse_dump(DUMP);
+
+        assert_eq!(items.len(), 2);
+        assert_eq!(
+            items[0],
+            Item::Internet {
+                server: "github.com".into(),
+                account: "alice".into(),
+                protocol: Some("htps".into()),
+                port: None,
+                path: Some("/login".into()),
+            }
+        );
+        assert_eq!(items[1].label(), "Slack Safe Storage (slack) [app]");
+    }
+
+    #[test]
+    fn test_items_into_logins() {
+        let mut items = parse_dump(DUMP).into_iter();
+
+        let login = items.next().unwrap().into_login(None);
+        assert_eq!(login.name, "github.com");
+        assert_eq!(login.url.as_deref(), Some("https://github.com/login"));
+        assert_eq!(login.username.as_deref(), Some("alice"));
+
+        let login = items.next().unwrap().into_login(None);
+        assert_eq!(login.name, "Slack Safe Storage");
+        assert!(login.url.is_none());
+    }
+}
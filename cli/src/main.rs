@@ -1,135 +1,3660 @@
 #![allow(dead_code)]
 
+mod agent;
+mod args;
+mod attempts;
+mod audit;
+mod biometric;
+mod browser;
+mod cache;
+mod clipboard;
+mod clock;
+mod config;
+mod crypto;
+mod defaults;
+mod demo;
+mod diff;
+mod dirs;
+mod entries;
+mod fido2;
+mod generator;
+mod history;
+mod keyboard;
+mod keystore;
+mod linux_keyring;
+mod logins;
+mod mac_keychain;
+mod manifest;
+mod migrate;
+mod plugins;
+mod prompt;
+mod provision;
+mod qr;
+mod rng;
+mod scratch;
+mod screenlock;
+mod secret;
+mod session;
+mod share;
+mod term;
+#[cfg(test)]
+mod testing;
+mod totp;
 mod types;
 mod vaults;
+mod win_credentials;
+mod yubikey;
 
 use crate::{
+    args::{Args, Command, ImportSource, LookupFormat, SecondFactor},
+    attempts::Attempts,
+    cache::VaultCache,
+    config::{
+        ApprovalConfig, BUILTIN_TEMPLATES, CachePolicy, Config, HistoryConfig, PromptConfig,
+        TemplateConfig, Theme,
+    },
+    crypto::{EntryCodec, KdfParams, MasterHeader, Slot, TokenDevice, VaultKey},
+    defaults::Defaults,
+    entries::{
+        CustomEntry, CustomField, Entry, EntryFormat, LicenseEntry, LoginEntry, RecoveryCodesEntry,
+        Template,
+    },
+    history::{Event, History, Source},
+    keystore::Keystore,
+    manifest::Manifest,
+    secret::SecretString,
+    session::Session,
     types::{InternalError, InternalResult},
-    vaults::VaultIndex,
+    vaults::{Vault, VaultIndex},
+};
+use std::{
+    io::{IsTerminal, Write},
+    path::{Path, PathBuf},
+    time::Duration,
 };
-use base64::{engine::general_purpose, Engine as _};
-use inquire::{Select, Text};
-use serde::{Deserialize, Serialize};
-use std::path::{Path, PathBuf};
 use turbocache::TurboCache;
+use zeroize::Zeroizing;
+
+pub(crate) const PROFILE_ENV: &str = "VUOTO_PROFILE";
+/// Data dir used instead of the default one
+pub(crate) const DATA_DIR_ENV: &str = "VUOTO_DATA_DIR";
+const MAX_ICON_LEN: usize = 16;
+const MAX_UNLOCK_ATTEMPTS: usize = 3;
+/// Wrong master passwords left from which on a coming wipe is announced
+const WIPE_WARNING: u32 = 5;
+/// Defaults for `qr send`
+const QR_INTERVAL_MS: u64 = 800;
+const QR_LOOPS: u32 = 3;
+/// Default for `kdf bench`
+const KDF_TARGET_MS: u64 = 500;
+/// Entry holding the key of `encrypt-file` w/o --entry
+pub(crate) const FILE_KEY_ENTRY: &str = "File key";
+/// Appended to the names of encrypted files
+const ENCRYPTED_FILE_EXT: &str = "vuoto";
+
+fn main() {
+    if let Err(err) = run() {
+        return_error(&err);
+    }
+}
+
+fn run() -> InternalResult<()> {
+    let args = Args::parse().map_err(|e| InternalError::Usage(e.to_string()))?;
+    let master_password = prompt::given_password(args.password_file.as_deref())?;
+
+    // checked once up front, generating passwords or keys fails after
+    if !matches!(args.command, Some(Command::Doctor)) {
+        crypto::self_test()?;
+
+        if let Err(err) = rng::health() {
+            eprintln!("[WARN]: {err}");
+        }
+    }
+    let profile = args.profile.or_else(|| std::env::var(PROFILE_ENV).ok());
+    let user_config = Config::load_user()?;
+    let data_dir = user_config.as_ref().and_then(|c| c.data_dir.as_deref());
+    let home_dir = get_app_dir(profile.as_deref(), data_dir)?;
+
+    let mut config = match user_config {
+        Some(config) => config,
+        None => Config::load(&home_dir)?,
+    };
+    config.keyfile = args.keyfile.map(PathBuf::from);
+    config.master_password = master_password;
+    config.vault = match args.vault.clone().or_else(|| {
+        std::env::var(args::VAULT_ENV)
+            .ok()
+            .filter(|v| !v.is_empty())
+    }) {
+        Some(vault) => Some(vault),
+        None => default_vault(&home_dir, &config)?,
+    };
+
+    if args.no_clipboard || clipboard::in_container() {
+        clipboard::disable();
+    }
+    clipboard::clear_after(config.clipboard.clear_after());
+
+    // https://no-color.org
+    match std::env::var_os("NO_COLOR").filter(|v| !v.is_empty()) {
+        Some(_) => prompt::set_theme(Theme::Plain),
+        None => prompt::set_theme(config.theme),
+    }
+
+    // expired scratchpads go on the next run, whatever it does
+    if let Err(err) = scratch::sweep(&home_dir, clock::now()) {
+        eprintln!("[WARN]: Failed to remove expired scratchpads: {err}");
+    }
+
+    // commands only prompt when they must, so they also work w/o a
+    // terminal, given the master password
+    let demo = match args.command {
+        Some(Command::Demo) => true,
+        Some(command) => {
+            // json is read by scripts, nobody is there to answer
+            if command.json() {
+                prompt::disable();
+            }
+
+            return run_command(&home_dir, &config, command);
+        }
+        None => false,
+    };
+
+    if let Some(answers) = args
+        .answers
+        .or_else(|| std::env::var(prompt::ANSWERS_ENV).ok())
+    {
+        prompt::load_answers(&answers)?;
+    }
+
+    if !prompt::is_scripted() {
+        term::ensure_interactive()?;
+    }
+
+    if args.minimal {
+        config.prompt.minimal = true;
+    }
+
+    if demo {
+        return run_demo(config);
+    }
+
+    if args.all_vaults {
+        return all_vaults_loop(&home_dir, &config);
+    }
+
+    // the default vault is for commands, the menu still offers all of them
+    let vault = match &args.vault {
+        Some(name) => find_vault(&home_dir, name)?,
+        None => vault_selection_loop(&home_dir, &config)?,
+    };
+    let (data, codec) = unlock_vault(&home_dir, &config, &vault)?;
+    // w/o the agent, the key sealing the session's stays in this process
+    let session = Session::new(
+        codec,
+        (config.key_cache.policy >= CachePolicy::Session).then_some(home_dir.as_path()),
+        config.session.lock_after(),
+        config.session.lock_on_sleep,
+        || relock(&home_dir, &config, &vault, &data),
+    )?;
+
+    login_selection_loop(&home_dir, &data, &config, &session)?;
+
+    Ok(())
+}
+
+fn run_command<P: AsRef<Path>>(
+    home_dir: &P,
+    config: &Config,
+    command: Command,
+) -> InternalResult<()> {
+    match command {
+        Command::InspectEntry {
+            vault,
+            name,
+            raw,
+            reason,
+        } => {
+            let vault = given_vault(home_dir, config, vault.as_deref())?;
+            let (vault, codec) = unlock_vault(home_dir, config, &vault)?;
+
+            let cache = cache::open_tracked(home_dir, &vault, &config.cache, &codec)?;
+            let entry_key = codec.key_for(&name);
+            let bytes = cache
+                .get(entry_key.as_bytes())?
+                .ok_or_else(|| InternalError::IO(format!("Entry '{name}' not found")))?;
+
+            let mut history = History::open(home_dir, &vault)?.with_mirror(&config.history);
+
+            // corrupted entries can't tell, so they are never gated
+            let break_glass = codec
+                .decode(entry_key.as_bytes(), &bytes)
+                .is_ok_and(|e| e.break_glass);
+
+            approve_release(
+                &config.approval,
+                &vault,
+                &format!("entry '{name}'"),
+                break_glass,
+            )?;
+
+            if break_glass {
+                log_break_glass_reason(&mut history, &name, reason)?;
+            }
+
+            if let Some(warning) = history.record_view(&name, Source::Command)? {
+                eprintln!("[WARN]: {warning}");
+            }
+
+            if raw {
+                // plain bytes are more telling, unless decryption fails too
+                let plain = codec.open(entry_key.as_bytes(), &bytes).unwrap_or(bytes);
+
+                print!("{}", entries::hex_dump(&plain));
+                return Ok(());
+            }
+
+            let entry = codec.decode(entry_key.as_bytes(), &bytes)?;
+            let json = serde_json::to_string_pretty(&entry)
+                .map_err(|e| InternalError::IO(format!("Failed to encode entry: {e}")))?;
+
+            println!("{json}");
+        }
+
+        Command::Audit {
+            vault,
+            score,
+            reuse_graph,
+            json,
+        } => {
+            let vault = given_vault(home_dir, config, vault.as_deref())?;
+            let (vault, codec) = unlock_vault(home_dir, config, &vault)?;
+
+            let cache = cache::open_tracked(home_dir, &vault, &config.cache, &codec)?;
+            let (entries, _) = load_entries(&cache, &codec)?;
+
+            if reuse_graph {
+                print!("{}", audit::reuse_graph(&entries));
+                return Ok(());
+            }
+            let history = History::open(home_dir, &vault)?;
+
+            let health = audit::health(&entries, &history);
+
+            if json {
+                let findings = health.actions.iter().map(|f| {
+                    serde_json::json!({
+                        "severity": f.severity.label().to_lowercase(),
+                        "entry": f.entry,
+                        "message": f.message,
+                    })
+                });
+
+                return print_json(&serde_json::json!({
+                    "vault": vault.name,
+                    "score": health.score,
+                    "findings": findings.collect::<Vec<_>>(),
+                }));
+            }
+
+            if score {
+                println!("Health score: {}/100", health.score);
+            }
+
+            print_findings(&health.actions);
+        }
+
+        Command::QrSend {
+            vault,
+            name,
+            interval,
+            loops,
+            reason,
+        } => {
+            let vault = given_vault(home_dir, config, vault.as_deref())?;
+            let (vault, codec) = unlock_vault(home_dir, config, &vault)?;
+
+            let cache = cache::open_tracked(home_dir, &vault, &config.cache, &codec)?;
+            let entry_key = codec.key_for(&name);
+            let bytes = cache
+                .get(entry_key.as_bytes())?
+                .ok_or_else(|| InternalError::IO(format!("Entry '{name}' not found")))?;
+            let entry = codec.decode(entry_key.as_bytes(), &bytes)?;
+
+            let mut history = History::open(home_dir, &vault)?.with_mirror(&config.history);
+
+            approve_release(
+                &config.approval,
+                &vault,
+                &format!("entry '{name}'"),
+                entry.break_glass,
+            )?;
+
+            if entry.break_glass {
+                log_break_glass_reason(&mut history, &name, reason)?;
+            }
+
+            if let Some(warning) = history.record_view(&name, Source::Command)? {
+                eprintln!("[WARN]: {warning}");
+            }
+
+            // JSON so the receiver doesn't depend on this vault's format
+            let frames = qr::frames(&entry.encode(EntryFormat::Json)?);
+            let rendered = frames
+                .iter()
+                .map(|f| qr::render(f))
+                .collect::<InternalResult<Vec<_>>>()?;
+
+            let interval = std::time::Duration::from_millis(interval.unwrap_or(QR_INTERVAL_MS));
+
+            // a single code doesn't need animating
+            let loops = if rendered.len() == 1 {
+                1
+            } else {
+                loops.unwrap_or(QR_LOOPS)
+            };
+
+            for _ in 0..loops {
+                for (i, code) in rendered.iter().enumerate() {
+                    // clear screen, so frames replace each other
+                    print!("\x1b[2J\x1b[H{code}\nFrame {}/{}\n", i + 1, rendered.len());
+                    std::io::stdout().flush()?;
+
+                    if rendered.len() > 1 {
+                        std::thread::sleep(interval);
+                    }
+                }
+            }
+        }
+
+        Command::QrReceive { vault, file } => {
+            let vault = given_vault(home_dir, config, vault.as_deref())?;
+
+            let input = match &file {
+                Some(file) => std::fs::read_to_string(file)
+                    .map_err(|e| InternalError::IO(format!("Failed to read '{file}': {e}")))?,
+                None => std::io::read_to_string(std::io::stdin())?,
+            };
+
+            let mut assembler = qr::Assembler::default();
+            let mut data = None;
+
+            for line in input.lines().filter(|l| !l.trim().is_empty()) {
+                if let Some(done) = assembler.push(line)? {
+                    data = Some(done);
+                    break;
+                }
+            }
+
+            let Some(data) = data else {
+                let missing: Vec<String> =
+                    assembler.missing().iter().map(|i| i.to_string()).collect();
+
+                return Err(InternalError::IO(format!(
+                    "Incomplete transfer, missing frames {} of {}",
+                    missing.join(", "),
+                    assembler.total()
+                )));
+            };
+
+            let entry = Entry::decode(&data)?;
+
+            let (vault, codec) = unlock_vault(home_dir, config, &vault)?;
+            let cache = cache::open_tracked(home_dir, &vault, &config.cache, &codec)?;
+            let key = codec.key(&entry);
+
+            if cache.get(key.as_bytes())?.is_some() {
+                return Err(InternalError::IO(format!(
+                    "Entry '{}' already exists",
+                    entry.name()
+                )));
+            }
+
+            cache.set(key.as_bytes(), &codec.encode(&entry)?)?;
+            println!("Received entry '{}'.", entry.name());
+        }
+
+        Command::ShareOnce {
+            vault,
+            name,
+            bind,
+            timeout,
+            reason,
+        } => {
+            let vault = given_vault(home_dir, config, vault.as_deref())?;
+            let (vault, codec) = unlock_vault(home_dir, config, &vault)?;
+
+            let cache = cache::open_tracked(home_dir, &vault, &config.cache, &codec)?;
+            let entry_key = codec.key_for(&name);
+            let bytes = cache
+                .get(entry_key.as_bytes())?
+                .ok_or_else(|| InternalError::IO(format!("Entry '{name}' not found")))?;
+            let entry = codec.decode(entry_key.as_bytes(), &bytes)?;
+
+            let mut history = History::open(home_dir, &vault)?.with_mirror(&config.history);
+
+            approve_release(
+                &config.approval,
+                &vault,
+                &format!("entry '{name}'"),
+                entry.break_glass,
+            )?;
+
+            if entry.break_glass {
+                log_break_glass_reason(&mut history, &name, reason)?;
+            }
+
+            let bind = bind.as_deref().unwrap_or(share::DEFAULT_BIND);
+            let timeout = timeout.unwrap_or(share::DEFAULT_TIMEOUT_SECS);
+            let share = share::Share::bind(bind, share::payload(&entry))?;
+
+            println!("Link: {}", share.url()?);
+            println!("PIN: {}", share.pin());
+            println!("Cert fingerprint (SHA-256): {}", share.fingerprint());
+            println!("Waiting {timeout}s for the recipient, Ctrl+C to cancel ...");
+
+            match share.serve(std::time::Duration::from_secs(timeout))? {
+                share::Outcome::Served(peer) => {
+                    println!("Shared w/ {peer}, link closed.");
+
+                    if let Some(warning) = history.record_view(&name, Source::Command)? {
+                        eprintln!("[WARN]: {warning}");
+                    }
+                }
+                share::Outcome::Locked => {
+                    return Err(InternalError::IO(
+                        "Too many wrong PINs, link closed w/o sharing".into(),
+                    ));
+                }
+                share::Outcome::Expired => {
+                    return Err(InternalError::IO("Link expired w/o being used".into()));
+                }
+            }
+        }
+
+        // needs the interactive setup, dispatched from `run`
+        Command::Demo => unreachable!("demo is not a plain command"),
+
+        Command::VerifyVault { vault, reset } => {
+            let vault = given_vault(home_dir, config, vault.as_deref())?;
+            let (vault, codec) = unlock_vault(home_dir, config, &vault)?;
+            let cache = cache::open_tracked(home_dir, &vault, &config.cache, &codec)?;
+
+            if reset {
+                let count = cache.reset_manifest()?;
+                println!("Recorded {count} values as the vault's known good state.");
+
+                return Ok(());
+            }
+
+            let report = cache.verify()?;
+            let problems = [
+                ("MISSING", &report.missing),
+                ("CHANGED", &report.changed),
+                ("UNKNOWN", &report.unknown),
+            ];
+
+            for (problem, keys) in problems {
+                for key in keys {
+                    // hashed keys don't reveal names, intact values still do
+                    let name = cache
+                        .get(key)?
+                        .and_then(|value| codec.decode(key, &value).ok())
+                        .map(|entry| entry.name().to_string())
+                        .unwrap_or_else(|| String::from_utf8_lossy(key).into_owned());
+
+                    println!("[{problem}] {name}");
+                }
+            }
+
+            if !report.is_clean() {
+                return Err(InternalError::Corrupted(format!(
+                    "Vault '{}' doesn't match its manifest, restore it from a backup or accept \
+                     its current state w/ --reset",
+                    vault.name
+                )));
+            }
+
+            println!("Vault '{}' matches its manifest.", vault.name);
+        }
+
+        Command::Unlock { vault } => {
+            config.key_cache.ensure_allows(CachePolicy::Agent)?;
+
+            let vault = given_vault(home_dir, config, vault.as_deref())?;
+            let (key, slot) = unlock(home_dir, config, &vault)?;
+            agent::add(home_dir, &vault, &key, slot)?;
+
+            let mut until = vec!["`vuoto lock`".to_string()];
+
+            if let Some(after) = config.session.lock_after() {
+                until.push(format!("{}s w/o use", after.as_secs()));
+            }
+            if let Some(ttl) = config.key_cache.agent_ttl() {
+                until.push(format!("{}s from now", ttl.as_secs()));
+            }
+
+            println!(
+                "Vault '{}' stays unlocked until {}.",
+                vault.name,
+                until.join(", or ")
+            );
+        }
+
+        Command::Lock { all } => {
+            if agent::lock(home_dir)? {
+                println!("Locked all vaults.");
+            } else {
+                println!("No vault is unlocked.");
+            }
+
+            if all {
+                let vault_idx = VaultIndex::open(&home_dir.as_ref())?;
+
+                for vault in vault_idx.vaults().iter().filter_map(|n| vault_idx.get(n)) {
+                    if keystore::enrolled_slot(home_dir, vault).is_none() {
+                        continue;
+                    }
+
+                    keystore::remove(keystore_of(home_dir, vault)?.as_ref(), home_dir, vault)?;
+                    println!("Removed vault '{}' from the OS keychain.", vault.name);
+                }
+
+                clipboard::clear()?;
+            }
+        }
+
+        Command::Status => print_key_cache(home_dir, config)?,
+
+        Command::Agent => agent::serve(
+            home_dir,
+            config.session.lock_after(),
+            config.session.lock_on_sleep,
+            config.key_cache.agent_ttl(),
+        )?,
+
+        Command::RecoveryKit { vault } => {
+            let vault = given_vault(home_dir, config, vault.as_deref())?;
+            let keyfile = read_keyfile(config, &vault)?;
+            let keyfile = keyfile.as_deref().map(Vec::as_slice);
+            let (mut header, key, _) =
+                unlock_master(home_dir, config, &vault, keyfile, "print a recovery kit")?;
+
+            if let Some(created_at) = header.kit_created_at()
+                && !prompt::confirm(
+                    &format!(
+                        "Replace the recovery kit created {}? It stops working.",
+                        clock::format_timestamp(created_at)
+                    ),
+                    false,
+                )?
+            {
+                return Ok(());
+            }
+
+            let created_at = clock::now();
+            let code = header.set_kit(&key, created_at)?;
+            header.save(home_dir, &vault)?;
+
+            print_recovery_kit(&vault, &header, &code, created_at)?;
+        }
+
+        Command::Scratch { vault, clear } => {
+            let vault = given_vault(home_dir, config, vault.as_deref())?;
+            let (vault, codec) = unlock_vault(home_dir, config, &vault)?;
+
+            if clear {
+                if scratch::remove(home_dir, &vault)? {
+                    println!("Scratchpad of '{}' deleted.", vault.name);
+                } else {
+                    println!("Scratchpad of '{}' is empty.", vault.name);
+                }
+                return Ok(());
+            }
+
+            let key = codec.scratch_key();
+            let text = scratch::load(home_dir, &vault, &key, clock::now())?.unwrap_or_default();
+            let edited = Zeroizing::new(prompt::editor("Scratchpad:", &text, ".txt")?);
+
+            if edited.trim().is_empty() {
+                scratch::remove(home_dir, &vault)?;
+                println!("Scratchpad of '{}' deleted.", vault.name);
+                return Ok(());
+            }
+
+            let expires_at = clock::now().saturating_add(config.scratch.ttl_secs);
+            scratch::save(home_dir, &vault, &key, &edited, expires_at)?;
+
+            println!(
+                "Scratchpad of '{}' saved, it's deleted {}.",
+                vault.name,
+                clock::format_timestamp(expires_at)
+            );
+        }
+
+        Command::Recover { vault } => {
+            let vault = given_vault(home_dir, config, vault.as_deref())?;
+
+            if !MasterHeader::exists(home_dir, &vault) {
+                return Err(InternalError::IO(format!(
+                    "Vault '{}' has no master password yet, unlock it first",
+                    vault.name
+                )));
+            }
+
+            let mut header = load_header(home_dir, config, &vault)?;
+
+            if header.kit_created_at().is_none() {
+                return Err(InternalError::IO(format!(
+                    "Vault '{}' has no recovery kit",
+                    vault.name
+                )));
+            }
+
+            let code = prompt::password("Recovery key:")?;
+            let key = header
+                .open_kit(code.expose())?
+                .ok_or_else(|| InternalError::IO("Wrong recovery key".into()))?;
+
+            println!("Set a new master password for vault '{}'.", vault.name);
+
+            let keyfile = read_keyfile(config, &vault)?;
+            let keyfile = keyfile.as_deref().map(Vec::as_slice);
+            let password = prompt::new_password("New master password:")?;
+            let new_key = header.rewrap(
+                Slot::Master,
+                password.expose(),
+                keyfile,
+                header.kdf(Slot::Master),
+            )?;
+
+            rekey(
+                home_dir,
+                config,
+                &vault,
+                Slot::Master,
+                key,
+                new_key,
+                &mut header,
+            )?;
+            Attempts::reset(home_dir, &vault)?;
+
+            println!(
+                "Master password of '{}' changed, its recovery kit stays valid.",
+                vault.name
+            );
+        }
+
+        Command::KeychainAdd { vault, biometric } => {
+            config.key_cache.ensure_allows(CachePolicy::Keychain)?;
+
+            let vault = given_vault(home_dir, config, vault.as_deref())?;
+            let store = match biometric {
+                true => biometric::os()?,
+                false => keystore::os()?,
+            };
+            let (key, slot) = unlock(home_dir, config, &vault)?;
+
+            // a secret in the other keystore would linger
+            if keystore::enrolled_slot(home_dir, &vault).is_some()
+                && keystore::enrolled_biometric(home_dir, &vault) != biometric
+            {
+                keystore::remove(keystore_of(home_dir, &vault)?.as_ref(), home_dir, &vault)?;
+            }
+
+            keystore::enroll(store.as_ref(), home_dir, &vault, &key, slot)?;
+
+            if biometric {
+                println!(
+                    "Vault '{}' now unlocks w/ biometrics on this machine, or its master password if they fail.",
+                    vault.name
+                );
+            } else {
+                println!(
+                    "Vault '{}' now unlocks w/o its master password on this machine.",
+                    vault.name
+                );
+            }
+        }
+
+        Command::KeychainRemove { vault } => {
+            let vault = given_vault(home_dir, config, vault.as_deref())?;
+
+            if keystore::remove(keystore_of(home_dir, &vault)?.as_ref(), home_dir, &vault)? {
+                println!("Removed vault '{}' from the OS keychain.", vault.name);
+            } else {
+                println!("Vault '{}' is not in the OS keychain.", vault.name);
+            }
+        }
+
+        Command::TwoFactorEnroll { vault, factor } => {
+            let vault = given_vault(home_dir, config, vault.as_deref())?;
+            let keyfile = read_keyfile(config, &vault)?;
+            let keyfile = keyfile.as_deref().map(Vec::as_slice);
+            let (mut header, key, password) =
+                unlock_master(home_dir, config, &vault, keyfile, "enroll a hardware key")?;
+
+            let (device, passwordless, challenge) = match factor {
+                SecondFactor::Yubikey { slot } => {
+                    let mut challenge = vec![0u8; yubikey::CHALLENGE_SIZE];
+                    rng::fill(&mut challenge)?;
+
+                    (TokenDevice::Yubikey { slot }, false, challenge)
+                }
+                SecondFactor::Fido2 { passwordless } => {
+                    println!("Touch the security key to create a credential for vuoto.");
+                    let device = fido2::enroll(&config.fido2)?;
+
+                    let mut salt = vec![0u8; fido2::SALT_SIZE];
+                    rng::fill(&mut salt)?;
+
+                    (device, passwordless, salt)
+                }
+            };
+
+            let response = token_response(config, &device, &challenge)?;
+
+            let kdf = header.kdf(Slot::Master);
+            let code =
+                header.set_token(device.clone(), &challenge, &response, kdf, passwordless)?;
+            let new_key = header.rewrap(Slot::Master, password.expose(), keyfile, kdf)?;
+
+            rekey(
+                home_dir,
+                config,
+                &vault,
+                Slot::Master,
+                key,
+                new_key,
+                &mut header,
+            )?;
+
+            match passwordless {
+                true => println!(
+                    "Vault '{}' now unlocks w/ the {} alone, or its master password and a recovery code.",
+                    vault.name,
+                    device.name()
+                ),
+                false => println!(
+                    "Vault '{}' now requires the {} next to its master password.",
+                    vault.name,
+                    device.name()
+                ),
+            }
+
+            println!(
+                "Recovery code, standing in for the {} if it's lost, store it offline:\n\n    {}\n",
+                device.name(),
+                code.as_str()
+            );
+        }
+
+        Command::TwoFactorRemove { vault } => {
+            let vault = given_vault(home_dir, config, vault.as_deref())?;
+            let keyfile = read_keyfile(config, &vault)?;
+            let keyfile = keyfile.as_deref().map(Vec::as_slice);
+            let (mut header, key, password) =
+                unlock_master(home_dir, config, &vault, keyfile, "remove its hardware key")?;
+
+            if !header.has_token() {
+                println!("Vault '{}' doesn't require a hardware key.", vault.name);
+                return Ok(());
+            }
+
+            header.remove_token();
+            let new_key = header.rewrap(
+                Slot::Master,
+                password.expose(),
+                keyfile,
+                header.kdf(Slot::Master),
+            )?;
+
+            rekey(
+                home_dir,
+                config,
+                &vault,
+                Slot::Master,
+                key,
+                new_key,
+                &mut header,
+            )?;
+            println!("Vault '{}' no longer requires a hardware key.", vault.name);
+        }
+
+        Command::Doctor => {
+            let checks = [
+                ("Crypto self-test", crypto::self_test()),
+                ("OS random source", rng::health()),
+            ];
+            let mut failed = 0;
+
+            for (check, res) in checks {
+                match res {
+                    Ok(()) => println!("[OK]   {check}"),
+                    Err(err) => {
+                        failed += 1;
+                        println!("[FAIL] {check}: {err}");
+                    }
+                }
+            }
+
+            if failed > 0 {
+                return Err(InternalError::IO(format!("{failed} check(s) failed")));
+            }
+        }
+
+        Command::VerifyHistory { vault } => {
+            let vault = given_vault(home_dir, config, vault.as_deref())?;
+            let res = history::verify(home_dir, &vault)?;
+
+            println!(
+                "History of '{}' is intact: {} records, {} covered by the latest checkpoint.",
+                vault.name, res.records, res.checkpointed
+            );
+        }
+
+        Command::MigrateHistory { vault } => {
+            let vault = given_vault(home_dir, config, vault.as_deref())?;
+            // only the vault's owner may accept its history as it is
+            unlock(home_dir, config, &vault)?;
+            let records = history::migrate(home_dir, &vault)?;
+
+            println!(
+                "Chained {records} records of the history of '{}'.",
+                vault.name
+            );
+        }
+
+        Command::Passwd { vault, decoy } => {
+            let vault = given_vault(home_dir, config, vault.as_deref())?;
+            let (key, slot) = unlock(home_dir, config, &vault)?;
+            let mut header = load_header(home_dir, config, &vault)?;
+
+            // a newly configured keyfile is added, see `unlock`
+            let keyfile = read_keyfile(config, &vault)?;
+            let keyfile = keyfile.as_deref().map(Vec::as_slice);
+
+            if decoy {
+                return set_decoy(home_dir, config, &vault, slot, header, keyfile);
+            }
+
+            // w/ the decoy password this changes the decoy one, just the same
+            let password = prompt::new_password("New master password:")?;
+            let new_key = header.rewrap(slot, password.expose(), keyfile, header.kdf(slot))?;
+
+            rekey(home_dir, config, &vault, slot, key, new_key, &mut header)?;
+            println!("Master password of '{}' changed.", vault.name);
+        }
+
+        Command::RekdfVault { vault, algorithm } => {
+            let vault = given_vault(home_dir, config, vault.as_deref())?;
+
+            if !MasterHeader::exists(home_dir, &vault) {
+                return Err(InternalError::IO(format!(
+                    "Vault '{}' has no master password yet, unlock it first",
+                    vault.name
+                )));
+            }
+
+            let mut header = load_header(home_dir, config, &vault)?;
+
+            if header.kdf(Slot::Master).algorithm == algorithm {
+                println!("Vault '{}' already uses {}.", vault.name, algorithm.name());
+                return Ok(());
+            }
+
+            let keyfile = read_keyfile(config, &vault)?;
+            let keyfile = keyfile.as_deref().map(Vec::as_slice);
+            let (key, slot, password) = ask_password(home_dir, config, &vault, &header, keyfile)?;
+
+            let kdf = KdfParams::recommended(algorithm);
+            let new_key = header.rewrap(slot, password.expose(), keyfile, kdf)?;
+
+            rekey(home_dir, config, &vault, slot, key, new_key, &mut header)?;
+            println!("Vault '{}' now derives its key w/ {kdf}.", vault.name);
+        }
+
+        Command::KdfBench { vault, target } => {
+            let vault = given_vault(home_dir, config, vault.as_deref())?;
+
+            if !MasterHeader::exists(home_dir, &vault) {
+                return Err(InternalError::IO(format!(
+                    "Vault '{}' has no master password yet, unlock it first",
+                    vault.name
+                )));
+            }
+
+            let mut header = MasterHeader::load(home_dir, &vault)?;
+            let target = Duration::from_millis(target.unwrap_or(KDF_TARGET_MS));
+
+            println!("Timing key derivation, this may take a few seconds...");
+            let (kdf, took) = crypto::bench_kdf(header.recommended_kdf(), target)?;
+
+            println!("{kdf} takes {} ms here.", took.as_millis());
+
+            if !header.kdf(Slot::Master).weaker_than(&kdf) {
+                println!("Vault '{}' already uses these or stronger.", vault.name);
+                return Ok(());
+            }
+
+            header.set_tuned(kdf);
+            header.save(home_dir, &vault)?;
+
+            println!(
+                "Saved, the key of '{}' is re-derived the next time it's unlocked.",
+                vault.name
+            );
+        }
+
+        Command::ListFormats { export } => {
+            let registry = plugins::Registry::load();
+
+            let formats: Vec<(&str, String)> = if export {
+                registry
+                    .exporters()
+                    .iter()
+                    .map(|p| (p.name(), p.description()))
+                    .collect()
+            } else {
+                registry
+                    .importers()
+                    .iter()
+                    .map(|p| (p.name(), p.description()))
+                    .collect()
+            };
+
+            for (name, description) in formats {
+                println!("{name:<16}{description}");
+            }
+        }
+
+        Command::Import { source, vault } => {
+            let vault = given_vault(home_dir, config, vault.as_deref())?;
+            let entries = read_imported(source)?;
+            let (vault, codec) = unlock_vault(home_dir, config, &vault)?;
+
+            // grown once up front, imports easily fill a vault
+            let (cache, grown) =
+                cache::open_to_fit(home_dir, &vault, &config.cache, entries.len())?;
+            let cache = VaultCache::new(home_dir, &vault, cache, &codec)?;
+
+            if let Some(capacity) = grown {
+                VaultIndex::open(&home_dir.as_ref())?.set_capacity(&vault.name, Some(capacity))?;
+            }
+
+            let (mut imported, mut overwritten, mut skipped) = (0, 0, 0);
+            // choice applied to all remaining conflicts, once picked
+            let mut remembered = None;
+
+            for entry in entries {
+                let key = codec.key(&entry);
+
+                let Some(bytes) = cache.get(key.as_bytes())? else {
+                    cache.set(key.as_bytes(), &codec.encode(&entry)?)?;
+                    imported += 1;
+                    continue;
+                };
+
+                // importing twice is a no-op, w/o a terminal existing entries
+                // are always kept, as are corrupted ones, to be repaired
+                let existing = match codec.decode(key.as_bytes(), &bytes) {
+                    Ok(existing) if existing.to_value()? != entry.to_value()? => existing,
+                    _ => {
+                        skipped += 1;
+                        continue;
+                    }
+                };
+
+                if !term::is_interactive()
+                    || !overwrite_on_import(&existing, &entry, &mut remembered)?
+                {
+                    skipped += 1;
+                    continue;
+                }
+
+                let replaced = replace_imported(existing, entry);
+                cache.set(key.as_bytes(), &codec.encode(&replaced)?)?;
+                overwritten += 1;
+            }
+
+            println!(
+                "Imported {imported} entries, overwrote {overwritten}, skipped {skipped} existing."
+            );
+        }
+
+        Command::MigrateDevice { export: true, file } => {
+            let passphrase = prompt::new_password("Transfer passphrase:")?;
+            let (bundle, count) = migrate::export(
+                home_dir,
+                dirs::config_dir().as_deref(),
+                passphrase.expose(),
+                Default::default(),
+            )?;
+
+            std::fs::write(&file, bundle)
+                .map_err(|e| InternalError::IO(format!("Failed to write '{file}': {e}")))?;
+
+            println!("Bundled {count} files into '{file}'.");
+        }
+
+        Command::MigrateDevice {
+            export: false,
+            file,
+        } => {
+            let bundle = std::fs::read(&file)
+                .map_err(|e| InternalError::IO(format!("Failed to read '{file}': {e}")))?;
+            let passphrase = prompt::password("Transfer passphrase:")?;
+
+            let count = migrate::import(
+                home_dir,
+                dirs::config_dir().as_deref(),
+                &bundle,
+                passphrase.expose(),
+            )?;
+            println!("Unpacked {count} files, unlock w/ your existing master password.");
+        }
+
+        Command::Lookup { path, format } => {
+            let index = VaultIndex::open(&home_dir.as_ref())?;
+            let (vault, name, field) = split_lookup(&index, &path)?;
+            let value = release_secret(home_dir, config, &vault, name, field)?;
+
+            let mut stdout = std::io::stdout();
+
+            match format {
+                LookupFormat::Ansible => stdout.write_all(value.as_bytes())?,
+                LookupFormat::Json => {
+                    let json = Zeroizing::new(serde_json::json!({ "value": *value }).to_string());
+                    writeln!(stdout, "{}", json.as_str())?;
+                }
+            }
+
+            stdout.flush()?;
+        }
+
+        Command::Get {
+            vault,
+            name,
+            field,
+            json: true,
+            show_secrets,
+        } => {
+            let vault = given_vault(home_dir, config, vault.as_deref())?;
+            let entry = release_entry(home_dir, config, &vault, &name)?;
+
+            let Some(field) = field else {
+                return print_json(&entry_json(&entry, show_secrets));
+            };
+
+            let secret = entry.is_secret_field(Some(&field));
+            let mut json = serde_json::json!({
+                "name": name,
+                "field": field,
+                "secret": secret,
+            });
+
+            if show_secrets || !secret {
+                json["value"] = entry_value(&entry, Some(&field))?.as_str().into();
+            }
+
+            print_json(&json)?;
+        }
+
+        Command::Get {
+            vault, name, field, ..
+        } => {
+            let vault = given_vault(home_dir, config, vault.as_deref())?;
+            let value = release_secret(home_dir, config, &vault, &name, field.as_deref())?;
+            let mut stdout = std::io::stdout();
+
+            // undecorated for `$(vuoto get ..)`, which strips a newline anyway
+            stdout.write_all(value.as_bytes())?;
+            if term::is_decorated() {
+                writeln!(stdout)?;
+            }
+
+            stdout.flush()?;
+        }
+
+        Command::List {
+            vault,
+            json,
+            show_secrets,
+        } => {
+            let vault = given_vault(home_dir, config, vault.as_deref())?;
+            let (vault, codec) = unlock_vault(home_dir, config, &vault)?;
+            let cache = cache::open_tracked(home_dir, &vault, &config.cache, &codec)?;
+
+            let (mut entries, undecodable) = load_entries(&cache, &codec)?;
+            entries::sort_entries(&mut entries);
+
+            if json && show_secrets {
+                approve_release(&config.approval, &vault, "all entries", false)?;
+
+                // recorded like `get`, quiet about unusual access too
+                let mut history = History::open(home_dir, &vault)?.with_mirror(&config.history);
+
+                for entry in entries.iter().filter(|e| !e.break_glass) {
+                    history.record_view(entry.name(), Source::Command)?;
+                }
+
+                let break_glass = entries.iter().filter(|e| e.break_glass).count();
+
+                if break_glass > 0 {
+                    eprintln!("[WARN]: Secrets of {break_glass} break-glass entries are left out.");
+                }
+            }
+
+            if json {
+                // break-glass secrets are only released w/ a logged reason
+                let list = entries
+                    .iter()
+                    .map(|e| entry_json(e, show_secrets && !e.break_glass));
+                print_json(&serde_json::Value::Array(list.collect()))?;
+            } else {
+                for entry in &entries {
+                    println!("{}", entry.name());
+                }
+            }
+
+            if !undecodable.is_empty() {
+                eprintln!(
+                    "[WARN]: {} corrupted entries are not listed, see `vuoto entry inspect`.",
+                    undecodable.len()
+                );
+            }
+        }
+
+        Command::Add {
+            vault,
+            name,
+            username,
+            url,
+            generate,
+            password_stdin,
+        } => {
+            let mut login = LoginEntry {
+                name,
+                password: SecretString::default(),
+                username,
+                url,
+            };
+            // before unlocking, so scripts fail fast
+            login.validate()?;
+
+            let vault = given_vault(home_dir, config, vault.as_deref())?;
+            let (vault, codec) = unlock_vault(home_dir, config, &vault)?;
+
+            let (cache, grown) = cache::open_or_grow(home_dir, &vault, &config.cache)?;
+            let cache = VaultCache::new(home_dir, &vault, cache, &codec)?;
+
+            if let Some(capacity) = grown {
+                VaultIndex::open(&home_dir.as_ref())?.set_capacity(&vault.name, Some(capacity))?;
+            }
+
+            let entry_key = codec.key_for(&login.name);
+
+            if cache.get(entry_key.as_bytes())?.is_some() {
+                return Err(InternalError::Exists(format!(
+                    "Entry '{}' already exists",
+                    login.name
+                )));
+            }
+
+            login.password = if generate {
+                generator::generate(&config.generator)?.into()
+            } else if password_stdin || !std::io::stdin().is_terminal() {
+                read_stdin_line()?
+            } else {
+                prompt::new_password("Password:")?
+            };
+
+            if login.password.expose().is_empty() {
+                return Err(InternalError::Invalid("Password must be non-empty".into()));
+            }
+
+            let mut entry = Entry::new(Template::Login(login));
+            entry.detect_2fa();
+
+            cache.set(codec.key(&entry).as_bytes(), &codec.encode(&entry)?)?;
+            println!("Entry '{}' added.", entry.name());
+        }
+
+        Command::Remove { vault, name, yes } => {
+            let vault = given_vault(home_dir, config, vault.as_deref())?;
+            let (vault, codec) = unlock_vault(home_dir, config, &vault)?;
+            let cache = cache::open_tracked(home_dir, &vault, &config.cache, &codec)?;
+            let entry_key = codec.key_for(&name);
+
+            if cache.get(entry_key.as_bytes())?.is_none() {
+                return Err(InternalError::NotFound(format!("Entry '{name}' not found")));
+            }
+
+            if !yes && !prompt::confirm(&format!("Permanently delete entry '{name}'?"), false)? {
+                return Ok(());
+            }
+
+            cache.del(entry_key.as_bytes())?;
+            println!("Entry '{name}' deleted.");
+        }
+
+        Command::VaultList { json: true } => {
+            let vault_idx = VaultIndex::open(&home_dir.as_ref())?;
+            let default = default_vault(home_dir, config)?;
+
+            let list = vault_idx
+                .vaults()
+                .iter()
+                .filter_map(|n| vault_idx.get(n))
+                .map(|vault| {
+                    serde_json::json!({
+                        "name": vault.name,
+                        "archived": vault.archived,
+                        "default": default.as_ref() == Some(&vault.name),
+                    })
+                });
+
+            print_json(&serde_json::Value::Array(list.collect()))?;
+        }
+
+        Command::VaultList { json: false } => {
+            let vault_idx = VaultIndex::open(&home_dir.as_ref())?;
+            let default = default_vault(home_dir, config)?;
+
+            for vault in vault_idx.vaults().iter().filter_map(|n| vault_idx.get(n)) {
+                let mut line = vault.name.clone();
+
+                if default.as_ref() == Some(&vault.name) {
+                    line.push_str(" (default)");
+                }
+                if vault.archived {
+                    line.push_str(" (archived)");
+                }
+
+                println!("{line}");
+            }
+        }
+
+        Command::VaultDefault { name, clear, json } => {
+            let mut defaults = Defaults::load(home_dir)?;
+            let changed = name.is_some() || clear;
+
+            if changed {
+                defaults.vault = match name {
+                    Some(name) => Some(find_vault(home_dir, &name)?.name),
+                    None => None,
+                };
+                defaults.save(home_dir)?;
+            }
+
+            match (json, changed, default_vault(home_dir, config)?) {
+                (true, _, vault) => print_json(&serde_json::json!({ "default": vault }))?,
+                (false, true, _) if clear => println!("Default vault unset."),
+                (false, true, Some(vault)) => println!("Default vault set to '{vault}'."),
+                (false, _, Some(vault)) => println!("{vault}"),
+                (false, _, None) => {
+                    return Err(InternalError::NotFound("No default vault set".into()));
+                }
+            }
+        }
+
+        Command::VaultCreate { name, json } => {
+            let mut vault_idx = VaultIndex::open(&home_dir.as_ref())?;
+
+            if vault_idx.get(&name).is_some() {
+                return Err(InternalError::IO(format!("Vault '{name}' already exists")));
+            }
+
+            vault_idx.add(&name)?;
+            let vault = find_vault(home_dir, &name)?;
+
+            // sets the master password right away
+            unlock(home_dir, config, &vault)?;
+
+            match json {
+                true => print_json(&serde_json::json!({ "created": name }))?,
+                false => println!("Vault '{name}' created."),
+            }
+        }
+
+        Command::VaultRename {
+            name,
+            new_name,
+            json,
+        } => {
+            let vault = find_vault(home_dir, &name)?;
+            VaultIndex::open(&home_dir.as_ref())?.rename(&vault.name, &new_name)?;
+            Defaults::vault_moved(home_dir, &vault.name, Some(&new_name))?;
+
+            match json {
+                true => print_json(&serde_json::json!({ "renamed": name, "to": new_name }))?,
+                false => println!("Vault '{name}' renamed to '{new_name}'."),
+            }
+        }
+
+        Command::VaultDelete { name, yes, json } => {
+            let vault = find_vault(home_dir, &name)?;
+            let mut vault_idx = VaultIndex::open(&home_dir.as_ref())?;
+
+            if !yes {
+                return delete_vault(home_dir, &mut vault_idx, &vault);
+            }
+
+            destroy_vault(home_dir, &mut vault_idx, &vault)?;
+
+            match json {
+                true => print_json(&serde_json::json!({ "deleted": name }))?,
+                false => println!("Vault '{name}' deleted."),
+            }
+        }
+
+        Command::EncryptFile {
+            vault,
+            path,
+            entry,
+            output,
+        } => {
+            let vault = given_vault(home_dir, config, vault.as_deref())?;
+            let data = Zeroizing::new(
+                std::fs::read(&path)
+                    .map_err(|e| InternalError::IO(format!("Failed to read '{path}': {e}")))?,
+            );
+            let output = output.unwrap_or_else(|| format!("{path}.{ENCRYPTED_FILE_EXT}"));
+            let name = entry.unwrap_or_else(|| FILE_KEY_ENTRY.to_string());
+            refuse_existing(&output)?;
+
+            let (vault, codec) = unlock_vault(home_dir, config, &vault)?;
+            let cache = cache::open_tracked(home_dir, &vault, &config.cache, &codec)?;
+            let entry_key = codec.key_for(&name);
+
+            let secret = match cache.get(entry_key.as_bytes())? {
+                // a password could be guessed offline against the file
+                Some(bytes) => file_key_secret(&codec.decode(entry_key.as_bytes(), &bytes)?)
+                    .ok_or_else(|| {
+                        InternalError::Invalid(format!(
+                            "Entry '{name}' isn't a {FILE_KEY_ENTRY} entry, pass a new name \
+                             to create one"
+                        ))
+                    })?,
+                None => {
+                    let secret = VaultKey::random()?.to_base64().to_string();
+                    let entry = Entry::new(Template::Custom(CustomEntry {
+                        name: name.clone(),
+                        kind: FILE_KEY_ENTRY.into(),
+                        fields: vec![CustomField {
+                            name: "Key".into(),
+                            value: secret.clone(),
+                            secret: true,
+                        }],
+                    }));
+
+                    // stored first, a file is never left w/o its key
+                    cache.set(codec.key(&entry).as_bytes(), &codec.encode(&entry)?)?;
+                    println!("Created entry '{name}' holding the key.");
+
+                    secret
+                }
+            };
+            let secret = Zeroizing::new(secret);
+
+            write_new_file(&output, &crypto::encrypt_file(&data, &name, &secret)?)?;
+            println!("Encrypted '{path}' to '{output}', the original is kept.");
+        }
+
+        Command::DecryptFile {
+            vault,
+            path,
+            output,
+        } => {
+            let vault = given_vault(home_dir, config, vault.as_deref())?;
+            let bytes = std::fs::read(&path)
+                .map_err(|e| InternalError::IO(format!("Failed to read '{path}': {e}")))?;
+            crypto::validate_file(&bytes)?;
+            let output = match output {
+                Some(output) => output,
+                None => path
+                    .strip_suffix(&format!(".{ENCRYPTED_FILE_EXT}"))
+                    .filter(|p| !p.is_empty())
+                    .map(str::to_string)
+                    .ok_or_else(|| {
+                        InternalError::IO(format!(
+                            "'{path}' doesn't end in .{ENCRYPTED_FILE_EXT}, pass --output"
+                        ))
+                    })?,
+            };
+            refuse_existing(&output)?;
+
+            let (vault, codec) = unlock_vault(home_dir, config, &vault)?;
+            let cache = cache::open_tracked(home_dir, &vault, &config.cache, &codec)?;
+            let (entries, _) = load_entries(&cache, &codec)?;
+
+            // the file only holds a tag of its entry's name
+            let mut found = None;
+
+            for entry in entries {
+                if let Some(secret) = entry.field(None).map(Zeroizing::new)
+                    && crypto::is_file_of(&bytes, entry.name(), &secret)?
+                {
+                    found = Some((entry, secret));
+                    break;
+                }
+            }
+
+            let (entry, secret) = found.ok_or_else(|| {
+                InternalError::NotFound(format!(
+                    "No entry of '{}' holds the key of '{path}'",
+                    vault.name
+                ))
+            })?;
+            let name = entry.name().to_string();
+
+            if entry.break_glass {
+                return Err(InternalError::IO(format!(
+                    "Entry '{name}' is break-glass, use `vuoto entry inspect --reason`"
+                )));
+            }
+
+            approve_release(&config.approval, &vault, &format!("file '{path}'"), false)?;
+
+            let data = Zeroizing::new(crypto::decrypt_file(&bytes, &secret)?);
+
+            let mut history = History::open(home_dir, &vault)?.with_mirror(&config.history);
+
+            if let Some(warning) = history.record_view(&name, Source::Command)? {
+                eprintln!("[WARN]: {warning}");
+            }
+
+            write_new_file(&output, &data)?;
+            println!("Decrypted '{path}' to '{output}'.");
+        }
+
+        Command::Provision {
+            vault,
+            unit,
+            secrets,
+            encrypt,
+            dir,
+        } => {
+            let (store, unit_dir) = provision_target(&unit, encrypt, dir)?;
+
+            let vault = given_vault(home_dir, config, vault.as_deref())?;
+            let (vault, codec) = unlock_vault(home_dir, config, &vault)?;
+            let cache = cache::open_tracked(home_dir, &vault, &config.cache, &codec)?;
+            let (mut entries, _) = load_entries(&cache, &codec)?;
+            entries::sort_entries(&mut entries);
+
+            let secrets = match secrets.is_empty() {
+                false => secrets,
+                true => {
+                    let names: Vec<&str> = entries
+                        .iter()
+                        .filter(|e| !e.break_glass && e.field(None).is_some())
+                        .map(Entry::name)
+                        .collect();
+                    let picked =
+                        prompt::multi_select(&format!("Secrets for {unit}:"), names.clone())?;
+
+                    picked.into_iter().map(|i| names[i].to_string()).collect()
+                }
+            };
+
+            if secrets.is_empty() {
+                return Err(InternalError::IO("No secrets to provision".into()));
+            }
+
+            let mut creds = Vec::new();
+            let mut released = Vec::new();
+
+            for spec in &secrets {
+                let (cred, spec) = match spec.split_once('=') {
+                    Some((cred, spec)) => (Some(cred), spec),
+                    None => (None, spec.as_str()),
+                };
+                let (name, field) = match spec.split_once('#') {
+                    Some((name, field)) => (name, Some(field)),
+                    None => (spec, None),
+                };
+
+                let entry = entries
+                    .iter()
+                    .find(|e| e.name() == name)
+                    .ok_or_else(|| InternalError::IO(format!("Entry '{name}' not found")))?;
+
+                if entry.break_glass {
+                    return Err(InternalError::IO(format!(
+                        "Entry '{name}' is break-glass, use `vuoto entry inspect --reason`"
+                    )));
+                }
+
+                let value = entry.field(field).ok_or_else(|| {
+                    InternalError::IO(match field {
+                        Some(field) => format!("Entry '{name}' has no field '{field}'"),
+                        None => format!("Entry '{name}' has no secret, pick a field w/ #FIELD"),
+                    })
+                })?;
+
+                creds.push(provision::Credential {
+                    name: match cred {
+                        Some(cred) => provision::credential_name(cred, None),
+                        None => provision::credential_name(name, field),
+                    },
+                    value: Zeroizing::new(value),
+                });
+                released.push(entry);
+            }
+
+            approve_release(
+                &config.approval,
+                &vault,
+                &format!("{} secrets for unit '{unit}'", creds.len()),
+                false,
+            )?;
+
+            let mut history = History::open(home_dir, &vault)?.with_mirror(&config.history);
+
+            for entry in &released {
+                if let Some(warning) = history.record_view(entry.name(), Source::Command)? {
+                    eprintln!("[WARN]: {warning}");
+                }
+            }
+
+            let paths = provision::provision(&unit_dir, &store, &creds)?;
+
+            println!(
+                "Provisioned {} credentials for {unit}, add to its [Service] section:\n",
+                paths.len()
+            );
+
+            for (cred, path) in creds.iter().zip(&paths) {
+                println!("{}", provision::unit_line(&store, &cred.name, path));
+            }
+        }
+
+        Command::Deprovision { unit, encrypt, dir } => {
+            let (_, unit_dir) = provision_target(&unit, encrypt, dir)?;
+
+            match provision::deprovision(&unit_dir)? {
+                0 => println!(
+                    "Nothing is provisioned for {unit} in {}.",
+                    unit_dir.display()
+                ),
+                removed => println!("Removed {removed} credentials of {unit}."),
+            }
+        }
+
+        Command::Export {
+            format,
+            vault,
+            file,
+            protect,
+            reason,
+        } => {
+            let registry = plugins::Registry::load();
+            let exporter = registry
+                .exporter(&format)
+                .ok_or_else(|| InternalError::IO(format!("Unknown export format '{format}'")))?;
+
+            let vault = given_vault(home_dir, config, vault.as_deref())?;
+            let (vault, codec) = unlock_vault(home_dir, config, &vault)?;
+            let cache = cache::open_tracked(home_dir, &vault, &config.cache, &codec)?;
+            let (mut entries, undecodable) = load_entries(&cache, &codec)?;
+            entries::sort_entries(&mut entries);
+
+            if !undecodable.is_empty() {
+                eprintln!(
+                    "Skipping {} corrupted entries, see `vuoto entry inspect`.",
+                    undecodable.len()
+                );
+            }
+
+            let break_glass = entries.iter().filter(|e| e.break_glass).count();
+
+            // w/o a reason to log, break-glass entries stay in the vault
+            if break_glass > 0 && reason.is_none() {
+                entries.retain(|e| !e.break_glass);
+                eprintln!("Skipping {break_glass} break-glass entries, export them w/ --reason.");
+            }
+
+            let break_glass = entries.iter().any(|e| e.break_glass);
+            approve_release(&config.approval, &vault, "all entries", break_glass)?;
+
+            let mut history = History::open(home_dir, &vault)?.with_mirror(&config.history);
+
+            for entry in entries.iter().filter(|e| e.break_glass) {
+                log_break_glass_reason(&mut history, entry.name(), reason.clone())?;
+            }
+
+            for entry in &entries {
+                if let Some(warning) = history.record_view(entry.name(), Source::Command)? {
+                    eprintln!("[WARN]: {warning}");
+                }
+            }
+
+            let mut output = exporter.export(&entries)?;
+
+            if protect {
+                let passphrase = prompt::new_password("Export passphrase:")?;
+                output = crypto::protect(&output, passphrase.expose(), Default::default())?;
+            }
+
+            match file {
+                Some(file) => std::fs::write(&file, output)
+                    .map_err(|e| InternalError::IO(format!("Failed to write '{file}': {e}")))?,
+                None => std::io::stdout().write_all(&output)?,
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Secret of entry `name` in `vault`, or its `field`, the current code of
+/// its TOTP secret for `otp`, for commands printing it, w/o a terminal
+/// only w/ a key of `vuoto unlock`, the keychain or a given master password
+fn release_secret<P: AsRef<Path>>(
+    home_dir: &P,
+    config: &Config,
+    vault: &Vault,
+    name: &str,
+    field: Option<&str>,
+) -> InternalResult<Zeroizing<String>> {
+    let entry = release_entry(home_dir, config, vault, name)?;
+    entry_value(&entry, field)
+}
+
+/// Unlock `vault` and look up `name` for `get`, once the release is
+/// approved and the view recorded
+fn release_entry<P: AsRef<Path>>(
+    home_dir: &P,
+    config: &Config,
+    vault: &Vault,
+    name: &str,
+) -> InternalResult<Entry> {
+    let (key, slot) = match cached_key(home_dir, config, vault) {
+        Some(cached) => cached,
+        None if term::is_interactive() || config.master_password.is_some() => {
+            unlock(home_dir, config, vault)?
+        }
+        None => {
+            return Err(InternalError::Locked(format!(
+                "Vault '{}' is locked, run `vuoto unlock --vault {}` first",
+                vault.name, vault.name
+            )));
+        }
+    };
+    let (vault, codec) = open_unlocked(home_dir, config, vault, key, slot)?;
+
+    let cache = cache::open_tracked(home_dir, &vault, &config.cache, &codec)?;
+    let entry_key = codec.key_for(name);
+    let bytes = cache
+        .get(entry_key.as_bytes())?
+        .ok_or_else(|| InternalError::NotFound(format!("Entry '{name}' not found")))?;
+    let entry = codec.decode(entry_key.as_bytes(), &bytes)?;
+
+    if entry.break_glass {
+        return Err(InternalError::IO(format!(
+            "Entry '{name}' is break-glass, use `vuoto entry inspect --reason`"
+        )));
+    }
+
+    approve_release(&config.approval, &vault, &format!("entry '{name}'"), false)?;
+
+    // recorded, but stays quiet about unusual access
+    History::open(home_dir, &vault)?
+        .with_mirror(&config.history)
+        .record_view(name, Source::Command)?;
+
+    Ok(entry)
+}
+
+/// `field` of the entry, its current TOTP code for "otp"
+fn entry_value(entry: &Entry, field: Option<&str>) -> InternalResult<Zeroizing<String>> {
+    let name = entry.name();
+
+    let value = match field {
+        Some(otp) if otp.eq_ignore_ascii_case("otp") => {
+            let secret = entry.totp_secret().ok_or_else(|| {
+                InternalError::NotFound(format!("Entry '{name}' has no TOTP secret"))
+            })?;
+
+            Zeroizing::new(totp::code(secret, clock::now())?)
+        }
+        _ => Zeroizing::new(entry.field(field).ok_or_else(|| {
+            InternalError::NotFound(match field {
+                Some(field) => format!("Entry '{name}' has no field '{field}'"),
+                None => format!("Entry '{name}' has no secret, pick one of its fields"),
+            })
+        })?),
+    };
+
+    Ok(value)
+}
+
+/// Entry as `{"name", "fields"}` for --json, w/ secret fields only if
+/// `show_secrets`
+fn entry_json(entry: &Entry, show_secrets: bool) -> serde_json::Value {
+    let fields = entry
+        .field_values()
+        .into_iter()
+        .filter(|f| show_secrets || !f.secret)
+        .map(|f| (f.name, f.value.into()));
+
+    serde_json::json!({
+        "name": entry.name(),
+        "fields": serde_json::Map::from_iter(fields),
+    })
+}
+
+fn print_json(value: &serde_json::Value) -> InternalResult<()> {
+    let json = Zeroizing::new(
+        serde_json::to_string(value)
+            .map_err(|e| InternalError::IO(format!("Failed to encode JSON: {e}")))?,
+    );
+
+    println!("{}", json.as_str());
+    Ok(())
+}
+
+/// First line of stdin, e.g. a password piped in by a script
+fn read_stdin_line() -> InternalResult<SecretString> {
+    let mut raw = Zeroizing::new(String::new());
+    std::io::stdin().read_line(&mut raw)?;
+
+    Ok(raw.lines().next().unwrap_or_default().into())
+}
+
+/// Vault, entry name and field of a `<VAULT>/<ENTRY>[#FIELD]` path, the
+/// shortest existing vault wins since vault names may contain `/` too
+fn split_lookup<'a>(
+    index: &VaultIndex,
+    path: &'a str,
+) -> InternalResult<(Vault, &'a str, Option<&'a str>)> {
+    let (vault, rest) = path
+        .match_indices('/')
+        .map(|(i, _)| (&path[..i], &path[i + 1..]))
+        .find_map(|(name, rest)| index.get(name).map(|v| (v.clone(), rest)))
+        .ok_or_else(|| match path.split_once('/') {
+            Some((name, _)) => InternalError::NotFound(format!("Vault '{name}' not found")),
+            None => InternalError::Usage(format!(
+                "Invalid lookup '{path}', expected <VAULT>/<ENTRY>[#FIELD]"
+            )),
+        })?;
+
+    let (name, field) = match rest.rsplit_once('#') {
+        Some((name, field)) => (name, Some(field)),
+        None => (rest, None),
+    };
+
+    if name.is_empty() || field.is_some_and(str::is_empty) {
+        return Err(InternalError::Usage(format!(
+            "Invalid lookup '{path}', expected <VAULT>/<ENTRY>[#FIELD]"
+        )));
+    }
+
+    Ok((vault, name, field))
+}
+
+/// How and where credentials of `unit` are written, see `vuoto provision`
+fn provision_target(
+    unit: &str,
+    encrypt: bool,
+    dir: Option<String>,
+) -> InternalResult<(provision::Store, PathBuf)> {
+    let (store, default_dir) = match encrypt {
+        true => (provision::Store::SystemdCreds, provision::ENCRYPTED_DIR),
+        false => (provision::Store::Files, provision::RUNTIME_DIR),
+    };
+    let base = dir.map_or_else(|| PathBuf::from(default_dir), PathBuf::from);
+
+    Ok((store, provision::unit_dir(&base, unit)?))
+}
+
+/// Walk through a throwaway vault w/ sample entries
+fn run_demo(mut config: Config) -> InternalResult<()> {
+    // keep demo activity out of the user's log pipeline
+    config.history = HistoryConfig::default();
+
+    let (dir, vault) = demo::DemoDir::create()?;
+    let cache = cache::open(&dir.path(), &vault, &config.cache)?;
+
+    // nothing worth a master password in here
+    let codec = EntryCodec::new(vault.format, VaultKey::random()?);
+
+    for entry in demo::sample_entries() {
+        cache.set(codec.key(&entry).as_bytes(), &codec.encode(&entry)?)?;
+    }
+
+    drop(cache);
+
+    term::header("vuoto demo");
+    println!("This is a throwaway vault, it's deleted when you quit.\n");
+
+    for (i, step) in demo::TUTORIAL.iter().enumerate() {
+        println!("  {}. {step}", i + 1);
+    }
+
+    term::footer("vuoto demo");
+
+    // nothing to lock either
+    let session = Session::new(codec, None, None, false, || {
+        unreachable!("demo never locks")
+    })?;
+
+    login_selection_loop(&dir.path(), &vault, &config, &session)
+}
+
+/// Unlock `vault` for reading and writing its entries, returning it routed
+/// to the data the password opened, see [`Slot::data_vault`]
+fn unlock_vault<P: AsRef<Path>>(
+    home_dir: &P,
+    config: &Config,
+    vault: &Vault,
+) -> InternalResult<(Vault, EntryCodec)> {
+    let (key, slot) = match cached_key(home_dir, config, vault) {
+        Some(cached) => cached,
+        None => unlock(home_dir, config, vault)?,
+    };
+
+    open_unlocked(home_dir, config, vault, key, slot)
+}
+
+/// Create empty decoy data of `vault` if it has none, so every vault has it
+/// whether a decoy password is set or not, see [`Slot::data_vault`]
+fn create_decoy_data<P: AsRef<Path>>(
+    home_dir: &P,
+    config: &Config,
+    vault: &Vault,
+) -> InternalResult<()> {
+    let data = Slot::Decoy.data_vault(vault);
+
+    if home_dir.as_ref().join(&data.slug).exists() {
+        return Ok(());
+    }
+
+    // its manifest is sealed like any other, under a key nobody has
+    let codec = EntryCodec::new(data.format, VaultKey::random()?);
+    cache::open_tracked(home_dir, &data, &config.cache, &codec)?;
+
+    Ok(())
+}
+
+/// Key of `vault` kept by the agent or the OS keychain, if any and the
+/// [`CachePolicy`] still allows keeping it there
+fn cached_key<P: AsRef<Path>>(
+    home_dir: &P,
+    config: &Config,
+    vault: &Vault,
+) -> Option<(VaultKey, Slot)> {
+    let policy = config.key_cache.policy;
+
+    (policy >= CachePolicy::Agent)
+        .then(|| agent::get(home_dir, vault))
+        .flatten()
+        .or_else(|| {
+            (policy >= CachePolicy::Keychain)
+                .then(|| keychain_key(home_dir, vault))
+                .flatten()
+        })
+}
+
+/// [`unlock_vault`] w/ the key at hand
+fn open_unlocked<P: AsRef<Path>>(
+    home_dir: &P,
+    config: &Config,
+    vault: &Vault,
+    key: VaultKey,
+    slot: Slot,
+) -> InternalResult<(Vault, EntryCodec)> {
+    create_decoy_data(home_dir, config, vault)?;
+
+    let vault = slot.data_vault(vault);
+
+    // empty decoy data may still have the manifest it was created w/
+    if slot == Slot::Decoy
+        && cache::open(home_dir, &vault, &config.cache)?
+            .iter()?
+            .next()
+            .is_none()
+    {
+        Manifest::remove(home_dir, &vault)?;
+    }
+
+    let codec = EntryCodec::new(vault.format, key);
+    let cache = cache::open_tracked(home_dir, &vault, &config.cache, &codec)?;
+
+    // older versions used plain names as keys, and sealed values w/o them
+    let mut legacy = Vec::new();
+
+    for i in cache.iter()? {
+        let (key, value) = i?;
+
+        if !EntryCodec::is_hashed_key(&key) || crypto::is_v1_sealed(&value) {
+            legacy.push((key, value));
+        }
+    }
+
+    for (key, value) in legacy {
+        // corrupted entries stay where they are, to be repaired
+        if let Ok(entry) = codec.decode_legacy(&key, &value) {
+            let new_key = codec.key(&entry);
+            cache.set(new_key.as_bytes(), &codec.encode(&entry)?)?;
+
+            if new_key.as_bytes() != key {
+                cache.del(&key)?;
+            }
+        }
+    }
+
+    Ok((vault, codec))
+}
+
+/// Key of `vault` from the OS keychain, if it was added there w/ `vuoto
+/// keychain add`
+fn keychain_key<P: AsRef<Path>>(home_dir: &P, vault: &Vault) -> Option<(VaultKey, Slot)> {
+    keystore::enrolled_slot(home_dir, vault)?;
+
+    // failed or unavailable biometrics fall back to the master password
+    let res = keystore_of(home_dir, vault)
+        .and_then(|store| keystore::unlock(store.as_ref(), home_dir, vault));
+
+    match res {
+        Ok(Some(cached)) => Some(cached),
+        Ok(None) => {
+            eprintln!(
+                "[WARN]: OS keychain holds no valid key for vault '{}', run `vuoto keychain add` again.",
+                vault.name
+            );
+            None
+        }
+        Err(err) => {
+            eprintln!("[WARN]: {err}");
+            None
+        }
+    }
+}
+
+/// Keystore `vault` was added to, the biometric one w/ `keychain add
+/// --biometric`
+fn keystore_of<P: AsRef<Path>>(home_dir: &P, vault: &Vault) -> InternalResult<Box<dyn Keystore>> {
+    match keystore::enrolled_biometric(home_dir, vault) {
+        true => biometric::os(),
+        false => keystore::os(),
+    }
+}
+
+/// Derive the vault key from its master (or decoy) password, setting one
+/// up (and encrypting the vault's entries) on first use
+fn unlock<P: AsRef<Path>>(
+    home_dir: &P,
+    config: &Config,
+    vault: &Vault,
+) -> InternalResult<(VaultKey, Slot)> {
+    if !MasterHeader::exists(home_dir, vault) {
+        let cache = cache::open(home_dir, vault, &config.cache)?;
+        let (sealed, plain) = split_sealed(&cache)?;
+
+        // entries sealed by older versions keep the app wide password
+        if !sealed.is_empty() && MasterHeader::legacy_exists(home_dir) {
+            MasterHeader::adopt_legacy(home_dir, vault)?;
+        } else {
+            let keyfile = read_keyfile(config, vault)?;
+
+            // stdout is kept to the JSON w/ --json
+            if !prompt::is_disabled() {
+                println!("Set a master password for vault '{}'.", vault.name);
+
+                if keyfile.is_some() {
+                    println!("The keyfile will be required to unlock it too.");
+                }
+            }
+
+            let password = match &config.master_password {
+                Some(password) => password.clone(),
+                None => prompt::new_password("New master password:")?,
+            };
+            let key = MasterHeader::create(
+                home_dir,
+                vault,
+                password.expose(),
+                keyfile.as_deref().map(Vec::as_slice),
+                Default::default(),
+            )?;
+
+            // raw values are sealed as is, corrupted entries stay repairable
+            for (k, v) in &plain {
+                cache.set(k, &crypto::seal(&key, k, v)?)?;
+            }
+
+            if !plain.is_empty() {
+                println!("Encrypted {} entries.", plain.len());
+            }
+
+            return Ok((key, Slot::Master));
+        }
+    }
+
+    let mut header = load_header(home_dir, config, vault)?;
+
+    if let Some(key) = header.passwordless_key() {
+        return Ok((key, Slot::Master));
+    }
+
+    let keyfile = read_keyfile(config, vault)?;
+    let keyfile = keyfile.as_deref().map(Vec::as_slice);
+    let (key, slot, password) = ask_password(home_dir, config, vault, &header, keyfile)?;
+
+    // benchmarks only apply to the master password
+    if slot == Slot::Decoy || !header.needs_upgrade() {
+        return Ok((key, slot));
+    }
+
+    // only now the password is at hand to re-derive the key
+    let kdf = header.recommended_kdf();
+    let new_key = header.rewrap(slot, password.expose(), keyfile, kdf)?;
+    rekey(
+        home_dir,
+        config,
+        vault,
+        slot,
+        key,
+        new_key.clone(),
+        &mut header,
+    )?;
+    println!("Upgraded key derivation of vault '{}'.", vault.name);
+
+    Ok((new_key, slot))
+}
+
+/// Unlock `vault` again after its session locked, the password must open
+/// the same data as before, see [`Slot::data_vault`]
+fn relock<P: AsRef<Path>>(
+    home_dir: &P,
+    config: &Config,
+    vault: &Vault,
+    data: &Vault,
+) -> InternalResult<EntryCodec> {
+    let header = load_header(home_dir, config, vault)?;
+
+    let (key, slot) = match header.passwordless_key() {
+        Some(key) => (key, Slot::Master),
+        None => {
+            let keyfile = read_keyfile(config, vault)?;
+            let keyfile = keyfile.as_deref().map(Vec::as_slice);
+            let (key, slot, _) = ask_password(home_dir, config, vault, &header, keyfile)?;
+
+            (key, slot)
+        }
+    };
+
+    if slot.data_vault(vault) != *data {
+        return Err(InternalError::IO("Wrong master password".into()));
+    }
+
+    Ok(EntryCodec::new(data.format, key))
+}
+
+/// Master header of `vault`, along w/ its hardware key's response if it
+/// needs one, restored from the recovery code if the key doesn't answer
+fn load_header<P: AsRef<Path>>(
+    home_dir: &P,
+    config: &Config,
+    vault: &Vault,
+) -> InternalResult<MasterHeader> {
+    let mut header = MasterHeader::load(home_dir, vault)?;
+
+    let Some((device, challenge)) = header.token_challenge()? else {
+        return Ok(header);
+    };
+
+    match token_response(config, &device, &challenge) {
+        Ok(response) => {
+            header.set_response(&response);
+            return Ok(header);
+        }
+        Err(err) => eprintln!("[WARN]: {err}"),
+    }
+
+    if !prompt::confirm("Unlock w/ the recovery code instead?", false)? {
+        return Err(InternalError::IO(format!(
+            "Vault '{}' requires its {}",
+            vault.name,
+            device.name()
+        )));
+    }
+
+    for _ in 0..MAX_UNLOCK_ATTEMPTS {
+        let code = prompt::password("Recovery code:")?;
+
+        if header.recover(code.expose())? {
+            eprintln!(
+                "[WARN]: Unlocking w/o the {}, run `vuoto 2fa enroll` to pair a replacement.",
+                device.name()
+            );
+            return Ok(header);
+        }
+
+        eprintln!("Wrong recovery code.");
+    }
+
+    Err(InternalError::IO("Too many wrong recovery codes".into()))
+}
+
+/// Answer of the hardware key `device` to `challenge`
+fn token_response(
+    config: &Config,
+    device: &TokenDevice,
+    challenge: &[u8],
+) -> InternalResult<Zeroizing<Vec<u8>>> {
+    // stdout stays clean for `vuoto lookup`
+    eprintln!("Touch the {} if it blinks.", device.name());
+
+    match device {
+        TokenDevice::Yubikey { slot } => yubikey::respond(&config.yubikey, *slot, challenge),
+        TokenDevice::Fido2 { credential, uv } => {
+            fido2::respond(&config.fido2, credential, *uv, challenge)
+        }
+    }
+}
+
+/// Header, key and password of `vault` unlocked w/ its master password
+/// and `keyfile`, which `action` needs
+fn unlock_master<P: AsRef<Path>>(
+    home_dir: &P,
+    config: &Config,
+    vault: &Vault,
+    keyfile: Option<&[u8]>,
+    action: &str,
+) -> InternalResult<(MasterHeader, VaultKey, SecretString)> {
+    if !MasterHeader::exists(home_dir, vault) {
+        return Err(InternalError::IO(format!(
+            "Vault '{}' has no master password yet, unlock it first",
+            vault.name
+        )));
+    }
+
+    let header = load_header(home_dir, config, vault)?;
+    let (key, slot, password) = ask_password(home_dir, config, vault, &header, keyfile)?;
+
+    if slot == Slot::Decoy {
+        return Err(InternalError::IO(format!(
+            "Unlock w/ the master password to {action}"
+        )));
+    }
+
+    Ok((header, key, password))
+}
+
+/// Prompt for the master password until it unlocks `header`, returning
+/// the key, the slot it opened and the password, wrong ones are counted
+/// and delayed across runs, see [`Attempts`]
+fn ask_password<P: AsRef<Path>>(
+    home_dir: &P,
+    config: &Config,
+    vault: &Vault,
+    header: &MasterHeader,
+    keyfile: Option<&[u8]>,
+) -> InternalResult<(VaultKey, Slot, SecretString)> {
+    match (header.requires_keyfile(), keyfile) {
+        (true, None) => {
+            return Err(InternalError::IO(format!(
+                "Vault '{}' requires a keyfile, pass --keyfile or set it under [keyfiles]",
+                vault.name
+            )));
+        }
+        (false, Some(_)) => eprintln!(
+            "[WARN]: Vault '{}' doesn't use a keyfile, run `vuoto passwd` to add it.",
+            vault.name
+        ),
+        _ => {}
+    }
+
+    let mut attempts = Attempts::load(home_dir, vault);
+    let wipe_after = config.unlock.wipe_after_failures;
+    // a given password can't be retyped
+    let tries = match config.master_password {
+        Some(_) => 1,
+        None => MAX_UNLOCK_ATTEMPTS,
+    };
+
+    for _ in 0..tries {
+        let wait = attempts.wait(clock::now());
+
+        if wait > 0 {
+            eprintln!(
+                "Waiting {wait}s after {} wrong master passwords.",
+                attempts.failures
+            );
+            std::thread::sleep(Duration::from_secs(wait));
+        }
+
+        let password = match &config.master_password {
+            Some(password) => password.clone(),
+            None => {
+                // checked before every attempt, wrong ones count all the same
+                if let Some(warning) = keyboard::warning() {
+                    eprintln!("[WARN]: {warning}.");
+                }
+
+                prompt::password(&format!("Master password for '{}':", vault.name))?
+            }
+        };
+
+        if let Some((key, slot, password)) =
+            unlock_tolerant(header, password, keyfile, config.unlock.typo_tolerant)?
+        {
+            if attempts.failures > 0 {
+                eprintln!(
+                    "[WARN]: {} wrong master passwords were entered since vault '{}' was last unlocked.",
+                    attempts.failures, vault.name
+                );
+                Attempts::reset(home_dir, vault)?;
+            }
+
+            return Ok((key, slot, password));
+        }
+
+        attempts.record_failure(home_dir, vault, clock::now())?;
+
+        if wipe_after > 0 && attempts.failures >= wipe_after {
+            let mut vault_idx = VaultIndex::open(&home_dir.as_ref())?;
+            destroy_vault(home_dir, &mut vault_idx, vault)?;
+
+            return Err(InternalError::IO(format!(
+                "Vault '{}' was wiped after {} wrong master passwords",
+                vault.name, attempts.failures
+            )));
+        }
+
+        let left = wipe_after.saturating_sub(attempts.failures);
+
+        if wipe_after > 0 && left <= WIPE_WARNING {
+            eprintln!(
+                "Wrong master password, vault '{}' is wiped after {left} more.",
+                vault.name
+            );
+        } else if config.master_password.is_none() {
+            eprintln!("Wrong master password.");
+        }
+    }
+
+    Err(InternalError::IO(match config.master_password {
+        Some(_) => format!("Wrong master password given for vault '{}'", vault.name),
+        None => "Too many wrong master passwords".into(),
+    }))
+}
+
+/// Re-encrypt all entries `slot` opens in `vault` from `old_key` to
+/// `new_key`, then replace its master header w/ `header`
+fn rekey<P: AsRef<Path>>(
+    home_dir: &P,
+    config: &Config,
+    vault: &Vault,
+    slot: Slot,
+    old_key: VaultKey,
+    new_key: VaultKey,
+    header: &mut MasterHeader,
+) -> InternalResult<()> {
+    // the printed recovery kit must keep opening the vault
+    if slot == Slot::Master {
+        header.rewrap_kit(&old_key, &new_key)?;
+    }
+
+    let data = slot.data_vault(vault);
+    let old_codec = EntryCodec::new(vault.format, old_key);
+    let new_codec = EntryCodec::new(vault.format, new_key.clone());
+
+    let cache = cache::open(home_dir, &data, &config.cache)?;
+    let capacity = cache::capacity(&data, &config.cache);
+    let mut unreadable = 0;
+
+    // re-encrypt into a fresh cache, swapped in once complete along w/ the
+    // header, whose salt and verifier are those of the new key
+    let header_raw = header.encode()?;
+    let cache = cache::rewrite(
+        home_dir,
+        &data,
+        cache,
+        capacity,
+        Some((&crypto::header_file(vault), &header_raw)),
+        // keys are hashed w/ the vault key, so they change too
+        |k, value| match old_codec.decode(k, value) {
+            Ok(entry) => Ok((
+                new_codec.key(&entry).into_bytes(),
+                new_codec.encode(&entry)?,
+            )),
+            Err(_) => {
+                unreadable += 1;
+                Ok((k.to_vec(), value.to_vec()))
+            }
+        },
+    )?;
+    let manifest = Manifest::of(&cache)?;
+    drop(cache);
+
+    manifest.save(home_dir, &data, &new_codec.manifest_key())?;
+
+    // the vault is rekeyed by now, an unreadable scratchpad can't stop that
+    let old_scratch = old_codec.scratch_key();
+    let new_scratch = new_codec.scratch_key();
+
+    if let Err(err) = scratch::rekey(home_dir, &data, &old_scratch, &new_scratch, clock::now()) {
+        eprintln!("[WARN]: Deleted the scratchpad, it couldn't be re-encrypted: {err}");
+        scratch::remove(home_dir, &data)?;
+    }
+
+    if unreadable > 0 {
+        eprintln!("Kept {unreadable} unreadable entries as is, see `vuoto entry inspect`.");
+    }
+
+    // the keychain's copy of the old key no longer opens the vault
+    if keystore::enrolled_slot(home_dir, vault) == Some(slot) {
+        let res = keystore_of(home_dir, vault)
+            .and_then(|store| keystore::enroll(store.as_ref(), home_dir, vault, &new_key, slot));
+
+        if let Err(err) = res {
+            eprintln!("[WARN]: Failed to update the OS keychain: {err}");
+        }
+    }
+
+    Ok(())
+}
+
+/// Unlock `header` w/ `password`, or w/ what was likely meant if
+/// `typo_tolerant`, returning the password that matched
+fn unlock_tolerant(
+    header: &MasterHeader,
+    password: SecretString,
+    keyfile: Option<&[u8]>,
+    typo_tolerant: bool,
+) -> InternalResult<Option<(VaultKey, Slot, SecretString)>> {
+    if let Some((key, slot)) = header.unlock(password.expose(), keyfile)? {
+        return Ok(Some((key, slot, password)));
+    }
+
+    if !typo_tolerant {
+        return Ok(None);
+    }
+
+    for variant in password.typo_variants() {
+        if let Some((key, slot)) = header.unlock(variant.expose(), keyfile)? {
+            return Ok(Some((key, slot, variant)));
+        }
+    }
+
+    Ok(None)
+}
+
+/// Set up (or replace) the duress password of `vault`, opening a fresh,
+/// empty set of decoy entries
+fn set_decoy<P: AsRef<Path>>(
+    home_dir: &P,
+    config: &Config,
+    vault: &Vault,
+    slot: Slot,
+    mut header: MasterHeader,
+    keyfile: Option<&[u8]>,
+) -> InternalResult<()> {
+    if slot == Slot::Decoy {
+        return Err(InternalError::IO(
+            "Unlock w/ the master password to set a decoy password".into(),
+        ));
+    }
+
+    let decoy = Slot::Decoy.data_vault(vault);
+
+    // the header never tells if one is set, only entries under it do
+    if cache::open(home_dir, &decoy, &config.cache)?
+        .iter()?
+        .next()
+        .is_some()
+        && !prompt::confirm(
+            "Replace the decoy password? Current decoy entries are deleted.",
+            false,
+        )?
+    {
+        return Ok(());
+    }
+
+    let password = prompt::new_password("New decoy password:")?;
+
+    if let Some((_, Slot::Master)) = header.unlock(password.expose(), keyfile)? {
+        return Err(InternalError::IO(
+            "Decoy password must differ from the master password".into(),
+        ));
+    }
+
+    header.rewrap(
+        Slot::Decoy,
+        password.expose(),
+        keyfile,
+        header.kdf(Slot::Master),
+    )?;
+
+    // entries of a replaced decoy password are unreadable now
+    remove_vault_data(home_dir, &decoy)?;
+    header.save(home_dir, vault)?;
+    create_decoy_data(home_dir, config, vault)?;
+
+    println!(
+        "Decoy password of '{}' set, it unlocks an empty vault to fill w/ harmless entries.",
+        vault.name
+    );
+
+    Ok(())
+}
+
+/// Contents of the keyfile to unlock `vault` w/, if any
+fn read_keyfile(config: &Config, vault: &Vault) -> InternalResult<Option<Zeroizing<Vec<u8>>>> {
+    let Some(path) = config.keyfile_for(&vault.name) else {
+        return Ok(None);
+    };
+
+    let data = std::fs::read(path).map_err(|e| {
+        InternalError::IO(format!("Failed to read keyfile '{}': {e}", path.display()))
+    })?;
+
+    if data.is_empty() {
+        return Err(InternalError::IO(format!(
+            "Keyfile '{}' is empty",
+            path.display()
+        )));
+    }
+
+    Ok(Some(Zeroizing::new(data)))
+}
+
+/// Raw keys and values of a vault, split into sealed and plain ones
+fn split_sealed(cache: &TurboCache) -> InternalResult<(Vec<RawEntry>, Vec<RawEntry>)> {
+    let mut sealed = Vec::new();
+    let mut plain = Vec::new();
+
+    for i in cache.iter()? {
+        let (k, v) = i?;
+
+        match crypto::is_sealed(&v) {
+            true => sealed.push((k, v)),
+            false => plain.push((k, v)),
+        }
+    }
+
+    Ok((sealed, plain))
+}
+
+/// Print the key cache policy, and which vault keys the agent and the OS
+/// keychain hold
+fn print_key_cache<P: AsRef<Path>>(home_dir: &P, config: &Config) -> InternalResult<()> {
+    let vault_idx = VaultIndex::open(&home_dir.as_ref())?;
+    let status = agent::status(home_dir);
+
+    match config.key_cache.agent_ttl() {
+        Some(ttl) => println!(
+            "Key cache policy: {}, agent keys expire after {}s",
+            config.key_cache.policy.name(),
+            ttl.as_secs()
+        ),
+        None => println!("Key cache policy: {}", config.key_cache.policy.name()),
+    }
+
+    match &status {
+        Some(status) => println!("Agent: running, {} interactive session(s)", status.sessions),
+        None => println!("Agent: not running"),
+    }
+
+    for vault in vault_idx.vaults().iter().filter_map(|n| vault_idx.get(n)) {
+        let mut cached = Vec::new();
+
+        let held = status
+            .iter()
+            .flat_map(|s| &s.vaults)
+            .find(|(slug, _)| *slug == vault.slug);
+
+        if let Some((_, secs)) = held {
+            cached.push(format!("agent (unlocked {secs}s ago)"));
+        }
+
+        if keystore::enrolled_slot(home_dir, vault).is_some() {
+            match keystore::enrolled_biometric(home_dir, vault) {
+                true => cached.push("OS keychain (biometric)".to_string()),
+                false => cached.push("OS keychain".to_string()),
+            }
+        }
+
+        match cached.is_empty() {
+            true => println!("  {}: not cached", vault.name),
+            false => println!("  {}: {}", vault.name, cached.join(", ")),
+        }
+    }
+
+    Ok(())
+}
+
+/// Vault set w/ `vuoto vault default`, else the configured one
+fn default_vault<P: AsRef<Path>>(home_dir: &P, config: &Config) -> InternalResult<Option<String>> {
+    Ok(Defaults::load(home_dir)?
+        .vault
+        .or_else(|| config.default_vault.clone()))
+}
+
+/// Vault given to a command, w/o one the global --vault, `VUOTO_VAULT` or
+/// the default vault, in that order
+fn given_vault<P: AsRef<Path>>(
+    home_dir: &P,
+    config: &Config,
+    vault: Option<&str>,
+) -> InternalResult<Vault> {
+    let name = vault.or(config.vault.as_deref()).ok_or_else(|| {
+        InternalError::Usage(
+            "No vault given, use --vault or set one w/ `vuoto vault default <NAME>`".into(),
+        )
+    })?;
+
+    find_vault(home_dir, name)
+}
+
+fn find_vault<P: AsRef<Path>>(home_dir: &P, name: &str) -> InternalResult<Vault> {
+    VaultIndex::open(&home_dir.as_ref())?
+        .get(name)
+        .cloned()
+        .ok_or_else(|| InternalError::NotFound(format!("Vault '{name}' not found")))
+}
+
+fn login_selection_loop<P: AsRef<Path>>(
+    home_dir: &P,
+    vault: &Vault,
+    config: &Config,
+    session: &Session,
+) -> InternalResult<()> {
+    let (cache, grown) = cache::open_or_grow(home_dir, vault, &config.cache)?;
+    let cache = VaultCache::new(home_dir, vault, cache, &session.codec()?)?;
+
+    if let Some(capacity) = grown {
+        VaultIndex::open(&home_dir.as_ref())?.set_capacity(&vault.name, Some(capacity))?;
+    }
+
+    let mut history = History::open(home_dir, vault)?.with_mirror(&config.history);
+
+    let (entries, _) = load_entries(&cache, &session.codec()?)?;
+    let health = audit::health(&entries, &history);
+
+    if !health.actions.is_empty() {
+        println!(
+            "Vault health: {}/100, {} issue(s), see '< Audit vault >'.",
+            health.score,
+            health.actions.len()
+        );
+    }
+
+    loop {
+        let codec = session.codec()?;
+        let (mut entries, undecodable) = load_entries(&cache, &codec)?;
+        entries::sort_entries(&mut entries);
+
+        let mut options: Vec<String> = entries.iter().map(|e| e.label()).collect();
+        options.extend(undecodable.iter().map(|(key, _)| corrupted_label(key)));
+
+        // only labels wait on the user, not decrypted entries
+        let keys: Vec<String> = entries.iter().map(|e| codec.key(e)).collect();
+        drop(entries);
+        drop(codec);
+
+        // menu options
+        options.insert(0, "< Create new entry >".into());
+        options.push("< Organize entries >".into());
+        options.push("< Audit vault >".into());
+        options.push("< Quit >".into());
+
+        let menu_len = options.len();
+
+        // let user pick, w/o holding the key meanwhile
+        let choice = prompt::select_index("Your entries:", options)?;
+
+        if choice == menu_len - 1 {
+            break;
+        }
+
+        // the codec is taken again after each prompt, which re-prompts for
+        // the password if the session locked meanwhile
+        if choice == 0 {
+            let entry = prompt_new_entry(config)?;
+            let codec = session.codec()?;
+            cache.set(codec.key(&entry).as_bytes(), &codec.encode(&entry)?)?;
+
+            // loop again so new entry appears in list
+            continue;
+        } else if choice == menu_len - 3 || choice == menu_len - 2 {
+            let (mut entries, _) = load_entries(&cache, &session.codec()?)?;
+            entries::sort_entries(&mut entries);
+
+            match choice == menu_len - 3 {
+                true => organize_entries(&cache, session, &mut entries)?,
+                false => show_audit(&entries, &history),
+            }
+            continue;
+        } else if choice > keys.len() {
+            let (key, bytes) = &undecodable[choice - 1 - keys.len()];
+            manage_corrupted_entry(&cache, session, key, bytes)?;
+        } else {
+            // show details, then back to the list unless user quits
+            if show_entry(&cache, session, &mut history, keys[choice - 1].as_bytes())? {
+                break;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// An unlocked vault in the merged list of [`all_vaults_loop`]
+struct OpenVault<'a> {
+    vault: Vault,
+    cache: VaultCache,
+    session: Session<'a>,
+    history: History,
+}
+
+/// Unlock several vaults and list their entries together, prefixed w/ the
+/// vault they belong to
+fn all_vaults_loop<P: AsRef<Path>>(home_dir: &P, config: &Config) -> InternalResult<()> {
+    let vault_idx = VaultIndex::open(&home_dir.as_ref())?;
+    let vaults: Vec<Vault> = vault_idx
+        .vaults()
+        .iter()
+        .filter_map(|name| vault_idx.get(name).filter(|v| !v.archived).cloned())
+        .collect();
+
+    let names: Vec<&str> = vaults.iter().map(|v| v.name.as_str()).collect();
+    let picked = prompt::multi_select("Vaults to unlock:", names)?;
+
+    if picked.is_empty() {
+        println!("No vaults selected.");
+        return Ok(());
+    }
+
+    let mut open = Vec::with_capacity(picked.len());
+    let home = home_dir.as_ref();
+
+    for vault in picked.into_iter().map(|i| vaults[i].clone()) {
+        let (data, codec) = unlock_vault(home_dir, config, &vault)?;
+        let cache = cache::open_tracked(home_dir, &data, &config.cache, &codec)?;
+        let history = History::open(home_dir, &data)?.with_mirror(&config.history);
+        let relocked = data.clone();
+
+        // each vault locks on its own, as w/ a single one
+        let session = Session::new(
+            codec,
+            (config.key_cache.policy >= CachePolicy::Session).then_some(home),
+            config.session.lock_after(),
+            config.session.lock_on_sleep,
+            move || relock(&home, config, &vault, &relocked),
+        )?;
+
+        open.push(OpenVault {
+            vault: data,
+            cache,
+            session,
+            history,
+        });
+    }
+
+    loop {
+        // (vault, entry key) pairs, in list order
+        let mut listed = Vec::new();
+        let mut options = Vec::new();
+        let mut corrupted = 0;
+
+        for (i, v) in open.iter().enumerate() {
+            let codec = v.session.codec()?;
+            let (mut entries, undecodable) = load_entries(&v.cache, &codec)?;
+            entries::sort_entries(&mut entries);
+
+            corrupted += undecodable.len();
+
+            // only labels wait on the user, not decrypted entries
+            for entry in entries {
+                options.push(format!("[{}] {}", v.vault.name, entry.label()));
+                listed.push((i, codec.key(&entry)));
+            }
+        }
+
+        if corrupted > 0 {
+            println!("{corrupted} corrupted entries are hidden, open their vault to repair them.");
+        }
+
+        options.push("< Quit >".into());
+
+        let choice = prompt::select_index("All entries:", options)?;
+
+        if choice == listed.len() {
+            break;
+        }
+
+        let (i, key) = &listed[choice];
+        let v = &mut open[*i];
+
+        if show_entry(&v.cache, &v.session, &mut v.history, key.as_bytes())? {
+            break;
+        }
+    }
+
+    Ok(())
+}
+
+/// Raw (key, value) pair as stored in the cache
+type RawEntry = (Vec<u8>, Vec<u8>);
+
+/// Read all entries from the cache, also returning raw (key, value) pairs of
+/// entries which failed to decode
+fn load_entries(
+    cache: &VaultCache,
+    codec: &EntryCodec,
+) -> InternalResult<(Vec<Entry>, Vec<RawEntry>)> {
+    let mut entries = Vec::new();
+    let mut undecodable = Vec::new();
+
+    for i in cache.iter()? {
+        let (key, value) = i?;
+
+        match codec.decode(&key, &value) {
+            Ok(entry) => entries.push(entry),
+            Err(_) => undecodable.push((key, value)),
+        }
+    }
+
+    Ok((entries, undecodable))
+}
+
+fn corrupted_label(key: &[u8]) -> String {
+    let name = entries::name_for_key(key).unwrap_or_else(|| format!("{key:?}"));
+
+    format!("[!] {name} (corrupted)")
+}
+
+/// Inspect, repair or delete an entry which failed to decode
+fn manage_corrupted_entry(
+    cache: &VaultCache,
+    session: &Session,
+    key: &[u8],
+    bytes: &[u8],
+) -> InternalResult<()> {
+    let codec = session.codec()?;
+
+    if let Err(err) = codec.decode(key, bytes) {
+        eprintln!("{err}");
+    }
+
+    // entries which decrypt fine are repaired in plain, the rest as is
+    let plain = codec.open(key, bytes).unwrap_or_else(|_| bytes.to_vec());
+    drop(codec);
+
+    let actions = vec!["Show raw bytes", "Repair in editor", "Delete entry", "Back"];
+
+    match prompt::select("Corrupted entry:", actions)? {
+        "Show raw bytes" => print!("{}", entries::hex_dump(&plain)),
+
+        "Repair in editor" => {
+            let edited = prompt::editor("Entry JSON:", &String::from_utf8_lossy(&plain), ".json")?;
+
+            let entry = match Entry::decode(edited.as_bytes()) {
+                Ok(entry) => entry,
+                Err(err) => {
+                    eprintln!("Still not a valid entry, nothing saved: {err}");
+                    return Ok(());
+                }
+            };
+
+            // name may have been fixed too, so the key can change
+            let codec = session.codec()?;
+            let new_key = codec.key(&entry);
+            cache.set(new_key.as_bytes(), &codec.encode(&entry)?)?;
+
+            if new_key.as_bytes() != key {
+                cache.del(key)?;
+            }
+
+            println!("Entry '{}' repaired.", entry.name());
+        }
+
+        "Delete entry" if prompt::confirm("Permanently delete this entry?", false)? => {
+            cache.del(key)?;
+            println!("Entry deleted.");
+        }
+
+        _ => {}
+    }
+
+    Ok(())
+}
+
+/// Show details of the entry stored under `key` and its actions, returns
+/// `true` when user chose to quit
+fn show_entry(
+    cache: &VaultCache,
+    session: &Session,
+    history: &mut History,
+    key: &[u8],
+) -> InternalResult<bool> {
+    let Some(mut entry) = read_entry(cache, session, key)? else {
+        return Ok(false);
+    };
+
+    if entry.break_glass {
+        let name = entry.name().to_string();
+        drop(entry);
+
+        if !confirm_break_glass(history, &name)? {
+            return Ok(false);
+        }
+
+        let Some(confirmed) = read_entry(cache, session, key)? else {
+            return Ok(false);
+        };
+        entry = confirmed;
+    }
+
+    if let Some(warning) = history.record_view(entry.name(), Source::Interactive)? {
+        eprintln!("[WARN]: {warning}");
+    }
+
+    match &entry.template {
+        Template::Login(login) => show_login_entry(login),
+        Template::License(license) => show_license_entry(license),
+        Template::RecoveryCodes(codes) => show_recovery_codes(codes),
+        Template::Custom(custom) => show_custom_entry(custom),
+    }
+
+    if entry.has_2fa || entry.has_totp() {
+        println!("2FA: enabled\n");
+    }
+
+    if let Some(notes) = &entry.notes {
+        println!("Notes:\n{notes}\n");
+    }
+
+    let stats = history.view_stats(entry.name());
+
+    if let Some(last) = stats.last {
+        println!(
+            "Viewed {} times, last at {}\n",
+            stats.count,
+            clock::format_timestamp(last)
+        );
+    }
+
+    let mut actions = Vec::new();
+
+    if let Template::RecoveryCodes(codes) = &entry.template
+        && codes.unused_count() > 0
+    {
+        actions.push("Consume next unused code");
+    }
+
+    if let Template::Custom(custom) = &entry.template
+        && custom.fields.iter().any(|f| f.secret)
+    {
+        actions.push("Copy secret field");
+    }
+
+    actions.push("Edit notes");
+
+    if !entry.history.is_empty() {
+        actions.push("View notes history");
+    }
+
+    actions.push("Back");
+    actions.push("Quit");
+
+    // only the actions wait on the user, not the decrypted entry
+    drop(entry);
+    let action = prompt::select("Action:", actions)?;
+
+    match action {
+        "Back" => return Ok(false),
+        "Quit" => return Ok(true),
+        _ => {}
+    }
+
+    let Some(mut entry) = read_entry(cache, session, key)? else {
+        return Ok(false);
+    };
+
+    match action {
+        "Consume next unused code" => {
+            if let Template::RecoveryCodes(codes) = &mut entry.template {
+                consume_recovery_code(codes);
+
+                let codec = session.codec()?;
+                cache.set(codec.key(&entry).as_bytes(), &codec.encode(&entry)?)?;
+            }
+        }
+
+        "Copy secret field" => {
+            if let Template::Custom(custom) = &entry.template {
+                copy_secret_field(custom)?;
+            }
+        }
+
+        "Edit notes" if edit_notes(&mut entry)? => {
+            let codec = session.codec()?;
+            cache.set(codec.key(&entry).as_bytes(), &codec.encode(&entry)?)?;
+            println!("Notes saved.");
+        }
+
+        "View notes history" => show_notes_history(&entry),
+
+        _ => {}
+    }
+
+    Ok(false)
+}
+
+/// Entry stored under `key`, `None` if it was deleted meanwhile
+fn read_entry(cache: &VaultCache, session: &Session, key: &[u8]) -> InternalResult<Option<Entry>> {
+    let Some(bytes) = cache.get(key)? else {
+        eprintln!("Entry is gone, it was deleted meanwhile.");
+        return Ok(None);
+    };
+
+    Ok(Some(session.codec()?.decode(key, &bytes)?))
+}
+
+/// Ask on the terminal before a command releases secrets, when the config
+/// requires it for the vault or entry
+///
+/// NOTE: prompts go to the terminal even if stdin/stdout are redirected, w/o
+/// any terminal the release is denied
+fn approve_release(
+    approval: &ApprovalConfig,
+    vault: &Vault,
+    what: &str,
+    break_glass: bool,
+) -> InternalResult<()> {
+    if !approval.required(&vault.name, break_glass) {
+        return Ok(());
+    }
+
+    let message = format!(
+        "Allow this command to read {what} of vault '{}'?",
+        vault.name
+    );
+
+    match prompt::confirm(&message, false) {
+        Ok(true) => Ok(()),
+        Ok(false) => Err(InternalError::IO("Access denied".into())),
+        Err(err) => Err(InternalError::IO(format!(
+            "Access denied, approval required but not given: {err}"
+        ))),
+    }
+}
+
+/// Log the `--reason` a command accesses a break-glass entry w/, which
+/// is mandatory
+fn log_break_glass_reason(
+    history: &mut History,
+    name: &str,
+    reason: Option<String>,
+) -> InternalResult<()> {
+    let reason = reason.filter(|r| !r.trim().is_empty()).ok_or_else(|| {
+        InternalError::IO(format!(
+            "'{name}' is a break-glass entry, pass --reason to access it"
+        ))
+    })?;
+
+    history.append(Event::BreakGlass {
+        entry: name.to_string(),
+        reason,
+    })
+}
+
+/// Ask why a break-glass entry is opened and log the reason, returns
+/// `false` if the user backed out
+fn confirm_break_glass(history: &mut History, name: &str) -> InternalResult<bool> {
+    println!("'{name}' is a break-glass entry, access is logged w/ a reason.");
+
+    let reason = prompt::text_skippable_validated("Reason (esc to go back):", |s| {
+        if s.trim().is_empty() {
+            Err("A reason is required".into())
+        } else {
+            Ok(())
+        }
+    })?;
+
+    let Some(reason) = reason else {
+        return Ok(false);
+    };
+
+    history.append(Event::BreakGlass {
+        entry: name.to_string(),
+        reason: reason.trim().to_string(),
+    })?;
+
+    Ok(true)
+}
+
+/// Whether an imported entry replaces the existing one of the same name,
+/// asking w/ a field level diff unless a choice for all is `remembered`
+fn overwrite_on_import(
+    existing: &Entry,
+    imported: &Entry,
+    remembered: &mut Option<bool>,
+) -> InternalResult<bool> {
+    if let Some(overwrite) = *remembered {
+        return Ok(overwrite);
+    }
+
+    let (old, new) = (existing.field_values(), imported.field_values());
+    let mut reveal = false;
+
+    loop {
+        println!("\nEntry '{}' already exists:", existing.name());
+        print!("{}", diff::fields(&old, &new, reveal));
+
+        let toggle = match reveal {
+            true => "Hide secrets",
+            false => "Show secrets",
+        };
+        let choices = vec![
+            "Keep existing",
+            "Overwrite",
+            toggle,
+            "Keep existing for all remaining",
+            "Overwrite all remaining",
+        ];
+
+        match prompt::select("Conflict:", choices)? {
+            "Keep existing" => return Ok(false),
+            "Overwrite" => return Ok(true),
+            "Keep existing for all remaining" => {
+                *remembered = Some(false);
+                return Ok(false);
+            }
+            "Overwrite all remaining" => {
+                *remembered = Some(true);
+                return Ok(true);
+            }
+            _ => reveal = !reveal,
+        }
+    }
+}
+
+/// Entries to import from a file, a browser or an OS credential store
+fn read_imported(source: ImportSource) -> InternalResult<Vec<Entry>> {
+    let (format, file) = match source {
+        ImportSource::File { format, file } => (format, file),
+        ImportSource::Browser(browser) => {
+            let message = format!("{} primary password:", browser.name());
+            let logins = browser::logins(browser, &mut || prompt::password(&message))?;
+            let (entries, undecryptable) = logins::into_entries(logins);
+
+            if undecryptable > 0 {
+                eprintln!(
+                    "[WARN]: Skipped {undecryptable} {} logins which couldn't be decrypted",
+                    browser.name()
+                );
+            }
+
+            return Ok(entries);
+        }
+        ImportSource::Keychain => return read_keychain(),
+        ImportSource::WindowsCredentials => return read_windows_credentials(),
+        ImportSource::SecretService => {
+            return read_linux_keyring("Secret Service", linux_keyring::secret_service_logins);
+        }
+        ImportSource::KWallet => {
+            return read_linux_keyring("KWallet", linux_keyring::kwallet_logins);
+        }
+    };
+
+    let registry = plugins::Registry::load();
+    let importer = registry
+        .importer(&format)
+        .ok_or_else(|| InternalError::IO(format!("Unknown import format '{format}'")))?;
+
+    let mut input = std::fs::read(&file)
+        .map_err(|e| InternalError::IO(format!("Failed to read '{file}': {e}")))?;
+
+    if crypto::is_protected(&input) {
+        let passphrase = prompt::password("Export passphrase:")?;
+        input = crypto::unprotect(&input, passphrase.expose())?;
+    }
+
+    importer.import(&input)
+}
+
+/// Login entries of the keychain items the user picks, all of them w/o a
+/// terminal
+fn read_keychain() -> InternalResult<Vec<Entry>> {
+    let items = pick_imported(
+        "Keychain items to import:",
+        mac_keychain::items()?,
+        mac_keychain::Item::label,
+    )?;
+
+    if !items.is_empty() {
+        eprintln!("macOS asks to allow access to each item, denying it skips the item");
+    }
+
+    let logins = items
+        .into_iter()
+        .map(|item| {
+            let password = mac_keychain::password(&item)?;
+            Ok(item.into_login(password))
+        })
+        .collect::<InternalResult<Vec<_>>>()?;
+    let (entries, denied) = logins::into_entries(logins);
+
+    if denied > 0 {
+        eprintln!("[WARN]: Skipped {denied} Keychain items access was denied to");
+    }
+
+    Ok(entries)
+}
+
+/// Login entries of the Credential Manager credentials the user picks, all
+/// of them w/o a terminal
+fn read_windows_credentials() -> InternalResult<Vec<Entry>> {
+    let credentials = pick_imported(
+        "Windows credentials to import:",
+        win_credentials::credentials()?,
+        win_credentials::Credential::label,
+    )?;
+
+    let logins = credentials
+        .into_iter()
+        .map(win_credentials::Credential::into_login)
+        .collect();
+    let (entries, unreadable) = logins::into_entries(logins);
+
+    if unreadable > 0 {
+        eprintln!(
+            "[WARN]: Skipped {unreadable} Windows credentials whose password isn't readable text"
+        );
+    }
+
+    Ok(entries)
+}
+
+/// Login entries of the Secret Service items or KWallet entries the user
+/// picks, all of them w/o a terminal
+fn read_linux_keyring(
+    store: &str,
+    logins: fn(linux_keyring::Pick) -> InternalResult<Vec<logins::Login>>,
+) -> InternalResult<Vec<Entry>> {
+    let message = format!("{store} items to import:");
+    let logins = logins(&mut |labels| {
+        pick_imported(&message, (0..labels.len()).collect(), |i| {
+            labels[*i].clone()
+        })
+    })?;
+    let (entries, unread) = logins::into_entries(logins);
 
-const HOME_DIR: &str = "vuoto_cli";
+    if unread > 0 {
+        eprintln!("[WARN]: Skipped {unread} {store} items which couldn't be read");
+    }
 
-fn main() -> InternalResult<()> {
-    let home_dir = get_app_dir()?;
-    let vault = vault_selection_loop(&home_dir)?;
+    Ok(entries)
+}
 
-    login_selection_loop(&home_dir, &vault)?;
+/// Items of an OS store the user picks to import, on a terminal
+fn pick_imported<T>(
+    message: &str,
+    items: Vec<T>,
+    label: impl Fn(&T) -> String,
+) -> InternalResult<Vec<T>> {
+    if !term::is_interactive() || items.is_empty() {
+        return Ok(items);
+    }
 
-    Ok(())
+    let picked = prompt::multi_select(message, items.iter().map(label).collect())?;
+
+    Ok(items
+        .into_iter()
+        .enumerate()
+        .filter(|(i, _)| picked.contains(i))
+        .map(|(_, item)| item)
+        .collect())
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-struct LoginEntry {
-    name: String,
-    password: String,
-    username: Option<String>,
-    url: Option<String>,
+/// Imported entry replacing `existing`, which keeps its notes history and
+/// its place in the list
+fn replace_imported(mut existing: Entry, mut imported: Entry) -> Entry {
+    let notes = imported.notes.take().unwrap_or_default();
+
+    imported.history = std::mem::take(&mut existing.history);
+    imported.notes = existing.notes.take();
+    imported.set_notes(notes);
+    imported.position = imported.position.or(existing.position);
+
+    imported
 }
 
-fn login_selection_loop<P: AsRef<Path>>(home_dir: &P, vault: &str) -> InternalResult<()> {
-    let cache_path = home_dir.as_ref().join(vault);
-    let cache = TurboCache::new(cache_path, 512)?;
+/// Edit notes in the user's editor, previewing a diff before saving
+fn edit_notes(entry: &mut Entry) -> InternalResult<bool> {
+    let current = entry.notes.clone().unwrap_or_default();
 
-    loop {
-        let mut options: Vec<String> = Vec::new();
-
-        // collect existing entries
-        for i in cache.iter()? {
-            let (key, value) = i?;
-
-            let decoded: String = String::from_utf8(key.clone())
-                .unwrap_or_else(|_| format!("(invalid key: {:?})", key));
-            let entry: LoginEntry = serde_json::from_slice(&value).unwrap_or(LoginEntry {
-                name: decoded.clone(),
-                password: "".into(),
-                username: None,
-                url: None,
-            });
+    let edited = prompt::editor("Notes:", &current, ".md")?;
 
-            options.push(entry.name);
-        }
+    let diff = diff::unified(&current, &edited);
 
-        // menu options
-        options.insert(0, "< Create new entry >".into());
+    if diff.is_empty() {
+        println!("No changes.");
+        return Ok(false);
+    }
 
-        // let user pick
-        let ans = Select::new("Your entries:", options).prompt();
+    println!("\n{diff}");
 
-        match ans {
-            Ok(choice) => {
-                if choice == "< Create new entry >" {
-                    let entry = prompt_new_entry()?;
+    let save = prompt::confirm("Save these changes?", true)?;
 
-                    let key = general_purpose::STANDARD.encode(&entry.name);
-                    let val = serde_json::to_vec(&entry).map_err(|e| {
-                        InternalError::IO(format!("Failed to serialize entry: {e}"))
-                    })?;
+    if save {
+        entry.set_notes(edited);
+    }
 
-                    cache.set(key.as_bytes(), &val)?;
+    Ok(save)
+}
 
-                    // loop again so new entry appears in list
-                    continue;
-                } else {
-                    // fetch and show details
-                    let key = general_purpose::STANDARD.encode(&choice);
+fn show_notes_history(entry: &Entry) {
+    term::header("Notes History");
 
-                    if let Some(val) = cache.get(key.as_bytes())? {
-                        let entry: LoginEntry = serde_json::from_slice(&val).map_err(|e| {
-                            InternalError::IO(format!("Failed to decode entry: {e}"))
-                        })?;
+    for rev in entry.history.iter().rev() {
+        println!(
+            "--- replaced {} ---",
+            clock::format_timestamp(rev.replaced_at)
+        );
+        println!("{}\n", rev.notes);
+    }
 
-                        println!("\n=== Entry Details ===");
-                        println!("Name: {}", entry.name);
+    term::footer("Notes History");
+}
 
-                        if let Some(u) = entry.username {
-                            println!("Username: {}", u);
-                        }
+/// Pin, reorder or set icons on entries
+fn organize_entries(
+    cache: &VaultCache,
+    session: &Session,
+    entries: &mut [Entry],
+) -> InternalResult<()> {
+    if entries.is_empty() {
+        println!("No entries to organize.");
+        return Ok(());
+    }
 
-                        println!("Password: {}", entry.password);
+    let labels: Vec<String> = entries.iter().map(|e| e.label()).collect();
+    let idx = prompt::select_index("Entry:", labels)?;
+    let break_glass = if entries[idx].break_glass {
+        "Unmark break-glass"
+    } else {
+        "Mark as break-glass"
+    };
 
-                        if let Some(u) = entry.url {
-                            println!("URL: {}", u);
-                        }
+    let mut actions = vec!["Pin at position", "Unpin", "Set icon", break_glass];
 
-                        println!("=====================\n");
-                    } else {
-                        eprintln!("Entry not found!");
-                    }
+    // TOTP secrets imply 2FA, no point in unmarking
+    if !entries[idx].has_totp() {
+        actions.push(if entries[idx].has_2fa {
+            "Unmark 2FA"
+        } else {
+            "Mark as 2FA enabled"
+        });
+    }
 
-                    // exit app after showing one
-                    break;
+    let action = prompt::select("Action:", actions)?;
+
+    // taken after the prompts, see `Session::codec`
+    let codec = || session.codec();
+
+    match action {
+        "Mark as break-glass" | "Unmark break-glass" => {
+            let codec = codec()?;
+            entries[idx].break_glass = !entries[idx].break_glass;
+            cache.set(
+                codec.key(&entries[idx]).as_bytes(),
+                &codec.encode(&entries[idx])?,
+            )?;
+        }
+
+        "Mark as 2FA enabled" | "Unmark 2FA" => {
+            let codec = codec()?;
+            entries[idx].has_2fa = !entries[idx].has_2fa;
+            cache.set(
+                codec.key(&entries[idx]).as_bytes(),
+                &codec.encode(&entries[idx])?,
+            )?;
+        }
+
+        "Set icon" => {
+            let icon = prompt::text_validated("Icon (leave empty to clear):", |s| {
+                if s.trim().len() <= MAX_ICON_LEN {
+                    Ok(())
+                } else {
+                    Err("Icon is too long".into())
+                }
+            })?;
+
+            let icon = icon.trim();
+            entries[idx].icon = (!icon.is_empty()).then(|| icon.to_string());
+            let codec = codec()?;
+
+            cache.set(
+                codec.key(&entries[idx]).as_bytes(),
+                &codec.encode(&entries[idx])?,
+            )?;
+        }
+
+        _ => {
+            let slot = if action == "Unpin" {
+                None
+            } else {
+                let pinned = entries.iter().filter(|e| e.position.is_some()).count();
+                let pos = prompt::number("Position (1 = top):", pinned + 1)?;
+
+                Some(pos.saturating_sub(1))
+            };
+
+            let before: Vec<Option<u32>> = entries.iter().map(|e| e.position).collect();
+            entries::reposition(entries, idx, slot);
+            let codec = codec()?;
+
+            // only persist entries whose position actually changed
+            for (entry, old) in entries.iter().zip(before) {
+                if entry.position != old {
+                    cache.set(codec.key(entry).as_bytes(), &codec.encode(entry)?)?;
                 }
             }
+        }
+    }
+
+    Ok(())
+}
+
+fn show_login_entry(entry: &LoginEntry) {
+    term::header("Entry Details");
+    println!("Name: {}", entry.name);
+
+    if let Some(u) = &entry.username {
+        println!("Username: {}", u);
+    }
+
+    println!("Password: {}", entry.password.expose());
+
+    if let Some(u) = &entry.url {
+        println!("URL: {}", u);
+    }
+
+    term::footer("Entry Details");
+}
+
+fn show_license_entry(entry: &LicenseEntry) {
+    term::header("License Details");
+    println!("Name: {}", entry.name);
+    println!("Product: {}", entry.product);
+    println!("Key: {}", entry.formatted_key());
+
+    if let Some(d) = &entry.purchase_date {
+        println!("Purchased: {}", d);
+    }
+
+    if let Some(d) = &entry.maintenance_until {
+        let expired = entry
+            .maintenance_expiry()
+            .is_some_and(|expiry| expiry < clock::today());
+
+        if expired {
+            println!("Maintenance until: {} (EXPIRED)", d);
+        } else {
+            println!("Maintenance until: {}", d);
+        }
+    }
+
+    if !entry.machines.is_empty() {
+        println!("Assigned machines:");
+
+        for m in &entry.machines {
+            println!("  - {}", m);
+        }
+    }
+
+    term::footer("License Details");
+}
+
+/// Show a custom entry, secret fields are masked
+fn show_custom_entry(entry: &CustomEntry) {
+    term::header(&entry.kind);
+    println!("Name: {}", entry.name);
+
+    for f in &entry.fields {
+        if f.secret {
+            println!("{}: ********", f.name);
+        } else {
+            println!("{}: {}", f.name, f.value);
+        }
+    }
+
+    term::footer(&entry.kind);
+}
+
+fn show_audit(entries: &[Entry], history: &History) {
+    let health = audit::health(entries, history);
+
+    term::header("Audit Report");
+    println!("Health score: {}/100", health.score);
+
+    if health.actions.is_empty() {
+        println!("No issues found.");
+    }
+
+    print_findings(&health.actions);
+
+    term::footer("Audit Report");
+}
+
+fn print_findings(findings: &[audit::Finding]) {
+    for f in findings {
+        println!("  [{}] {}: {}", f.severity.label(), f.entry, f.message);
+    }
+}
+
+fn show_recovery_codes(entry: &RecoveryCodesEntry) {
+    term::header("Recovery Codes");
+    println!("Name: {}", entry.name);
+    println!("Unused: {}/{}", entry.unused_count(), entry.codes.len());
+
+    for c in &entry.codes {
+        match c.used_at {
+            Some(ts) => println!("  [x] {} (used {})", c.code, clock::format_timestamp(ts)),
+            None => println!("  [ ] {}", c.code),
+        }
+    }
+
+    term::footer("Recovery Codes");
+}
+
+fn consume_recovery_code(entry: &mut RecoveryCodesEntry) {
+    let Some(code) = entry.consume() else {
+        return;
+    };
+
+    match clipboard::copy(code) {
+        Ok(()) => println!("Recovery code copied to clipboard and marked as used."),
+        Err(err) => {
+            // clipboard is best-effort, fallback to showing the code
+            eprintln!("[WARN]: {err}");
+            println!("Recovery code (marked as used): {code}");
+        }
+    }
+}
+
+/// Copy one of the entry's secret fields to the clipboard
+fn copy_secret_field(entry: &CustomEntry) -> InternalResult<()> {
+    let secrets: Vec<&CustomField> = entry.fields.iter().filter(|f| f.secret).collect();
+
+    let field = match secrets.as_slice() {
+        [field] => *field,
+        _ => {
+            let names: Vec<&str> = secrets.iter().map(|f| f.name.as_str()).collect();
+            secrets[prompt::select_index("Field:", names)?]
+        }
+    };
 
-            Err(err) => return_error(format!("{err}")),
+    match clipboard::copy(&field.value) {
+        Ok(()) => println!("{} copied to clipboard.", field.name),
+        Err(err) => {
+            // clipboard is best-effort, fallback to showing the value
+            eprintln!("[WARN]: {err}");
+            println!("{}: {}", field.name, field.value);
         }
     }
 
     Ok(())
 }
 
-fn prompt_new_entry() -> InternalResult<LoginEntry> {
-    let name = Text::new("Entry name:")
-        .prompt()
-        .map_err(|e| InternalError::IO(format!("Failed to read input: {e}")))?;
+fn prompt_new_entry(config: &Config) -> InternalResult<Entry> {
+    let mut entry = Entry::new(prompt_new_template(config)?);
+    entry.detect_2fa();
+
+    Ok(entry)
+}
+
+fn prompt_new_template(config: &Config) -> InternalResult<Template> {
+    let prompt = &config.prompt;
+
+    // minimal mode always creates logins
+    let template = if prompt.minimal {
+        "Login".to_string()
+    } else {
+        let mut options: Vec<String> = BUILTIN_TEMPLATES.iter().map(|t| t.to_string()).collect();
+        options.extend(config.templates.iter().map(|t| t.name.clone()));
+
+        prompt::select("Template:", options)?
+    };
+
+    let name = prompt::text("Entry name:")?;
+
+    if let Some(custom) = config.template(&template) {
+        return Ok(Template::Custom(prompt_custom_fields(name, custom)?));
+    }
+
+    match template.as_str() {
+        "Recovery codes" => {
+            let codes = prompt::text("Codes (separated by spaces or commas):")?;
+
+            Ok(Template::RecoveryCodes(RecoveryCodesEntry::from_input(
+                name, &codes,
+            )))
+        }
+
+        "License" => Ok(Template::License(prompt_license_fields(name, prompt)?)),
+
+        _ => Ok(Template::Login(prompt_login_fields(name, prompt)?)),
+    }
+}
+
+fn prompt_license_fields(name: String, prompt: &PromptConfig) -> InternalResult<LicenseEntry> {
+    let product = prompt::text("Product:")?;
+    let key = prompt::text("License key:")?;
+
+    let purchase_date = if prompt.asks("purchase_date") {
+        prompt_optional_date("Purchase date (YYYY-MM-DD, optional):")?
+    } else {
+        None
+    };
+
+    let maintenance_until = if prompt.asks("maintenance_until") {
+        prompt_optional_date("Maintenance until (YYYY-MM-DD, optional):")?
+    } else {
+        None
+    };
+
+    let machines = if prompt.asks("machines") {
+        prompt::text_skippable("Assigned machines (comma separated, optional):")?
+            .map(|m| {
+                m.split(',')
+                    .map(|s| s.trim().to_string())
+                    .filter(|s| !s.is_empty())
+                    .collect()
+            })
+            .unwrap_or_default()
+    } else {
+        Vec::new()
+    };
+
+    Ok(LicenseEntry {
+        name,
+        product,
+        key,
+        purchase_date,
+        maintenance_until,
+        machines,
+    })
+}
+
+fn prompt_optional_date(message: &str) -> InternalResult<Option<String>> {
+    let ans = prompt::text_skippable_validated(message, |s| {
+        if s.trim().is_empty() || clock::parse_date(s).is_some() {
+            Ok(())
+        } else {
+            Err("Expected a date as YYYY-MM-DD".into())
+        }
+    })?;
+
+    Ok(ans.map(|s| s.trim().to_string()).filter(|s| !s.is_empty()))
+}
+
+fn prompt_custom_fields(name: String, template: &TemplateConfig) -> InternalResult<CustomEntry> {
+    let mut fields = Vec::new();
+
+    for field in &template.fields {
+        let value = match &field.generate {
+            Some(policy) => {
+                let message = format!("{} (empty to generate):", field.name);
+
+                match prompt::text_skippable(&message)?.filter(|s| !s.is_empty()) {
+                    Some(value) => value,
+                    None => {
+                        println!("Generated a new {}.", field.name);
+                        generator::generate(policy)?
+                    }
+                }
+            }
+
+            None if field.optional => {
+                let message = format!("{} (optional):", field.name);
 
-    let username = Text::new("Username (optional):")
-        .prompt_skippable()
-        .map_err(|e| InternalError::IO(format!("Failed to read username: {e}")))?;
+                match prompt::text_skippable(&message)?.filter(|s| !s.is_empty()) {
+                    Some(value) => value,
+                    None => continue,
+                }
+            }
+
+            None => prompt::text(&format!("{}:", field.name))?,
+        };
+
+        fields.push(CustomField {
+            name: field.name.clone(),
+            value,
+            secret: field.secret,
+        });
+    }
+
+    Ok(CustomEntry {
+        name,
+        kind: template.name.clone(),
+        fields,
+    })
+}
+
+fn prompt_login_fields(name: String, prompt: &PromptConfig) -> InternalResult<LoginEntry> {
+    let username = if prompt.asks("username") {
+        prompt::text_skippable("Username (optional):")?
+    } else {
+        None
+    };
 
-    let password = Text::new("Password:")
-        .prompt()
-        .map_err(|e| InternalError::IO(format!("Failed to read password: {e}")))?;
+    let password = prompt::text("Password:")?.into();
 
-    let url = Text::new("URL (optional):")
-        .prompt_skippable()
-        .map_err(|e| InternalError::IO(format!("Failed to read URL: {e}")))?;
+    let url = if prompt.asks("url") {
+        prompt::text_skippable("URL (optional):")?
+    } else {
+        None
+    };
 
     Ok(LoginEntry {
         name,
@@ -139,66 +3664,368 @@ fn prompt_new_entry() -> InternalResult<LoginEntry> {
     })
 }
 
-fn vault_selection_loop<P: AsRef<Path>>(home_dir: &P) -> InternalResult<String> {
-    let vault = loop {
+fn vault_selection_loop<P: AsRef<Path>>(home_dir: &P, config: &Config) -> InternalResult<Vault> {
+    loop {
         let mut vault_idx = VaultIndex::open(&home_dir.as_ref())?;
-        let mut options = vault_idx.vaults().to_vec();
 
         // no vaults yet => force creation
-        if options.is_empty() {
+        if vault_idx.vaults().is_empty() {
             let new_vault = prompt_new_vault()?;
             vault_idx.add(&new_vault)?;
 
-            break new_vault;
-        } else {
-            // add special option
-            options.push("< Create new vault >".into());
+            match vault_idx.get(&new_vault) {
+                Some(vault) => return Ok(vault.clone()),
+                None => continue,
+            }
+        }
 
-            let ans = Select::new("Your Vaults:", options).prompt();
+        // active vaults first, archived ones at the bottom
+        let mut vaults: Vec<Vault> = vault_idx
+            .vaults()
+            .iter()
+            .filter_map(|name| vault_idx.get(name).cloned())
+            .collect();
+        vaults.sort_by_key(|v| v.archived);
 
-            match ans {
-                Ok(choice) => {
-                    if choice == "< Create new vault >" {
-                        let new_vault = prompt_new_vault()?;
-                        vault_idx.add(&new_vault)?;
+        let mut options: Vec<String> = vaults
+            .iter()
+            .map(|v| match v.archived {
+                true => format!("{} (archived)", v.name),
+                false => v.name.clone(),
+            })
+            .collect();
 
-                        continue;
-                    } else {
-                        break choice;
-                    }
-                }
+        // add special option
+        options.push("< Create new vault >".into());
 
-                Err(err) => return_error(format!("{err}")),
-            }
+        let choice = prompt::select_index("Your Vaults:", options)?;
+
+        if choice == vaults.len() {
+            let new_vault = prompt_new_vault()?;
+            vault_idx.add(&new_vault)?;
+
+            continue;
+        }
+
+        if let Some(vault) = manage_vault(home_dir, config, &mut vault_idx, &vaults[choice])? {
+            return Ok(vault);
         }
+    }
+}
+
+/// Vault submenu, returns the vault if it should be opened
+fn manage_vault<P: AsRef<Path>>(
+    home_dir: &P,
+    config: &Config,
+    vault_idx: &mut VaultIndex,
+    vault: &Vault,
+) -> InternalResult<Option<Vault>> {
+    let archive = if vault.archived {
+        "Unarchive"
+    } else {
+        "Archive"
     };
+    let (convert, target) = match vault.format {
+        EntryFormat::Json => ("Convert entries to MessagePack", EntryFormat::MessagePack),
+        EntryFormat::MessagePack => ("Convert entries to JSON", EntryFormat::Json),
+    };
+    let actions = vec![
+        "Open",
+        "Rename",
+        archive,
+        convert,
+        "Set cache capacity",
+        "Delete",
+        "Back",
+    ];
+
+    match prompt::select(&format!("Vault '{}':", vault.name), actions)? {
+        "Open" => return Ok(Some(vault.clone())),
+
+        "Rename" => {
+            let new_name = prompt::text_validated("New name:", |s| {
+                vaults::validate_name(s).map_err(|e| e.to_string())
+            })?;
+
+            // e.g. name already taken, keep the menu going
+            match vault_idx.rename(&vault.name, &new_name) {
+                Ok(_) => Defaults::vault_moved(home_dir, &vault.name, Some(&new_name))?,
+                Err(err) => eprintln!("[ERROR]: {err}"),
+            }
+        }
+
+        "Archive" | "Unarchive" => {
+            let msg = format!("{archive} vault '{}'?", vault.name);
+
+            if prompt::confirm(&msg, true)? {
+                vault_idx.set_archived(&vault.name, !vault.archived)?;
+            }
+        }
+
+        "Set cache capacity" => {
+            let current = cache::capacity(vault, &config.cache);
+            let capacity = prompt::number("Cache capacity:", current as usize)?;
+            let capacity = u32::try_from(capacity)
+                .ok()
+                .filter(|&c| c > 0)
+                .ok_or_else(|| InternalError::IO(format!("Invalid cache capacity {capacity}")))?;
+
+            let cache = cache::open(home_dir, vault, &config.cache)?;
+            cache::rebuild(home_dir, vault, cache, capacity)?;
+            vault_idx.set_capacity(&vault.name, Some(capacity))?;
+        }
+
+        "Delete" => delete_vault(home_dir, vault_idx, vault)?,
+
+        action if action == convert => convert_vault(home_dir, config, vault_idx, vault, target)?,
+
+        _ => {}
+    }
 
-    Ok(vault)
+    Ok(None)
 }
 
-fn prompt_new_vault() -> InternalResult<String> {
-    let ans = Text::new("Enter name for new vault:")
-        .prompt()
-        .map_err(|e| InternalError::IO(format!("Failed to read input: {e}")))?;
+/// Re-encode all entries of a vault into `format` and make it the default
+/// for new writes
+fn convert_vault<P: AsRef<Path>>(
+    home_dir: &P,
+    config: &Config,
+    vault_idx: &mut VaultIndex,
+    vault: &Vault,
+    format: EntryFormat,
+) -> InternalResult<()> {
+    let (data, codec) = unlock_vault(home_dir, config, vault)?;
+    let cache = cache::open_tracked(home_dir, &data, &config.cache, &codec)?;
+    let (entries, undecodable) = load_entries(&cache, &codec)?;
+
+    // decoding detects the format, so a half converted vault still reads
+    // fine and conversion can simply be re-run
+    let target = codec.with_format(format);
+
+    for entry in &entries {
+        cache.set(target.key(entry).as_bytes(), &target.encode(entry)?)?;
+    }
+
+    vault_idx.set_format(&vault.name, format)?;
+
+    println!("Converted {} entries.", entries.len());
+
+    if !undecodable.is_empty() {
+        println!(
+            "{} corrupted entries were left untouched.",
+            undecodable.len()
+        );
+    }
 
-    Ok(ans)
+    Ok(())
 }
 
-fn return_error(msg: String) -> ! {
-    eprintln!("[ERROR]: {msg}");
+/// Print the recovery kit of `vault` w/ its key `code`, laid out to be
+/// printed on paper
+fn print_recovery_kit(
+    vault: &Vault,
+    header: &MasterHeader,
+    code: &str,
+    created_at: u64,
+) -> InternalResult<()> {
+    let hardware_key = header.token_challenge()?.map(|(device, _)| device.name());
+
+    println!("VUOTO EMERGENCY RECOVERY KIT");
+    println!("============================");
+    println!();
+    println!("Vault:           {}", vault.name);
+    println!("Vault id:        {}", vault.slug);
+    println!("Created:         {}", clock::format_timestamp(created_at));
+    println!("Key derivation:  {}", header.kdf(Slot::Master));
+    println!(
+        "Keyfile:         {}",
+        match header.requires_keyfile() {
+            true => "required w/ the master password",
+            false => "none",
+        }
+    );
+    println!("Hardware key:    {}", hardware_key.unwrap_or("none"));
+    println!();
+    println!("Recovery key:");
+    println!();
+    println!("    {code}");
+    println!();
+    println!("{}", qr::render(code)?);
+    println!("If the master password is lost, run");
+    println!();
+    println!("    vuoto recover --vault {}", vault.name);
+    println!();
+    println!("and enter the recovery key to set a new one. Anyone holding this kit");
+    println!("can open the vault, keep it offline, e.g. printed in a safe. A new kit");
+    println!("replaces this one.");
 
-    std::process::exit(1);
+    Ok(())
 }
 
-pub(crate) fn get_app_dir() -> InternalResult<PathBuf> {
-    let base = if cfg!(debug_assertions) {
-        std::env::temp_dir()
-    } else {
-        env_home::env_home_dir()
-            .ok_or_else(|| InternalError::IO("Unable to read home dir".into()))?
+/// Random key of an entry created by `encrypt-file`, `None` for others,
+/// whose secret may be a guessable password
+fn file_key_secret(entry: &Entry) -> Option<String> {
+    match &entry.template {
+        Template::Custom(custom) if custom.kind == FILE_KEY_ENTRY => entry
+            .field(None)
+            .filter(|secret| VaultKey::from_base64(secret).is_ok()),
+        _ => None,
+    }
+}
+
+/// Fail early, before asking for the master password, if `path` exists
+fn refuse_existing(path: &str) -> InternalResult<()> {
+    match Path::new(path).exists() {
+        true => Err(InternalError::IO(format!("'{path}' already exists"))),
+        false => Ok(()),
+    }
+}
+
+/// Write `data` to a file which must not exist yet, only readable by its
+/// owner on Unix
+fn write_new_file(path: &str, data: &[u8]) -> InternalResult<()> {
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+
+    let err = |e: std::io::Error| InternalError::IO(format!("Failed to write '{path}': {e}"));
+    options
+        .open(path)
+        .map_err(err)?
+        .write_all(data)
+        .map_err(err)
+}
+
+/// Remove the cache and history stored under `vault`'s slug
+fn remove_vault_data<P: AsRef<Path>>(home_dir: &P, vault: &Vault) -> InternalResult<()> {
+    match std::fs::remove_dir_all(home_dir.as_ref().join(&vault.slug)) {
+        Ok(()) => {}
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+        Err(err) => return Err(err.into()),
+    }
+
+    Manifest::remove(home_dir, vault)?;
+    scratch::remove(home_dir, vault)?;
+    history::remove(home_dir, vault)
+}
+
+/// Permanently delete vault and its data after the user re-types its name
+fn delete_vault<P: AsRef<Path>>(
+    home_dir: &P,
+    vault_idx: &mut VaultIndex,
+    vault: &Vault,
+) -> InternalResult<()> {
+    println!(
+        "This permanently deletes vault '{}' and all of its entries.",
+        vault.name
+    );
+
+    let typed = prompt::text("Type the vault name to confirm:")?;
+
+    if typed != vault.name {
+        println!("Name does not match, nothing was deleted.");
+        return Ok(());
+    }
+
+    destroy_vault(home_dir, vault_idx, vault)?;
+    println!("Vault '{}' deleted.", vault.name);
+
+    Ok(())
+}
+
+/// Remove `vault` from the index along w/ all of its data and keys
+fn destroy_vault<P: AsRef<Path>>(
+    home_dir: &P,
+    vault_idx: &mut VaultIndex,
+    vault: &Vault,
+) -> InternalResult<()> {
+    // drop from index first, so a failure below never leaves a listed
+    // vault w/o data
+    vault_idx.remove(&vault.name)?;
+    Defaults::vault_moved(home_dir, &vault.name, None)?;
+
+    remove_vault_data(home_dir, vault)?;
+    remove_vault_data(home_dir, &Slot::Decoy.data_vault(vault))?;
+    MasterHeader::remove(home_dir, vault)?;
+
+    if keystore::enrolled_slot(home_dir, vault).is_some() {
+        keystore::remove(keystore_of(home_dir, vault)?.as_ref(), home_dir, vault)?;
+    }
+
+    Attempts::reset(home_dir, vault)
+}
+
+fn prompt_new_vault() -> InternalResult<String> {
+    prompt::text_validated("Enter name for new vault:", |s| {
+        vaults::validate_name(s).map_err(|e| e.to_string())
+    })
+}
+
+fn return_error(err: &InternalError) -> ! {
+    eprintln!("[ERROR]: {err}");
+
+    std::process::exit(err.exit_code());
+}
+
+/// Data dir of the default profile, or a sibling dir per named profile
+///
+/// `VUOTO_DATA_DIR` wins over the configured `data_dir`, both over the
+/// default dirs, see [`dirs::app_dirs`]. State files stay in the data dir
+/// unless it is a default one.
+pub(crate) fn get_app_dir(
+    profile: Option<&str>,
+    data_dir: Option<&Path>,
+) -> InternalResult<PathBuf> {
+    let profile = profile.filter(|p| !p.is_empty());
+
+    if let Some(name) = profile {
+        let valid = name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+
+        if !valid {
+            return Err(InternalError::IO(format!(
+                "Invalid profile name '{name}', use letters, digits, '-' and '_'"
+            )));
+        }
+    }
+
+    let profiled = |dir: PathBuf| match profile {
+        None => dir,
+        Some(name) => {
+            let mut dir_name = dir.file_name().unwrap_or_default().to_os_string();
+            dir_name.push(format!("-{name}"));
+
+            dir.with_file_name(dir_name)
+        }
     };
 
-    let dir = base.join(HOME_DIR);
+    let data_dir = std::env::var_os(DATA_DIR_ENV)
+        .filter(|d| !d.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| data_dir.map(Path::to_path_buf));
+
+    let dir = match data_dir {
+        Some(dir) => profiled(dir),
+        None => {
+            let app_dirs = dirs::app_dirs()?;
+            let (data, state) = (profiled(app_dirs.data), profiled(app_dirs.state));
+
+            if let Some(legacy) = app_dirs.legacy.map(profiled)
+                && dirs::migrate(&legacy, &data, &state)?
+            {
+                eprintln!("Moved {} to {}.", legacy.display(), data.display());
+            }
+
+            if state != data {
+                dirs::use_state_dir(state)?;
+            }
+
+            data
+        }
+    };
 
     std::fs::create_dir_all(&dir)
         .map_err(|e| InternalError::IO(format!("Failed to create app dir: {}", e)))?;
@@ -0,0 +1,229 @@
+use crate::{
+    crypto::{self, VaultKey},
+    types::{InternalError, InternalResult},
+    vaults::Vault,
+};
+use base64::{Engine as _, engine::general_purpose};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+use turbocache::TurboCache;
+
+const MANIFEST_VERSION: u8 = 1;
+const MANIFEST_AAD: &[u8] = b"vuoto manifest";
+
+/// SHA-256 of every value in a vault's cache, sealed next to it, so bit-rot
+/// and lost writes show up even where the cache itself reads fine
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub(crate) struct Manifest {
+    version: u8,
+    /// hex hash by base64 cache key
+    hashes: BTreeMap<String, String>,
+}
+
+impl Default for Manifest {
+    fn default() -> Self {
+        Self {
+            version: MANIFEST_VERSION,
+            hashes: BTreeMap::new(),
+        }
+    }
+}
+
+/// Keys whose values don't match the manifest
+#[derive(Debug, Default)]
+pub(crate) struct Report {
+    /// recorded, but gone from the cache
+    pub missing: Vec<Vec<u8>>,
+    /// value differs from the recorded one
+    pub changed: Vec<Vec<u8>>,
+    /// in the cache, but never recorded
+    pub unknown: Vec<Vec<u8>>,
+}
+
+impl Report {
+    pub fn is_clean(&self) -> bool {
+        self.missing.is_empty() && self.changed.is_empty() && self.unknown.is_empty()
+    }
+}
+
+impl Manifest {
+    /// Manifest of the cache's current contents
+    pub fn of(cache: &TurboCache) -> InternalResult<Self> {
+        let mut manifest = Self::default();
+
+        for item in cache.iter()? {
+            let (key, value) = item?;
+            manifest.record(&key, Some(&value));
+        }
+
+        Ok(manifest)
+    }
+
+    pub fn len(&self) -> usize {
+        self.hashes.len()
+    }
+
+    /// Record `key` set to `value`, or deleted w/ `None`
+    pub fn record(&mut self, key: &[u8], value: Option<&[u8]>) {
+        let key = general_purpose::STANDARD.encode(key);
+
+        match value {
+            Some(value) => self.hashes.insert(key, hash(value)),
+            None => self.hashes.remove(&key),
+        };
+    }
+
+    /// Compare the cache against the recorded hashes
+    pub fn verify(&self, cache: &TurboCache) -> InternalResult<Report> {
+        let mut report = Report::default();
+        let mut seen = 0;
+
+        for item in cache.iter()? {
+            let (key, value) = item?;
+
+            match self.hashes.get(&general_purpose::STANDARD.encode(&key)) {
+                Some(recorded) => {
+                    seen += 1;
+
+                    if *recorded != hash(&value) {
+                        report.changed.push(key);
+                    }
+                }
+                None => report.unknown.push(key),
+            }
+        }
+
+        if seen < self.hashes.len() {
+            for key in self.hashes.keys() {
+                let key = general_purpose::STANDARD
+                    .decode(key)
+                    .map_err(|_| InternalError::Corrupted("Invalid manifest key".into()))?;
+
+                if cache.get(&key)?.is_none() {
+                    report.missing.push(key);
+                }
+            }
+        }
+
+        Ok(report)
+    }
+
+    /// Manifest of `vault`, `None` if it has none yet
+    pub fn load<P: AsRef<Path>>(
+        home_dir: &P,
+        vault: &Vault,
+        key: &VaultKey,
+    ) -> InternalResult<Option<Self>> {
+        let sealed = match std::fs::read(manifest_path(home_dir, vault)) {
+            Ok(sealed) => sealed,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+
+        let unreadable =
+            || InternalError::Corrupted("Integrity manifest is unreadable or tampered".into());
+        let raw = crypto::open(key, MANIFEST_AAD, &sealed).map_err(|_| unreadable())?;
+        let manifest: Self = serde_json::from_slice(&raw).map_err(|_| unreadable())?;
+
+        if manifest.version > MANIFEST_VERSION {
+            return Err(InternalError::IO(format!(
+                "Manifest version {} is newer than supported, please update vuoto",
+                manifest.version
+            )));
+        }
+
+        Ok(Some(manifest))
+    }
+
+    pub fn save<P: AsRef<Path>>(
+        &self,
+        home_dir: &P,
+        vault: &Vault,
+        key: &VaultKey,
+    ) -> InternalResult<()> {
+        let raw = serde_json::to_vec(self)
+            .map_err(|e| InternalError::IO(format!("Failed to encode manifest: {e}")))?;
+
+        // write + rename, so a crash never leaves a half written manifest
+        let path = manifest_path(home_dir, vault);
+        let tmp = path.with_extension("tmp");
+        std::fs::write(&tmp, crypto::seal(key, MANIFEST_AAD, &raw)?)?;
+        std::fs::rename(&tmp, &path)?;
+
+        Ok(())
+    }
+
+    pub fn remove<P: AsRef<Path>>(home_dir: &P, vault: &Vault) -> InternalResult<()> {
+        match std::fs::remove_file(manifest_path(home_dir, vault)) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+}
+
+fn hash(value: &[u8]) -> String {
+    Sha256::digest(value)
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
+
+fn manifest_path<P: AsRef<Path>>(home_dir: &P, vault: &Vault) -> PathBuf {
+    home_dir.as_ref().join(format!("{}.manifest", vault.slug))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::vault;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_manifest_detects_changes() {
+        let temp_dir = TempDir::new().unwrap();
+        let cache = TurboCache::new(temp_dir.path().join("cache"), 16).unwrap();
+
+        for key in [b"a", b"b", b"c"] {
+            cache.set(key, b"value").unwrap();
+        }
+
+        let manifest = Manifest::of(&cache).unwrap();
+        assert!(manifest.verify(&cache).unwrap().is_clean());
+
+        cache.set(b"a", b"valuf").unwrap();
+        cache.del(b"b").unwrap();
+        cache.set(b"d", b"value").unwrap();
+
+        let report = manifest.verify(&cache).unwrap();
+        assert_eq!(report.changed, vec![b"a".to_vec()]);
+        assert_eq!(report.missing, vec![b"b".to_vec()]);
+        assert_eq!(report.unknown, vec![b"d".to_vec()]);
+    }
+
+    #[test]
+    fn test_manifest_is_sealed() {
+        let temp_dir = TempDir::new().unwrap();
+        let vault = vault();
+        let key = VaultKey::random().unwrap();
+
+        assert!(
+            Manifest::load(&temp_dir.path(), &vault, &key)
+                .unwrap()
+                .is_none()
+        );
+
+        let mut manifest = Manifest::default();
+        manifest.record(b"a", Some(b"value"));
+        manifest.save(&temp_dir.path(), &vault, &key).unwrap();
+
+        let loaded = Manifest::load(&temp_dir.path(), &vault, &key).unwrap();
+        assert_eq!(loaded, Some(manifest));
+
+        let other = VaultKey::random().unwrap();
+        assert!(Manifest::load(&temp_dir.path(), &vault, &other).is_err());
+    }
+}
@@ -0,0 +1,287 @@
+use crate::{
+    crypto::{self, KdfParams},
+    types::{InternalError, InternalResult},
+};
+use serde::{Deserialize, Serialize};
+use std::path::{Component, Path, PathBuf};
+
+/// v2 bundles the config dir too
+const BUNDLE_VERSION: u8 = 2;
+const COMPRESS_LEVEL: i32 = 9;
+/// Upper bound when unpacking, vaults are small
+const MAX_BUNDLE_SIZE: usize = 1 << 30;
+
+/// Contents of the app dir, paths are relative and `/` separated
+#[derive(Debug, Serialize, Deserialize)]
+struct Bundle {
+    version: u8,
+    files: Vec<BundleFile>,
+    /// contents of the config dir, when apart from the app dir
+    #[serde(default)]
+    config: Vec<BundleFile>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct BundleFile {
+    path: String,
+    data: Vec<u8>,
+}
+
+/// Bundle config, vault index, master header and all vault data in
+/// `home_dir` into a single file encrypted under `passphrase`, along w/
+/// `config_dir` if the config is kept apart
+pub(crate) fn export<P: AsRef<Path>>(
+    home_dir: &P,
+    config_dir: Option<&Path>,
+    passphrase: &str,
+    kdf: KdfParams,
+) -> InternalResult<(Vec<u8>, usize)> {
+    let mut files = Vec::new();
+    collect(home_dir.as_ref(), "", &mut files)?;
+    files.sort_by(|a, b| a.path.cmp(&b.path));
+
+    let mut config = Vec::new();
+
+    if let Some(dir) = config_dir.filter(|d| is_apart(d, home_dir.as_ref()) && d.is_dir()) {
+        collect(dir, "", &mut config)?;
+        config.sort_by(|a, b| a.path.cmp(&b.path));
+    }
+
+    let count = files.len() + config.len();
+    let bundle = Bundle {
+        version: BUNDLE_VERSION,
+        files,
+        config,
+    };
+
+    let raw = rmp_serde::to_vec(&bundle)
+        .map_err(|e| InternalError::IO(format!("Failed to encode bundle: {e}")))?;
+    let compressed = zstd::bulk::compress(&raw, COMPRESS_LEVEL)?;
+
+    Ok((crypto::protect(&compressed, passphrase, kdf)?, count))
+}
+
+/// Unpack a bundle made by [`export`] into `home_dir`, and its config into
+/// `config_dir`, nothing is written if any file already exists w/ other
+/// contents
+pub(crate) fn import<P: AsRef<Path>>(
+    home_dir: &P,
+    config_dir: Option<&Path>,
+    data: &[u8],
+    passphrase: &str,
+) -> InternalResult<usize> {
+    let compressed = crypto::unprotect(data, passphrase)?;
+    let raw = zstd::bulk::decompress(&compressed, MAX_BUNDLE_SIZE)?;
+
+    let bundle: Bundle = rmp_serde::from_slice(&raw)
+        .map_err(|e| InternalError::IO(format!("Invalid bundle: {e}")))?;
+
+    if bundle.version > BUNDLE_VERSION {
+        return Err(InternalError::IO(format!(
+            "Bundle version {} is newer than supported, please update vuoto",
+            bundle.version
+        )));
+    }
+
+    // w/o a config dir of its own, the config goes next to the vaults
+    let config_dir = match (config_dir, bundle.config.is_empty()) {
+        (_, true) => home_dir.as_ref(),
+        (Some(dir), false) => dir,
+        (None, false) => {
+            return Err(InternalError::IO(
+                "Bundle holds a config, but there's no config dir to unpack it to".into(),
+            ));
+        }
+    };
+
+    let files = bundle.files.iter().map(|f| (home_dir.as_ref(), f));
+    let config = bundle.config.iter().map(|f| (config_dir, f));
+
+    let mut targets = Vec::with_capacity(bundle.files.len() + bundle.config.len());
+    let mut conflicts = Vec::new();
+
+    for (dir, file) in files.chain(config) {
+        let target = target_path(dir, &file.path)?;
+
+        match std::fs::read(&target) {
+            Ok(existing) if existing == file.data => continue,
+            Ok(_) => conflicts.push(file.path.clone()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e.into()),
+        }
+
+        targets.push((target, &file.data));
+    }
+
+    if !conflicts.is_empty() {
+        return Err(InternalError::IO(format!(
+            "Refusing to overwrite existing files: {}",
+            conflicts.join(", ")
+        )));
+    }
+
+    for (target, data) in &targets {
+        if let Some(parent) = target.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        // write + rename, so an interrupted import can simply be re-run
+        let tmp = target.with_extension("migrate.tmp");
+        std::fs::write(&tmp, data)?;
+        std::fs::rename(&tmp, target)?;
+    }
+
+    Ok(targets.len())
+}
+
+/// Whether the config dir `config_dir` isn't the app dir, e.g. w/ XDG dirs
+fn is_apart(config_dir: &Path, home_dir: &Path) -> bool {
+    match (config_dir.canonicalize(), home_dir.canonicalize()) {
+        (Ok(config), Ok(home)) => config != home,
+        _ => config_dir != home_dir,
+    }
+}
+
+fn collect(dir: &Path, prefix: &str, files: &mut Vec<BundleFile>) -> InternalResult<()> {
+    for item in std::fs::read_dir(dir)? {
+        let item = item?;
+        let name = item.file_name().to_string_lossy().into_owned();
+
+        // leftovers of interrupted writes
+        if name.ends_with(".tmp") {
+            continue;
+        }
+
+        let path = format!("{prefix}{name}");
+        let file_type = item.file_type()?;
+
+        // special files, e.g. the agent's socket, can't be bundled
+        if file_type.is_dir() {
+            collect(&item.path(), &format!("{path}/"), files)?;
+        } else if file_type.is_file() {
+            files.push(BundleFile {
+                path,
+                data: std::fs::read(item.path())?,
+            });
+        }
+    }
+
+    Ok(())
+}
+
+/// Resolve a bundled path, rejecting anything escaping `home_dir`
+fn target_path(home_dir: &Path, path: &str) -> InternalResult<PathBuf> {
+    let relative = Path::new(path);
+
+    if !relative
+        .components()
+        .all(|c| matches!(c, Component::Normal(_)))
+    {
+        return Err(InternalError::IO(format!("Invalid path in bundle: {path}")));
+    }
+
+    Ok(home_dir.join(relative))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TEST_KDF;
+    use tempfile::TempDir;
+
+    fn old_machine() -> TempDir {
+        let dir = TempDir::new().unwrap();
+
+        std::fs::write(dir.path().join("config.toml"), "[prompt]\n").unwrap();
+        std::fs::write(dir.path().join("index.vuoto"), b"VUOTOIDX").unwrap();
+        std::fs::create_dir(dir.path().join("abc")).unwrap();
+        std::fs::write(dir.path().join("abc").join("data"), [0xc7, 1, 2]).unwrap();
+        std::fs::write(dir.path().join("master.vuoto.tmp"), "half").unwrap();
+
+        dir
+    }
+
+    #[test]
+    fn test_migrate_roundtrip() {
+        let old = old_machine();
+        let (bundle, count) =
+            export(&old.path(), Some(old.path()), "passphrase", TEST_KDF).unwrap();
+        assert_eq!(count, 3);
+
+        let new = TempDir::new().unwrap();
+        assert!(import(&new.path(), None, &bundle, "wrong").is_err());
+        assert_eq!(import(&new.path(), None, &bundle, "passphrase").unwrap(), 3);
+
+        assert_eq!(
+            std::fs::read(new.path().join("abc").join("data")).unwrap(),
+            [0xc7, 1, 2]
+        );
+        assert!(!new.path().join("master.vuoto.tmp").exists());
+
+        // identical files are no conflict
+        assert_eq!(import(&new.path(), None, &bundle, "passphrase").unwrap(), 0);
+    }
+
+    #[test]
+    fn test_migrate_separate_config_dir() {
+        let old_data = old_machine();
+        std::fs::remove_file(old_data.path().join("config.toml")).unwrap();
+        let old_config = TempDir::new().unwrap();
+        std::fs::write(old_config.path().join("config.toml"), "[prompt]\n").unwrap();
+
+        let (bundle, count) = export(
+            &old_data.path(),
+            Some(old_config.path()),
+            "passphrase",
+            TEST_KDF,
+        )
+        .unwrap();
+        assert_eq!(count, 3);
+
+        let new_data = TempDir::new().unwrap();
+        let new_config = TempDir::new().unwrap();
+
+        // never dropped silently
+        assert!(import(&new_data.path(), None, &bundle, "passphrase").is_err());
+
+        let count = import(
+            &new_data.path(),
+            Some(new_config.path()),
+            &bundle,
+            "passphrase",
+        )
+        .unwrap();
+        assert_eq!(count, 3);
+        assert_eq!(
+            std::fs::read_to_string(new_config.path().join("config.toml")).unwrap(),
+            "[prompt]\n"
+        );
+        assert!(!new_data.path().join("config.toml").exists());
+        assert!(new_data.path().join("index.vuoto").exists());
+    }
+
+    #[test]
+    fn test_migrate_refuses_conflicts() {
+        let old = old_machine();
+        let (bundle, _) = export(&old.path(), None, "passphrase", TEST_KDF).unwrap();
+
+        let new = TempDir::new().unwrap();
+        std::fs::write(new.path().join("config.toml"), "[cache]\n").unwrap();
+
+        assert!(import(&new.path(), None, &bundle, "passphrase").is_err());
+
+        // nothing written
+        assert!(!new.path().join("index.vuoto").exists());
+        assert_eq!(
+            std::fs::read_to_string(new.path().join("config.toml")).unwrap(),
+            "[cache]\n"
+        );
+    }
+
+    #[test]
+    fn test_reject_escaping_paths() {
+        assert!(target_path(Path::new("/home"), "../etc/passwd").is_err());
+        assert!(target_path(Path::new("/home"), "/etc/passwd").is_err());
+        assert!(target_path(Path::new("/home"), "abc/data").is_ok());
+    }
+}
@@ -0,0 +1,398 @@
+use crate::{
+    entries::{self, Entry},
+    types::{InternalError, InternalResult},
+};
+use serde_json::Value;
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+/// Executables named `vuoto-import-<format>` on `PATH` become importers
+const IMPORTER_PREFIX: &str = "vuoto-import-";
+/// Executables named `vuoto-export-<format>` on `PATH` become exporters
+const EXPORTER_PREFIX: &str = "vuoto-export-";
+
+/// Reads entries from a foreign format
+pub(crate) trait ImporterPlugin {
+    /// Format name used w/ `--format`
+    fn name(&self) -> &str;
+    fn description(&self) -> String;
+    fn import(&self, input: &[u8]) -> InternalResult<Vec<Entry>>;
+}
+
+/// Writes entries into a foreign format
+pub(crate) trait ExporterPlugin {
+    /// Format name used w/ `--format`
+    fn name(&self) -> &str;
+    fn description(&self) -> String;
+    fn export(&self, entries: &[Entry]) -> InternalResult<Vec<u8>>;
+}
+
+/// Known importers and exporters, looked up by format name
+///
+/// Built-in formats are registered at compile time (see cargo features),
+/// external ones are discovered on `PATH`. When names collide the plugin
+/// registered first wins, so built-ins can't be shadowed.
+#[derive(Default)]
+pub(crate) struct Registry {
+    importers: Vec<Box<dyn ImporterPlugin>>,
+    exporters: Vec<Box<dyn ExporterPlugin>>,
+}
+
+impl Registry {
+    /// Registry w/ all built-in and external plugins
+    pub fn load() -> Self {
+        let mut registry = Self::default();
+
+        registry.register_builtins();
+
+        if let Some(path) = std::env::var_os("PATH") {
+            for dir in std::env::split_paths(&path) {
+                registry.discover(&dir);
+            }
+        }
+
+        registry
+    }
+
+    pub fn register_importer(&mut self, plugin: Box<dyn ImporterPlugin>) {
+        if self.importer(plugin.name()).is_none() {
+            self.importers.push(plugin);
+        }
+    }
+
+    pub fn register_exporter(&mut self, plugin: Box<dyn ExporterPlugin>) {
+        if self.exporter(plugin.name()).is_none() {
+            self.exporters.push(plugin);
+        }
+    }
+
+    pub fn importers(&self) -> &[Box<dyn ImporterPlugin>] {
+        &self.importers
+    }
+
+    pub fn exporters(&self) -> &[Box<dyn ExporterPlugin>] {
+        &self.exporters
+    }
+
+    pub fn importer(&self, name: &str) -> Option<&dyn ImporterPlugin> {
+        self.importers
+            .iter()
+            .find(|p| p.name() == name)
+            .map(|p| p.as_ref())
+    }
+
+    pub fn exporter(&self, name: &str) -> Option<&dyn ExporterPlugin> {
+        self.exporters
+            .iter()
+            .find(|p| p.name() == name)
+            .map(|p| p.as_ref())
+    }
+
+    fn register_builtins(&mut self) {
+        #[cfg(feature = "json-format")]
+        {
+            self.register_importer(Box::new(JsonFormat));
+            self.register_exporter(Box::new(JsonFormat));
+        }
+    }
+
+    /// Register external plugins found in `dir`
+    fn discover(&mut self, dir: &Path) {
+        let Ok(read_dir) = std::fs::read_dir(dir) else {
+            return;
+        };
+
+        let mut found: Vec<(String, PathBuf)> = read_dir
+            .flatten()
+            .filter(|e| is_executable(&e.path()))
+            .filter_map(|e| Some((e.file_name().into_string().ok()?, e.path())))
+            .collect();
+
+        // read_dir order is platform specific
+        found.sort();
+
+        for (file_name, path) in found {
+            if let Some(name) = file_name.strip_prefix(IMPORTER_PREFIX) {
+                self.register_importer(Box::new(External::new(name, path)));
+            } else if let Some(name) = file_name.strip_prefix(EXPORTER_PREFIX) {
+                self.register_exporter(Box::new(External::new(name, path)));
+            }
+        }
+    }
+}
+
+/// Array of versioned entries, as produced by [`Entry::to_value`]
+#[cfg(feature = "json-format")]
+struct JsonFormat;
+
+#[cfg(feature = "json-format")]
+impl ImporterPlugin for JsonFormat {
+    fn name(&self) -> &str {
+        "json"
+    }
+
+    fn description(&self) -> String {
+        "vuoto's own JSON entry format".into()
+    }
+
+    fn import(&self, input: &[u8]) -> InternalResult<Vec<Entry>> {
+        entries_from_json(input)
+    }
+}
+
+#[cfg(feature = "json-format")]
+impl ExporterPlugin for JsonFormat {
+    fn name(&self) -> &str {
+        "json"
+    }
+
+    fn description(&self) -> String {
+        "vuoto's own JSON entry format".into()
+    }
+
+    fn export(&self, entries: &[Entry]) -> InternalResult<Vec<u8>> {
+        let mut out = entries_to_json(entries)?;
+        out.push(b'\n');
+
+        Ok(out)
+    }
+}
+
+/// Plugin backed by an external executable
+///
+/// Protocol: input is written to the plugin's stdin and output read from its
+/// stdout, entries are always exchanged as a JSON array (see [`JsonFormat`]).
+/// Importers get the raw file on stdin and print entries, exporters get
+/// entries on stdin and print the exported file. Any non-zero exit status
+/// fails the operation, stderr is passed through to the user.
+struct External {
+    name: String,
+    path: PathBuf,
+}
+
+impl External {
+    fn new(name: &str, path: PathBuf) -> Self {
+        Self {
+            name: name.to_string(),
+            path,
+        }
+    }
+
+    fn run(&self, input: &[u8]) -> InternalResult<Vec<u8>> {
+        let err = |e: std::io::Error| {
+            InternalError::IO(format!("Plugin '{}' failed: {e}", self.path.display()))
+        };
+
+        let mut child = Command::new(&self.path)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::inherit())
+            .spawn()
+            .map_err(err)?;
+
+        let mut stdin = child.stdin.take();
+
+        // feed stdin from another thread, so a plugin writing output before
+        // consuming all input can't deadlock on a full pipe
+        let output = std::thread::scope(|scope| {
+            let writer = scope.spawn(move || match stdin.as_mut() {
+                Some(stdin) => stdin.write_all(input),
+                None => Ok(()),
+            });
+
+            let output = child.wait_with_output();
+
+            // a plugin may exit w/o reading all of its input
+            match writer.join().expect("stdin writer panicked") {
+                Err(e) if e.kind() != std::io::ErrorKind::BrokenPipe => Err(e),
+                _ => output,
+            }
+        })
+        .map_err(err)?;
+
+        if !output.status.success() {
+            return Err(InternalError::IO(format!(
+                "Plugin '{}' exited w/ {}",
+                self.path.display(),
+                output.status
+            )));
+        }
+
+        Ok(output.stdout)
+    }
+}
+
+impl ImporterPlugin for External {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn description(&self) -> String {
+        format!("external: {}", self.path.display())
+    }
+
+    fn import(&self, input: &[u8]) -> InternalResult<Vec<Entry>> {
+        entries_from_json(&self.run(input)?)
+    }
+}
+
+impl ExporterPlugin for External {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn description(&self) -> String {
+        format!("external: {}", self.path.display())
+    }
+
+    fn export(&self, entries: &[Entry]) -> InternalResult<Vec<u8>> {
+        self.run(&entries_to_json(entries)?)
+    }
+}
+
+fn entries_from_json(input: &[u8]) -> InternalResult<Vec<Entry>> {
+    let values: Vec<Value> = serde_json::from_slice(input)
+        .map_err(|e| InternalError::IO(format!("Expected a JSON array of entries: {e}")))?;
+
+    values.into_iter().map(Entry::from_value).collect()
+}
+
+/// Canonical JSON of `entries`, so exports of the same data are
+/// byte-identical however the vault was loaded
+fn entries_to_json(entries: &[Entry]) -> InternalResult<Vec<u8>> {
+    let mut sorted: Vec<&Entry> = entries.iter().collect();
+    sorted.sort_by(|a, b| entries::compare_entries(a, b));
+
+    let values = sorted
+        .into_iter()
+        .map(|e| e.to_value().map(canonical))
+        .collect::<InternalResult<Vec<Value>>>()?;
+
+    serde_json::to_vec_pretty(&values)
+        .map_err(|e| InternalError::IO(format!("Failed to serialize entries: {e}")))
+}
+
+/// `value` w/ object keys sorted, serde_json keeps insertion order instead
+/// once any dependency enables its `preserve_order` feature
+fn canonical(value: Value) -> Value {
+    match value {
+        Value::Object(map) => {
+            let mut fields: Vec<(String, Value)> = map.into_iter().collect();
+            fields.sort_by(|a, b| a.0.cmp(&b.0));
+
+            Value::Object(fields.into_iter().map(|(k, v)| (k, canonical(v))).collect())
+        }
+        Value::Array(values) => Value::Array(values.into_iter().map(canonical).collect()),
+        value => value,
+    }
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+
+    path.metadata()
+        .map(|m| m.is_file() && m.permissions().mode() & 0o111 != 0)
+        .unwrap_or(false)
+}
+
+#[cfg(not(unix))]
+fn is_executable(path: &Path) -> bool {
+    path.is_file()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entries::{LoginEntry, Template};
+    use tempfile::TempDir;
+
+    fn login(name: &str) -> Entry {
+        Entry::new(Template::Login(LoginEntry {
+            name: name.into(),
+            password: "hunter2".into(),
+            username: None,
+            url: None,
+        }))
+    }
+
+    #[cfg(feature = "json-format")]
+    #[test]
+    fn test_json_roundtrip() {
+        let registry = Registry::load();
+        let exported = registry
+            .exporter("json")
+            .unwrap()
+            .export(&[login("a"), login("b")])
+            .unwrap();
+
+        let imported = registry
+            .importer("json")
+            .unwrap()
+            .import(&exported)
+            .unwrap();
+
+        let names: Vec<&str> = imported.iter().map(|e| e.name()).collect();
+        assert_eq!(names, vec!["a", "b"]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_discover_external_plugins() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let temp_dir = TempDir::new().unwrap();
+        let script = temp_dir.path().join("vuoto-import-echo");
+
+        // echoes its input back, so it imports vuoto's own JSON
+        std::fs::write(&script, "#!/bin/sh\ncat\n").unwrap();
+        std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        // not executable, ignored
+        std::fs::write(temp_dir.path().join("vuoto-export-nope"), "").unwrap();
+
+        let mut registry = Registry::default();
+        registry.discover(temp_dir.path());
+
+        assert!(registry.exporter("nope").is_none());
+
+        let input = entries_to_json(&[login("mail")]).unwrap();
+        let imported = registry.importer("echo").unwrap().import(&input).unwrap();
+
+        assert_eq!(imported[0].name(), "mail");
+    }
+
+    #[test]
+    fn test_export_is_deterministic() {
+        let mut pinned = login("z");
+        pinned.position = Some(0);
+
+        let first = entries_to_json(&[login("b"), pinned, login("a")]).unwrap();
+
+        let mut pinned = login("z");
+        pinned.position = Some(0);
+
+        let second = entries_to_json(&[login("a"), login("b"), pinned]).unwrap();
+        assert_eq!(first, second);
+
+        let values: Vec<Value> = serde_json::from_slice(&first).unwrap();
+        let names: Vec<&str> = values.iter().map(|v| v["name"].as_str().unwrap()).collect();
+        assert_eq!(names, vec!["z", "a", "b"]);
+    }
+
+    #[test]
+    fn test_first_registered_wins() {
+        let mut registry = Registry::default();
+        registry.register_importer(Box::new(External::new("x", "/a".into())));
+        registry.register_importer(Box::new(External::new("x", "/b".into())));
+
+        assert_eq!(registry.importers().len(), 1);
+        assert_eq!(
+            registry.importer("x").unwrap().description(),
+            "external: /a"
+        );
+    }
+}
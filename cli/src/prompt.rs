@@ -0,0 +1,389 @@
+use crate::{
+    config::Theme,
+    secret::SecretString,
+    types::{InternalError, InternalResult},
+};
+use inquire::{
+    Confirm, CustomType, Editor, MultiSelect, Password, PasswordDisplayMode, Select, Text,
+    ui::{Color, ErrorMessageRenderConfig, RenderConfig, StyleSheet, Styled},
+    validator::Validation,
+};
+use serde_json::Value;
+use std::{
+    collections::VecDeque,
+    fmt::Display,
+    path::Path,
+    sync::{
+        Mutex,
+        atomic::{AtomicBool, Ordering},
+    },
+};
+
+pub(crate) const ANSWERS_ENV: &str = "VUOTO_ANSWERS";
+/// Master password used instead of prompting, for automation
+pub(crate) const PASSWORD_ENV: &str = "VUOTO_PASSWORD";
+
+/// Scripted answers, consumed in order, `None` when prompting the terminal
+static ANSWERS: Mutex<Option<VecDeque<Value>>> = Mutex::new(None);
+static DISABLED: AtomicBool = AtomicBool::new(false);
+
+/// Validator used by text prompts, returns an error message on failure
+pub(crate) type Validator = fn(&str) -> Result<(), String>;
+
+/// Render all prompts in `theme`
+pub(crate) fn set_theme(theme: Theme) {
+    let config = match theme {
+        Theme::Default => return,
+        Theme::Plain => RenderConfig::empty(),
+        Theme::Light => RenderConfig::default_colored()
+            .with_prompt_prefix(Styled::new("?").with_fg(Color::DarkGreen))
+            .with_answered_prompt_prefix(Styled::new(">").with_fg(Color::DarkGreen))
+            .with_help_message(StyleSheet::new().with_fg(Color::DarkBlue))
+            .with_answer(StyleSheet::new().with_fg(Color::DarkBlue))
+            .with_highlighted_option_prefix(Styled::new(">").with_fg(Color::DarkBlue))
+            .with_selected_checkbox(Styled::new("[x]").with_fg(Color::DarkGreen))
+            .with_selected_option(Some(StyleSheet::new().with_fg(Color::DarkBlue)))
+            .with_error_message(
+                ErrorMessageRenderConfig::default_colored()
+                    .with_prefix(Styled::new("#").with_fg(Color::DarkRed))
+                    .with_message(StyleSheet::new().with_fg(Color::DarkRed)),
+            ),
+    };
+
+    inquire::set_global_render_config(config);
+}
+
+/// Load scripted answers from a JSON file (or an inline JSON array), all
+/// subsequent prompts are answered from it w/o touching the terminal
+///
+/// Answers are a JSON array consumed in prompt order, where strings answer
+/// text prompts and pick select options by label, arrays of labels pick
+/// several options, booleans answer confirmations, numbers answer numeric
+/// prompts and `null` skips optional prompts.
+pub(crate) fn load_answers(source: &str) -> InternalResult<()> {
+    let raw = if source.trim_start().starts_with('[') {
+        source.to_string()
+    } else {
+        std::fs::read_to_string(Path::new(source)).map_err(|e| {
+            InternalError::IO(format!("Failed to read answers file '{source}': {e}"))
+        })?
+    };
+
+    let answers: Vec<Value> = serde_json::from_str(&raw)
+        .map_err(|e| InternalError::IO(format!("Invalid answers file '{source}': {e}")))?;
+
+    *lock() = Some(answers.into());
+
+    Ok(())
+}
+
+/// Master password given up front, the first line of `file` (e.g.
+/// `/dev/fd/3`) or else `VUOTO_PASSWORD`, which is cleared so processes
+/// started later never inherit it
+///
+/// NOTE: call it before spawning any thread
+pub(crate) fn given_password(file: Option<&str>) -> InternalResult<Option<SecretString>> {
+    let env = std::env::var(PASSWORD_ENV).ok().map(SecretString::from);
+
+    if env.is_some() {
+        // SAFETY: no other thread is running yet, see above
+        unsafe { std::env::remove_var(PASSWORD_ENV) };
+    }
+
+    let Some(file) = file else {
+        return Ok(env);
+    };
+
+    let raw =
+        SecretString::from(std::fs::read_to_string(file).map_err(|e| {
+            InternalError::IO(format!("Failed to read password file '{file}': {e}"))
+        })?);
+    let line = raw.expose().lines().next().unwrap_or_default();
+
+    Ok(Some(line.into()))
+}
+
+/// Fail every prompt instead of showing it, e.g. for `--json` output which
+/// must not be mixed w/ prompts
+pub(crate) fn disable() {
+    DISABLED.store(true, Ordering::Relaxed);
+}
+
+pub(crate) fn is_disabled() -> bool {
+    DISABLED.load(Ordering::Relaxed)
+}
+
+/// Whether prompts are answered from a script
+pub(crate) fn is_scripted() -> bool {
+    lock().is_some()
+}
+
+fn lock() -> std::sync::MutexGuard<'static, Option<VecDeque<Value>>> {
+    ANSWERS.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Pop the next scripted answer, `Ok(None)` when not in scripted mode
+fn next_answer(message: &str) -> InternalResult<Option<Value>> {
+    if is_disabled() {
+        return Err(InternalError::IO(format!(
+            "Prompt '{message}' can't be shown w/ --json"
+        )));
+    }
+
+    match lock().as_mut() {
+        None => Ok(None),
+        Some(answers) => answers.pop_front().map(Some).ok_or_else(|| {
+            InternalError::IO(format!("No scripted answer left for prompt '{message}'"))
+        }),
+    }
+}
+
+fn scripted_error(message: &str, answer: &Value) -> InternalError {
+    InternalError::IO(format!(
+        "Scripted answer {answer} is not valid for prompt '{message}'"
+    ))
+}
+
+fn read_error(message: &str, err: inquire::InquireError) -> InternalError {
+    InternalError::IO(format!("Failed to read '{message}': {err}"))
+}
+
+pub(crate) fn text(message: &str) -> InternalResult<String> {
+    text_validated(message, |_| Ok(()))
+}
+
+pub(crate) fn text_validated(message: &str, validator: Validator) -> InternalResult<String> {
+    if let Some(answer) = next_answer(message)? {
+        let Value::String(s) = &answer else {
+            return Err(scripted_error(message, &answer));
+        };
+
+        validator(s).map_err(|e| InternalError::IO(format!("{e} (prompt '{message}')")))?;
+
+        return Ok(s.clone());
+    }
+
+    Text::new(message)
+        .with_validator(move |s: &str| {
+            Ok(match validator(s) {
+                Ok(()) => Validation::Valid,
+                Err(e) => Validation::Invalid(e.into()),
+            })
+        })
+        .prompt()
+        .map_err(|e| read_error(message, e))
+}
+
+pub(crate) fn text_skippable(message: &str) -> InternalResult<Option<String>> {
+    text_skippable_validated(message, |_| Ok(()))
+}
+
+pub(crate) fn text_skippable_validated(
+    message: &str,
+    validator: Validator,
+) -> InternalResult<Option<String>> {
+    if let Some(answer) = next_answer(message)? {
+        return match &answer {
+            Value::Null => Ok(None),
+            Value::String(s) => {
+                validator(s).map_err(|e| InternalError::IO(format!("{e} (prompt '{message}')")))?;
+                Ok(Some(s.clone()))
+            }
+            _ => Err(scripted_error(message, &answer)),
+        };
+    }
+
+    Text::new(message)
+        .with_validator(move |s: &str| {
+            Ok(match validator(s) {
+                Ok(()) => Validation::Valid,
+                Err(e) => Validation::Invalid(e.into()),
+            })
+        })
+        .prompt_skippable()
+        .map_err(|e| read_error(message, e))
+}
+
+/// Read a password w/o echoing it
+pub(crate) fn password(message: &str) -> InternalResult<SecretString> {
+    read_password(message, false)
+}
+
+/// Read a new password, typed twice to rule out typos
+///
+/// NOTE: scripted answers hold it only once
+pub(crate) fn new_password(message: &str) -> InternalResult<SecretString> {
+    read_password(message, true)
+}
+
+fn read_password(message: &str, confirm: bool) -> InternalResult<SecretString> {
+    if let Some(answer) = next_answer(message)? {
+        let Value::String(s) = answer else {
+            return Err(scripted_error(message, &answer));
+        };
+
+        return Ok(s.into());
+    }
+
+    let prompt = Password::new(message).with_display_mode(PasswordDisplayMode::Masked);
+
+    let prompt = if confirm {
+        prompt.with_custom_confirmation_message("Repeat password:")
+    } else {
+        prompt.without_confirmation()
+    };
+
+    prompt
+        .prompt()
+        .map(SecretString::from)
+        .map_err(|e| read_error(message, e))
+}
+
+/// Pick one of `options`, returning its index
+pub(crate) fn select_index<T: Display>(message: &str, options: Vec<T>) -> InternalResult<usize> {
+    if let Some(answer) = next_answer(message)? {
+        let Value::String(s) = &answer else {
+            return Err(scripted_error(message, &answer));
+        };
+
+        return options
+            .iter()
+            .position(|o| o.to_string() == *s)
+            .ok_or_else(|| scripted_error(message, &answer));
+    }
+
+    Select::new(message, options)
+        .raw_prompt()
+        .map(|o| o.index)
+        .map_err(|e| read_error(message, e))
+}
+
+/// Pick one of `options`, returning it
+pub(crate) fn select<T: Display>(message: &str, mut options: Vec<T>) -> InternalResult<T> {
+    let labels: Vec<String> = options.iter().map(|o| o.to_string()).collect();
+    let idx = select_index(message, labels)?;
+
+    Ok(options.swap_remove(idx))
+}
+
+/// Pick any number of `options`, returning their indices in order
+pub(crate) fn multi_select<T: Display>(
+    message: &str,
+    options: Vec<T>,
+) -> InternalResult<Vec<usize>> {
+    if let Some(answer) = next_answer(message)? {
+        let Value::Array(picked) = &answer else {
+            return Err(scripted_error(message, &answer));
+        };
+
+        let mut indices = picked
+            .iter()
+            .map(|p| {
+                options
+                    .iter()
+                    .position(|o| p.as_str() == Some(&o.to_string()))
+                    .ok_or_else(|| scripted_error(message, &answer))
+            })
+            .collect::<InternalResult<Vec<usize>>>()?;
+        indices.sort_unstable();
+
+        return Ok(indices);
+    }
+
+    MultiSelect::new(message, options)
+        .raw_prompt()
+        .map(|picked| picked.iter().map(|o| o.index).collect())
+        .map_err(|e| read_error(message, e))
+}
+
+pub(crate) fn confirm(message: &str, default: bool) -> InternalResult<bool> {
+    if let Some(answer) = next_answer(message)? {
+        return answer
+            .as_bool()
+            .ok_or_else(|| scripted_error(message, &answer));
+    }
+
+    Confirm::new(message)
+        .with_default(default)
+        .prompt()
+        .map_err(|e| read_error(message, e))
+}
+
+pub(crate) fn number(message: &str, default: usize) -> InternalResult<usize> {
+    if let Some(answer) = next_answer(message)? {
+        return answer
+            .as_u64()
+            .map(|n| n as usize)
+            .ok_or_else(|| scripted_error(message, &answer));
+    }
+
+    CustomType::<usize>::new(message)
+        .with_default(default)
+        .prompt()
+        .map_err(|e| read_error(message, e))
+}
+
+/// Edit multi-line text in the user's editor
+pub(crate) fn editor(message: &str, text: &str, extension: &str) -> InternalResult<String> {
+    if let Some(answer) = next_answer(message)? {
+        let Value::String(s) = answer else {
+            return Err(scripted_error(message, &answer));
+        };
+
+        return Ok(s);
+    }
+
+    Editor::new(message)
+        .with_predefined_text(text)
+        .with_file_extension(extension)
+        .prompt()
+        .map_err(|e| read_error(message, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // NOTE: answers are process wide, so everything is exercised in a
+    // single test to avoid races between parallel tests
+    #[test]
+    fn test_scripted_answers() {
+        load_answers(
+            r#"["github", null, "License", ["b", "a"], true, 3, "notes", "bad-date", "x"]"#,
+        )
+        .unwrap();
+        assert!(is_scripted());
+
+        assert_eq!(text("Entry name:").unwrap(), "github");
+        assert_eq!(text_skippable("URL (optional):").unwrap(), None);
+        assert_eq!(
+            select("Template:", vec!["Login", "License"]).unwrap(),
+            "License"
+        );
+        assert_eq!(
+            multi_select("Vaults:", vec!["a", "b", "c"]).unwrap(),
+            vec![0, 1]
+        );
+        assert!(confirm("Save?", false).unwrap());
+        assert_eq!(number("Position:", 1).unwrap(), 3);
+        assert_eq!(editor("Notes:", "", ".md").unwrap(), "notes");
+
+        let res = text_validated("Date:", |s| {
+            if s.len() == 10 {
+                Ok(())
+            } else {
+                Err("Expected a date".into())
+            }
+        });
+        assert!(res.is_err());
+
+        // type mismatches are reported instead of being coerced
+        assert!(confirm("Save?", false).is_err());
+
+        // exhausted answers
+        assert!(text("Entry name:").is_err());
+
+        *lock() = None;
+        assert!(!is_scripted());
+    }
+}
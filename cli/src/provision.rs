@@ -0,0 +1,250 @@
+use crate::types::{InternalError, InternalResult};
+use std::path::{Path, PathBuf};
+use zeroize::Zeroizing;
+
+/// Parent of each unit's dir of plain credential files, a tmpfs on systemd
+/// systems, so nothing is left on disk after a reboot
+pub(crate) const RUNTIME_DIR: &str = "/run/vuoto";
+/// Parent of each unit's dir of credentials encrypted by `systemd-creds`,
+/// kept on disk to be there at boot
+pub(crate) const ENCRYPTED_DIR: &str = "/etc/vuoto/credstore";
+/// Lists the files written for a unit, so nothing else is ever removed
+const LIST_NAME: &str = ".vuoto-provisioned";
+
+/// Secret handed to a service, as the file `name` in its unit's dir
+pub(crate) struct Credential {
+    pub name: String,
+    pub value: Zeroizing<String>,
+}
+
+/// How credentials are written
+pub(crate) enum Store {
+    /// plain files, only readable by their owner
+    Files,
+    /// encrypted w/ `systemd-creds encrypt`, to the host's TPM2 and/or
+    /// `/var/lib/systemd/credential.secret`
+    SystemdCreds,
+}
+
+/// Line of the unit's `[Service]` section loading `credential` at `path`
+pub(crate) fn unit_line(store: &Store, name: &str, path: &Path) -> String {
+    match store {
+        Store::Files => format!("LoadCredential={name}:{}", path.display()),
+        Store::SystemdCreds => format!("LoadCredentialEncrypted={name}:{}", path.display()),
+    }
+}
+
+/// Dir of `unit` under `base`, refusing names which would leave it
+pub(crate) fn unit_dir(base: &Path, unit: &str) -> InternalResult<PathBuf> {
+    let valid = !unit.is_empty()
+        && !unit.starts_with('.')
+        && unit
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "@:._-".contains(c));
+
+    if !valid {
+        return Err(InternalError::IO(format!("Invalid unit name '{unit}'")));
+    }
+
+    Ok(base.join(unit))
+}
+
+/// Credential name for a field of an entry, w/ anything systemd doesn't
+/// accept in one replaced by `_`
+pub(crate) fn credential_name(entry: &str, field: Option<&str>) -> String {
+    let name = match field {
+        Some(field) => format!("{entry}-{field}"),
+        None => entry.to_string(),
+    };
+
+    name.chars()
+        .map(|c| match c.is_ascii_alphanumeric() || "._-".contains(c) {
+            true => c,
+            false => '_',
+        })
+        .collect::<String>()
+        .trim_start_matches('.')
+        .to_string()
+}
+
+/// Replace the credentials in `dir` w/ `creds`, returning their paths
+#[cfg(unix)]
+pub(crate) fn provision(
+    dir: &Path,
+    store: &Store,
+    creds: &[Credential],
+) -> InternalResult<Vec<PathBuf>> {
+    use std::{
+        fs::{DirBuilder, OpenOptions, Permissions},
+        io::Write,
+        os::unix::fs::{DirBuilderExt, OpenOptionsExt, PermissionsExt},
+        process::{Command, Stdio},
+    };
+
+    let mut names = std::collections::HashSet::new();
+
+    for cred in creds {
+        if cred.name.is_empty() || !names.insert(&cred.name) {
+            return Err(InternalError::IO(format!(
+                "Credential name '{}' is empty or used twice",
+                cred.name
+            )));
+        }
+    }
+
+    // secrets left out this time must not linger
+    deprovision(dir)?;
+
+    DirBuilder::new().recursive(true).mode(0o700).create(dir)?;
+    std::fs::set_permissions(dir, Permissions::from_mode(0o700))?;
+
+    let mut paths = Vec::new();
+    let mut list = String::new();
+
+    for cred in creds {
+        let path = dir.join(&cred.name);
+
+        // listed first, so a failure halfway still gets cleaned up
+        list.push_str(&cred.name);
+        list.push('\n');
+        std::fs::write(dir.join(LIST_NAME), &list)?;
+
+        match store {
+            Store::Files => {
+                let mut file = OpenOptions::new()
+                    .write(true)
+                    .create_new(true)
+                    .mode(0o400)
+                    .open(&path)?;
+                file.write_all(cred.value.as_bytes())?;
+            }
+            Store::SystemdCreds => {
+                let err = |e: std::io::Error| {
+                    InternalError::IO(format!("Failed to run systemd-creds: {e}"))
+                };
+
+                let mut child = Command::new("systemd-creds")
+                    .arg("encrypt")
+                    .arg(format!("--name={}", cred.name))
+                    .arg("-")
+                    .arg(&path)
+                    .stdin(Stdio::piped())
+                    .stdout(Stdio::null())
+                    .stderr(Stdio::inherit())
+                    .spawn()
+                    .map_err(err)?;
+
+                if let Some(mut stdin) = child.stdin.take() {
+                    stdin.write_all(cred.value.as_bytes()).map_err(err)?;
+                }
+
+                let status = child.wait().map_err(err)?;
+
+                if !status.success() {
+                    return Err(InternalError::IO(format!(
+                        "systemd-creds failed to encrypt '{}', exited w/ {status}",
+                        cred.name
+                    )));
+                }
+
+                std::fs::set_permissions(&path, Permissions::from_mode(0o400))?;
+            }
+        }
+
+        paths.push(path);
+    }
+
+    Ok(paths)
+}
+
+#[cfg(not(unix))]
+pub(crate) fn provision(
+    _dir: &Path,
+    _store: &Store,
+    _creds: &[Credential],
+) -> InternalResult<Vec<PathBuf>> {
+    Err(InternalError::IO(
+        "Provisioning credentials requires a Unix system".into(),
+    ))
+}
+
+/// Remove the credentials provisioned to `dir`, and the dir once empty,
+/// returning how many there were
+pub(crate) fn deprovision(dir: &Path) -> InternalResult<usize> {
+    let list = match std::fs::read_to_string(dir.join(LIST_NAME)) {
+        Ok(list) => list,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+        Err(e) => return Err(e.into()),
+    };
+
+    let mut removed = 0;
+
+    for name in list.lines().filter(|n| !n.is_empty() && !n.contains('/')) {
+        match std::fs::remove_file(dir.join(name)) {
+            Ok(()) => removed += 1,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e.into()),
+        }
+    }
+
+    std::fs::remove_file(dir.join(LIST_NAME))?;
+    // files placed there by others stay, and so does the dir
+    let _ = std::fs::remove_dir(dir);
+
+    Ok(removed)
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+    use tempfile::TempDir;
+
+    fn cred(name: &str, value: &str) -> Credential {
+        Credential {
+            name: name.into(),
+            value: Zeroizing::new(value.into()),
+        }
+    }
+
+    #[test]
+    fn test_provision_and_deprovision() {
+        let temp_dir = TempDir::new().unwrap();
+        let dir = unit_dir(temp_dir.path(), "my-service.service").unwrap();
+
+        let paths = provision(
+            &dir,
+            &Store::Files,
+            &[cred("db", "s3cret"), cred("api", "token")],
+        )
+        .unwrap();
+
+        assert_eq!(paths, vec![dir.join("db"), dir.join("api")]);
+        assert_eq!(std::fs::read_to_string(&paths[0]).unwrap(), "s3cret");
+
+        let mode = |p: &Path| std::fs::metadata(p).unwrap().permissions().mode() & 0o777;
+        assert_eq!(mode(&dir), 0o700);
+        assert_eq!(mode(&paths[0]), 0o400);
+
+        // a secret left out is removed on the next run
+        provision(&dir, &Store::Files, &[cred("db", "new")]).unwrap();
+        assert!(!dir.join("api").exists());
+        assert_eq!(std::fs::read_to_string(dir.join("db")).unwrap(), "new");
+
+        std::fs::write(dir.join("other"), "kept").unwrap();
+        assert_eq!(deprovision(&dir).unwrap(), 1);
+        assert!(!dir.join("db").exists());
+        assert!(dir.join("other").exists());
+        assert_eq!(deprovision(&dir).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_names_stay_inside_the_dir() {
+        assert!(unit_dir(Path::new("/run/vuoto"), "../etc").is_err());
+        assert!(unit_dir(Path::new("/run/vuoto"), "").is_err());
+        assert_eq!(
+            credential_name("../db", Some("Root password")),
+            "_db-Root_password"
+        );
+    }
+}
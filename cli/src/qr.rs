@@ -0,0 +1,195 @@
+use crate::types::{InternalError, InternalResult};
+use base64::{Engine as _, engine::general_purpose};
+use qrcode::{EcLevel, QrCode, render::unicode};
+use sha2::{Digest, Sha256};
+
+/// Prefix of every frame, bump the version on format changes
+const FRAME_PREFIX: &str = "vuoto1";
+/// Payload bytes per frame, keeps codes small enough for terminal screens
+pub(crate) const CHUNK_SIZE: usize = 256;
+
+/// Split `data` into frames of the form
+/// `vuoto1:<index>/<total>:<sha256 of data>:<base64 chunk>`, index is 1-based
+pub(crate) fn frames(data: &[u8]) -> Vec<String> {
+    let checksum = hex_digest(data);
+
+    // empty data still needs a frame to carry the checksum
+    let chunks: Vec<&[u8]> = if data.is_empty() {
+        vec![&[]]
+    } else {
+        data.chunks(CHUNK_SIZE).collect()
+    };
+
+    let total = chunks.len();
+
+    chunks
+        .iter()
+        .enumerate()
+        .map(|(i, chunk)| {
+            format!(
+                "{FRAME_PREFIX}:{}/{total}:{checksum}:{}",
+                i + 1,
+                general_purpose::STANDARD.encode(chunk)
+            )
+        })
+        .collect()
+}
+
+/// Render a frame as a QR code made of unicode half blocks
+pub(crate) fn render(frame: &str) -> InternalResult<String> {
+    let code = QrCode::with_error_correction_level(frame, EcLevel::L)
+        .map_err(|e| InternalError::IO(format!("Failed to encode QR code: {e}")))?;
+
+    Ok(code
+        .render::<unicode::Dense1x2>()
+        .dark_color(unicode::Dense1x2::Light)
+        .light_color(unicode::Dense1x2::Dark)
+        .build())
+}
+
+/// Collects scanned frames in any order, duplicates are ignored
+#[derive(Debug, Default)]
+pub(crate) struct Assembler {
+    checksum: String,
+    chunks: Vec<Option<Vec<u8>>>,
+}
+
+impl Assembler {
+    /// Add a scanned frame, returns the verified data once all frames are in
+    pub fn push(&mut self, frame: &str) -> InternalResult<Option<Vec<u8>>> {
+        let invalid = || InternalError::IO(format!("Invalid frame: {frame}"));
+
+        let mut parts = frame.trim().splitn(4, ':');
+
+        if parts.next() != Some(FRAME_PREFIX) {
+            return Err(invalid());
+        }
+
+        let (index, total) = parts
+            .next()
+            .and_then(|p| p.split_once('/'))
+            .ok_or_else(invalid)?;
+        let index: usize = index.parse().map_err(|_| invalid())?;
+        let total: usize = total.parse().map_err(|_| invalid())?;
+        let checksum = parts.next().ok_or_else(invalid)?;
+        let chunk = general_purpose::STANDARD
+            .decode(parts.next().ok_or_else(invalid)?)
+            .map_err(|_| invalid())?;
+
+        if total == 0 || index == 0 || index > total {
+            return Err(invalid());
+        }
+
+        if self.chunks.is_empty() {
+            self.checksum = checksum.to_string();
+            self.chunks = vec![None; total];
+        } else if self.checksum != checksum || self.chunks.len() != total {
+            return Err(InternalError::IO(
+                "Frame belongs to a different transfer".into(),
+            ));
+        }
+
+        self.chunks[index - 1].get_or_insert(chunk);
+
+        if !self.missing().is_empty() {
+            return Ok(None);
+        }
+
+        let data: Vec<u8> = self.chunks.iter().flatten().flatten().copied().collect();
+
+        if hex_digest(&data) != self.checksum {
+            return Err(InternalError::IO(
+                "Checksum mismatch, frames were corrupted".into(),
+            ));
+        }
+
+        Ok(Some(data))
+    }
+
+    /// 1-based indices of frames not scanned yet
+    pub fn missing(&self) -> Vec<usize> {
+        self.chunks
+            .iter()
+            .enumerate()
+            .filter(|(_, c)| c.is_none())
+            .map(|(i, _)| i + 1)
+            .collect()
+    }
+
+    pub fn total(&self) -> usize {
+        self.chunks.len()
+    }
+}
+
+fn hex_digest(data: &[u8]) -> String {
+    Sha256::digest(data)
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn data(len: usize) -> Vec<u8> {
+        (0..len).map(|i| (i % 251) as u8).collect()
+    }
+
+    #[test]
+    fn test_reassemble_out_of_order() {
+        let input = data(CHUNK_SIZE * 3 + 10);
+        let mut frames = frames(&input);
+        assert_eq!(frames.len(), 4);
+
+        frames.reverse();
+
+        let mut assembler = Assembler::default();
+        assert!(assembler.push(&frames[0]).unwrap().is_none());
+        assert!(assembler.push(&frames[0]).unwrap().is_none());
+        assert!(assembler.push(&frames[1]).unwrap().is_none());
+        assert_eq!(assembler.missing(), vec![1, 2]);
+        assert!(assembler.push(&frames[2]).unwrap().is_none());
+
+        assert_eq!(assembler.push(&frames[3]).unwrap(), Some(input));
+    }
+
+    #[test]
+    fn test_detect_corruption() {
+        let frames = frames(&data(CHUNK_SIZE + 1));
+
+        // same checksum, tampered chunk
+        let (head, _) = frames[1].rsplit_once(':').unwrap();
+        let tampered = format!("{head}:{}", general_purpose::STANDARD.encode(b"x"));
+
+        let mut assembler = Assembler::default();
+        assembler.push(&frames[0]).unwrap();
+        assert!(assembler.push(&tampered).is_err());
+    }
+
+    #[test]
+    fn test_reject_foreign_frames() {
+        let mut assembler = Assembler::default();
+        assembler.push(&frames(&data(CHUNK_SIZE + 1))[0]).unwrap();
+
+        assert!(assembler.push(&frames(&data(CHUNK_SIZE + 2))[1]).is_err());
+        assert!(assembler.push("not a frame").is_err());
+        assert!(assembler.push("vuoto1:3/2:abc:AA==").is_err());
+    }
+
+    #[test]
+    fn test_empty_data() {
+        let frames = frames(b"");
+        assert_eq!(frames.len(), 1);
+
+        let mut assembler = Assembler::default();
+        assert_eq!(assembler.push(&frames[0]).unwrap(), Some(Vec::new()));
+    }
+
+    #[test]
+    fn test_frames_fit_in_qr_codes() {
+        for frame in frames(&data(CHUNK_SIZE * 2)) {
+            assert!(!render(&frame).unwrap().is_empty());
+        }
+    }
+}
@@ -0,0 +1,91 @@
+use crate::types::{InternalError, InternalResult};
+use std::sync::OnceLock;
+
+const SAMPLE_SIZE: usize = 64;
+/// Bounds on set bits in a sample, ~8 standard deviations off 256, so a
+/// healthy source practically never trips them
+const MIN_SET_BITS: u32 = 160;
+const MAX_SET_BITS: u32 = 352;
+
+/// Outcome of the health check, run once per process
+static HEALTH: OnceLock<Result<(), String>> = OnceLock::new();
+
+/// Health of the OS RNG, checked on first use
+pub(crate) fn health() -> InternalResult<()> {
+    HEALTH
+        .get_or_init(check)
+        .clone()
+        .map_err(InternalError::Entropy)
+}
+
+/// Fill `bytes` from the OS RNG, refused if it failed its health check
+pub(crate) fn fill(bytes: &mut [u8]) -> InternalResult<()> {
+    health()?;
+
+    getrandom::fill(bytes)
+        .map_err(|e| InternalError::Entropy(format!("OS random source failed: {e}")))
+}
+
+/// Draw two samples and reject a source which errors, repeats itself or
+/// is clearly biased
+fn check() -> Result<(), String> {
+    let mut first = [0u8; SAMPLE_SIZE];
+    let mut second = [0u8; SAMPLE_SIZE];
+
+    for sample in [&mut first, &mut second] {
+        getrandom::fill(sample).map_err(|e| format!("OS random source is unavailable: {e}"))?;
+    }
+
+    if first == second {
+        return Err("OS random source returned the same bytes twice".into());
+    }
+
+    for sample in [&first, &second] {
+        verify_sample(sample)?;
+    }
+
+    Ok(())
+}
+
+fn verify_sample(sample: &[u8]) -> Result<(), String> {
+    if sample.iter().all(|b| *b == sample[0]) {
+        return Err("OS random source returned a constant".into());
+    }
+
+    let set_bits: u32 = sample.iter().map(|b| b.count_ones()).sum();
+
+    if !(MIN_SET_BITS..=MAX_SET_BITS).contains(&set_bits) {
+        return Err(format!(
+            "OS random source looks broken ({set_bits} of {} bits set)",
+            sample.len() * 8
+        ));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rng_health() {
+        assert!(health().is_ok());
+
+        let mut bytes = [0u8; 32];
+        fill(&mut bytes).unwrap();
+        assert_ne!(bytes, [0u8; 32]);
+    }
+
+    #[test]
+    fn test_reject_broken_samples() {
+        assert!(verify_sample(&[0u8; SAMPLE_SIZE]).is_err());
+        assert!(verify_sample(&[0xffu8; SAMPLE_SIZE]).is_err());
+        assert!(verify_sample(&[0x5au8; SAMPLE_SIZE]).is_err());
+
+        // biased towards zero bits
+        let mut sample = [0x01u8; SAMPLE_SIZE];
+        sample[0] = 0xff;
+        assert!(verify_sample(&sample).is_err());
+    }
+}
@@ -0,0 +1,226 @@
+use crate::{
+    crypto::{self, VaultKey},
+    types::{InternalError, InternalResult},
+    vaults::Vault,
+};
+use base64::{Engine as _, engine::general_purpose};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use zeroize::Zeroizing;
+
+const SCRATCH_EXT: &str = "scratch";
+
+/// Scratchpad of a vault, sealed next to it until it expires
+#[derive(Debug, Serialize, Deserialize)]
+struct Stored {
+    /// unix timestamp
+    expires_at: u64,
+    /// base64
+    sealed: String,
+}
+
+impl Stored {
+    fn read(path: &Path) -> InternalResult<Option<Self>> {
+        let raw = match std::fs::read(path) {
+            Ok(raw) => raw,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+
+        serde_json::from_slice(&raw)
+            .map(Some)
+            .map_err(|_| InternalError::IO("Invalid scratchpad".into()))
+    }
+}
+
+/// Text of `vault`'s scratchpad, `None` if it's empty or expired at `now`,
+/// in which case it's removed
+pub(crate) fn load<P: AsRef<Path>>(
+    home_dir: &P,
+    vault: &Vault,
+    key: &VaultKey,
+    now: u64,
+) -> InternalResult<Option<Zeroizing<String>>> {
+    let path = path(home_dir, vault);
+
+    let Some(stored) = Stored::read(&path)? else {
+        return Ok(None);
+    };
+
+    if stored.expires_at <= now {
+        remove(home_dir, vault)?;
+        return Ok(None);
+    }
+
+    let invalid = || InternalError::IO("Scratchpad can't be opened".into());
+    let sealed = general_purpose::STANDARD
+        .decode(&stored.sealed)
+        .map_err(|_| invalid())?;
+    let plain = Zeroizing::new(
+        crypto::open(key, &aad(vault, stored.expires_at), &sealed).map_err(|_| invalid())?,
+    );
+
+    String::from_utf8(plain.to_vec())
+        .map(|text| Some(Zeroizing::new(text)))
+        .map_err(|_| invalid())
+}
+
+/// Seal `text` as `vault`'s scratchpad until `expires_at`
+pub(crate) fn save<P: AsRef<Path>>(
+    home_dir: &P,
+    vault: &Vault,
+    key: &VaultKey,
+    text: &str,
+    expires_at: u64,
+) -> InternalResult<()> {
+    // the expiry is bound to the text, so it can't be pushed back
+    let sealed = crypto::seal(key, &aad(vault, expires_at), text.as_bytes())?;
+
+    let raw = serde_json::to_vec(&Stored {
+        expires_at,
+        sealed: general_purpose::STANDARD.encode(sealed),
+    })
+    .map_err(|e| InternalError::IO(format!("Failed to encode scratchpad: {e}")))?;
+
+    // write + rename, so a crash never leaves a half written scratchpad
+    let path = path(home_dir, vault);
+    let tmp = path.with_extension("scratch.tmp");
+    std::fs::write(&tmp, raw)?;
+    std::fs::rename(&tmp, &path)?;
+
+    Ok(())
+}
+
+/// Re-seal `vault`'s scratchpad under a new key, keeping its expiry
+pub(crate) fn rekey<P: AsRef<Path>>(
+    home_dir: &P,
+    vault: &Vault,
+    old_key: &VaultKey,
+    new_key: &VaultKey,
+    now: u64,
+) -> InternalResult<()> {
+    let Some(text) = load(home_dir, vault, old_key, now)? else {
+        return Ok(());
+    };
+    let Some(stored) = Stored::read(&path(home_dir, vault))? else {
+        return Ok(());
+    };
+
+    save(home_dir, vault, new_key, &text, stored.expires_at)
+}
+
+/// Drop `vault`'s scratchpad, `false` if it had none
+pub(crate) fn remove<P: AsRef<Path>>(home_dir: &P, vault: &Vault) -> InternalResult<bool> {
+    match std::fs::remove_file(path(home_dir, vault)) {
+        Ok(()) => Ok(true),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(false),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Remove every scratchpad expired at `now`, w/o any vault unlocked,
+/// returning how many were removed
+pub(crate) fn sweep<P: AsRef<Path>>(home_dir: &P, now: u64) -> InternalResult<usize> {
+    let entries = match std::fs::read_dir(home_dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+        Err(e) => return Err(e.into()),
+    };
+    let mut removed = 0;
+
+    for entry in entries {
+        let path = entry?.path();
+
+        if path.extension().is_none_or(|ext| ext != SCRATCH_EXT) {
+            continue;
+        }
+
+        // unreadable ones can't be decrypted either
+        let expired = Stored::read(&path)
+            .map(|s| s.is_none_or(|s| s.expires_at <= now))
+            .unwrap_or(true);
+
+        if expired {
+            std::fs::remove_file(&path)?;
+            removed += 1;
+        }
+    }
+
+    Ok(removed)
+}
+
+fn aad(vault: &Vault, expires_at: u64) -> Vec<u8> {
+    format!("{}.{SCRATCH_EXT}.{expires_at}", vault.slug).into_bytes()
+}
+
+fn path<P: AsRef<Path>>(home_dir: &P, vault: &Vault) -> PathBuf {
+    home_dir
+        .as_ref()
+        .join(format!("{}.{SCRATCH_EXT}", vault.slug))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::vault;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_scratchpad_expires() {
+        let temp_dir = TempDir::new().unwrap();
+        let key = VaultKey::random().unwrap();
+        let vault = vault();
+
+        assert!(load(&temp_dir.path(), &vault, &key, 0).unwrap().is_none());
+
+        save(&temp_dir.path(), &vault, &key, "token abc", 100).unwrap();
+        let text = load(&temp_dir.path(), &vault, &key, 99).unwrap().unwrap();
+        assert_eq!(text.as_str(), "token abc");
+
+        // other keys can't read it
+        let other = VaultKey::random().unwrap();
+        assert!(load(&temp_dir.path(), &vault, &other, 99).is_err());
+
+        assert!(load(&temp_dir.path(), &vault, &key, 100).unwrap().is_none());
+        assert!(!remove(&temp_dir.path(), &vault).unwrap());
+    }
+
+    #[test]
+    fn test_expiry_cant_be_pushed_back() {
+        let temp_dir = TempDir::new().unwrap();
+        let key = VaultKey::random().unwrap();
+        let vault = vault();
+
+        save(&temp_dir.path(), &vault, &key, "token abc", 100).unwrap();
+
+        let path = path(&temp_dir.path(), &vault);
+        let mut stored = Stored::read(&path).unwrap().unwrap();
+        stored.expires_at = 1000;
+        std::fs::write(&path, serde_json::to_vec(&stored).unwrap()).unwrap();
+
+        assert!(load(&temp_dir.path(), &vault, &key, 500).is_err());
+    }
+
+    #[test]
+    fn test_sweep_keeps_live_scratchpads() {
+        let temp_dir = TempDir::new().unwrap();
+        let key = VaultKey::random().unwrap();
+        let vault = vault();
+        let other = Vault {
+            slug: "fedcba9876543210".into(),
+            ..vault.clone()
+        };
+
+        save(&temp_dir.path(), &vault, &key, "a", 100).unwrap();
+        save(&temp_dir.path(), &other, &key, "b", 200).unwrap();
+
+        assert_eq!(sweep(&temp_dir.path(), 150).unwrap(), 1);
+        assert!(!path(&temp_dir.path(), &vault).exists());
+        assert!(path(&temp_dir.path(), &other).exists());
+
+        let next = VaultKey::random().unwrap();
+        rekey(&temp_dir.path(), &other, &key, &next, 150).unwrap();
+        let text = load(&temp_dir.path(), &other, &next, 150).unwrap().unwrap();
+        assert_eq!(text.as_str(), "b");
+    }
+}
@@ -0,0 +1,189 @@
+use std::time::{Duration, Instant, SystemTime};
+
+/// Wall clock time passing beyond the monotonic clock's, which stands
+/// still while suspended, taken as sleep
+const SUSPEND_GAP: Duration = Duration::from_secs(5);
+/// How often the screen's lock state is asked for, it may start a process
+const LOCK_POLL: Duration = Duration::from_secs(5);
+
+/// Notices the screen locking or the machine sleeping, when polled
+/// regularly
+pub(crate) struct Watch {
+    wall: SystemTime,
+    mono: Instant,
+    checked: Instant,
+    /// screen lock state when last checked
+    locked: bool,
+}
+
+impl Watch {
+    pub fn new() -> Self {
+        Self {
+            wall: SystemTime::now(),
+            mono: Instant::now(),
+            checked: Instant::now(),
+            // e.g. started over SSH while the screen is locked
+            locked: platform::is_locked(),
+        }
+    }
+
+    /// Whether the machine slept or the screen locked since the last poll
+    pub fn triggered(&mut self) -> bool {
+        let (wall, mono) = (SystemTime::now(), Instant::now());
+        let slept = slept(self.wall, self.mono, wall, mono);
+
+        self.wall = wall;
+        self.mono = mono;
+
+        if slept {
+            return true;
+        }
+
+        if mono.duration_since(self.checked) < LOCK_POLL {
+            return false;
+        }
+
+        self.checked = mono;
+
+        let was_locked = self.locked;
+        self.locked = platform::is_locked();
+
+        self.locked && !was_locked
+    }
+}
+
+fn slept(
+    wall_then: SystemTime,
+    mono_then: Instant,
+    wall_now: SystemTime,
+    mono_now: Instant,
+) -> bool {
+    // a wall clock set back never counts
+    let wall = wall_now.duration_since(wall_then).unwrap_or_default();
+
+    wall.saturating_sub(mono_now.duration_since(mono_then)) >= SUSPEND_GAP
+}
+
+/// LockedHint of the logind session, set by the lock screens of GNOME, KDE
+/// and others
+#[cfg(target_os = "linux")]
+mod platform {
+    use std::process::{Command, Stdio};
+
+    pub(super) fn is_locked() -> bool {
+        let session = std::env::var("XDG_SESSION_ID").unwrap_or_else(|_| "auto".into());
+
+        Command::new("loginctl")
+            .args(["show-session", &session, "--property=LockedHint", "--value"])
+            .stdin(Stdio::null())
+            .stderr(Stdio::null())
+            .output()
+            .is_ok_and(|out| out.status.success() && out.stdout.trim_ascii() == b"yes")
+    }
+}
+
+/// `CGSSessionScreenIsLocked` of the current session
+#[cfg(target_os = "macos")]
+mod platform {
+    use std::ffi::{c_char, c_void};
+
+    /// kCFStringEncodingUTF8
+    const UTF8: u32 = 0x0800_0100;
+
+    #[link(name = "CoreGraphics", kind = "framework")]
+    unsafe extern "C" {
+        fn CGSessionCopyCurrentDictionary() -> *const c_void;
+    }
+
+    #[link(name = "CoreFoundation", kind = "framework")]
+    unsafe extern "C" {
+        static kCFBooleanTrue: *const c_void;
+
+        fn CFStringCreateWithCString(
+            alloc: *const c_void,
+            string: *const c_char,
+            encoding: u32,
+        ) -> *const c_void;
+        fn CFDictionaryGetValue(dict: *const c_void, key: *const c_void) -> *const c_void;
+        fn CFRelease(object: *const c_void);
+    }
+
+    pub(super) fn is_locked() -> bool {
+        // SAFETY: both created objects are released once, the value looked
+        // up is only borrowed from the dictionary
+        unsafe {
+            let session = CGSessionCopyCurrentDictionary();
+
+            if session.is_null() {
+                return false;
+            }
+
+            let key = CFStringCreateWithCString(
+                std::ptr::null(),
+                c"CGSSessionScreenIsLocked".as_ptr(),
+                UTF8,
+            );
+            let locked = !key.is_null() && CFDictionaryGetValue(session, key) == kCFBooleanTrue;
+
+            if !key.is_null() {
+                CFRelease(key);
+            }
+            CFRelease(session);
+
+            locked
+        }
+    }
+}
+
+/// The input desktop can't be opened while the lock screen's secure
+/// desktop is shown
+#[cfg(windows)]
+mod platform {
+    use std::ffi::c_void;
+
+    const DESKTOP_SWITCHDESKTOP: u32 = 0x0100;
+
+    #[link(name = "user32")]
+    unsafe extern "system" {
+        fn OpenInputDesktop(flags: u32, inherit: i32, access: u32) -> *mut c_void;
+        fn CloseDesktop(desktop: *mut c_void) -> i32;
+    }
+
+    pub(super) fn is_locked() -> bool {
+        // SAFETY: the handle is closed right away, if there is one
+        unsafe {
+            let desktop = OpenInputDesktop(0, 0, DESKTOP_SWITCHDESKTOP);
+
+            if desktop.is_null() {
+                return true;
+            }
+
+            CloseDesktop(desktop);
+            false
+        }
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", windows)))]
+mod platform {
+    pub(super) fn is_locked() -> bool {
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sleep_is_a_wall_clock_gap() {
+        let (wall, mono) = (SystemTime::now(), Instant::now());
+        let later = |secs| Duration::from_secs(secs);
+
+        assert!(!slept(wall, mono, wall + later(2), mono + later(2)));
+        assert!(!slept(wall, mono, wall + later(60), mono + later(58)));
+        assert!(slept(wall, mono, wall + later(60), mono + later(2)));
+        // clock set back
+        assert!(!slept(wall, mono, wall - later(60), mono + later(2)));
+    }
+}
@@ -0,0 +1,111 @@
+use serde::{Deserialize, Serialize};
+use zeroize::Zeroize;
+
+/// Password or passphrase, wiped from memory on drop and never printed
+/// by `{:?}`
+///
+/// NOTE: copies taken w/ [`Self::expose`] (e.g. by `format!`) are not
+/// wiped, keep them short lived
+#[derive(Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub(crate) struct SecretString(String);
+
+impl SecretString {
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// What was likely meant if this was typed w/ a common typo: w/o
+    /// trailing whitespace, w/ the first letter's case flipped and w/ every
+    /// letter's case flipped (Caps Lock), w/o duplicates or itself
+    pub fn typo_variants(&self) -> Vec<SecretString> {
+        let mut first = self.0.chars();
+        let first = first
+            .next()
+            .map(|c| flip_case(c) + first.as_str())
+            .unwrap_or_default();
+        let inverted: String = self.0.chars().map(flip_case).collect();
+
+        let mut variants: Vec<SecretString> = Vec::new();
+
+        for variant in [self.0.trim_end().to_string(), first, inverted] {
+            if variant != self.0 && !variants.iter().any(|v| v.0 == variant) {
+                variants.push(variant.into());
+            }
+        }
+
+        variants
+    }
+}
+
+fn flip_case(c: char) -> String {
+    if c.is_lowercase() {
+        c.to_uppercase().collect()
+    } else {
+        c.to_lowercase().collect()
+    }
+}
+
+impl Drop for SecretString {
+    fn drop(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+impl std::fmt::Debug for SecretString {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("SecretString(..)")
+    }
+}
+
+impl From<String> for SecretString {
+    fn from(value: String) -> Self {
+        Self(value)
+    }
+}
+
+impl From<&str> for SecretString {
+    fn from(value: &str) -> Self {
+        Self(value.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_secret_string() {
+        let secret = SecretString::from("hunter2");
+
+        assert_eq!(format!("{secret:?}"), "SecretString(..)");
+        assert_eq!(serde_json::to_string(&secret).unwrap(), "\"hunter2\"");
+        assert_eq!(
+            serde_json::from_str::<SecretString>("\"hunter2\"").unwrap(),
+            secret
+        );
+    }
+
+    #[test]
+    fn test_typo_variants() {
+        let variants = |s: &str| {
+            SecretString::from(s)
+                .typo_variants()
+                .iter()
+                .map(|v| v.expose().to_string())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            variants("Hunter2 \n"),
+            ["Hunter2", "hunter2 \n", "hUNTER2 \n"]
+        );
+        assert_eq!(variants("h"), ["H"]);
+        assert_eq!(variants("42"), Vec::<String>::new());
+        assert!(variants("").is_empty());
+    }
+}
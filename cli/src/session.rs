@@ -0,0 +1,268 @@
+use crate::{
+    agent, clipboard,
+    crypto::{self, EntryCodec, VaultKey},
+    entries::EntryFormat,
+    screenlock::Watch,
+    types::{InternalError, InternalResult},
+};
+use std::{
+    path::{Path, PathBuf},
+    sync::{
+        Arc, Mutex, MutexGuard, Weak,
+        atomic::{AtomicUsize, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+/// Upper bound of how late the watchdog notices an idle session
+const MAX_POLL: Duration = Duration::from_secs(1);
+const SESSION_AAD: &[u8] = b"vuoto session";
+
+/// Sessions of this process so far, e.g. one per vault w/ `--all-vaults`
+static SESSIONS: AtomicUsize = AtomicUsize::new(0);
+
+struct State {
+    /// vault key sealed under the ephemeral key, `None` once locked
+    sealed: Option<Vec<u8>>,
+    last_active: Instant,
+}
+
+/// Where the ephemeral key sealing the vault key between actions is kept
+enum Ephemeral {
+    /// in the agent, so a memory dump of this process alone reveals no
+    /// vault key, and the session locks w/ the agent
+    Agent { home_dir: PathBuf, id: String },
+    /// w/o an agent, e.g. on Windows, it only keeps the key out of plain
+    /// sight
+    Local(VaultKey),
+}
+
+impl Ephemeral {
+    fn new(home_dir: Option<&Path>) -> InternalResult<Self> {
+        let key = VaultKey::random()?;
+
+        let Some(home_dir) = home_dir else {
+            return Ok(Self::Local(key));
+        };
+        let n = SESSIONS.fetch_add(1, Ordering::Relaxed);
+        let id = format!("{}-{n}", std::process::id());
+
+        match agent::add_session_key(&home_dir, &id, &key) {
+            Ok(()) => Ok(Self::Agent {
+                home_dir: home_dir.to_path_buf(),
+                id,
+            }),
+            Err(_) => Ok(Self::Local(key)),
+        }
+    }
+
+    /// Key to seal w/, a fresh one if the agent lost the last
+    fn sealing_key(&self) -> InternalResult<VaultKey> {
+        match self {
+            Self::Local(key) => Ok(key.clone()),
+            Self::Agent { home_dir, id } => match agent::session_key(home_dir, id) {
+                Some(key) => Ok(key),
+                None => {
+                    let key = VaultKey::random()?;
+                    agent::add_session_key(home_dir, id, &key)?;
+
+                    Ok(key)
+                }
+            },
+        }
+    }
+
+    /// Key to open w/, `None` once the agent locked
+    fn opening_key(&self) -> Option<VaultKey> {
+        match self {
+            Self::Local(key) => Some(key.clone()),
+            Self::Agent { home_dir, id } => agent::session_key(home_dir, id),
+        }
+    }
+}
+
+/// Unlocked vault of an interactive session, its key is dropped after
+/// `lock_after` w/o activity, or if `lock_on_sleep` once the screen locks
+/// or the machine sleeps, and re-derived w/ `relock` on next use
+///
+/// Between actions the key is only kept sealed under an ephemeral key,
+/// which w/ `home_dir` lives in the agent of that data dir
+pub(crate) struct Session<'a> {
+    state: Arc<Mutex<State>>,
+    format: EntryFormat,
+    ephemeral: Ephemeral,
+    relock: Box<dyn Fn() -> InternalResult<EntryCodec> + 'a>,
+}
+
+impl<'a> Session<'a> {
+    pub fn new<F>(
+        codec: EntryCodec,
+        home_dir: Option<&Path>,
+        lock_after: Option<Duration>,
+        lock_on_sleep: bool,
+        relock: F,
+    ) -> InternalResult<Self>
+    where
+        F: Fn() -> InternalResult<EntryCodec> + 'a,
+    {
+        let ephemeral = Ephemeral::new(home_dir)?;
+        let sealed = seal(&ephemeral, codec.vault_key())?;
+
+        let state = Arc::new(Mutex::new(State {
+            sealed: Some(sealed),
+            last_active: Instant::now(),
+        }));
+
+        if lock_after.is_some() || lock_on_sleep {
+            let watch = lock_on_sleep.then(Watch::new);
+            spawn_watchdog(Arc::downgrade(&state), lock_after, watch);
+        }
+
+        Ok(Self {
+            state,
+            format: codec.format,
+            ephemeral,
+            relock: Box::new(relock),
+        })
+    }
+
+    /// Codec for the next action, asking for the password again if the
+    /// session locked meanwhile
+    ///
+    /// NOTE: drop it before waiting on the user, otherwise the key outlives
+    /// the idle period
+    pub fn codec(&self) -> InternalResult<EntryCodec> {
+        let sealed = self.lock().sealed.clone();
+        let opened = sealed.and_then(|sealed| {
+            let key = self.ephemeral.opening_key()?;
+            crypto::open(&key, SESSION_AAD, &sealed).ok()
+        });
+
+        let key = match opened {
+            Some(encoded) => {
+                let encoded = zeroize::Zeroizing::new(encoded);
+                let encoded = std::str::from_utf8(&encoded)
+                    .map_err(|_| InternalError::IO("Invalid session key".into()))?;
+
+                VaultKey::from_base64(encoded)?
+            }
+            None => {
+                println!("Vault was locked meanwhile.");
+                let codec = (self.relock)()?;
+                self.lock().sealed = Some(seal(&self.ephemeral, codec.vault_key())?);
+
+                codec.vault_key().clone()
+            }
+        };
+
+        self.lock().last_active = Instant::now();
+
+        Ok(EntryCodec::new(self.format, key))
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl Drop for Session<'_> {
+    fn drop(&mut self) {
+        if let Ephemeral::Agent { home_dir, id } = &self.ephemeral {
+            let _ = agent::forget_session_key(home_dir, id);
+        }
+    }
+}
+
+fn seal(ephemeral: &Ephemeral, key: &VaultKey) -> InternalResult<Vec<u8>> {
+    crypto::seal(
+        &ephemeral.sealing_key()?,
+        SESSION_AAD,
+        key.to_base64().as_bytes(),
+    )
+}
+
+/// Drop the key of an idle session, or w/ `watch` once the screen locks,
+/// until the session itself is dropped
+fn spawn_watchdog(
+    state: Weak<Mutex<State>>,
+    lock_after: Option<Duration>,
+    mut watch: Option<Watch>,
+) {
+    let poll = lock_after.map_or(MAX_POLL, |after| MAX_POLL.min(after));
+
+    std::thread::spawn(move || {
+        loop {
+            std::thread::sleep(poll);
+
+            let Some(state) = state.upgrade() else {
+                return;
+            };
+
+            if watch.as_mut().is_some_and(Watch::triggered) {
+                state.lock().unwrap_or_else(|e| e.into_inner()).sealed = None;
+                let _ = clipboard::clear();
+                continue;
+            }
+
+            let mut state = state.lock().unwrap_or_else(|e| e.into_inner());
+
+            if lock_after.is_some_and(|after| state.last_active.elapsed() >= after) {
+                state.sealed = None;
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn codec() -> EntryCodec {
+        EntryCodec::new(EntryFormat::Json, VaultKey::random().unwrap())
+    }
+
+    #[test]
+    fn test_session_locks_when_idle() {
+        let relocks = AtomicUsize::new(0);
+        let session = Session::new(
+            codec(),
+            None,
+            Some(Duration::from_millis(50)),
+            false,
+            || {
+                relocks.fetch_add(1, Ordering::SeqCst);
+                Ok(codec())
+            },
+        )
+        .unwrap();
+
+        session.codec().unwrap();
+        assert_eq!(relocks.load(Ordering::SeqCst), 0);
+
+        std::thread::sleep(Duration::from_millis(300));
+        session.codec().unwrap();
+        assert_eq!(relocks.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_session_keeps_key_sealed() {
+        let codec = codec();
+        let key = codec.vault_key().to_base64();
+        let session = Session::new(codec, None, None, false, || panic!("relocked")).unwrap();
+
+        let sealed = session.lock().sealed.clone().unwrap();
+        assert!(!sealed.windows(key.len()).any(|w| w == key.as_bytes()));
+
+        let opened = session.codec().unwrap();
+        assert_eq!(opened.vault_key().to_base64(), key);
+    }
+
+    #[test]
+    fn test_session_wo_timeout_never_locks() {
+        let session = Session::new(codec(), None, None, false, || panic!("relocked")).unwrap();
+
+        std::thread::sleep(Duration::from_millis(20));
+        session.codec().unwrap();
+    }
+}
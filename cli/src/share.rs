@@ -0,0 +1,706 @@
+use crate::{
+    clock,
+    entries::{Entry, Template},
+    generator::{self, Charset, GeneratorPolicy},
+    rng,
+    types::{InternalError, InternalResult},
+};
+use ring::{
+    rand::SystemRandom,
+    signature::{ECDSA_P256_SHA256_ASN1_SIGNING, EcdsaKeyPair, KeyPair},
+};
+use rustls::{
+    ServerConfig, ServerConnection, StreamOwned,
+    pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer},
+};
+use sha2::{Digest, Sha256};
+use std::{
+    io::{Read, Write},
+    net::{IpAddr, Ipv4Addr, SocketAddr, TcpListener, TcpStream, UdpSocket},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+/// How long a link stays valid unless `--timeout` is given
+pub(crate) const DEFAULT_TIMEOUT_SECS: u64 = 300;
+/// Listen on all interfaces, so colleagues on the LAN can connect
+pub(crate) const DEFAULT_BIND: &str = "0.0.0.0:0";
+
+const PATH_LEN: usize = 22;
+const PIN_LEN: usize = 6;
+/// Wrong PINs before the link is closed w/o serving the secret
+const MAX_PIN_ATTEMPTS: u32 = 3;
+const MAX_REQUEST_SIZE: usize = 8 * 1024;
+const CONNECTION_TIMEOUT: Duration = Duration::from_secs(10);
+const ACCEPT_POLL: Duration = Duration::from_millis(100);
+/// Cert is valid from an hour ago (clock skew) to a day from now
+const CERT_BACKDATE_SECS: u64 = 3_600;
+const CERT_VALIDITY_SECS: u64 = 86_400;
+const CERT_SUBJECT: &str = "vuoto share-once";
+
+// DER encoded object identifiers
+const OID_ECDSA_SHA256: &[u8] = &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x04, 0x03, 0x02];
+const OID_EC_PUBLIC_KEY: &[u8] = &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x02, 0x01];
+const OID_P256: &[u8] = &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x03, 0x01, 0x07];
+const OID_COMMON_NAME: &[u8] = &[0x55, 0x04, 0x03];
+const OID_SUBJECT_ALT_NAME: &[u8] = &[0x55, 0x1d, 0x11];
+
+/// How a share ended
+#[derive(Debug, PartialEq)]
+pub(crate) enum Outcome {
+    /// secret was sent to given peer
+    Served(SocketAddr),
+    /// too many wrong PINs, secret was never sent
+    Locked,
+    /// nobody picked up the secret in time
+    Expired,
+}
+
+/// HTTPS endpoint serving a secret exactly once, behind a random path and
+/// a PIN which are handed to the recipient out of band
+pub(crate) struct Share {
+    listener: TcpListener,
+    tls: Arc<ServerConfig>,
+    host: IpAddr,
+    path: String,
+    pin: String,
+    fingerprint: String,
+    secret: String,
+}
+
+impl Share {
+    /// Listen on `addr` w/ a fresh self-signed cert, PIN and path
+    pub fn bind(addr: &str, secret: String) -> InternalResult<Self> {
+        let listener = TcpListener::bind(addr)
+            .map_err(|e| InternalError::IO(format!("Failed to listen on {addr}: {e}")))?;
+
+        let local = listener.local_addr()?;
+        let host = if local.ip().is_unspecified() {
+            lan_address().unwrap_or(IpAddr::V4(Ipv4Addr::LOCALHOST))
+        } else {
+            local.ip()
+        };
+
+        let (cert, key) = self_signed_cert(host, clock::now())?;
+        let fingerprint = fingerprint(&cert);
+
+        Ok(Self {
+            listener,
+            tls: tls_config(cert, key)?,
+            host,
+            path: random(PATH_LEN, Charset::Alphanumeric)?,
+            pin: random(PIN_LEN, Charset::Digits)?,
+            fingerprint,
+            secret,
+        })
+    }
+
+    /// Link to hand to the recipient
+    pub fn url(&self) -> InternalResult<String> {
+        let port = self.listener.local_addr()?.port();
+        let addr = SocketAddr::new(self.host, port);
+
+        Ok(format!("https://{addr}/{}", self.path))
+    }
+
+    pub fn pin(&self) -> &str {
+        &self.pin
+    }
+
+    /// SHA-256 of the cert, so the recipient can check the browser warning
+    /// is about this very cert
+    pub fn fingerprint(&self) -> &str {
+        &self.fingerprint
+    }
+
+    /// Serve until the secret was sent, the link got locked or `timeout`
+    /// passed, the listener is closed afterwards in any case
+    pub fn serve(self, timeout: Duration) -> InternalResult<Outcome> {
+        let deadline = Instant::now() + timeout;
+        let mut attempts = 0;
+
+        self.listener.set_nonblocking(true)?;
+
+        while Instant::now() < deadline {
+            let (stream, peer) = match self.listener.accept() {
+                Ok(conn) => conn,
+                Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {
+                    std::thread::sleep(ACCEPT_POLL);
+                    continue;
+                }
+                Err(e) => return Err(e.into()),
+            };
+
+            // failed handshakes are expected, browsers drop the first
+            // connection when warning about the self-signed cert
+            if let Ok(Some(outcome)) = self.handle(stream, peer, &mut attempts) {
+                return Ok(outcome);
+            }
+        }
+
+        Ok(Outcome::Expired)
+    }
+
+    /// Answer a single request, `Some` once the share is over
+    fn handle(
+        &self,
+        stream: TcpStream,
+        peer: SocketAddr,
+        attempts: &mut u32,
+    ) -> InternalResult<Option<Outcome>> {
+        stream.set_nonblocking(false)?;
+        stream.set_read_timeout(Some(CONNECTION_TIMEOUT))?;
+        stream.set_write_timeout(Some(CONNECTION_TIMEOUT))?;
+
+        let conn = ServerConnection::new(self.tls.clone())
+            .map_err(|e| InternalError::IO(format!("TLS setup failed: {e}")))?;
+        let mut tls = StreamOwned::new(conn, stream);
+
+        let request = read_request(&mut tls)?;
+
+        if request.path != format!("/{}", self.path) {
+            respond(&mut tls, "404 Not Found", "Not found.")?;
+            return Ok(None);
+        }
+
+        match request.method.as_str() {
+            "GET" => respond(&mut tls, "200 OK", &pin_form(None))?,
+
+            "POST" if form_value(&request.body, "pin").as_deref() == Some(self.pin.as_str()) => {
+                let page = format!("<pre>{}</pre>", html_escape(&self.secret));
+
+                // counts as served even if sending failed, it may have
+                // reached the peer partially
+                let _ = respond(&mut tls, "200 OK", &page);
+                return Ok(Some(Outcome::Served(peer)));
+            }
+
+            "POST" => {
+                *attempts += 1;
+
+                if *attempts >= MAX_PIN_ATTEMPTS {
+                    let _ = respond(
+                        &mut tls,
+                        "403 Forbidden",
+                        "Too many wrong PINs, link closed.",
+                    );
+                    return Ok(Some(Outcome::Locked));
+                }
+
+                let left = MAX_PIN_ATTEMPTS - *attempts;
+                respond(&mut tls, "403 Forbidden", &pin_form(Some(left)))?;
+            }
+
+            _ => respond(&mut tls, "405 Method Not Allowed", "Method not allowed.")?,
+        }
+
+        Ok(None)
+    }
+}
+
+/// Plain text of what an entry shares, i.e. its secrets w/o metadata
+pub(crate) fn payload(entry: &Entry) -> String {
+    let mut lines = Vec::new();
+
+    match &entry.template {
+        Template::Login(e) => {
+            if let Some(u) = &e.username {
+                lines.push(format!("Username: {u}"));
+            }
+
+            lines.push(format!("Password: {}", e.password.expose()));
+        }
+
+        Template::RecoveryCodes(e) => {
+            for c in e.codes.iter().filter(|c| c.used_at.is_none()) {
+                lines.push(c.code.clone());
+            }
+        }
+
+        Template::License(e) => {
+            lines.push(format!("Product: {}", e.product));
+            lines.push(format!("Key: {}", e.formatted_key()));
+        }
+
+        Template::Custom(e) => {
+            for f in &e.fields {
+                lines.push(format!("{}: {}", f.name, f.value));
+            }
+        }
+    }
+
+    lines.join("\n")
+}
+
+struct Request {
+    method: String,
+    path: String,
+    body: Vec<u8>,
+}
+
+fn read_request<R: Read>(stream: &mut R) -> InternalResult<Request> {
+    let invalid = || InternalError::IO("Invalid HTTP request".into());
+
+    let mut buf = Vec::new();
+    let mut chunk = [0u8; 1024];
+
+    let head_end = loop {
+        if let Some(pos) = buf.windows(4).position(|w| w == b"\r\n\r\n") {
+            break pos;
+        }
+
+        if buf.len() > MAX_REQUEST_SIZE {
+            return Err(invalid());
+        }
+
+        match stream.read(&mut chunk)? {
+            0 => return Err(invalid()),
+            n => buf.extend_from_slice(&chunk[..n]),
+        }
+    };
+
+    let head = std::str::from_utf8(&buf[..head_end]).map_err(|_| invalid())?;
+    let mut lines = head.split("\r\n");
+
+    let mut request_line = lines.next().ok_or_else(invalid)?.split(' ');
+    let method = request_line.next().ok_or_else(invalid)?.to_string();
+    let path = request_line.next().ok_or_else(invalid)?.to_string();
+
+    let content_length = lines
+        .filter_map(|l| l.split_once(':'))
+        .find(|(k, _)| k.trim().eq_ignore_ascii_case("content-length"))
+        .map(|(_, v)| v.trim().parse::<usize>().map_err(|_| invalid()))
+        .transpose()?
+        .unwrap_or(0);
+
+    if content_length > MAX_REQUEST_SIZE {
+        return Err(invalid());
+    }
+
+    let mut body = buf.split_off(head_end + 4);
+
+    while body.len() < content_length {
+        match stream.read(&mut chunk)? {
+            0 => return Err(invalid()),
+            n => body.extend_from_slice(&chunk[..n]),
+        }
+    }
+
+    body.truncate(content_length);
+
+    Ok(Request { method, path, body })
+}
+
+fn respond<S: Read + Write>(
+    tls: &mut StreamOwned<ServerConnection, S>,
+    status: &str,
+    body: &str,
+) -> InternalResult<()> {
+    let page = format!(
+        "<!doctype html><html><head><meta charset=\"utf-8\"><title>vuoto</title></head>\
+         <body>{body}</body></html>"
+    );
+
+    write!(
+        tls,
+        "HTTP/1.1 {status}\r\n\
+         Content-Type: text/html; charset=utf-8\r\n\
+         Content-Length: {}\r\n\
+         Cache-Control: no-store\r\n\
+         Connection: close\r\n\r\n{page}",
+        page.len()
+    )?;
+
+    tls.conn.send_close_notify();
+    tls.flush()?;
+
+    Ok(())
+}
+
+fn pin_form(attempts_left: Option<u32>) -> String {
+    let error = match attempts_left {
+        Some(n) => format!("<p>Wrong PIN, {n} attempt(s) left.</p>"),
+        None => String::new(),
+    };
+
+    format!(
+        "{error}<form method=\"post\"><label>PIN <input name=\"pin\" inputmode=\"numeric\" \
+         autocomplete=\"off\" autofocus></label> <button>Reveal</button></form>"
+    )
+}
+
+/// Value of `key` in an `application/x-www-form-urlencoded` body
+fn form_value(body: &[u8], key: &str) -> Option<String> {
+    let body = std::str::from_utf8(body).ok()?;
+
+    body.split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .find(|(k, _)| *k == key)
+        .map(|(_, v)| v.trim().to_string())
+}
+
+fn html_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn random(length: usize, charset: Charset) -> InternalResult<String> {
+    generator::generate(&GeneratorPolicy { length, charset })
+}
+
+/// Address other machines on the LAN reach us at
+///
+/// NOTE: connecting a UDP socket only picks a route, nothing is sent
+fn lan_address() -> Option<IpAddr> {
+    let socket = UdpSocket::bind("0.0.0.0:0").ok()?;
+    socket.connect("192.0.2.1:9").ok()?;
+
+    socket.local_addr().ok().map(|a| a.ip())
+}
+
+fn tls_config(cert: Vec<u8>, key: Vec<u8>) -> InternalResult<Arc<ServerConfig>> {
+    let err = |e: rustls::Error| InternalError::IO(format!("TLS setup failed: {e}"));
+
+    let config =
+        ServerConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+            .with_safe_default_protocol_versions()
+            .map_err(err)?
+            .with_no_client_auth()
+            .with_single_cert(
+                vec![CertificateDer::from(cert)],
+                PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(key)),
+            )
+            .map_err(err)?;
+
+    Ok(Arc::new(config))
+}
+
+/// `AA:BB:..` SHA-256 fingerprint, as shown by browsers
+fn fingerprint(cert: &[u8]) -> String {
+    Sha256::digest(cert)
+        .iter()
+        .map(|b| format!("{b:02X}"))
+        .collect::<Vec<_>>()
+        .join(":")
+}
+
+/// Fresh ECDSA P-256 key and a self-signed X.509 v3 cert for `host`,
+/// returned as (cert DER, PKCS#8 key DER)
+fn self_signed_cert(host: IpAddr, now: u64) -> InternalResult<(Vec<u8>, Vec<u8>)> {
+    let err = || InternalError::IO("Failed to generate TLS key".into());
+
+    // ring draws from the same OS source, only check its health
+    rng::health()?;
+    let random = SystemRandom::new();
+    let pkcs8 = EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_ASN1_SIGNING, &random)
+        .map_err(|_| err())?;
+    let key_pair =
+        EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_ASN1_SIGNING, pkcs8.as_ref(), &random)
+            .map_err(|_| err())?;
+
+    let mut serial = [0u8; 16];
+    rng::fill(&mut serial)?;
+    // positive and w/o leading zero byte
+    serial[0] = (serial[0] & 0x7f) | 0x40;
+
+    let signature_algorithm = der(0x30, &der(0x06, OID_ECDSA_SHA256));
+
+    let name = der(
+        0x30,
+        &der(
+            0x31,
+            &der(
+                0x30,
+                &[
+                    der(0x06, OID_COMMON_NAME),
+                    der(0x0c, CERT_SUBJECT.as_bytes()),
+                ]
+                .concat(),
+            ),
+        ),
+    );
+
+    let not_before = clock::format_utc_time(now.saturating_sub(CERT_BACKDATE_SECS));
+    let not_after = clock::format_utc_time(now + CERT_VALIDITY_SECS);
+    let validity = der(
+        0x30,
+        &[
+            der(0x17, not_before.as_bytes()),
+            der(0x17, not_after.as_bytes()),
+        ]
+        .concat(),
+    );
+
+    let public_key_info = der(
+        0x30,
+        &[
+            der(
+                0x30,
+                &[der(0x06, OID_EC_PUBLIC_KEY), der(0x06, OID_P256)].concat(),
+            ),
+            der(0x03, &[&[0u8][..], key_pair.public_key().as_ref()].concat()),
+        ]
+        .concat(),
+    );
+
+    let ip = match host {
+        IpAddr::V4(ip) => ip.octets().to_vec(),
+        IpAddr::V6(ip) => ip.octets().to_vec(),
+    };
+    let alt_names = der(0x30, &der(0x87, &ip));
+    let extensions = der(
+        0xa3,
+        &der(
+            0x30,
+            &der(
+                0x30,
+                &[der(0x06, OID_SUBJECT_ALT_NAME), der(0x04, &alt_names)].concat(),
+            ),
+        ),
+    );
+
+    let tbs = der(
+        0x30,
+        &[
+            der(0xa0, &der(0x02, &[2])),
+            der(0x02, &serial),
+            signature_algorithm.clone(),
+            name.clone(),
+            validity,
+            name,
+            public_key_info,
+            extensions,
+        ]
+        .concat(),
+    );
+
+    let signature = key_pair.sign(&random, &tbs).map_err(|_| err())?;
+
+    let cert = der(
+        0x30,
+        &[
+            tbs,
+            signature_algorithm,
+            der(0x03, &[&[0u8][..], signature.as_ref()].concat()),
+        ]
+        .concat(),
+    );
+
+    Ok((cert, pkcs8.as_ref().to_vec()))
+}
+
+/// DER tag-length-value
+fn der(tag: u8, content: &[u8]) -> Vec<u8> {
+    let mut out = vec![tag];
+    let len = content.len();
+
+    if len < 0x80 {
+        out.push(len as u8);
+    } else {
+        let bytes: Vec<u8> = len
+            .to_be_bytes()
+            .into_iter()
+            .skip_while(|b| *b == 0)
+            .collect();
+
+        out.push(0x80 | bytes.len() as u8);
+        out.extend(bytes);
+    }
+
+    out.extend_from_slice(content);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entries::{CustomEntry, CustomField, LoginEntry, RecoveryCodesEntry};
+    use rustls::{
+        ClientConfig, ClientConnection, DigitallySignedStruct, SignatureScheme,
+        client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier},
+        crypto::{WebPkiSupportedAlgorithms, verify_tls12_signature, verify_tls13_signature},
+        pki_types::{ServerName, UnixTime},
+    };
+
+    /// Trusts exactly one cert, i.e. what the recipient does after
+    /// comparing the fingerprint
+    #[derive(Debug)]
+    struct Pinned {
+        cert: Vec<u8>,
+        algorithms: WebPkiSupportedAlgorithms,
+    }
+
+    impl ServerCertVerifier for Pinned {
+        fn verify_server_cert(
+            &self,
+            end_entity: &CertificateDer<'_>,
+            _: &[CertificateDer<'_>],
+            _: &ServerName<'_>,
+            _: &[u8],
+            _: UnixTime,
+        ) -> Result<ServerCertVerified, rustls::Error> {
+            if end_entity.as_ref() == self.cert {
+                Ok(ServerCertVerified::assertion())
+            } else {
+                Err(rustls::Error::General("unexpected cert".into()))
+            }
+        }
+
+        fn verify_tls12_signature(
+            &self,
+            message: &[u8],
+            cert: &CertificateDer<'_>,
+            dss: &DigitallySignedStruct,
+        ) -> Result<HandshakeSignatureValid, rustls::Error> {
+            verify_tls12_signature(message, cert, dss, &self.algorithms)
+        }
+
+        fn verify_tls13_signature(
+            &self,
+            message: &[u8],
+            cert: &CertificateDer<'_>,
+            dss: &DigitallySignedStruct,
+        ) -> Result<HandshakeSignatureValid, rustls::Error> {
+            verify_tls13_signature(message, cert, dss, &self.algorithms)
+        }
+
+        fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+            self.algorithms.supported_schemes()
+        }
+    }
+
+    /// Send one request, returning the status line and body
+    fn request(addr: SocketAddr, cert: &[u8], method: &str, path: &str, body: &str) -> String {
+        let provider = Arc::new(rustls::crypto::ring::default_provider());
+        let verifier = Pinned {
+            cert: cert.to_vec(),
+            algorithms: provider.signature_verification_algorithms,
+        };
+
+        let config = ClientConfig::builder_with_provider(provider)
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .dangerous()
+            .with_custom_certificate_verifier(Arc::new(verifier))
+            .with_no_client_auth();
+
+        let conn = ClientConnection::new(Arc::new(config), ServerName::from(addr.ip())).unwrap();
+        let mut tls = StreamOwned::new(conn, TcpStream::connect(addr).unwrap());
+
+        write!(
+            tls,
+            "{method} {path} HTTP/1.1\r\nContent-Length: {}\r\n\r\n{body}",
+            body.len()
+        )
+        .unwrap();
+
+        let mut response = String::new();
+        let _ = tls.read_to_string(&mut response);
+
+        response
+    }
+
+    fn share(secret: &str) -> (Share, Vec<u8>) {
+        let mut share = Share::bind("127.0.0.1:0", secret.into()).unwrap();
+
+        // swap in a cert we know, to pin it on the client side
+        let (cert, key) = self_signed_cert(share.host, clock::now()).unwrap();
+        share.tls = tls_config(cert.clone(), key).unwrap();
+
+        (share, cert)
+    }
+
+    #[test]
+    fn test_serves_secret_once() {
+        let (share, cert) = share("hunter2 <b>");
+        let addr = share.listener.local_addr().unwrap();
+        let path = format!("/{}", share.path);
+        let pin = share.pin.clone();
+
+        let server = std::thread::spawn(move || share.serve(Duration::from_secs(10)));
+
+        assert!(request(addr, &cert, "GET", "/nope", "").starts_with("HTTP/1.1 404"));
+
+        let form = request(addr, &cert, "GET", &path, "");
+        assert!(form.starts_with("HTTP/1.1 200") && form.contains("name=\"pin\""));
+
+        let wrong = request(addr, &cert, "POST", &path, "pin=000000x");
+        assert!(wrong.starts_with("HTTP/1.1 403") && wrong.contains("2 attempt(s) left"));
+
+        let served = request(addr, &cert, "POST", &path, &format!("pin={pin}"));
+        assert!(served.contains("hunter2 &lt;b&gt;"));
+
+        assert!(matches!(
+            server.join().unwrap().unwrap(),
+            Outcome::Served(_)
+        ));
+
+        // listener is gone
+        assert!(TcpStream::connect(addr).is_err());
+    }
+
+    #[test]
+    fn test_locks_after_wrong_pins() {
+        let (share, cert) = share("hunter2");
+        let addr = share.listener.local_addr().unwrap();
+        let path = format!("/{}", share.path);
+
+        let server = std::thread::spawn(move || share.serve(Duration::from_secs(10)));
+
+        for _ in 0..MAX_PIN_ATTEMPTS {
+            let res = request(addr, &cert, "POST", &path, "pin=x");
+            assert!(!res.contains("hunter2"));
+        }
+
+        assert_eq!(server.join().unwrap().unwrap(), Outcome::Locked);
+    }
+
+    #[test]
+    fn test_expires() {
+        let share = Share::bind("127.0.0.1:0", "hunter2".into()).unwrap();
+
+        assert!(share.url().unwrap().starts_with("https://127.0.0.1:"));
+        assert_eq!(share.pin().len(), PIN_LEN);
+        assert_eq!(
+            share.serve(Duration::from_millis(10)).unwrap(),
+            Outcome::Expired
+        );
+    }
+
+    #[test]
+    fn test_der_long_length() {
+        assert_eq!(der(0x04, &[1, 2]), vec![0x04, 2, 1, 2]);
+
+        let long = der(0x04, &[0u8; 300]);
+        assert_eq!(&long[..4], &[0x04, 0x82, 0x01, 0x2c]);
+        assert_eq!(long.len(), 304);
+    }
+
+    #[test]
+    fn test_payload() {
+        let login = Entry::new(Template::Login(LoginEntry {
+            name: "mail".into(),
+            password: "hunter2".into(),
+            username: Some("me".into()),
+            url: Some("https://mail.example.com".into()),
+        }));
+        assert_eq!(payload(&login), "Username: me\nPassword: hunter2");
+
+        let mut codes = RecoveryCodesEntry::from_input("gh".into(), "one two");
+        codes.consume();
+        assert_eq!(payload(&Entry::new(Template::RecoveryCodes(codes))), "two");
+
+        let custom = Entry::new(Template::Custom(CustomEntry {
+            name: "db".into(),
+            kind: "Server".into(),
+            fields: vec![CustomField {
+                name: "Host".into(),
+                value: "db.local".into(),
+                secret: false,
+            }],
+        }));
+        assert_eq!(payload(&custom), "Host: db.local");
+    }
+}
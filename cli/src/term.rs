@@ -0,0 +1,45 @@
+use crate::{
+    prompt,
+    types::{InternalError, InternalResult},
+};
+use std::io::IsTerminal;
+
+/// Whether prompts can be shown, i.e. stdin is readable by a user and
+/// stderr (where prompts are rendered) is a terminal, and they aren't
+/// disabled
+pub(crate) fn is_interactive() -> bool {
+    std::io::stdin().is_terminal() && std::io::stderr().is_terminal() && !prompt::is_disabled()
+}
+
+/// Whether stdout is a terminal and may be decorated w/ banners
+pub(crate) fn is_decorated() -> bool {
+    std::io::stdout().is_terminal()
+}
+
+/// Refuse to continue when prompts can't be shown, instead of letting the
+/// prompt library fail w/ an opaque error
+pub(crate) fn ensure_interactive() -> InternalResult<()> {
+    if is_interactive() {
+        return Ok(());
+    }
+
+    Err(InternalError::IO(
+        "vuoto needs an interactive terminal, but stdin or stderr is not a TTY. \
+         Run it from a terminal; see `vuoto --help` for available flags"
+            .into(),
+    ))
+}
+
+/// Print section header, skipped when output is piped
+pub(crate) fn header(title: &str) {
+    if is_decorated() {
+        println!("\n=== {title} ===");
+    }
+}
+
+/// Print section footer matching a [`header`] w/ the same title
+pub(crate) fn footer(title: &str) {
+    if is_decorated() {
+        println!("{}\n", "=".repeat(title.chars().count() + 8));
+    }
+}
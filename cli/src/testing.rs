@@ -0,0 +1,24 @@
+use crate::{
+    crypto::{KdfAlgorithm, KdfParams},
+    entries::EntryFormat,
+    vaults::Vault,
+};
+
+/// Cheap parameters, tests don't need brute-force resistance
+pub(crate) const TEST_KDF: KdfParams = KdfParams {
+    algorithm: KdfAlgorithm::Argon2id,
+    m_cost: 64,
+    t_cost: 1,
+    p_cost: 1,
+};
+
+/// Vault `work`, w/ a fixed slug
+pub(crate) fn vault() -> Vault {
+    Vault {
+        name: "work".into(),
+        slug: "0123456789abcdef".into(),
+        archived: false,
+        format: EntryFormat::Json,
+        capacity: None,
+    }
+}
@@ -5,9 +5,62 @@ pub(crate) type InternalResult<T> = Result<T, InternalError>;
 #[derive(Debug)]
 pub(crate) enum InternalError {
     IO(String),
+    /// stored data failed authentication or is malformed
+    Corrupted(String),
+    /// OS random source is unavailable or failed its health check
+    Entropy(String),
+    /// a crypto primitive failed its known-answer test
+    SelfTest(String),
+    /// invalid command line
+    Usage(String),
+    /// vault, entry or field doesn't exist
+    NotFound(String),
+    /// vault key isn't cached and no terminal is there to ask for it
+    Locked(String),
+    /// given entry name or field value isn't valid
+    Invalid(String),
+    /// entry of that name already exists
+    Exists(String),
     Unknown(String),
 }
 
+impl InternalError {
+    /// Exit status of the process failing w/ this error, so scripts can
+    /// tell apart what went wrong
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            InternalError::Usage(_) => 2,
+            InternalError::NotFound(_) => 3,
+            InternalError::Locked(_) => 4,
+            InternalError::Invalid(_) => 5,
+            InternalError::Exists(_) => 6,
+            _ => 1,
+        }
+    }
+}
+
+impl std::fmt::Display for InternalError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            InternalError::IO(msg) => write!(f, "{msg}"),
+            InternalError::Corrupted(msg) => write!(f, "{msg}"),
+            InternalError::Entropy(msg) => {
+                write!(f, "{msg}, refusing to generate passwords or keys")
+            }
+            InternalError::SelfTest(primitive) => write!(
+                f,
+                "{primitive} produced wrong output, this build of vuoto is broken and refuses to run"
+            ),
+            InternalError::Usage(msg) => write!(f, "{msg}, see `vuoto --help`"),
+            InternalError::NotFound(msg) => write!(f, "{msg}"),
+            InternalError::Locked(msg) => write!(f, "{msg}"),
+            InternalError::Invalid(msg) => write!(f, "{msg}"),
+            InternalError::Exists(msg) => write!(f, "{msg}"),
+            InternalError::Unknown(msg) => write!(f, "{msg}"),
+        }
+    }
+}
+
 impl From<std::io::Error> for InternalError {
     fn from(err: std::io::Error) -> Self {
         InternalError::IO(format!("{}", err))
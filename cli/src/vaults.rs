@@ -1,63 +1,90 @@
-use crate::types::InternalResult;
+use crate::{entries::EntryFormat, rng, types::InternalResult};
 use std::{
     fs::{File, OpenOptions},
     io::{self, ErrorKind, Read, Seek, SeekFrom, Write},
-    path::Path,
+    path::{Component, Path},
 };
 
-const INDEX_PATH: &'static str = "index.vuoto";
-const RECORD_SIZE: usize = 16;
+const INDEX_PATH: &str = "index.vuoto";
 const MAGIC: &[u8; 8] = b"VUOTOIDX";
-const VERSION: u32 = 1;
+const VERSION: u32 = 2;
 const HEADER_SIZE: usize = MAGIC.len() + 4;
 
-pub struct VaultIndex {
-    vaults: Vec<String>,
-    file: File,
+/// v2 record layout: `[name; 64][slug; 16][flags; 1][capacity; 4][reserved; 11]`
+const NAME_SIZE: usize = 64;
+pub(crate) const SLUG_SIZE: usize = 16;
+const FLAGS_OFFSET: usize = NAME_SIZE + SLUG_SIZE;
+/// little endian u32, zero when unset
+const CAPACITY_OFFSET: usize = FLAGS_OFFSET + 1;
+const RECORD_SIZE: usize = 96;
+
+const FLAG_ARCHIVED: u8 = 0b0000_0001;
+/// entries are written as MessagePack instead of JSON
+const FLAG_MSGPACK: u8 = 0b0000_0010;
+
+/// v1 records only held the name, which also was the directory name
+const V1_VERSION: u32 = 1;
+const V1_RECORD_SIZE: usize = 16;
+/// Files next to a vault's data dir, named after its slug
+const SLUG_FILE_SUFFIXES: &[&str] = &[
+    ".master",
+    ".history",
+    ".history.checkpoint",
+    ".manifest",
+    ".keychain",
+];
+
+/// path separators and characters invalid in windows file names
+const FORBIDDEN_CHARS: &[char] = &['/', '\\', ':', '*', '?', '"', '<', '>', '|'];
+const RESERVED_NAMES: &[&str] = &[
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// Storage backing the index, abstracted so tests can observe file access
+pub trait IndexFile: Read + Write + Seek {
+    fn set_len(&mut self, size: u64) -> io::Result<()>;
+    fn sync_data(&mut self) -> io::Result<()>;
 }
 
-impl VaultIndex {
-    /// Open or create index
-    pub fn open<P: AsRef<Path>>(dir_path: &P) -> InternalResult<Self> {
-        let mut file = Self::read_file(dir_path)?;
-
-        if !Self::check_header(&mut file)? {
-            Self::init_file(&mut file)?;
-        }
-
-        file.seek(SeekFrom::Start(HEADER_SIZE as u64))?;
-        let mut vaults = Vec::new();
-        let mut buf = [0u8; RECORD_SIZE];
-
-        loop {
-            let n = file.read(&mut buf)?;
-
-            // EOF
-            if n == 0 {
-                break;
-            }
-
-            // partial record at end, ignore
-            if n < RECORD_SIZE {
-                break;
-            }
-
-            if buf.iter().all(|&b| b == 0) {
-                continue;
-            }
+impl IndexFile for File {
+    fn set_len(&mut self, size: u64) -> io::Result<()> {
+        File::set_len(self, size)
+    }
 
-            let len = buf.iter().position(|&b| b == 0).unwrap_or(RECORD_SIZE);
-            let name = std::str::from_utf8(&buf[..len])
-                .map_err(|e| io::Error::new(ErrorKind::InvalidData, e))?
-                .to_string();
+    fn sync_data(&mut self) -> io::Result<()> {
+        File::sync_data(self)
+    }
+}
 
-            vaults.push(name);
-        }
+/// A vault's display name and the directory its data lives in
+#[derive(Debug, Clone, PartialEq)]
+pub struct Vault {
+    pub name: String,
+    pub slug: String,
+    /// hidden from the default vault list
+    pub archived: bool,
+    /// encoding used when writing entries
+    pub format: EntryFormat,
+    /// cache capacity, `None` uses the configured default
+    pub capacity: Option<u32>,
+}
 
-        // update read/write pointer for future operations
-        file.seek(SeekFrom::End(0))?;
+pub struct VaultIndex<F: IndexFile = File> {
+    vaults: Vec<String>,
+    /// on-disk records by slot, `None` for free slots
+    slots: Vec<Option<Vault>>,
+    /// slots of records which failed to decode, never reused so they can
+    /// still be recovered by hand
+    quarantined: Vec<usize>,
+    file: F,
+}
 
-        Ok(Self { vaults, file })
+impl VaultIndex {
+    /// Open or create index
+    pub fn open<P: AsRef<Path>>(dir_path: &P) -> InternalResult<Self> {
+        let file = Self::read_file(dir_path)?;
+        Self::from_file(file, dir_path.as_ref())
     }
 
     /// Open or create a file handle
@@ -67,13 +94,48 @@ impl VaultIndex {
             .read(true)
             .write(true)
             .create(true)
+            .truncate(false)
             .open(&path)?;
 
         Ok(file)
     }
+}
+
+impl<F: IndexFile> VaultIndex<F> {
+    /// Load index from an open file handle, (re-)initializing or migrating
+    /// it if needed, `dir` holds the vaults' data
+    fn from_file(mut file: F, dir: &Path) -> InternalResult<Self> {
+        let mut quarantined = Vec::new();
+
+        let slots = match Self::read_version(&mut file)? {
+            Some(VERSION) => Self::read_records(&mut file, &mut quarantined)?,
+
+            Some(V1_VERSION) => {
+                let mut slots = Self::read_v1_records(&mut file)?;
+                Self::migrate_v1_slugs(dir, &mut slots)?;
+                Self::rewrite_file(&mut file, &slots)?;
+
+                slots
+            }
 
-    /// Validate file metadata
-    fn check_header(file: &mut File) -> InternalResult<bool> {
+            _ => {
+                Self::init_file(&mut file)?;
+                Vec::new()
+            }
+        };
+
+        let vaults = slots.iter().flatten().map(|v| v.name.clone()).collect();
+
+        Ok(Self {
+            vaults,
+            slots,
+            quarantined,
+            file,
+        })
+    }
+
+    /// Read format version from file metadata, `None` if header is invalid
+    fn read_version(file: &mut F) -> InternalResult<Option<u32>> {
         file.seek(SeekFrom::Start(0))?;
         let mut header = [0u8; HEADER_SIZE];
 
@@ -82,7 +144,7 @@ impl VaultIndex {
                 let (magic_bytes, version_bytes) = header.split_at(MAGIC.len());
 
                 if magic_bytes != MAGIC {
-                    return Ok(false);
+                    return Ok(None);
                 }
 
                 let version = u32::from_le_bytes([
@@ -92,9 +154,121 @@ impl VaultIndex {
                     version_bytes[3],
                 ]);
 
-                Ok(version == VERSION)
+                Ok(Some(version))
+            }
+
+            Err(err) if err.kind() == ErrorKind::UnexpectedEof => Ok(None),
+
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// Read all v2 records after the header, those failing to decode are
+    /// left out and their slots added to `quarantined`
+    fn read_records(
+        file: &mut F,
+        quarantined: &mut Vec<usize>,
+    ) -> InternalResult<Vec<Option<Vault>>> {
+        file.seek(SeekFrom::Start(HEADER_SIZE as u64))?;
+        let mut slots = Vec::new();
+        let mut buf = [0u8; RECORD_SIZE];
+
+        // partial record at end is ignored, and later overwritten
+        while Self::read_record(file, &mut buf)? {
+            // one bad record must not lock the user out of all vaults
+            match Self::decode_record(&buf) {
+                Ok(slot) => slots.push(slot),
+                Err(err) => {
+                    eprintln!("[WARN]: Skipped vault record {}: {err}", slots.len());
+                    quarantined.push(slots.len());
+                    slots.push(None);
+                }
+            }
+        }
+
+        Ok(slots)
+    }
+
+    /// Read all v1 records, where the name doubles as directory name
+    fn read_v1_records(file: &mut F) -> InternalResult<Vec<Option<Vault>>> {
+        file.seek(SeekFrom::Start(HEADER_SIZE as u64))?;
+        let mut slots = Vec::new();
+        let mut buf = [0u8; V1_RECORD_SIZE];
+
+        while Self::read_record(file, &mut buf)? {
+            if buf.iter().all(|&b| b == 0) {
+                continue;
             }
 
+            let Ok(name) = decode_str(&buf) else {
+                eprintln!("[WARN]: Skipped a vault record which isn't UTF-8");
+                continue;
+            };
+
+            slots.push(Some(Vault {
+                slug: name.clone(),
+                name,
+                archived: false,
+                format: EntryFormat::Json,
+                capacity: None,
+            }));
+        }
+
+        Ok(slots)
+    }
+
+    /// Give v1 vaults whose name isn't a safe dir name a fresh slug, their
+    /// data dir and files renamed to match
+    ///
+    /// NOTE: names which aren't a single path component (e.g. `..`) never
+    /// had a dir of their own, nothing of theirs is moved
+    fn migrate_v1_slugs(dir: &Path, slots: &mut [Option<Vault>]) -> InternalResult<()> {
+        for i in 0..slots.len() {
+            let Some(old) = slots[i].as_ref().map(|v| v.slug.clone()) else {
+                continue;
+            };
+
+            if validate_dir_name(&old).is_ok() {
+                continue;
+            }
+
+            let slug = generate_slug(slots)?;
+            let mut components = Path::new(&old).components();
+            let own_dir = matches!(
+                (components.next(), components.next()),
+                (Some(Component::Normal(_)), None)
+            );
+
+            if own_dir {
+                rename_if_exists(&dir.join(&old), &dir.join(&slug))?;
+
+                for suffix in SLUG_FILE_SUFFIXES {
+                    rename_if_exists(
+                        &dir.join(format!("{old}{suffix}")),
+                        &dir.join(format!("{slug}{suffix}")),
+                    )?;
+                }
+
+                let state = crate::dirs::state_dir(dir);
+                rename_if_exists(
+                    &state.join(format!("{old}{}", crate::attempts::ATTEMPTS_EXT)),
+                    &state.join(format!("{slug}{}", crate::attempts::ATTEMPTS_EXT)),
+                )?;
+            }
+
+            if let Some(vault) = slots[i].as_mut() {
+                vault.slug = slug;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Fill `buf` w/ the next record, `false` on (partial record at) EOF
+    fn read_record(file: &mut F, buf: &mut [u8]) -> InternalResult<bool> {
+        match file.read_exact(buf) {
+            Ok(()) => Ok(true),
+
             Err(err) if err.kind() == ErrorKind::UnexpectedEof => Ok(false),
 
             Err(err) => Err(err.into()),
@@ -102,7 +276,12 @@ impl VaultIndex {
     }
 
     /// Init or re-init index file and write header
-    fn init_file(file: &mut File) -> InternalResult<()> {
+    fn init_file(file: &mut F) -> InternalResult<()> {
+        Self::rewrite_file(file, &[])
+    }
+
+    /// Truncate file and write header followed by given records
+    fn rewrite_file(file: &mut F, slots: &[Option<Vault>]) -> InternalResult<()> {
         file.set_len(0)?;
         file.seek(SeekFrom::Start(0))?;
 
@@ -110,11 +289,16 @@ impl VaultIndex {
         file.write_all(MAGIC)?;
         file.write_all(&VERSION.to_le_bytes())?;
 
+        for slot in slots {
+            match slot {
+                Some(vault) => file.write_all(&Self::encode_record(vault))?,
+                None => file.write_all(&[0u8; RECORD_SIZE])?,
+            }
+        }
+
         file.flush()?;
         file.sync_data()?;
 
-        // after header, file length == HEADER_SIZE
-        file.seek(SeekFrom::Start(HEADER_SIZE as u64))?;
         Ok(())
     }
 
@@ -128,138 +312,323 @@ impl VaultIndex {
         &self.vaults
     }
 
+    /// Lookup a vault by its display name
+    pub fn get(&self, name: &str) -> Option<&Vault> {
+        self.slots.iter().flatten().find(|v| v.name == name)
+    }
+
     /// Add a new valut (avoids duplicates)
     pub fn add(&mut self, name: &str) -> InternalResult<()> {
-        if name.is_empty() {
-            return Err(io::Error::new(ErrorKind::InvalidInput, "name must be non-empty").into());
-        }
+        self.add_many(&[name])
+    }
 
-        if name.as_bytes().len() > RECORD_SIZE {
-            return Err(io::Error::new(
-                ErrorKind::InvalidInput,
-                format!("name byte-length must be <= {} bytes", RECORD_SIZE),
-            )
-            .into());
+    /// Add many vaults at once w/ a single sync (avoids duplicates)
+    ///
+    /// NOTE: All names are validated upfront, so an invalid name rejects
+    /// the whole batch w/o writing anything
+    pub fn add_many(&mut self, names: &[&str]) -> InternalResult<()> {
+        for name in names {
+            validate_name(name)?;
         }
 
-        if name.as_bytes().iter().any(|&b| b == 0) {
-            return Err(io::Error::new(ErrorKind::InvalidInput, "name cannot contain NUL").into());
+        let mut pending: Vec<&str> = Vec::new();
+
+        for &name in names {
+            if !self.vaults.iter().any(|v| v == name) && !pending.contains(&name) {
+                pending.push(name);
+            }
         }
 
-        // already present
-        if self.vaults.iter().any(|v| v == name) {
+        if pending.is_empty() {
             return Ok(());
         }
 
-        // prepare encodings (padded w/ zeros)
-        let mut record = [0u8; RECORD_SIZE];
-        let bytes = name.as_bytes();
+        // reuse free slots first, then append after the last record
+        let free_slots: Vec<usize> = (0..self.slots.len())
+            .filter(|&i| self.slots[i].is_none() && !self.quarantined.contains(&i))
+            .collect();
+        let new_slots = self.slots.len()..;
+
+        for (slot, name) in free_slots.into_iter().chain(new_slots).zip(&pending) {
+            let vault = Vault {
+                name: name.to_string(),
+                slug: generate_slug(&self.slots)?,
+                archived: false,
+                format: EntryFormat::Json,
+                capacity: None,
+            };
+
+            self.write_slot(slot, &Self::encode_record(&vault))?;
+
+            if slot < self.slots.len() {
+                self.slots[slot] = Some(vault);
+            } else {
+                self.slots.push(Some(vault));
+            }
+        }
 
-        record[..bytes.len()].copy_from_slice(bytes);
+        self.file.flush()?;
+        self.file.sync_data()?;
 
-        self.file.seek(SeekFrom::Start(HEADER_SIZE as u64))?;
-        let mut buf = [0u8; RECORD_SIZE];
-        let mut idx: u64 = 0;
-        let mut slot_idx: Option<u64> = None;
-
-        loop {
-            match self.file.read_exact(&mut buf) {
-                Ok(()) => {
-                    if buf.iter().all(|&b| b == 0) {
-                        slot_idx = Some(idx);
-                        break;
-                    }
-
-                    idx += 1;
-                }
+        self.vaults.extend(pending.into_iter().map(String::from));
+
+        Ok(())
+    }
 
-                Err(err) if err.kind() == ErrorKind::UnexpectedEof => break,
+    /// Change display name of a vault, its directory stays the same
+    pub fn rename(&mut self, name: &str, new_name: &str) -> InternalResult<bool> {
+        validate_name(new_name)?;
 
-                Err(err) => return Err(err.into()),
-            }
+        if self.vaults.iter().any(|v| v == new_name) {
+            return Err(io::Error::new(
+                ErrorKind::AlreadyExists,
+                format!("vault '{}' already exists", new_name),
+            )
+            .into());
         }
 
-        match slot_idx {
-            Some(slot) => {
-                let pos = Self::calculate_offset_for_slot(slot);
-                self.file.seek(SeekFrom::Start(pos))?;
-            }
+        let updated = self.update(name, |v| v.name = new_name.to_string())?;
 
-            None => {
-                self.file.seek(SeekFrom::End(0))?;
-            }
+        if updated && let Some(v) = self.vaults.iter_mut().find(|v| *v == name) {
+            *v = new_name.to_string();
         }
 
-        self.file.write_all(&record)?;
-        self.file.flush()?;
-        self.file.sync_data()?;
+        Ok(updated)
+    }
 
-        // Reset file pointer to end for future operations
-        self.file.seek(SeekFrom::End(0))?;
+    /// Archive or unarchive a vault
+    pub fn set_archived(&mut self, name: &str, archived: bool) -> InternalResult<bool> {
+        self.update(name, |v| v.archived = archived)
+    }
 
-        self.vaults.push(name.to_string());
+    /// Set the encoding used for newly written entries, returns `false` if
+    /// vault doesn't exist
+    pub fn set_format(&mut self, name: &str, format: EntryFormat) -> InternalResult<bool> {
+        self.update(name, |v| v.format = format)
+    }
 
-        Ok(())
+    /// Set the cache capacity, returns `false` if vault doesn't exist
+    pub fn set_capacity(&mut self, name: &str, capacity: Option<u32>) -> InternalResult<bool> {
+        self.update(name, |v| v.capacity = capacity)
     }
 
-    /// Delete valut name
-    pub fn remove(&mut self, name: &str) -> InternalResult<bool> {
-        if !self.vaults.iter().any(|v| v == name) {
+    /// Apply `f` to a vault's record and persist it
+    fn update<U: FnOnce(&mut Vault)>(&mut self, name: &str, f: U) -> InternalResult<bool> {
+        let Some(slot) = self.slot_of(name) else {
             return Ok(false);
-        }
+        };
 
-        // find the record (first match)
-        self.file.seek(SeekFrom::Start(HEADER_SIZE as u64))?;
-        let mut buf = [0u8; RECORD_SIZE];
-        let mut idx: u64 = 0;
-        let mut found = false;
+        let mut vault = self.slots[slot].clone().expect("slot is occupied");
+        f(&mut vault);
 
-        while let Ok(()) = self.file.read_exact(&mut buf) {
-            if buf.iter().all(|&b| b == 0) {
-                idx += 1;
-                continue;
-            }
+        self.write_slot(slot, &Self::encode_record(&vault))?;
+        self.file.flush()?;
+        self.file.sync_data()?;
 
-            let len = buf.iter().position(|&b| b == 0).unwrap_or(RECORD_SIZE);
-            let rec_name = std::str::from_utf8(&buf[..len])
-                .map_err(|e| io::Error::new(ErrorKind::InvalidData, e))?;
-            if rec_name == name {
-                found = true;
-                break;
-            }
+        self.slots[slot] = Some(vault);
+
+        Ok(true)
+    }
+
+    /// Encode vault into a record (padded w/ zeros)
+    fn encode_record(vault: &Vault) -> [u8; RECORD_SIZE] {
+        let mut record = [0u8; RECORD_SIZE];
+        let name = vault.name.as_bytes();
+        let slug = vault.slug.as_bytes();
 
-            idx += 1;
+        record[..name.len()].copy_from_slice(name);
+        record[NAME_SIZE..NAME_SIZE + slug.len()].copy_from_slice(slug);
+
+        if vault.archived {
+            record[FLAGS_OFFSET] |= FLAG_ARCHIVED;
         }
 
-        // Always remove from in-memory vector if present
-        if let Some(pos) = self.vaults.iter().position(|v| v == name) {
-            self.vaults.remove(pos);
+        if vault.format == EntryFormat::MessagePack {
+            record[FLAGS_OFFSET] |= FLAG_MSGPACK;
         }
 
-        if !found {
-            // Not found on disk, but was in memory - still return true since we did remove it
-            return Ok(true);
+        let capacity = vault.capacity.unwrap_or(0).to_le_bytes();
+        record[CAPACITY_OFFSET..CAPACITY_OFFSET + 4].copy_from_slice(&capacity);
+
+        record
+    }
+
+    /// Decode a record, `None` for free slots
+    fn decode_record(buf: &[u8; RECORD_SIZE]) -> InternalResult<Option<Vault>> {
+        if buf.iter().all(|&b| b == 0) {
+            return Ok(None);
         }
 
-        let pos = Self::calculate_offset_for_slot(idx);
+        let name = decode_str(&buf[..NAME_SIZE])?;
+        let slug = decode_str(&buf[NAME_SIZE..FLAGS_OFFSET])?;
+        let archived = buf[FLAGS_OFFSET] & FLAG_ARCHIVED != 0;
+        let format = match buf[FLAGS_OFFSET] & FLAG_MSGPACK {
+            0 => EntryFormat::Json,
+            _ => EntryFormat::MessagePack,
+        };
+
+        let mut capacity = [0u8; 4];
+        capacity.copy_from_slice(&buf[CAPACITY_OFFSET..CAPACITY_OFFSET + 4]);
+        let capacity = Some(u32::from_le_bytes(capacity)).filter(|&c| c > 0);
+
+        // never trust directory names read from disk
+        validate_dir_name(&slug)?;
+
+        Ok(Some(Vault {
+            name,
+            slug,
+            archived,
+            format,
+            capacity,
+        }))
+    }
+
+    /// Write record at given slot (w/o syncing)
+    fn write_slot(&mut self, slot: usize, record: &[u8; RECORD_SIZE]) -> InternalResult<()> {
+        let pos = Self::calculate_offset_for_slot(slot as u64);
+
         self.file.seek(SeekFrom::Start(pos))?;
+        self.file.write_all(record)?;
+
+        Ok(())
+    }
+
+    /// Slot holding vault w/ given name
+    fn slot_of(&self, name: &str) -> Option<usize> {
+        self.slots
+            .iter()
+            .position(|s| s.as_ref().is_some_and(|v| v.name == name))
+    }
 
-        let zeros = [0u8; RECORD_SIZE];
-        self.file.write_all(&zeros)?;
+    /// Delete valut name
+    pub fn remove(&mut self, name: &str) -> InternalResult<bool> {
+        let Some(pos) = self.vaults.iter().position(|v| v == name) else {
+            return Ok(false);
+        };
+
+        self.vaults.remove(pos);
+
+        // find the record (first match)
+        let Some(slot) = self.slot_of(name) else {
+            return Ok(true);
+        };
 
+        self.write_slot(slot, &[0u8; RECORD_SIZE])?;
         self.file.flush()?;
         self.file.sync_data()?;
 
-        // Reset file pointer to end for future operations
-        self.file.seek(SeekFrom::End(0))?;
+        self.slots[slot] = None;
 
         Ok(true)
     }
 }
+
+/// Random hex directory name, unique among `slots`
+fn generate_slug(slots: &[Option<Vault>]) -> InternalResult<String> {
+    loop {
+        let mut bytes = [0u8; SLUG_SIZE / 2];
+        rng::fill(&mut bytes)?;
+
+        let slug: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+
+        if !slots.iter().flatten().any(|v| v.slug == slug) {
+            return Ok(slug);
+        }
+    }
+}
+
+fn rename_if_exists(from: &Path, to: &Path) -> InternalResult<()> {
+    match std::fs::rename(from, to) {
+        Ok(()) => Ok(()),
+        Err(err) if err.kind() == ErrorKind::NotFound => Ok(()),
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// Decode a zero padded utf-8 string
+fn decode_str(buf: &[u8]) -> InternalResult<String> {
+    let len = buf.iter().position(|&b| b == 0).unwrap_or(buf.len());
+    let s =
+        std::str::from_utf8(&buf[..len]).map_err(|e| io::Error::new(ErrorKind::InvalidData, e))?;
+
+    Ok(s.to_string())
+}
+
+fn invalid_input(msg: String) -> InternalResult<()> {
+    Err(io::Error::new(ErrorKind::InvalidInput, msg).into())
+}
+
+/// Check if a display name can be stored in a record
+pub(crate) fn validate_name(name: &str) -> InternalResult<()> {
+    if name.is_empty() {
+        return invalid_input("name must be non-empty".into());
+    }
+
+    if name.len() > NAME_SIZE {
+        return invalid_input(format!("name byte-length must be <= {} bytes", NAME_SIZE));
+    }
+
+    if name.as_bytes().contains(&0) {
+        return invalid_input("name cannot contain NUL".into());
+    }
+
+    if let Some(c) = name.chars().find(|c| c.is_control()) {
+        return invalid_input(format!("name cannot contain control character {:?}", c));
+    }
+
+    if name.starts_with(char::is_whitespace) || name.ends_with(char::is_whitespace) {
+        return invalid_input("name cannot start or end w/ whitespace".into());
+    }
+
+    Ok(())
+}
+
+/// Check if name can be safely used as a directory name on every platform
+fn validate_dir_name(name: &str) -> InternalResult<()> {
+    if name.is_empty() || name.len() > SLUG_SIZE {
+        return invalid_input(format!("invalid vault directory '{}'", name));
+    }
+
+    if let Some(c) = name.chars().find(|c| c.is_control()) {
+        return invalid_input(format!("name cannot contain control character {:?}", c));
+    }
+
+    if let Some(c) = name.chars().find(|c| FORBIDDEN_CHARS.contains(c)) {
+        return invalid_input(format!("name cannot contain '{}'", c));
+    }
+
+    if name == "." || name == ".." {
+        return invalid_input("name cannot be '.' or '..'".into());
+    }
+
+    if name.starts_with(char::is_whitespace) || name.ends_with(char::is_whitespace) {
+        return invalid_input("name cannot start or end w/ whitespace".into());
+    }
+
+    // windows silently strips trailing dots
+    if name.ends_with('.') {
+        return invalid_input("name cannot end w/ '.'".into());
+    }
+
+    // windows device names are reserved w/ any extension, e.g. `nul.txt`
+    let stem = name.split('.').next().unwrap_or(name);
+
+    if RESERVED_NAMES.iter().any(|r| r.eq_ignore_ascii_case(stem)) {
+        return invalid_input(format!("'{}' is a reserved name", name));
+    }
+
+    // vault dirs live next to app files
+    if name.eq_ignore_ascii_case(INDEX_PATH) || name.eq_ignore_ascii_case("config.toml") {
+        return invalid_input(format!("'{}' is used by vuoto itself", name));
+    }
+
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::fs;
+    use std::{cell::Cell, fs, rc::Rc};
     use tempfile::TempDir;
 
     fn setup_temp_dir() -> TempDir {
@@ -340,7 +709,7 @@ mod tests {
         let temp_dir = setup_temp_dir();
         let mut index = VaultIndex::open(&temp_dir.path()).unwrap();
 
-        let long_name = "a".repeat(RECORD_SIZE + 1);
+        let long_name = "a".repeat(NAME_SIZE + 1);
         let result = index.add(&long_name);
         assert!(result.is_err());
         assert_eq!(index.vaults().len(), 0);
@@ -351,7 +720,7 @@ mod tests {
         let temp_dir = setup_temp_dir();
         let mut index = VaultIndex::open(&temp_dir.path()).unwrap();
 
-        let max_name = "a".repeat(RECORD_SIZE);
+        let max_name = "a".repeat(NAME_SIZE);
         index.add(&max_name).unwrap();
         assert_eq!(index.vaults().len(), 1);
         assert_eq!(index.vaults()[0], max_name);
@@ -367,6 +736,231 @@ mod tests {
         assert_eq!(index.vaults().len(), 0);
     }
 
+    #[test]
+    fn test_add_name_any_display_name() {
+        let temp_dir = setup_temp_dir();
+        let mut index = VaultIndex::open(&temp_dir.path()).unwrap();
+
+        // display names never touch the filesystem
+        for name in [
+            "../evil",
+            "C:\\foo",
+            "CON",
+            "work / personal 🔐",
+            INDEX_PATH,
+        ] {
+            index.add(name).unwrap();
+
+            let slug = &index.get(name).unwrap().slug;
+            assert_eq!(slug.len(), SLUG_SIZE);
+            assert!(slug.chars().all(|c| c.is_ascii_hexdigit()));
+        }
+
+        for name in ["tab\there", " padded", "padded "] {
+            assert!(index.add(name).is_err(), "accepted {:?}", name);
+        }
+
+        assert_eq!(index.vaults().len(), 5);
+    }
+
+    #[test]
+    fn test_rename_keeps_directory() {
+        let temp_dir = setup_temp_dir();
+        let mut index = VaultIndex::open(&temp_dir.path()).unwrap();
+
+        index.add_many(&["work", "home"]).unwrap();
+        let slug = index.get("work").unwrap().slug.clone();
+
+        assert!(index.rename("work", "Arbeit 💼").unwrap());
+        assert!(!index.rename("missing", "other").unwrap());
+        assert!(index.rename("home", "Arbeit 💼").is_err());
+
+        drop(index);
+        let index = VaultIndex::open(&temp_dir.path()).unwrap();
+
+        assert_eq!(index.vaults(), &["Arbeit 💼", "home"]);
+        assert!(index.get("work").is_none());
+        assert_eq!(index.get("Arbeit 💼").unwrap().slug, slug);
+    }
+
+    #[test]
+    fn test_set_format() {
+        let temp_dir = setup_temp_dir();
+        let mut index = VaultIndex::open(&temp_dir.path()).unwrap();
+
+        index.add_many(&["work", "home"]).unwrap();
+        assert_eq!(index.get("work").unwrap().format, EntryFormat::Json);
+
+        assert!(index.set_format("work", EntryFormat::MessagePack).unwrap());
+        index.set_archived("work", true).unwrap();
+
+        drop(index);
+        let index = VaultIndex::open(&temp_dir.path()).unwrap();
+
+        // both flags share a byte, neither clobbers the other
+        let work = index.get("work").unwrap();
+        assert_eq!(work.format, EntryFormat::MessagePack);
+        assert!(work.archived);
+        assert_eq!(index.get("home").unwrap().format, EntryFormat::Json);
+    }
+
+    #[test]
+    fn test_set_capacity() {
+        let temp_dir = setup_temp_dir();
+        let mut index = VaultIndex::open(&temp_dir.path()).unwrap();
+
+        index.add_many(&["work", "home"]).unwrap();
+        assert!(index.get("work").unwrap().capacity.is_none());
+
+        assert!(index.set_capacity("work", Some(4096)).unwrap());
+        assert!(!index.set_capacity("missing", Some(1)).unwrap());
+
+        drop(index);
+        let mut index = VaultIndex::open(&temp_dir.path()).unwrap();
+
+        assert_eq!(index.get("work").unwrap().capacity, Some(4096));
+        assert!(index.get("home").unwrap().capacity.is_none());
+
+        index.set_capacity("work", None).unwrap();
+        assert!(index.get("work").unwrap().capacity.is_none());
+    }
+
+    #[test]
+    fn test_archive_vault() {
+        let temp_dir = setup_temp_dir();
+        let mut index = VaultIndex::open(&temp_dir.path()).unwrap();
+
+        index.add_many(&["work", "old"]).unwrap();
+        assert!(index.set_archived("old", true).unwrap());
+        assert!(!index.set_archived("missing", true).unwrap());
+
+        drop(index);
+        let mut index = VaultIndex::open(&temp_dir.path()).unwrap();
+
+        // archived vaults stay listed, only flagged
+        assert_eq!(index.vaults(), &["work", "old"]);
+        assert!(!index.get("work").unwrap().archived);
+        assert!(index.get("old").unwrap().archived);
+
+        // flag survives a rename
+        index.rename("old", "older").unwrap();
+        assert!(index.get("older").unwrap().archived);
+
+        index.set_archived("older", false).unwrap();
+        drop(index);
+
+        let index = VaultIndex::open(&temp_dir.path()).unwrap();
+        assert!(!index.get("older").unwrap().archived);
+    }
+
+    #[test]
+    fn test_migrate_v1_index() {
+        let temp_dir = setup_temp_dir();
+        let index_path = temp_dir.path().join(INDEX_PATH);
+
+        // v1 layout w/ a freed slot in between
+        let mut file = File::create(&index_path).unwrap();
+        file.write_all(MAGIC).unwrap();
+        file.write_all(&V1_VERSION.to_le_bytes()).unwrap();
+
+        for name in ["vault1", "", "vault2"] {
+            let mut record = [0u8; V1_RECORD_SIZE];
+            record[..name.len()].copy_from_slice(name.as_bytes());
+            file.write_all(&record).unwrap();
+        }
+
+        drop(file);
+
+        let mut index = VaultIndex::open(&temp_dir.path()).unwrap();
+        assert_eq!(index.vaults(), &["vault1", "vault2"]);
+
+        // existing vault dirs are kept as-is
+        assert_eq!(index.get("vault1").unwrap().slug, "vault1");
+        assert_eq!(index.get("vault2").unwrap().slug, "vault2");
+
+        index.add("vault3").unwrap();
+        drop(index);
+
+        let mut file = File::open(&index_path).unwrap();
+        assert_eq!(VaultIndex::read_version(&mut file).unwrap(), Some(VERSION));
+
+        let index = VaultIndex::open(&temp_dir.path()).unwrap();
+        assert_eq!(index.vaults(), &["vault1", "vault2", "vault3"]);
+        assert_eq!(index.get("vault2").unwrap().slug, "vault2");
+    }
+
+    #[test]
+    fn test_migrate_v1_unsafe_names() {
+        let temp_dir = setup_temp_dir();
+        let index_path = temp_dir.path().join(INDEX_PATH);
+
+        let mut file = File::create(&index_path).unwrap();
+        file.write_all(MAGIC).unwrap();
+        file.write_all(&V1_VERSION.to_le_bytes()).unwrap();
+
+        for name in ["a:b", "..", "con", "ok"] {
+            let mut record = [0u8; V1_RECORD_SIZE];
+            record[..name.len()].copy_from_slice(name.as_bytes());
+            file.write_all(&record).unwrap();
+        }
+
+        drop(file);
+
+        std::fs::create_dir(temp_dir.path().join("a:b")).unwrap();
+        std::fs::write(temp_dir.path().join("a:b.master"), "").unwrap();
+
+        let index = VaultIndex::open(&temp_dir.path()).unwrap();
+        assert_eq!(index.vaults(), &["a:b", "..", "con", "ok"]);
+        assert_eq!(index.get("ok").unwrap().slug, "ok");
+
+        for name in ["a:b", "..", "con"] {
+            let slug = &index.get(name).unwrap().slug;
+            assert_eq!(slug.len(), SLUG_SIZE);
+            assert!(validate_dir_name(slug).is_ok());
+        }
+
+        // data of the vault moved along w/ its slug
+        let slug = &index.get("a:b").unwrap().slug;
+        assert!(temp_dir.path().join(slug).is_dir());
+        assert!(temp_dir.path().join(format!("{slug}.master")).exists());
+        assert!(!temp_dir.path().join("a:b").exists());
+        drop(index);
+
+        let index = VaultIndex::open(&temp_dir.path()).unwrap();
+        assert_eq!(index.vaults().len(), 4);
+    }
+
+    #[test]
+    fn test_unsafe_slug_on_disk_is_quarantined() {
+        let temp_dir = setup_temp_dir();
+        let index_path = temp_dir.path().join(INDEX_PATH);
+
+        let mut file = File::create(&index_path).unwrap();
+        file.write_all(MAGIC).unwrap();
+        file.write_all(&VERSION.to_le_bytes()).unwrap();
+        file.write_all(&VaultIndex::<File>::encode_record(&Vault {
+            name: "evil".into(),
+            slug: "../../etc".into(),
+            archived: false,
+            format: EntryFormat::Json,
+            capacity: None,
+        }))
+        .unwrap();
+        drop(file);
+
+        let mut index = VaultIndex::open(&temp_dir.path()).unwrap();
+        assert!(index.vaults().is_empty());
+        assert!(index.get("evil").is_none());
+
+        // its slot is never overwritten
+        index.add("vault1").unwrap();
+        drop(index);
+
+        let index = VaultIndex::open(&temp_dir.path()).unwrap();
+        assert_eq!(index.vaults(), &["vault1"]);
+        assert_eq!(index.quarantined, &[0]);
+    }
+
     #[test]
     fn test_remove_existing_vault() {
         let temp_dir = setup_temp_dir();
@@ -456,7 +1050,7 @@ mod tests {
         let unicode_names = vec!["café", "数据库", "🔐vault"];
 
         for name in &unicode_names {
-            if name.as_bytes().len() <= RECORD_SIZE {
+            if name.len() <= NAME_SIZE {
                 index.add(name).unwrap();
             }
         }
@@ -466,7 +1060,7 @@ mod tests {
         let index = VaultIndex::open(&temp_dir.path()).unwrap();
 
         for name in &unicode_names {
-            if name.as_bytes().len() <= RECORD_SIZE {
+            if name.len() <= NAME_SIZE {
                 assert!(index.vaults().contains(&name.to_string()));
             }
         }
@@ -571,13 +1165,16 @@ mod tests {
 
     #[test]
     fn test_calculate_offset_for_slot() {
-        assert_eq!(VaultIndex::calculate_offset_for_slot(0), HEADER_SIZE as u64);
         assert_eq!(
-            VaultIndex::calculate_offset_for_slot(1),
+            VaultIndex::<File>::calculate_offset_for_slot(0),
+            HEADER_SIZE as u64
+        );
+        assert_eq!(
+            VaultIndex::<File>::calculate_offset_for_slot(1),
             HEADER_SIZE as u64 + RECORD_SIZE as u64
         );
         assert_eq!(
-            VaultIndex::calculate_offset_for_slot(5),
+            VaultIndex::<File>::calculate_offset_for_slot(5),
             HEADER_SIZE as u64 + 5 * RECORD_SIZE as u64
         );
     }
@@ -610,6 +1207,190 @@ mod tests {
         assert_eq!(index.vaults().len(), 0);
     }
 
+    #[test]
+    fn test_add_many_basic() {
+        let temp_dir = setup_temp_dir();
+        let mut index = VaultIndex::open(&temp_dir.path()).unwrap();
+
+        index.add_many(&["vault1", "vault2", "vault3"]).unwrap();
+        assert_eq!(index.vaults(), &["vault1", "vault2", "vault3"]);
+
+        // Verify persistence
+        drop(index);
+        let index = VaultIndex::open(&temp_dir.path()).unwrap();
+        assert_eq!(index.vaults(), &["vault1", "vault2", "vault3"]);
+
+        let metadata = fs::metadata(temp_dir.path().join(INDEX_PATH)).unwrap();
+        assert_eq!(metadata.len(), HEADER_SIZE as u64 + 3 * RECORD_SIZE as u64);
+    }
+
+    #[test]
+    fn test_add_many_skips_duplicates() {
+        let temp_dir = setup_temp_dir();
+        let mut index = VaultIndex::open(&temp_dir.path()).unwrap();
+
+        index.add("vault1").unwrap();
+        index.add_many(&["vault1", "vault2", "vault2"]).unwrap();
+
+        assert_eq!(index.vaults(), &["vault1", "vault2"]);
+    }
+
+    #[test]
+    fn test_add_many_reuses_free_slots() {
+        let temp_dir = setup_temp_dir();
+        let mut index = VaultIndex::open(&temp_dir.path()).unwrap();
+
+        index
+            .add_many(&["vault1", "vault2", "vault3", "vault4"])
+            .unwrap();
+        index.remove("vault2").unwrap();
+        index.remove("vault3").unwrap();
+
+        index.add_many(&["vault5", "vault6", "vault7"]).unwrap();
+        assert_eq!(index.vaults().len(), 5);
+
+        // two slots reused, one appended
+        let metadata = fs::metadata(temp_dir.path().join(INDEX_PATH)).unwrap();
+        assert_eq!(metadata.len(), HEADER_SIZE as u64 + 5 * RECORD_SIZE as u64);
+
+        drop(index);
+        let index = VaultIndex::open(&temp_dir.path()).unwrap();
+        for name in ["vault1", "vault4", "vault5", "vault6", "vault7"] {
+            assert!(index.vaults().contains(&name.to_string()));
+        }
+    }
+
+    #[test]
+    fn test_add_many_invalid_name_rejects_batch() {
+        let temp_dir = setup_temp_dir();
+        let mut index = VaultIndex::open(&temp_dir.path()).unwrap();
+
+        let long_name = "a".repeat(NAME_SIZE + 1);
+        let result = index.add_many(&["vault1", &long_name]);
+
+        assert!(result.is_err());
+        assert_eq!(index.vaults().len(), 0);
+
+        drop(index);
+        let index = VaultIndex::open(&temp_dir.path()).unwrap();
+        assert_eq!(index.vaults().len(), 0);
+    }
+
+    /// File wrapper counting reads, to assert that slot lookups never
+    /// re-scan the file after open
+    struct CountingFile {
+        inner: File,
+        reads: Rc<Cell<usize>>,
+    }
+
+    impl Read for CountingFile {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.reads.set(self.reads.get() + 1);
+            self.inner.read(buf)
+        }
+    }
+
+    impl Write for CountingFile {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.inner.write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            self.inner.flush()
+        }
+    }
+
+    impl Seek for CountingFile {
+        fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+            self.inner.seek(pos)
+        }
+    }
+
+    impl IndexFile for CountingFile {
+        fn set_len(&mut self, size: u64) -> io::Result<()> {
+            self.inner.set_len(size)
+        }
+
+        fn sync_data(&mut self) -> io::Result<()> {
+            self.inner.sync_data()
+        }
+    }
+
+    fn open_counting(temp_dir: &TempDir) -> (VaultIndex<CountingFile>, Rc<Cell<usize>>) {
+        let reads = Rc::new(Cell::new(0));
+        let file = CountingFile {
+            inner: VaultIndex::read_file(&temp_dir.path()).unwrap(),
+            reads: reads.clone(),
+        };
+
+        (VaultIndex::from_file(file, temp_dir.path()).unwrap(), reads)
+    }
+
+    #[test]
+    fn test_slot_lookup_does_not_rescan_file() {
+        let temp_dir = setup_temp_dir();
+
+        {
+            let mut index = VaultIndex::open(&temp_dir.path()).unwrap();
+            index.add_many(&["vault1", "vault2", "vault3"]).unwrap();
+        }
+
+        let (mut index, reads) = open_counting(&temp_dir);
+        assert!(reads.get() > 0);
+        reads.set(0);
+
+        index.remove("vault2").unwrap();
+        index.add("vault4").unwrap();
+        index.add_many(&["vault5", "vault6"]).unwrap();
+        index.remove("vault1").unwrap();
+
+        assert_eq!(reads.get(), 0);
+
+        // in-memory slot map matches what lands on disk
+        drop(index);
+        let (index, _) = open_counting(&temp_dir);
+        let slots: Vec<Option<String>> = index
+            .slots
+            .iter()
+            .map(|s| s.as_ref().map(|v| v.name.clone()))
+            .collect();
+
+        assert_eq!(
+            slots,
+            vec![
+                None,
+                Some("vault4".to_string()),
+                Some("vault3".to_string()),
+                Some("vault5".to_string()),
+                Some("vault6".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_add_overwrites_partial_record() {
+        let temp_dir = setup_temp_dir();
+        let mut index = VaultIndex::open(&temp_dir.path()).unwrap();
+        index.add("test").unwrap();
+        drop(index);
+
+        let index_path = temp_dir.path().join(INDEX_PATH);
+        let mut file = OpenOptions::new().append(true).open(&index_path).unwrap();
+        file.write_all(&[1, 2, 3]).unwrap();
+        drop(file);
+
+        let mut index = VaultIndex::open(&temp_dir.path()).unwrap();
+        index.add("other").unwrap();
+        drop(index);
+
+        // new record is slot aligned, replacing the partial one
+        let metadata = fs::metadata(&index_path).unwrap();
+        assert_eq!(metadata.len(), HEADER_SIZE as u64 + 2 * RECORD_SIZE as u64);
+
+        let index = VaultIndex::open(&temp_dir.path()).unwrap();
+        assert_eq!(index.vaults(), &["test", "other"]);
+    }
+
     #[test]
     fn test_add_and_remove_same_name_multiple_times() {
         let temp_dir = setup_temp_dir();
@@ -0,0 +1,295 @@
+use crate::{
+    logins::Login,
+    types::{InternalError, InternalResult},
+};
+use zeroize::Zeroizing;
+
+/// Targets of credentials Windows keeps for its own accounts
+const SYSTEM_TARGET_PREFIXES: [&str; 3] = ["MicrosoftAccount:", "WindowsLive:", "virtualapp/"];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Kind {
+    /// saved by an app, e.g. git
+    Generic,
+    /// saved by Windows for a server, e.g. a share or a remote desktop
+    Domain,
+}
+
+/// Password credential of the user's Credential Manager
+pub(crate) struct Credential {
+    pub kind: Kind,
+    pub target: String,
+    pub username: String,
+    /// `None` if not text, or not revealed to apps, as for most domain
+    /// credentials
+    pub password: Option<Zeroizing<String>>,
+}
+
+/// Generic and domain password credentials of the user, w/o those of
+/// Windows itself
+pub(crate) fn credentials() -> InternalResult<Vec<Credential>> {
+    let mut credentials = platform::enumerate()?;
+
+    credentials.retain(|c| {
+        !SYSTEM_TARGET_PREFIXES
+            .iter()
+            .any(|p| c.target.starts_with(p))
+    });
+    Ok(credentials)
+}
+
+impl Credential {
+    /// Credential as shown when picking the ones to import
+    pub fn label(&self) -> String {
+        let kind = match self.kind {
+            Kind::Generic => "",
+            Kind::Domain => " [windows]",
+        };
+
+        match self.username.is_empty() {
+            true => format!("{}{kind}", self.target),
+            false => format!("{} ({}){kind}", self.target, self.username),
+        }
+    }
+
+    /// Login named after the host of its target if it has one, else after
+    /// the target w/o the prefix Windows adds
+    pub fn into_login(self) -> Login {
+        let target = self
+            .target
+            .split_once("target=")
+            .map_or(self.target.as_str(), |(_, t)| t);
+
+        let url = match self.kind {
+            // e.g. `git:https://github.com`
+            Kind::Generic => Some(target.strip_prefix("git:").unwrap_or(target))
+                .filter(|t| t.contains("://"))
+                .map(str::to_string),
+            Kind::Domain => target
+                .strip_prefix("TERMSRV/")
+                .map(|host| format!("rdp://{host}")),
+        };
+
+        match url {
+            Some(url) => Login::web(&url, &self.username, self.password),
+            None => Login {
+                name: target.to_string(),
+                url: None,
+                username: Some(self.username).filter(|u| !u.is_empty()),
+                password: self.password,
+            },
+        }
+    }
+}
+
+/// Password of a credential blob, UTF-16 as written by most apps, or UTF-8
+/// as by some
+fn decode_blob(blob: &[u8]) -> Option<Zeroizing<String>> {
+    let utf16 = || {
+        let units = Zeroizing::new(
+            blob.chunks_exact(2)
+                .map(|c| u16::from_le_bytes([c[0], c[1]]))
+                .collect::<Vec<u16>>(),
+        );
+        String::from_utf16(&units).ok()
+    };
+
+    let ascii_utf16 =
+        blob.len().is_multiple_of(2) && blob.iter().skip(1).step_by(2).all(|b| *b == 0);
+
+    let password = match ascii_utf16 {
+        true => utf16(),
+        false => std::str::from_utf8(blob)
+            .ok()
+            .map(str::to_string)
+            .or_else(|| utf16().filter(|_| blob.len().is_multiple_of(2))),
+    }?;
+
+    // binary secrets, e.g. tokens of apps, aren't passwords
+    if password.chars().any(char::is_control) {
+        return None;
+    }
+
+    Some(Zeroizing::new(password))
+}
+
+#[cfg(windows)]
+mod platform {
+    use super::*;
+    use std::ffi::c_void;
+
+    const CRED_TYPE_GENERIC: u32 = 1;
+    const CRED_TYPE_DOMAIN_PASSWORD: u32 = 2;
+    const CRED_ENUMERATE_ALL_CREDENTIALS: u32 = 0x1;
+    const ERROR_NOT_FOUND: i32 = 1168;
+
+    /// CREDENTIALW
+    #[repr(C)]
+    struct RawCredential {
+        flags: u32,
+        kind: u32,
+        target_name: *const u16,
+        comment: *const u16,
+        last_written: [u32; 2],
+        blob_size: u32,
+        blob: *const u8,
+        persist: u32,
+        attribute_count: u32,
+        attributes: *const c_void,
+        target_alias: *const u16,
+        user_name: *const u16,
+    }
+
+    #[link(name = "advapi32")]
+    unsafe extern "system" {
+        fn CredEnumerateW(
+            filter: *const u16,
+            flags: u32,
+            count: *mut u32,
+            credentials: *mut *mut *const RawCredential,
+        ) -> i32;
+        fn CredFree(buffer: *const c_void);
+    }
+
+    pub(super) fn enumerate() -> InternalResult<Vec<Credential>> {
+        let mut count = 0;
+        let mut raw = std::ptr::null_mut();
+
+        // SAFETY: the array and its credentials are allocated by Windows,
+        // only read while it is, and freed once copied
+        unsafe {
+            if CredEnumerateW(
+                std::ptr::null(),
+                CRED_ENUMERATE_ALL_CREDENTIALS,
+                &mut count,
+                &mut raw,
+            ) == 0
+            {
+                let error = std::io::Error::last_os_error();
+
+                if error.raw_os_error() == Some(ERROR_NOT_FOUND) {
+                    return Ok(Vec::new());
+                }
+
+                return Err(InternalError::IO(format!(
+                    "Failed to list Windows credentials: {error}"
+                )));
+            }
+
+            let credentials = std::slice::from_raw_parts(raw, count as usize)
+                .iter()
+                .filter_map(|c| read(&**c))
+                .collect();
+
+            CredFree(raw.cast());
+            Ok(credentials)
+        }
+    }
+
+    /// SAFETY: `raw` must be a credential of `CredEnumerateW`
+    unsafe fn read(raw: &RawCredential) -> Option<Credential> {
+        let kind = match raw.kind {
+            CRED_TYPE_GENERIC => Kind::Generic,
+            CRED_TYPE_DOMAIN_PASSWORD => Kind::Domain,
+            _ => return None,
+        };
+
+        let password = match raw.blob.is_null() {
+            true => None,
+            // SAFETY: the blob is `blob_size` bytes
+            false => {
+                decode_blob(unsafe { std::slice::from_raw_parts(raw.blob, raw.blob_size as usize) })
+            }
+        };
+
+        Some(Credential {
+            kind,
+            // SAFETY: both are null or terminated
+            target: unsafe { wide(raw.target_name) }.filter(|t| !t.is_empty())?,
+            username: unsafe { wide(raw.user_name) }.unwrap_or_default(),
+            password,
+        })
+    }
+
+    /// SAFETY: `s` must be null or a null terminated UTF-16 string
+    unsafe fn wide(s: *const u16) -> Option<String> {
+        if s.is_null() {
+            return None;
+        }
+
+        let mut len = 0;
+
+        // SAFETY: read up to the terminator
+        unsafe {
+            while *s.add(len) != 0 {
+                len += 1;
+            }
+
+            Some(String::from_utf16_lossy(std::slice::from_raw_parts(s, len)))
+        }
+    }
+}
+
+#[cfg(not(windows))]
+mod platform {
+    use super::*;
+
+    pub(super) fn enumerate() -> InternalResult<Vec<Credential>> {
+        Err(InternalError::IO(
+            "Windows credentials can only be imported on Windows".into(),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn credential(kind: Kind, target: &str, username: &str) -> Credential {
+        Credential {
+            kind,
+            target: target.into(),
+            username: username.into(),
+            password: None,
+        }
+    }
+
+    #[test]
+    fn test_targets_into_logins() {
+        let login = credential(Kind::Generic, "git:https://github.com", "alice").into_login();
+        assert_eq!(login.name, "github.com");
+        assert_eq!(login.url.as_deref(), Some("https://github.com"));
+        assert_eq!(login.username.as_deref(), Some("alice"));
+
+        let login = credential(Kind::Generic, "LegacyGeneric:target=backup-tool", "").into_login();
+        assert_eq!(login.name, "backup-tool");
+        assert!(login.url.is_none() && login.username.is_none());
+
+        let login = credential(Kind::Domain, "Domain:target=TERMSRV/build01", "CORP\\bob");
+        let login = login.into_login();
+        assert_eq!(login.name, "build01");
+        assert_eq!(login.url.as_deref(), Some("rdp://build01"));
+
+        let login = credential(Kind::Domain, "Domain:target=fileserver", "bob").into_login();
+        assert_eq!(login.name, "fileserver");
+        assert!(login.url.is_none());
+    }
+
+    #[test]
+    fn test_decode_blob() {
+        let utf16: Vec<u8> = "pässword"
+            .encode_utf16()
+            .flat_map(u16::to_le_bytes)
+            .collect();
+
+        assert_eq!(
+            decode_blob(&utf16).as_deref().map(String::as_str),
+            Some("pässword")
+        );
+        assert_eq!(
+            decode_blob(b"hunter22").as_deref().map(String::as_str),
+            Some("hunter22")
+        );
+        assert!(decode_blob(&[0x01, 0xff, 0x00, 0x9c, 0x12]).is_none());
+    }
+}
@@ -0,0 +1,107 @@
+use crate::{
+    config::YubikeyConfig,
+    crypto,
+    types::{InternalError, InternalResult},
+};
+use std::process::{Command, Stdio};
+use zeroize::Zeroizing;
+
+/// Slot programmed for challenge-response by `ykman otp chalresp`
+pub(crate) const DEFAULT_SLOT: u8 = 2;
+/// Random challenge sent on every unlock, well below the 64 byte limit
+pub(crate) const CHALLENGE_SIZE: usize = 32;
+/// HMAC-SHA1 output
+const RESPONSE_SIZE: usize = 20;
+
+/// HMAC-SHA1 of `challenge` under the secret in `slot` of the plugged in
+/// YubiKey, computed by `ykchalresp` (or the configured command)
+pub(crate) fn respond(
+    config: &YubikeyConfig,
+    slot: u8,
+    challenge: &[u8],
+) -> InternalResult<Zeroizing<Vec<u8>>> {
+    if !matches!(slot, 1 | 2) {
+        return Err(InternalError::IO(format!("Invalid YubiKey slot {slot}")));
+    }
+
+    let err = |e: std::io::Error| {
+        InternalError::IO(format!("Failed to run '{}': {e}", config.command.display()))
+    };
+
+    // a key requiring touch blinks until pressed, stderr tells the user
+    let output = Command::new(&config.command)
+        .arg(format!("-{slot}"))
+        .arg("-x")
+        .arg(crypto::hex(challenge))
+        .stdin(Stdio::null())
+        .stderr(Stdio::inherit())
+        .output()
+        .map_err(err)?;
+    let stdout = Zeroizing::new(output.stdout);
+
+    if !output.status.success() {
+        return Err(InternalError::IO(format!(
+            "No YubiKey answered the challenge in slot {slot}, '{}' exited w/ {}",
+            config.command.display(),
+            output.status
+        )));
+    }
+
+    let hex = std::str::from_utf8(&stdout).unwrap_or_default().trim();
+    let response = Zeroizing::new(
+        (0..hex.len())
+            .step_by(2)
+            .map(|i| {
+                hex.get(i..i + 2)
+                    .and_then(|b| u8::from_str_radix(b, 16).ok())
+            })
+            .collect::<Option<Vec<u8>>>()
+            .unwrap_or_default(),
+    );
+
+    if response.len() != RESPONSE_SIZE {
+        return Err(InternalError::IO(format!(
+            "'{}' gave no HMAC-SHA1 response, is slot {slot} programmed for challenge-response?",
+            config.command.display()
+        )));
+    }
+
+    Ok(response)
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+    use tempfile::TempDir;
+
+    fn fake_ykchalresp(temp_dir: &TempDir, script: &str) -> YubikeyConfig {
+        let path = temp_dir.path().join("ykchalresp");
+        std::fs::write(&path, format!("#!/bin/sh\n{script}\n")).unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        YubikeyConfig { command: path }
+    }
+
+    #[test]
+    fn test_respond_w_ykchalresp() {
+        let temp_dir = TempDir::new().unwrap();
+        let config = fake_ykchalresp(
+            &temp_dir,
+            r#"[ "$1 $2 $3" = "-2 -x 00ff" ] && echo 0123456789abcdef0123456789abcdef01234567"#,
+        );
+
+        let response = respond(&config, 2, &[0x00, 0xff]).unwrap();
+        assert_eq!(
+            crypto::hex(&response),
+            "0123456789abcdef0123456789abcdef01234567"
+        );
+
+        // wrong args make the fake exit w/ 1, like a missing key
+        assert!(respond(&config, 1, &[0x00, 0xff]).is_err());
+        assert!(respond(&config, 3, &[0x00, 0xff]).is_err());
+
+        let config = fake_ykchalresp(&temp_dir, "echo not hex");
+        assert!(respond(&config, 2, &[0x00]).is_err());
+    }
+}